@@ -1,112 +1,428 @@
 use std::{
-    borrow::BorrowMut,
-    io::{BufReader, BufWriter, Read, Write},
-    path::PathBuf,
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::{Scope, ScopedJoinHandle},
+    time::Instant,
 };
 
 use anyhow::{anyhow, Result};
 use bzip2::{read::BzDecoder, write::BzEncoder};
 use flate2::{read::GzDecoder, write::GzEncoder};
-use indicatif::ProgressBar;
 use xz2::{read::XzDecoder, write::XzEncoder};
-use zip::write::SimpleFileOptions;
+use zip::write::{FileOptions, SimpleFileOptions};
+use zip::{result::ZipError, AesMode};
+use zstd::stream::{read::Decoder as ZstdDecoder, write::Encoder as ZstdEncoder};
+use zstd::zstd_safe::get_dict_id_from_frame;
 
-use crate::util::{infer_input_file, prompt_error};
+use crate::cache::InnerCache;
+use crate::context::{Context, Duplicates, Encrypted, EntryOrder, MatchMode, Symlinks};
+use crate::cpio::{is_cpio, read_cpio, write_cpio, CpioEntry};
+use crate::filter::Decision;
+use crate::memory::Reservation;
+use crate::pipeline::{depth, encode_behind, read_ahead};
+use crate::provenance::{ProvenanceMode, PROVENANCE_ENTRY};
+use crate::reader::{
+    cpio_entry_kind, tar_entry_kind, unix_to_zip_datetime, zip_datetime_to_unix, EntryKind,
+    GNU_DUMPDIR,
+};
+use crate::rsyncable::RsyncableGzEncoder;
+use crate::rule::Rule;
+use crate::sink::{Sink, WriteSink};
+use crate::symlink::{follow, normalize};
+use crate::util::infer_input_file;
+use crate::workspace::Spool;
+use crate::zipfmt::{append_raw_entries, ZipDirectory};
+
+/// Bytes read from the start of an input to tell its format.
+const SNIFF_SIZE: usize = 8 << 10;
 
+/// Rewrites the archive `input_bytes` without the entries listed in
+/// `filter_list` and returns the result, in the format and compression of
+/// the input. Zip, tar (plain, gzip, bzip2, xz or zstd compressed) and cpio
+/// inputs are supported, nested archives are rewritten as well when listed
+/// paths reach into them, e.g. `app/lib.jar/META-INF/debug.txt`.
+///
+/// The rules of `filter_list` that matched an entry are removed from it,
+/// with [`MatchMode::First`], so what is left afterwards are the rules that
+/// matched nothing. With [`MatchMode::All`] the list is kept as it is and the
+/// matches are recorded in the occurrences of `ctx` instead.
+///
+/// # Errors
+///
+/// Fails on inputs of an unsupported format, corrupt archives, entries that
+/// fail to process while [`OnError::Abort`] applies, inputs exceeding the
+/// [`Untrusted`] limits, timeouts, cancellation through the token of `ctx`
+/// and errors of the registered [`EntryFilter`]s.
+///
+/// [`MatchMode::First`]: crate::MatchMode::First
+/// [`MatchMode::All`]: crate::MatchMode::All
+/// [`OnError::Abort`]: crate::OnError::Abort
+/// [`Untrusted`]: crate::Untrusted
+/// [`EntryFilter`]: crate::EntryFilter
 pub fn pack_archive(
-    progress_bar: &ProgressBar,
+    ctx: &Context,
     input_bytes: Vec<u8>,
     filter_list: &mut Vec<PathBuf>,
-    compression_level: u32,
 ) -> Result<Vec<u8>> {
+    pack_archive_into(ctx, input_bytes, filter_list, Vec::new())
+}
+
+/// Same as [`pack_archive`], but writes the result into `sink`.
+///
+/// Tar based outputs are streamed straight into the sink, zip and cpio
+/// outputs, and tar outputs with sorted entries or dereferenced symlinks,
+/// are assembled in memory first.
+pub fn pack_archive_into<S: Sink>(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+    mut sink: S,
+) -> Result<S> {
+    if let Some(untrusted) = &ctx.untrusted {
+        untrusted.check_depth(ctx.depth)?;
+    }
     let mime_type = infer_input_file(&input_bytes)?;
     match mime_type.as_str() {
-        "application/zip" => encode_zip(progress_bar, input_bytes, filter_list, compression_level),
-        "application/gzip" | "application/x-bzip2" | "application/x-xz" | "application/x-tar" => {
-            encode_tar(
-                progress_bar,
-                input_bytes,
-                filter_list,
-                compression_level,
-                mime_type.as_str(),
-            )
+        "application/zip" => {
+            let mut result = encode_zip(ctx, input_bytes, filter_list)?;
+            if ctx.symlinks == Symlinks::Dereference {
+                result = dereference_zip_links(ctx, result)?;
+            }
+            sink.write_all(&result)?;
+        }
+        "application/gzip"
+        | "application/x-bzip2"
+        | "application/x-xz"
+        | "application/zstd"
+        | "application/x-tar" => {
+            let decoder = tar_decoder(ctx, &input_bytes, &mime_type)?;
+            match sniff_stream(&mime_type, decoder)? {
+                StreamContent::Tar if !tar_in_memory(ctx) => {
+                    sink = encode_tar(ctx, input_bytes, filter_list, mime_type.as_str(), sink)?;
+                }
+                StreamContent::Tar => {
+                    sink = encode_tar_in_memory(
+                        ctx,
+                        input_bytes,
+                        filter_list,
+                        mime_type.as_str(),
+                        sink,
+                    )?;
+                }
+                StreamContent::Archive => {
+                    let result =
+                        encode_compressed_archive(ctx, input_bytes, filter_list, &mime_type)?;
+                    sink.write_all(&result)?;
+                }
+                StreamContent::Data => Err(anyhow!(
+                    "Unsupported File Type: The {} stream holds a single file, not an archive.",
+                    mime_type
+                ))?,
+            }
+        }
+        "application/x-cpio" => {
+            let result = encode_cpio(ctx, input_bytes, filter_list)?;
+            sink.write_all(&result)?;
         }
         _ => Err(anyhow!(
             "Unsupported File Type: The file with MIME type '{}' is not supported.",
             mime_type
         ))?,
     }
+    sink.finish()?;
+    Ok(sink)
+}
+
+/// Same as [`pack_archive_into`], but reads the input from `reader`, e.g. a
+/// file on disk.
+///
+/// Tar streams are decoded as they are read and filtered straight into the
+/// sink, zips are rewritten from the reader an entry at a time, neither the
+/// input nor the output is held in memory. Zip outputs are written into
+/// [`Sink::seekable`] sinks directly, through a temporary file otherwise.
+/// Other inputs, tar streams with sorted entries or dereferenced symlinks,
+/// and zips with duplicate paths, encrypted entries copied as they are,
+/// dereferenced symlinks or the provenance record stored as an entry, are
+/// read into memory first.
+pub fn pack_reader_into<R: Read + Seek + Send, S: Sink>(
+    ctx: &Context,
+    mut reader: R,
+    filter_list: &mut Vec<PathBuf>,
+    mut sink: S,
+) -> Result<S> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    (&mut reader)
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)?;
+    reader.rewind()?;
+    let mime_type = infer_input_file(&head)?;
+    let tar_stream = matches!(
+        mime_type.as_str(),
+        "application/gzip"
+            | "application/x-bzip2"
+            | "application/x-xz"
+            | "application/zstd"
+            | "application/x-tar"
+    );
+    if mime_type == "application/zip" {
+        if let Some(untrusted) = &ctx.untrusted {
+            untrusted.check_depth(ctx.depth)?;
+        }
+        if encode_zip_stream(ctx, &mut reader, filter_list, &mut sink)? {
+            sink.finish()?;
+            return Ok(sink);
+        }
+        reader.rewind()?;
+    }
+    if tar_stream && !tar_in_memory(ctx) {
+        let decoder = tar_stream_decoder(ctx, BufReader::new(&mut reader), &head, &mime_type)?;
+        let content = sniff_stream(&mime_type, decoder)?;
+        reader.rewind()?;
+        if content == StreamContent::Tar {
+            if let Some(untrusted) = &ctx.untrusted {
+                untrusted.check_depth(ctx.depth)?;
+            }
+            let reader = BufReader::with_capacity(ctx.buffer_size, reader);
+            sink = encode_tar_stream(ctx, reader, &head, filter_list, &mime_type, sink)?;
+            sink.finish()?;
+            return Ok(sink);
+        }
+    }
+    let mut input_bytes = Vec::new();
+    reader.read_to_end(&mut input_bytes)?;
+    pack_archive_into(ctx, input_bytes, filter_list, sink)
+}
+
+pub fn pack_initramfs(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+) -> Result<Vec<u8>> {
+    // an initramfs is a chain of uncompressed cpio archives (early microcode)
+    // optionally followed by a single compressed one holding the main image
+    let mut segments = Vec::new();
+    let mut offset = 0;
+    while offset < input_bytes.len() {
+        let rest = &input_bytes[offset..];
+        if !is_cpio(rest) {
+            segments.push(rest);
+            break;
+        }
+        let (_, consumed) = read_cpio(rest)?;
+        segments.push(&rest[..consumed]);
+        offset += consumed;
+    }
+
+    let (main_segment, early_segments) = segments.split_last().ok_or_else(|| {
+        anyhow!("Invalid Initramfs: The input does not contain any cpio segment.")
+    })?;
+
+    let mut result = Vec::new();
+    for (index, segment) in early_segments.iter().enumerate() {
+        ctx.progress
+            .message(format!("preserving segment: {}", index));
+        result.extend_from_slice(segment);
+    }
+
+    if is_cpio(main_segment) {
+        result.extend(encode_cpio(ctx, main_segment.to_vec(), filter_list)?);
+    } else {
+        let mime_type = infer_input_file(main_segment)?;
+        let mut decoder = tar_decoder(ctx, main_segment, &mime_type)?;
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded)?;
+        let filtered = encode_cpio(ctx, decoded, filter_list)?;
+
+        let mut encoder = tar_encoder(ctx, &mime_type, Vec::new())?;
+        encoder.write_all(&filtered)?;
+        result.extend(encoder.inner()?);
+    }
+    Ok(result)
+}
+
+/// Reads an entry of `size` bytes into `buffer` in chunks of
+/// `ctx.buffer_size`, so a cancellation is noticed while copying large
+/// entries. The buffer is reused across entries to avoid an allocation each.
+///
+/// Returns `false` if reading ran past `--entry-timeout` and the entry is
+/// skipped.
+fn read_entry<R: Read>(
+    ctx: &Context,
+    path: &str,
+    reader: &mut R,
+    size: u64,
+    buffer: &mut Vec<u8>,
+) -> Result<bool> {
+    buffer.clear();
+    buffer.reserve(size.try_into()?);
+    let deadline = ctx.entry_timeout.map(|timeout| Instant::now() + timeout);
+    let mut remaining = size;
+    while remaining > 0 {
+        ctx.check()?;
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            ctx.entry_error(anyhow!(
+                "Entry Timeout: Reading '{}' did not finish within --entry-timeout.",
+                path
+            ))?;
+            return Ok(false);
+        }
+        let chunk = remaining.min(ctx.buffer_size as u64);
+        let read = reader.take(chunk).read_to_end(buffer)?;
+        if read as u64 != chunk {
+            Err(anyhow!(
+                "Truncated Entry: The archive ended before the entry was complete."
+            ))?;
+        }
+        ctx.progress.bytes_processed(chunk);
+        remaining -= chunk;
+    }
+    Ok(true)
+}
+
+pub trait WriteEncoder<W = Vec<u8>>: Write {
+    fn inner(self: Box<Self>) -> Result<W>;
 }
 
-trait WriteEncoder: Write {
-    fn inner(self: Box<Self>) -> Result<Vec<u8>>;
+impl<W: Write> WriteEncoder<W> for GzEncoder<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
+        Ok(self.finish()?)
+    }
 }
 
-impl WriteEncoder for GzEncoder<Vec<u8>> {
-    fn inner(self: Box<Self>) -> Result<Vec<u8>> {
+impl<W: Write> WriteEncoder<W> for BzEncoder<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
         Ok(self.finish()?)
     }
 }
 
-impl WriteEncoder for BzEncoder<Vec<u8>> {
-    fn inner(self: Box<Self>) -> Result<Vec<u8>> {
+impl<W: Write> WriteEncoder<W> for XzEncoder<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
         Ok(self.finish()?)
     }
 }
 
-impl WriteEncoder for XzEncoder<Vec<u8>> {
-    fn inner(self: Box<Self>) -> Result<Vec<u8>> {
+impl<W: Write> WriteEncoder<W> for ZstdEncoder<'static, W> {
+    fn inner(self: Box<Self>) -> Result<W> {
         Ok(self.finish()?)
     }
 }
 
-impl WriteEncoder for BufWriter<Vec<u8>> {
-    fn inner(self: Box<Self>) -> Result<Vec<u8>> {
-        Ok(self.into_inner()?)
+impl<W: Write> WriteEncoder<W> for BufWriter<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
+        Ok(self.into_inner().map_err(|err| err.into_error())?)
     }
 }
 
-enum TarEncoder {
-    Gzip(GzEncoder<Vec<u8>>),
-    Bzip2(BzEncoder<Vec<u8>>),
-    Xz2(XzEncoder<Vec<u8>>),
-    XTar(BufWriter<Vec<u8>>),
+pub enum TarEncoder<W: Write = Vec<u8>> {
+    Gzip(GzEncoder<W>),
+    Bzip2(BzEncoder<W>),
+    Xz2(XzEncoder<W>),
+    Zstd(ZstdEncoder<'static, W>),
+    XTar(BufWriter<W>),
 }
 
 impl TarEncoder {
-    fn new(mime_type: &str, compression_level: u32) -> Result<Self> {
+    pub fn new(mime_type: &str, compression_level: u32) -> Result<Self> {
+        TarEncoder::with_writer(mime_type, compression_level, Vec::new())
+    }
+}
+
+impl<W: Write> TarEncoder<W> {
+    pub fn with_writer(mime_type: &str, compression_level: u32, writer: W) -> Result<Self> {
         match mime_type {
             "application/gzip" => {
-                let result = GzEncoder::new(Vec::new(), flate2::Compression::new(compression_level));
+                let result = GzEncoder::new(writer, flate2::Compression::new(compression_level));
                 Ok(TarEncoder::Gzip(result))
             }
             "application/x-bzip2" => {
-                let reuslt = BzEncoder::new(Vec::new(), bzip2::Compression::new(compression_level));
+                let reuslt = BzEncoder::new(writer, bzip2::Compression::new(compression_level));
                 Ok(TarEncoder::Bzip2(reuslt))
             }
             "application/x-xz" => {
-                let result = XzEncoder::new(Vec::new(), compression_level);
+                let result = XzEncoder::new(writer, compression_level);
                 Ok(TarEncoder::Xz2(result))
             }
+            "application/zstd" => {
+                let result = ZstdEncoder::new(writer, compression_level as i32)?;
+                Ok(TarEncoder::Zstd(result))
+            }
             "application/x-tar" => {
-                let result = BufWriter::new(Vec::new());
+                let result = BufWriter::new(writer);
                 Ok(TarEncoder::XTar(result))
             }
             _ => Err(anyhow!("Unsupported Encoding Format: The provided MIME type does not correspond to a supported encoding format.")),
         }
     }
 
-    fn encoder(self) -> Box<dyn WriteEncoder> {
+    pub fn encoder<'a>(self) -> Box<dyn WriteEncoder<W> + 'a>
+    where
+        W: 'a,
+    {
         match self {
             TarEncoder::Gzip(result) => Box::new(result),
             TarEncoder::Bzip2(result) => Box::new(result),
             TarEncoder::Xz2(result) => Box::new(result),
+            TarEncoder::Zstd(result) => Box::new(result),
             TarEncoder::XTar(result) => Box::new(result),
         }
     }
 }
 
-fn create_tar_decoder<'a>(reader: &'a [u8], mime_type: &str) -> Result<Box<dyn Read + 'a>> {
+impl<W: Write> WriteEncoder<W> for RsyncableGzEncoder<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
+        Ok(self.finish()?)
+    }
+}
+
+/// Encoder of a tar output, gzip is made rsyncable, xz uses the options of
+/// `ctx` and zstd its dictionary and long distance window.
+fn tar_encoder<'a, W: Write + 'a>(
+    ctx: &Context,
+    mime_type: &str,
+    writer: W,
+) -> Result<Box<dyn WriteEncoder<W> + 'a>> {
+    if ctx.rsyncable && mime_type == "application/gzip" {
+        return Ok(Box::new(RsyncableGzEncoder::new(
+            writer,
+            ctx.compression_level,
+        )));
+    }
+    if mime_type == "application/x-xz" && !ctx.xz.is_default() {
+        return ctx.xz.encoder(writer, ctx.compression_level);
+    }
+    if mime_type == "application/zstd" && (ctx.zstd_dict.is_some() || ctx.zstd_long.is_some()) {
+        let level = ctx.compression_level as i32;
+        let mut encoder = match &ctx.zstd_dict {
+            Some(dict) => ZstdEncoder::with_dictionary(writer, level, dict)?,
+            None => ZstdEncoder::new(writer, level)?,
+        };
+        if let Some(window_log) = ctx.zstd_long {
+            encoder.long_distance_matching(true)?;
+            encoder.window_log(window_log)?;
+        }
+        return Ok(Box::new(encoder));
+    }
+    Ok(TarEncoder::with_writer(mime_type, ctx.compression_level, writer)?.encoder())
+}
+
+/// Largest zstd window decoded, in bits, the limit of `zstd --long`.
+pub const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+pub fn create_tar_decoder<'a>(
+    reader: &'a [u8],
+    mime_type: &str,
+) -> Result<Box<dyn Read + Send + 'a>> {
+    stream_decoder(reader, mime_type)
+}
+
+/// Decoder of the `mime_type` stream read from `reader`.
+pub(crate) fn stream_decoder<'a, R: BufRead + Send + 'a>(
+    reader: R,
+    mime_type: &str,
+) -> Result<Box<dyn Read + Send + 'a>> {
     match mime_type {
         "application/gzip" => {
             Ok(Box::new(GzDecoder::new(reader)))
@@ -117,6 +433,11 @@ fn create_tar_decoder<'a>(reader: &'a [u8], mime_type: &str) -> Result<Box<dyn R
         "application/x-xz" => {
             Ok(Box::new(XzDecoder::new(reader)))
         }
+        "application/zstd" => {
+            let mut decoder = ZstdDecoder::with_buffer(reader)?;
+            decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+            Ok(Box::new(decoder))
+        }
         "application/x-tar" => {
             Ok(Box::new(BufReader::new(reader)))
         }
@@ -124,10 +445,129 @@ fn create_tar_decoder<'a>(reader: &'a [u8], mime_type: &str) -> Result<Box<dyn R
     }
 }
 
+/// Decoder of a tar input, zstd frames naming a dictionary are decoded with
+/// the one of `ctx`. Untrusted zstd inputs are limited to the default window
+/// of 128 MiB.
+fn tar_decoder<'a>(
+    ctx: &Context,
+    reader: &'a [u8],
+    mime_type: &str,
+) -> Result<Box<dyn Read + Send + 'a>> {
+    tar_stream_decoder(ctx, reader, reader, mime_type)
+}
+
+/// Same as [`tar_decoder`], for a stream read from `reader` starting with
+/// `head`.
+fn tar_stream_decoder<'a, R: BufRead + Send + 'a>(
+    ctx: &Context,
+    reader: R,
+    head: &[u8],
+    mime_type: &str,
+) -> Result<Box<dyn Read + Send + 'a>> {
+    if mime_type != "application/zstd" {
+        return stream_decoder(reader, mime_type);
+    }
+    let mut decoder = match get_dict_id_from_frame(head) {
+        Some(_) => {
+            let dict = ctx.zstd_dict.as_ref().ok_or_else(|| {
+                anyhow!("Missing Zstd Dictionary: The input was compressed with a dictionary, pass it with --zstd-dict use:<FILE>.")
+            })?;
+            ZstdDecoder::with_dictionary(reader, dict)?
+        }
+        None => ZstdDecoder::with_buffer(reader)?,
+    };
+    if ctx.untrusted.is_none() {
+        decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+    }
+    Ok(Box::new(decoder))
+}
+
+/// What a tar or compressed stream holds, judged from the start of its
+/// content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamContent {
+    Tar,
+    /// Another archive, like the cpio of a `.cpio.gz` or the zip of a
+    /// `.zip.xz`, filtered after decompressing it.
+    Archive,
+    /// A single compressed file, like a `.json.gz`.
+    Data,
+}
+
+/// What the stream `input_bytes` of `mime_type` holds.
+pub fn stream_content(input_bytes: &[u8], mime_type: &str) -> Result<StreamContent> {
+    sniff_stream(mime_type, create_tar_decoder(input_bytes, mime_type)?)
+}
+
+pub(crate) fn sniff_stream(mime_type: &str, decoder: impl Read) -> Result<StreamContent> {
+    if mime_type == "application/x-tar" {
+        return Ok(StreamContent::Tar);
+    }
+    let mut head = Vec::with_capacity(512);
+    decoder.take(512).read_to_end(&mut head)?;
+    if is_tar_header(&head) {
+        Ok(StreamContent::Tar)
+    } else if is_cpio(&head) || infer::is_archive(&head) {
+        Ok(StreamContent::Archive)
+    } else {
+        Ok(StreamContent::Data)
+    }
+}
+
+/// Whether `block` starts a tar: an end of archive block, or a header with a
+/// valid checksum, which old v7 archives without magic have too.
+fn is_tar_header(block: &[u8]) -> bool {
+    let Some(block) = block.get(..512) else {
+        return false;
+    };
+    if block.iter().all(|&b| b == 0) {
+        return true;
+    }
+    // the checksum is computed with its own field filled with spaces
+    let sum: u32 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| u32::from(if (148..156).contains(&i) { b' ' } else { b }))
+        .sum();
+    tar::Header::from_byte_slice(block)
+        .cksum()
+        .is_ok_and(|cksum| cksum == sum)
+}
+
+/// Whether the entry content `bytes` is an archive to descend into.
+/// Compressed single files are not, while streams that cannot be decoded
+/// are, to fail when the archive is read.
+pub fn holds_archive(bytes: &[u8]) -> bool {
+    if !infer::is_archive(bytes) {
+        return false;
+    }
+    let Ok(mime_type) = infer_input_file(bytes) else {
+        return false;
+    };
+    !matches!(stream_content(bytes, &mime_type), Ok(StreamContent::Data))
+}
+
+/// Filters the archive held by a compressed stream, like a `.cpio.gz`, and
+/// compresses the result again.
+fn encode_compressed_archive(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+    mime_type: &str,
+) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    tar_decoder(ctx, &input_bytes, mime_type)?.read_to_end(&mut decoded)?;
+    drop(input_bytes);
+    let filtered = pack_archive(ctx, decoded, filter_list)?;
+    let mut encoder = tar_encoder(ctx, mime_type, Vec::new())?;
+    encoder.write_all(&filtered)?;
+    encoder.inner()
+}
+
 fn retain_inner_vec(input: &mut Vec<PathBuf>, filter: &str) -> Result<Vec<PathBuf>> {
     let mut inner_list = Vec::new();
     input.retain_mut(|e| {
-        if e.starts_with(filter) {
+        if Rule::new(e).path.starts_with(filter) {
             inner_list.push(std::mem::take(e));
             return false;
         }
@@ -136,215 +576,1683 @@ fn retain_inner_vec(input: &mut Vec<PathBuf>, filter: &str) -> Result<Vec<PathBu
     Ok(inner_list)
 }
 
-fn zip_handle_inner_archive(
-    progress_bar: &ProgressBar,
+/// The rules of `filter_list` for the nested archive at `path`: the ones
+/// leading into it, taken out of it unless every occurrence is matched, and
+/// copies of the ones with a depth modifier reaching it.
+fn inner_filter_list(
+    ctx: &Context,
+    filter_list: &mut Vec<PathBuf>,
+    path: &str,
+) -> Result<Vec<PathBuf>> {
+    let mut inner: Vec<PathBuf> = filter_list
+        .iter()
+        .filter(|e| Rule::new(e).reaches_every_nested(path, ctx.depth))
+        .cloned()
+        .collect();
+    match ctx.match_mode {
+        MatchMode::First => inner.extend(retain_inner_vec(filter_list, path)?),
+        MatchMode::All => inner.extend(
+            filter_list
+                .iter()
+                .filter(|e| Rule::new(e).path.starts_with(path))
+                .cloned(),
+        ),
+    }
+    Ok(inner)
+}
+
+/// Content of a kept entry, spooled to a temporary file when buffering a
+/// rewritten nested archive would exceed `--max-memory`.
+enum EntryData<'a> {
+    Memory {
+        data: Cow<'a, [u8]>,
+        /// Held until the entry is written.
+        _reservation: Option<Reservation>,
+    },
+    Spooled(Spool),
+}
+
+impl EntryData<'_> {
+    fn len(&self) -> u64 {
+        match self {
+            EntryData::Memory { data, .. } => data.len() as u64,
+            EntryData::Spooled(spool) => spool.len,
+        }
+    }
+
+    fn reader(&mut self) -> Box<dyn Read + '_> {
+        match self {
+            EntryData::Memory { data, .. } => Box::new(&**data),
+            EntryData::Spooled(spool) => Box::new(BufReader::new(&mut spool.file)),
+        }
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        match self {
+            EntryData::Memory { data, .. } => Ok(data.into_owned()),
+            EntryData::Spooled(mut spool) => {
+                let mut data = Vec::with_capacity(spool.len.try_into()?);
+                spool.file.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// Rewrites the nested archive at `path` within the memory limit, if any.
+fn pack_inner_archive(
+    ctx: &Context,
     entry_bytes: Vec<u8>,
     filter_list: &mut Vec<PathBuf>,
-    compression_level: u32,
     path: &str,
-    options: SimpleFileOptions,
-    zip_writer: &mut zip::ZipWriter<std::io::Cursor<&mut Vec<u8>>>,
-) -> Result<()> {
-    let result = pack_archive(progress_bar, entry_bytes, filter_list, compression_level)?;
-    zip_writer.start_file(path, options)?;
-    zip_writer.write_all(&result)?;
+) -> Result<EntryData<'static>> {
+    // entry filters may decide by location, the cache is not accounted for
+    // in the memory budget, untrusted limits count every entry read and
+    // every occurrence of a listed entry is recorded
+    let reusable =
+        ctx.filters.is_empty() && ctx.untrusted.is_none() && ctx.match_mode == MatchMode::First;
+    let key = (reusable && ctx.memory_limit.is_none())
+        .then(|| InnerCache::key(&entry_bytes, path, ctx.depth, filter_list));
+    if let Some(cached) = key.as_ref().and_then(|key| ctx.inner_cache.get(key)) {
+        ctx.progress
+            .message(format!("reusing rewritten archive: {}", path));
+        return Ok(EntryData::Memory {
+            data: Cow::Owned(cached.to_vec()),
+            _reservation: None,
+        });
+    }
+    let nested = ctx.nested(path);
+    let Some(limit) = &ctx.memory_limit else {
+        let result = pack_archive(&nested, entry_bytes, filter_list)?;
+        if let Some(key) = key {
+            ctx.inner_cache.insert(key, &result);
+        }
+        return Ok(EntryData::Memory {
+            data: Cow::Owned(result),
+            _reservation: None,
+        });
+    };
+    let size = entry_bytes.len() as u64;
+    let _input = limit.reserve(path, size)?;
+    // the rewritten archive is about as large as the input
+    if let Some(reservation) = limit.try_reserve(size) {
+        let result = pack_archive(&nested, entry_bytes, filter_list)?;
+        return Ok(EntryData::Memory {
+            data: Cow::Owned(result),
+            _reservation: Some(reservation),
+        });
+    }
+    ctx.progress.message(format!("spooling to disk: {}", path));
+    let Some(workspace) = &ctx.workspace else {
+        let sink = WriteSink(BufWriter::new(tempfile::tempfile()?));
+        let sink = pack_archive_into(&nested, entry_bytes, filter_list, sink)?;
+        let file = sink.0.into_inner().map_err(|err| err.into_error())?;
+        return Ok(EntryData::Spooled(Spool::from_file(file)?));
+    };
+    // spooled copies are addressed by content, the disk has room for them
+    let key = reusable.then(|| InnerCache::key(&entry_bytes, path, ctx.depth, filter_list));
+    if let Some(spool) = key
+        .as_ref()
+        .map(|key| workspace.get(key))
+        .transpose()?
+        .flatten()
+    {
+        ctx.progress
+            .message(format!("reusing spooled archive: {}", path));
+        return Ok(EntryData::Spooled(spool));
+    }
+    let sink = WriteSink(BufWriter::new(workspace.spool()?));
+    let sink = pack_archive_into(&nested, entry_bytes, filter_list, sink)?;
+    let file = sink.0.into_inner().map_err(|err| err.into_error())?;
+    let spool = match key {
+        Some(key) => workspace.store(path, &key, file)?,
+        None => workspace.finish(path, file)?,
+    };
+    Ok(EntryData::Spooled(spool))
+}
 
-    Ok(())
+/// An entry waiting for the ones before it to be written.
+enum PendingEntry<'scope> {
+    Directory,
+    File(Vec<u8>),
+    Symlink(String),
+    Nested(ScopedJoinHandle<'scope, Result<EntryData<'static>>>),
+}
+
+/// Writes the entries of a zip in order while its nested archives are
+/// rewritten on up to `ctx.jobs` worker threads. Entries following a nested
+/// archive still being rewritten are held until it is done.
+struct ZipQueue<'scope, 'env, W: Write + Seek> {
+    scope: &'scope Scope<'scope, 'env>,
+    zip: zip::ZipWriter<W>,
+    pending: VecDeque<(String, FileOptions<'env, ()>, PendingEntry<'scope>)>,
+    running: usize,
+}
+
+impl<'scope, 'env, W: Write + Seek> ZipQueue<'scope, 'env, W> {
+    fn new(scope: &'scope Scope<'scope, 'env>, zip: zip::ZipWriter<W>) -> Self {
+        ZipQueue {
+            scope,
+            zip,
+            pending: VecDeque::new(),
+            running: 0,
+        }
+    }
+
+    fn directory(&mut self, name: String, options: FileOptions<'env, ()>) -> Result<()> {
+        if self.pending.is_empty() {
+            self.zip.add_directory(name, options)?;
+        } else {
+            self.pending
+                .push_back((name, options, PendingEntry::Directory));
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, name: String, options: FileOptions<'env, ()>, data: &[u8]) -> Result<()> {
+        if self.pending.is_empty() {
+            self.zip.start_file(name, options)?;
+            self.zip.write_all(data)?;
+        } else {
+            self.pending
+                .push_back((name, options, PendingEntry::File(data.to_vec())));
+        }
+        Ok(())
+    }
+
+    fn symlink(
+        &mut self,
+        name: String,
+        options: FileOptions<'env, ()>,
+        target: String,
+    ) -> Result<()> {
+        if self.pending.is_empty() {
+            self.zip.add_symlink(name, target, options)?;
+        } else {
+            self.pending
+                .push_back((name, options, PendingEntry::Symlink(target)));
+        }
+        Ok(())
+    }
+
+    /// Rewrites the nested archive at `path` with its own slice of the
+    /// filter list, on a worker thread if `ctx.jobs` allows.
+    fn nested(
+        &mut self,
+        ctx: &'env Context,
+        entry_bytes: Vec<u8>,
+        mut filter_list: Vec<PathBuf>,
+        path: String,
+        options: FileOptions<'env, ()>,
+    ) -> Result<()> {
+        let name = ctx.entry_name(&path).into_owned();
+        if ctx.jobs <= 1 {
+            let mut result = pack_inner_archive(ctx, entry_bytes, &mut filter_list, &path)?;
+            return self.write_nested(name, options, &mut result);
+        }
+        while self.running >= ctx.jobs {
+            self.write_front(true)?;
+        }
+        let handle = self.scope.spawn(move || {
+            // archives nested deeper are rewritten on the worker itself
+            let ctx = Context {
+                jobs: 1,
+                ..ctx.clone()
+            };
+            pack_inner_archive(&ctx, entry_bytes, &mut filter_list, &path)
+        });
+        self.pending
+            .push_back((name, options, PendingEntry::Nested(handle)));
+        self.running += 1;
+        // keep the entries written so far out of memory
+        while self.write_front(false)? {}
+        Ok(())
+    }
+
+    /// Copies `entry` without recompressing it, once the entries before it
+    /// are written.
+    fn raw_copy(&mut self, entry: zip::read::ZipFile) -> Result<()> {
+        while self.write_front(true)? {}
+        self.zip.raw_copy_file(entry)?;
+        Ok(())
+    }
+
+    fn write_nested(
+        &mut self,
+        name: String,
+        options: FileOptions<'env, ()>,
+        result: &mut EntryData,
+    ) -> Result<()> {
+        self.zip.start_file(name, options)?;
+        std::io::copy(&mut result.reader(), &mut self.zip)?;
+        Ok(())
+    }
+
+    /// Writes the first pending entry, returns `false` if there is none or
+    /// it is a nested archive still being rewritten and `wait` is not set.
+    fn write_front(&mut self, wait: bool) -> Result<bool> {
+        let Some((_, _, entry)) = self.pending.front() else {
+            return Ok(false);
+        };
+        if let PendingEntry::Nested(handle) = entry {
+            if !wait && !handle.is_finished() {
+                return Ok(false);
+            }
+        }
+        let (name, options, entry) = self.pending.pop_front().unwrap();
+        match entry {
+            PendingEntry::Directory => self.zip.add_directory(name, options)?,
+            PendingEntry::File(data) => {
+                self.zip.start_file(name, options)?;
+                self.zip.write_all(&data)?;
+            }
+            PendingEntry::Symlink(target) => self.zip.add_symlink(name, target, options)?,
+            PendingEntry::Nested(handle) => {
+                self.running -= 1;
+                let mut result = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                self.write_nested(name, options, &mut result)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Waits for every nested archive and returns the writer with all of
+    /// the entries written to it.
+    fn finish(mut self) -> Result<zip::ZipWriter<W>> {
+        while self.write_front(true)? {}
+        Ok(self.zip)
+    }
 }
 
-fn process_zip_entry(
+fn process_zip_entry<'env, W: Write + Seek>(
+    ctx: &'env Context,
     entry: &mut zip::read::ZipFile,
-    zip_writer: &mut zip::ZipWriter<std::io::Cursor<&mut Vec<u8>>>,
+    zip_writer: &mut ZipQueue<'_, 'env, W>,
     filter_list: &mut Vec<PathBuf>,
-    progress_bar: &ProgressBar,
-    compression_level: u32,
+    buffer: &mut Vec<u8>,
 ) -> Result<()> {
     let path = entry.name().to_owned();
-    let options = SimpleFileOptions::default()
-        .compression_level(Some(compression_level.into()))
+    // stored entries reject any compression level
+    let compression_level = match entry.compression() {
+        zip::CompressionMethod::Stored => None,
+        _ => Some(ctx.compression_level.into()),
+    };
+    let mut options = SimpleFileOptions::default()
+        .compression_level(compression_level)
         .compression_method(entry.compression())
         .unix_permissions(entry.unix_mode().unwrap_or(0o777));
+    if let (Some(alignment), zip::CompressionMethod::Stored) = (ctx.zip_align, entry.compression())
+    {
+        options = options.with_alignment(alignment);
+    }
+    if let Some(password) = ctx.entry_password(entry.encrypted()) {
+        options = options.with_aes_encryption(AesMode::Aes256, password);
+    }
+    // rewritten entries would get the current time otherwise
+    if let Some(mut modified) = entry.last_modified() {
+        let mtime = zip_datetime_to_unix(modified);
+        let clamped = ctx.clamped_mtime(mtime);
+        if clamped < mtime {
+            modified = unix_to_zip_datetime(clamped);
+        }
+        options = options.last_modified_time(modified);
+    }
 
-    progress_bar.set_message(format!("processing: {}", path));
+    ctx.progress.message(format!("processing: {}", path));
 
-    if let Some(found_file) = filter_list.iter().position(|e| e.ends_with(&path)) {
-        filter_list.swap_remove(found_file);
-    } else {
+    ctx.check_entry(&path, entry.size())?;
+    if let Some(untrusted) = &ctx.untrusted {
+        if !matches!(
+            entry.compression(),
+            zip::CompressionMethod::Stored | zip::CompressionMethod::Deflated
+        ) {
+            Err(anyhow!(
+                "Untrusted Archive: The entry '{}' uses the unsupported compression method {}.",
+                path,
+                entry.compression()
+            ))?;
+        }
+        untrusted.check_ratio(&path, entry.size(), entry.compressed_size())?;
+    }
+
+    if !ctx.take_match(filter_list, &path) {
+        let mtime = entry.last_modified().map(zip_datetime_to_unix);
         if entry.is_dir() {
-            zip_writer.add_directory(&path, options)?;
+            if ctx.decide(&path, EntryKind::Directory, mtime, None)? == Decision::Remove {
+                return Ok(());
+            }
+            zip_writer.directory(ctx.entry_name(&path).into_owned(), options)?;
+        }
+        // symlinks are not files to the zip crate
+        if entry.is_symlink() {
+            let size = entry.size();
+            if !read_entry(ctx, &path, entry, size, buffer)? {
+                return Ok(());
+            }
+            // the target is stored as the content
+            let mut target = String::from_utf8_lossy(buffer).into_owned();
+            if !ctx.keep_symlink(&path, Path::new(&target))? {
+                return Ok(());
+            }
+            match ctx.decide(&path, EntryKind::Symlink, mtime, Some(target.as_bytes()))? {
+                Decision::Keep => {}
+                Decision::Remove => return Ok(()),
+                Decision::Replace(data) => target = String::from_utf8_lossy(&data).into_owned(),
+            }
+            zip_writer.symlink(ctx.entry_name(&path).into_owned(), options, target)?;
         }
         if entry.is_file() {
-            let mut entry_bytes = vec![Default::default(); entry.size().try_into()?];
-            entry.read_exact(&mut entry_bytes)?;
-
-            if infer::is_archive(&entry_bytes) {
-                progress_bar.set_message(format!("inner archive: {}", &path));
-                let mut inner_filter_list = retain_inner_vec(filter_list, &path)?;
-                if !inner_filter_list.is_empty() {
-                    zip_handle_inner_archive(
-                        progress_bar,
-                        entry_bytes,
-                        &mut inner_filter_list,
-                        compression_level,
-                        path.as_str(),
+            let size = entry.size();
+            if !read_entry(ctx, &path, entry, size, buffer)? {
+                return Ok(());
+            }
+            let mut entry_bytes = Cow::Borrowed(buffer.as_slice());
+
+            match ctx.decide(&path, EntryKind::File, mtime, Some(&entry_bytes))? {
+                Decision::Keep => {}
+                Decision::Remove => return Ok(()),
+                Decision::Replace(data) => entry_bytes = Cow::Owned(data),
+            }
+
+            if holds_archive(&entry_bytes) {
+                ctx.progress.message(format!("inner archive: {}", &path));
+                let inner_filter_list = inner_filter_list(ctx, filter_list, &path)?;
+                if !inner_filter_list.is_empty() || !ctx.filters.is_empty() {
+                    zip_writer.nested(
+                        ctx,
+                        entry_bytes.into_owned(),
+                        inner_filter_list,
+                        path,
                         options,
-                        zip_writer,
                     )?;
                     return Ok(());
                 }
             }
-            zip_writer.start_file(path, options)?;
-            zip_writer.write_all(&entry_bytes)?;
+            zip_writer.file(ctx.entry_name(&path).into_owned(), options, &entry_bytes)?;
         }
     }
     Ok(())
 }
 
+/// Whether kept zip entries can be copied as they are: no entry filters are
+/// registered, entries are not renamed, aligned, encrypted or have their
+/// modification time clamped, and are not checked as untrusted or for the
+/// symlinks policy.
+fn zip_entries_unchanged(ctx: &Context) -> bool {
+    ctx.filters.is_empty()
+        && !ctx.symlinks.checks_entries()
+        && !ctx.windows_safe
+        && ctx.zip_align.is_none()
+        && !(ctx.encrypt_output && ctx.depth == 0)
+        && ctx.clamp_mtime.is_none()
+        && ctx.untrusted.is_none()
+}
+
+/// Writes the zip `reader` without the removed entries by rebuilding only
+/// the central directory of `directory`, leaving every kept entry untouched.
+/// The provenance record stored as an entry is left to the caller. Returns
+/// `false`, with nothing written, when entries need rewriting, i.e. nested
+/// archives are filtered, entry filters are registered, entries are
+/// renamed, aligned, encrypted, sorted or have their modification time
+/// clamped, or every entry has to be checked as untrusted.
+fn encode_zip_selective<R: Read + Seek, W: Write>(
+    ctx: &Context,
+    reader: &mut R,
+    directory: Result<ZipDirectory>,
+    filter_list: &mut Vec<PathBuf>,
+    writer: &mut W,
+) -> Result<bool> {
+    if !zip_entries_unchanged(ctx) || ctx.entry_order != EntryOrder::Unsorted {
+        return Ok(false);
+    }
+    let directory = match directory {
+        Ok(directory) => directory,
+        Err(e) => {
+            ctx.progress
+                .message(format!("rewriting every entry, {:#}", e));
+            return Ok(false);
+        }
+    };
+    let records = &directory.records;
+
+    // occurrences are recorded once the entries are known to be removed here
+    let probe = Context {
+        occurrences: Arc::default(),
+        ..ctx.clone()
+    };
+    let mut remaining = filter_list.clone();
+    let keep: Vec<bool> = records
+        .iter()
+        .map(|record| !probe.take_match(&mut remaining, &record.name))
+        .collect();
+    // rules with a depth modifier may reach into any nested archive
+    let nested = records.iter().zip(&keep).any(|(record, &keep)| {
+        keep && remaining.iter().any(|e| {
+            let rule = Rule::new(e);
+            rule.path.starts_with(&record.name)
+                || rule.reaches_every_nested(&record.name, ctx.depth)
+        })
+    });
+    if nested {
+        return Ok(false);
+    }
+
+    ctx.occurrences
+        .lock()
+        .unwrap()
+        .append(&mut probe.occurrences.lock().unwrap());
+    for (record, _) in records.iter().zip(&keep).filter(|(_, &keep)| !keep) {
+        ctx.check()?;
+        ctx.progress.message(format!("removing: {}", record.name));
+    }
+    let comment = match provenance_record(ctx, ProvenanceMode::Comment, &remaining)? {
+        Some(record) => Some(record),
+        None => ctx.archive_comment().map(str::to_owned),
+    };
+    directory.write_kept(reader, &keep, comment.as_deref().map(str::as_bytes), writer)?;
+    *filter_list = remaining;
+    Ok(true)
+}
+
+/// Opens an entry for reading, decrypting it with the password if it is
+/// encrypted.
+fn open_zip_entry<'a, R: Read + Seek>(
+    ctx: &Context,
+    zip_entries: &'a mut zip::ZipArchive<R>,
+    index: usize,
+) -> Result<zip::read::ZipFile<'a>> {
+    let entry = match &ctx.password {
+        Some(password) => zip_entries.by_index_decrypt(index, password.as_bytes()),
+        None => zip_entries.by_index(index),
+    };
+    match entry {
+        Ok(entry) => Ok(entry),
+        Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => Err(anyhow!(
+            "Encrypted Archive: The archive has encrypted entries, pass its password with --password-file, --key or EXPURGATOR_PASSWORD."
+        ))?,
+        Err(ZipError::InvalidPassword) => Err(anyhow!(
+            "Encrypted Archive: The password is not correct."
+        ))?,
+        Err(err) => Err(err)?,
+    }
+}
+
+/// Applies the `--encrypted` policy to an entry, returns whether it was
+/// handled: with `skip-encrypted` encrypted entries are removed if listed,
+/// else left to be copied as they are, with `fail` they are refused.
+fn skip_encrypted_entry<R: Read + Seek>(
+    ctx: &Context,
+    zip_entries: &mut zip::ZipArchive<R>,
+    index: usize,
+    filter_list: &mut Vec<PathBuf>,
+    skipped: &mut Vec<usize>,
+) -> Result<bool> {
+    let entry = zip_entries.by_index_raw(index)?;
+    if !entry.encrypted() {
+        return Ok(false);
+    }
+    let path = entry.name().to_owned();
+    match ctx.encrypted {
+        Encrypted::Decrypt => return Ok(false),
+        Encrypted::Fail => Err(anyhow!(
+            "Encrypted Archive: The entry '{}' is encrypted, use --encrypted decrypt or skip-encrypted.",
+            ctx.parent.join(&path).display()
+        ))?,
+        Encrypted::SkipEncrypted => {}
+    }
+    if !ctx.take_match(filter_list, &path) {
+        ctx.skipped_encrypted
+            .lock()
+            .unwrap()
+            .push(ctx.parent.join(&path));
+        skipped.push(index);
+    }
+    Ok(true)
+}
+
+/// Applies the `--duplicates` policy to the entries of a zip sharing a
+/// path. Returns `None` if there are none, else the archive left with a
+/// single copy of each path and, with `keep-all`, the indices of the other
+/// copies, which are appended as they are once it is written.
+fn dedup_zip_entries(
+    ctx: &Context,
+    input_bytes: &[u8],
+    filter_list: &[PathBuf],
+) -> Result<Option<(Vec<u8>, Vec<usize>)>> {
+    let Ok(directory) = ZipDirectory::parse(input_bytes) else {
+        return Ok(None);
+    };
+    let records = &directory.records;
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, record) in records.iter().enumerate() {
+        by_name.entry(&record.name).or_default().push(index);
+    }
+    if by_name.len() == records.len() {
+        return Ok(None);
+    }
+
+    let mut keep = vec![true; records.len()];
+    let mut copies = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let indices = &by_name[record.name.as_str()];
+        if indices.len() == 1 {
+            continue;
+        }
+        let kept = match ctx.duplicates {
+            Duplicates::Error => Err(anyhow!(
+                "Duplicate Entries: The entry '{}' appears {} times, use --duplicates keep-first, keep-last or keep-all.",
+                ctx.parent.join(&record.name).display(),
+                indices.len()
+            ))?,
+            Duplicates::KeepFirst => indices[0],
+            Duplicates::KeepLast | Duplicates::KeepAll => indices[indices.len() - 1],
+        };
+        if index == kept {
+            continue;
+        }
+        keep[index] = false;
+        if ctx.duplicates == Duplicates::KeepAll {
+            // listed paths lose every copy
+            if filter_list
+                .iter()
+                .any(|e| Rule::new(e).matches(&record.name, ctx.depth))
+            {
+                continue;
+            }
+            copies.push(index);
+        }
+        ctx.progress.message(format!("duplicate: {}", record.name));
+        ctx.duplicate_entries
+            .lock()
+            .unwrap()
+            .push(ctx.parent.join(&record.name));
+    }
+    let unique = directory.remove_entries(input_bytes, &keep)?;
+    Ok(Some((unique, copies)))
+}
+
 fn encode_zip(
-    progress_bar: &ProgressBar,
+    ctx: &Context,
     input_bytes: Vec<u8>,
     filter_list: &mut Vec<PathBuf>,
-    compression_level: u32,
 ) -> Result<Vec<u8>> {
-    let decoder = std::io::Cursor::new(input_bytes);
+    let Some((unique, copies)) = dedup_zip_entries(ctx, &input_bytes, filter_list)? else {
+        return encode_unique_zip(ctx, input_bytes, filter_list);
+    };
+    let result = encode_unique_zip(ctx, unique, filter_list)?;
+    if copies.is_empty() {
+        return Ok(result);
+    }
+    append_raw_entries(&result, &input_bytes, &copies)
+}
 
-    let mut zip_entries = zip::ZipArchive::new(decoder).unwrap();
-    let mut result: Vec<u8> = Vec::new();
-    {
-        let encoder = std::io::Cursor::new(&mut result);
-        let mut zip = zip::ZipWriter::new(encoder);
+/// Replaces each symlink of a zip by a copy of the regular file it points
+/// to, under the name of the link. The other entries are kept untouched and
+/// the copies appended after them. Links to anything else, e.g. directories,
+/// missing entries or paths outside the archive, are dropped.
+fn dereference_zip_links(ctx: &Context, zip_bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut zip_entries = zip::ZipArchive::new(std::io::Cursor::new(zip_bytes.as_slice()))
+        .map_err(|e| anyhow!("Invalid Zip Archive: {}.", e))?;
+    let mut links = HashMap::new();
+    let mut files = HashMap::new();
+    let mut link_indices = Vec::new();
+    for i in 0..zip_entries.len() {
+        let entry = zip_entries.by_index_raw(i)?;
+        let name = entry.name().to_owned();
+        let (is_symlink, is_dir) = (entry.is_symlink(), entry.is_dir());
+        // encrypted entries copied without the password stay as they are
+        if entry.encrypted() && ctx.password.is_none() {
+            continue;
+        }
+        drop(entry);
+        let Some(path) = normalize(Path::new(&name)) else {
+            continue;
+        };
+        if is_symlink {
+            let mut target = String::new();
+            open_zip_entry(ctx, &mut zip_entries, i)?.read_to_string(&mut target)?;
+            links.insert(path, PathBuf::from(target));
+            link_indices.push((i, name));
+        } else if !is_dir {
+            files.insert(path, i);
+        }
+    }
+    if link_indices.is_empty() {
+        drop(zip_entries);
+        return Ok(zip_bytes);
+    }
+    let directory = ZipDirectory::parse(&zip_bytes)?;
+    let mut keep = vec![true; directory.records.len()];
+    for (i, _) in &link_indices {
+        keep[*i] = false;
+    }
+    let mut result = directory.remove_entries(&zip_bytes, &keep)?;
 
-        for i in 0..zip_entries.len() {
-            let mut entry = zip_entries.by_index(i)?;
-            process_zip_entry(
-                &mut entry,
-                &mut zip,
-                filter_list,
-                progress_bar,
-                compression_level,
-            )?;
+    let mut zip = zip::ZipWriter::new_append(std::io::Cursor::new(&mut result))?;
+    let mut buffer = Vec::new();
+    for (i, name) in link_indices {
+        let target = normalize(Path::new(&name))
+            .and_then(|link| follow(&links, &link))
+            .and_then(|target| files.get(&target).copied());
+        let Some(target) = target else {
+            ctx.drop_symlink(&name);
+            continue;
+        };
+        ctx.progress
+            .message(format!("dereferencing symlink: {}", name));
+        let link = zip_entries.by_index_raw(i)?;
+        let (encrypted, modified) = (link.encrypted(), link.last_modified());
+        drop(link);
+        let mut target = open_zip_entry(ctx, &mut zip_entries, target)?;
+        // stored entries reject any compression level
+        let compression_level = match target.compression() {
+            zip::CompressionMethod::Stored => None,
+            _ => Some(ctx.compression_level.into()),
+        };
+        let mut options = SimpleFileOptions::default()
+            .compression_level(compression_level)
+            .compression_method(target.compression())
+            .unix_permissions(target.unix_mode().unwrap_or(0o644) & 0o7777);
+        if let Some(modified) = modified {
+            options = options.last_modified_time(modified);
         }
-        zip.finish()?;
+        if let Some(password) = ctx.entry_password(encrypted) {
+            options = options.with_aes_encryption(AesMode::Aes256, password);
+        }
+        buffer.clear();
+        target.read_to_end(&mut buffer)?;
+        zip.start_file(name, options)?;
+        zip.write_all(&buffer)?;
     }
+    zip.finish()?;
     Ok(result)
 }
 
-fn tar_handle_inner_archive(
-    progress_bar: &ProgressBar,
+/// Writes a zip whose entry paths are unique.
+fn encode_unique_zip(
+    ctx: &Context,
     input_bytes: Vec<u8>,
     filter_list: &mut Vec<PathBuf>,
-    path: &str,
-    compression_level: u32,
-) -> Result<(Vec<u8>, bool)> {
-    if infer::is_archive(&input_bytes) {
-        progress_bar.set_message(format!("inner archive: {}", path));
-        let mut inner_filter_list = retain_inner_vec(filter_list, path)?;
-        if !inner_filter_list.is_empty() {
-            let inner_entry_bytes = pack_archive(
-                progress_bar,
-                input_bytes,
-                &mut inner_filter_list,
-                compression_level,
-            )?;
-            return Ok((inner_entry_bytes, true));
-        }
-    }
-    Ok((input_bytes, false))
-}
-
-fn encode_tar(
-    progress_bar: &ProgressBar,
-    input_bytes: Vec<u8>,
-    filter_list: &mut Vec<PathBuf>,
-    compression_level: u32,
-    mime_type: &str,
 ) -> Result<Vec<u8>> {
-    let decoder = create_tar_decoder(&input_bytes, mime_type)?;
-    let mut tar_archive = tar::Archive::new(decoder);
-
-    let tar_encoder = TarEncoder::new(mime_type, compression_level).unwrap();
-    let encoder = tar_encoder.encoder();
-    let mut tar_writer = tar::Builder::new(encoder);
-    for entry in tar_archive.entries()? {
-        match entry {
-            Ok(mut entry) => {
-                let path = (*entry.path()?).to_owned();
-                let path = path.to_string_lossy().to_string();
-                progress_bar.set_message(format!("processing: {}", path));
+    let mut result = Vec::new();
+    let mut reader = std::io::Cursor::new(input_bytes.as_slice());
+    let directory = ZipDirectory::parse(&input_bytes);
+    if encode_zip_selective(ctx, &mut reader, directory, filter_list, &mut result)? {
+        if let Some(record) = provenance_record(ctx, ProvenanceMode::Entry, filter_list)? {
+            let mut zip = zip::ZipWriter::new_append(std::io::Cursor::new(result))?;
+            zip.start_file(PROVENANCE_ENTRY, SimpleFileOptions::default())?;
+            zip.write_all(record.as_bytes())?;
+            result = zip.finish()?.into_inner();
+        }
+        return Ok(result);
+    }
+    let skipped = encode_zip_entries(ctx, reader, filter_list, std::io::Cursor::new(&mut result))?;
+    if !skipped.is_empty() {
+        result = append_raw_entries(&result, &input_bytes, &skipped)?;
+    }
+    Ok(result)
+}
 
-                if let Some(found_file) = filter_list.iter().position(|e| e.ends_with(&path)) {
-                    filter_list.swap_remove(found_file);
-                } else {
-                    match entry.header().entry_type() {
-                        tar::EntryType::Directory => {
-                            progress_bar.set_message(format!("adding directory: {}", path));
-                            tar_writer.append_dir(&path, ".")?;
-                        }
-                        tar::EntryType::Regular
-                        | tar::EntryType::GNUSparse
-                        | tar::EntryType::Continuous
-                        | tar::EntryType::Fifo
-                        | tar::EntryType::Char
-                        | tar::EntryType::Block
-                        | tar::EntryType::GNULongName
-                        | tar::EntryType::XGlobalHeader
-                        | tar::EntryType::XHeader => {
-                            progress_bar.set_message(format!("adding file: {}", path));
+/// Rewrites the entries of the zip `reader`, whose entry paths are unique,
+/// into `writer` one at a time. Returns the indices of the encrypted entries
+/// left to be copied as they are, which the zip writer cannot do.
+fn encode_zip_entries<R: Read + Seek, W: Write + Seek>(
+    ctx: &Context,
+    reader: R,
+    filter_list: &mut Vec<PathBuf>,
+    writer: W,
+) -> Result<Vec<usize>> {
+    let mut zip_entries =
+        zip::ZipArchive::new(reader).map_err(|e| anyhow!("Invalid Zip Archive: {}.", e))?;
+    let mut skipped = Vec::new();
+    std::thread::scope(|scope| -> Result<()> {
+        let mut zip = zip::ZipWriter::new(writer);
+        match ctx.archive_comment() {
+            Some(comment) => zip.set_comment(comment),
+            None => zip.set_raw_comment(zip_entries.comment().into()),
+        }
 
-                            // read exactly the size of the current entry
-                            let mut inner_entry =
-                                vec![Default::default(); entry.header().size()?.try_into()?];
-                            entry.read_exact(&mut inner_entry)?;
-
-                            let (inner_entry, is_archive) = tar_handle_inner_archive(
-                                progress_bar,
-                                inner_entry,
-                                filter_list,
-                                &path,
-                                compression_level,
+        let entries = (0..zip_entries.len())
+            .map(|i| {
+                let entry = zip_entries.by_index_raw(i)?;
+                Ok((entry.name().to_owned(), entry.size()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut queue = ZipQueue::new(scope, zip);
+        let mut buffer = Vec::new();
+        for i in ctx.entry_order.sort(&entries) {
+            ctx.check()?;
+            if skip_encrypted_entry(ctx, &mut zip_entries, i, filter_list, &mut skipped)? {
+                continue;
+            }
+            // every listed path was found, the rest is copied as it is, but for
+            // links: raw copies lose the file type
+            if filter_list.is_empty()
+                && zip_entries_unchanged(ctx)
+                && !zip_entries.by_index_raw(i)?.is_symlink()
+            {
+                queue.raw_copy(zip_entries.by_index_raw(i)?)?;
+                continue;
+            }
+            let mut entry = open_zip_entry(ctx, &mut zip_entries, i)?;
+            let path = entry.name().to_owned();
+            ctx.progress.entry_started(&path);
+            process_zip_entry(ctx, &mut entry, &mut queue, filter_list, &mut buffer)?;
+            ctx.progress.entry_finished(&path);
+        }
+        let mut zip = queue.finish()?;
+        if let Some(record) = provenance_record(ctx, ProvenanceMode::Entry, filter_list)? {
+            let mut options = SimpleFileOptions::default();
+            if let Some(password) = ctx.entry_password(false) {
+                options = options.with_aes_encryption(AesMode::Aes256, password);
+            }
+            zip.start_file(PROVENANCE_ENTRY, options)?;
+            zip.write_all(record.as_bytes())?;
+        }
+        if let Some(record) = provenance_record(ctx, ProvenanceMode::Comment, filter_list)? {
+            zip.set_comment(record);
+        }
+        zip.finish()?;
+        Ok(())
+    })?;
+    Ok(skipped)
+}
+
+/// Rewrites the zip read from `reader` into `sink` without holding the
+/// archive in memory: the kept entries are copied from the reader, or
+/// rewritten one at a time into the sink, through a temporary file if the
+/// sink cannot seek. Returns `false`, with nothing written, when the archive
+/// has to be rewritten in memory, i.e. it has entries sharing a path or
+/// encrypted entries to copy as they are, its symlinks are dereferenced or
+/// the provenance record is stored as an entry.
+fn encode_zip_stream<R: Read + Seek, S: Sink>(
+    ctx: &Context,
+    reader: &mut R,
+    filter_list: &mut Vec<PathBuf>,
+    sink: &mut S,
+) -> Result<bool> {
+    let provenance_entry = ctx
+        .provenance()
+        .is_some_and(|provenance| provenance.mode == ProvenanceMode::Entry);
+    if ctx.symlinks == Symlinks::Dereference || provenance_entry {
+        return Ok(false);
+    }
+    let directory = ZipDirectory::read(reader);
+    if let Ok(directory) = &directory {
+        let mut names = HashSet::new();
+        if !directory
+            .records
+            .iter()
+            .all(|record| names.insert(&record.name))
+        {
+            return Ok(false);
+        }
+    }
+    if ctx.encrypted == Encrypted::SkipEncrypted {
+        let mut zip_entries = zip::ZipArchive::new(&mut *reader)
+            .map_err(|e| anyhow!("Invalid Zip Archive: {}.", e))?;
+        for i in 0..zip_entries.len() {
+            if zip_entries.by_index_raw(i)?.encrypted() {
+                return Ok(false);
+            }
+        }
+    }
+    if encode_zip_selective(ctx, reader, directory, filter_list, sink)? {
+        return Ok(true);
+    }
+    if let Some(writer) = sink.seekable() {
+        encode_zip_entries(ctx, reader, filter_list, writer)?;
+        return Ok(true);
+    }
+    // the zip writer seeks back to complete each entry
+    let file = match &ctx.workspace {
+        Some(workspace) => workspace.spool()?,
+        None => tempfile::NamedTempFile::new()?,
+    };
+    let mut writer = BufWriter::with_capacity(ctx.buffer_size, file);
+    encode_zip_entries(ctx, &mut *reader, filter_list, &mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    let mut spool = match &ctx.workspace {
+        Some(workspace) => workspace.finish("the zip output", file)?,
+        None => Spool::from_file(file.into_file())?,
+    };
+    std::io::copy(&mut spool.file, sink)?;
+    Ok(true)
+}
+
+/// Provenance record to store as `mode` in the top level archive, given the
+/// filter list entries left unmatched.
+fn provenance_record(
+    ctx: &Context,
+    mode: ProvenanceMode,
+    filter_list: &[PathBuf],
+) -> Result<Option<String>> {
+    match ctx.provenance() {
+        Some(provenance) if provenance.mode == mode => Ok(Some(
+            provenance.record(ctx.unmatched(filter_list.to_vec()).len())?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+fn tar_handle_inner_archive<'a>(
+    ctx: &Context,
+    input_bytes: Cow<'a, [u8]>,
+    filter_list: &mut Vec<PathBuf>,
+    path: &str,
+) -> Result<EntryData<'a>> {
+    if holds_archive(&input_bytes) {
+        ctx.progress.message(format!("inner archive: {}", path));
+        let mut inner_filter_list = inner_filter_list(ctx, filter_list, path)?;
+        if !inner_filter_list.is_empty() || !ctx.filters.is_empty() {
+            return pack_inner_archive(ctx, input_bytes.into_owned(), &mut inner_filter_list, path);
+        }
+    }
+    Ok(EntryData::Memory {
+        data: input_bytes,
+        _reservation: None,
+    })
+}
+
+/// Output format of rewritten tar archives, by default the headers of the
+/// input are kept as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarFormat {
+    Gnu,
+    Ustar,
+    Pax,
+}
+
+pub const TAR_FORMATS: [&str; 3] = ["pax", "gnu", "ustar"];
+
+impl TarFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "gnu" => Ok(TarFormat::Gnu),
+            "ustar" => Ok(TarFormat::Ustar),
+            "pax" => Ok(TarFormat::Pax),
+            _ => Err(anyhow!(
+                "Invalid Tar Format: '{}' is not one of: {}.",
+                name,
+                TAR_FORMATS.join(", ")
+            )),
+        }
+    }
+}
+
+/// Copies the metadata of `header` into an empty header of another format.
+fn convert_tar_header(header: &tar::Header, mut converted: tar::Header) -> Result<tar::Header> {
+    let entry_type = header.entry_type();
+    converted.set_entry_type(entry_type);
+    // some writers leave numeric fields empty
+    converted.set_mode(header.mode().unwrap_or(0o644));
+    converted.set_uid(header.uid().unwrap_or(0));
+    converted.set_gid(header.gid().unwrap_or(0));
+    converted.set_mtime(header.mtime().unwrap_or(0));
+    converted.set_size(header.size()?);
+    if let Ok(Some(username)) = header.username() {
+        converted.set_username(username)?;
+    }
+    if let Ok(Some(groupname)) = header.groupname() {
+        converted.set_groupname(groupname)?;
+    }
+    if matches!(entry_type, tar::EntryType::Char | tar::EntryType::Block) {
+        converted.set_device_major(header.device_major()?.unwrap_or(0))?;
+        converted.set_device_minor(header.device_minor()?.unwrap_or(0))?;
+    }
+    Ok(converted)
+}
+
+/// Longest prefix of `path` fitting the name field of a ustar header.
+fn truncate_tar_name(path: &str) -> &str {
+    let mut end = path.len().min(100);
+    while !path.is_char_boundary(end) {
+        end -= 1;
+    }
+    &path[..end]
+}
+
+fn pax_records(records: &[(String, Vec<u8>)]) -> impl Iterator<Item = (&str, &[u8])> {
+    records.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+}
+
+/// Appends an entry, or a link when `link_name` is given, in `format`. PAX
+/// `records` (extended attributes) are dropped for gnu and ustar, which
+/// cannot carry them, and ustar fails on paths too long for its header.
+fn append_tar_entry<W: Write, R: Read>(
+    builder: &mut tar::Builder<W>,
+    format: Option<TarFormat>,
+    header: &tar::Header,
+    path: &str,
+    link_name: Option<&Path>,
+    mut records: Vec<(String, Vec<u8>)>,
+    data: R,
+) -> Result<()> {
+    let mut header = match format {
+        None => header.clone(),
+        Some(TarFormat::Gnu) => convert_tar_header(header, tar::Header::new_gnu())?,
+        Some(TarFormat::Ustar | TarFormat::Pax) => {
+            convert_tar_header(header, tar::Header::new_ustar())?
+        }
+    };
+    if matches!(format, Some(TarFormat::Gnu | TarFormat::Ustar)) {
+        records.clear();
+    }
+    if matches!(format, None | Some(TarFormat::Gnu)) {
+        // long paths use the GNU extension
+        builder.append_pax_extensions(pax_records(&records))?;
+        match link_name {
+            Some(link_name) => builder.append_link(&mut header, path, link_name)?,
+            None => builder.append_data(&mut header, path, data)?,
+        }
+        return Ok(());
+    }
+
+    let ustar = format == Some(TarFormat::Ustar);
+    if header.set_path(path).is_err() {
+        if ustar {
+            Err(anyhow!(
+                "Tar Format Error: The path '{}' is too long for a ustar header, use --tar-format pax or gnu.",
+                path
+            ))?;
+        }
+        records.push(("path".to_string(), path.as_bytes().to_vec()));
+        header.set_path(truncate_tar_name(path))?;
+    }
+    if let Some(link_name) = link_name {
+        if header.set_link_name(link_name).is_err() {
+            let link_name = link_name.to_string_lossy();
+            if ustar {
+                Err(anyhow!(
+                    "Tar Format Error: The link target '{}' is too long for a ustar header, use --tar-format pax or gnu.",
+                    link_name
+                ))?;
+            }
+            records.push(("linkpath".to_string(), link_name.as_bytes().to_vec()));
+            header.set_link_name(truncate_tar_name(&link_name))?;
+        }
+    }
+    builder.append_pax_extensions(pax_records(&records))?;
+    header.set_cksum();
+    builder.append(&header, data)?;
+    Ok(())
+}
+
+/// PAX records carrying extended attributes (SELinux labels, capabilities)
+/// and POSIX ACLs of a tar entry.
+fn tar_xattrs<R: Read>(entry: &mut tar::Entry<R>) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut xattrs = Vec::new();
+    if let Some(extensions) = entry.pax_extensions()? {
+        for extension in extensions {
+            let extension = extension?;
+            let key = extension.key()?;
+            if key.starts_with("SCHILY.xattr.")
+                || key.starts_with("LIBARCHIVE.xattr.")
+                || key.starts_with("SCHILY.acl.")
+            {
+                xattrs.push((key.to_string(), extension.value_bytes().to_vec()));
+            }
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Removes the names of filtered entries from a GNU dumpdir listing, a
+/// sequence of NUL terminated records prefixed with a control character.
+/// Without this the scrubbed names remain readable in the listing.
+fn scrub_dumpdir(dir: &str, listing: &[u8], filter_list: &[PathBuf], depth: usize) -> Vec<u8> {
+    let dir = Path::new(dir);
+    let mut result = Vec::with_capacity(listing.len());
+    for record in listing
+        .split(|&b| b == 0)
+        .filter(|record| !record.is_empty())
+    {
+        let removed = match record.split_first() {
+            Some((b'Y' | b'N' | b'D', name)) => {
+                let path = dir.join(&*String::from_utf8_lossy(name));
+                filter_list
+                    .iter()
+                    .any(|e| Rule::new(e).matches(&path, depth))
+            }
+            _ => false,
+        };
+        if !removed {
+            result.extend_from_slice(record);
+            result.push(0);
+        }
+    }
+    result.push(0);
+    result
+}
+
+/// Decompresses, filters and recompresses on separate threads connected by
+/// bounded channels, so that decoding and encoding overlap. Entries are
+/// filtered on the calling thread, in order, while the data of the next ones
+/// is decompressed up to `ctx.prefetch` bytes ahead.
+fn encode_tar<W: Write>(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+    mime_type: &str,
+    writer: W,
+) -> Result<W> {
+    encode_tar_stream(
+        ctx,
+        input_bytes.as_slice(),
+        &input_bytes,
+        filter_list,
+        mime_type,
+        writer,
+    )
+}
+
+/// Same as [`encode_tar`], for a tar stream read from `reader` starting with
+/// `head`.
+fn encode_tar_stream<R: BufRead + Send, W: Write>(
+    ctx: &Context,
+    reader: R,
+    head: &[u8],
+    filter_list: &mut Vec<PathBuf>,
+    mime_type: &str,
+    writer: W,
+) -> Result<W> {
+    let depth = depth(ctx.prefetch);
+    std::thread::scope(|scope| {
+        let decoder = read_ahead(
+            scope,
+            depth,
+            tar_stream_decoder(ctx, reader, head, mime_type)?,
+        );
+        let encoder = encode_behind(scope, depth, writer, move |blocks| {
+            tar_encoder(ctx, mime_type, blocks)
+        });
+        filter_tar(ctx, decoder, filter_list, encoder)?.finish()
+    })
+}
+
+/// Copies the entries of the tar read from `decoder` that are kept into
+/// `writer`.
+fn filter_tar<R: Read, W: Write>(
+    ctx: &Context,
+    decoder: R,
+    filter_list: &mut Vec<PathBuf>,
+    writer: W,
+) -> Result<W> {
+    let progress = ctx.progress;
+    let mut tar_archive = tar::Archive::new(Counted(decoder, 0));
+    let mut tar_writer = tar::Builder::new(writer);
+    let mut buffer = Vec::new();
+    // offset of the header following the last entry read, unknown after an
+    // invalid one
+    let mut next_header = Some(0);
+    let mut entries = tar_archive.entries()?;
+    let copy_rest = loop {
+        ctx.check()?;
+        if next_header.is_some() && tar_rest_unchanged(ctx, filter_list) {
+            break true;
+        }
+        let Some(entry) = entries.next() else {
+            break false;
+        };
+        match entry {
+            Ok(mut entry) => {
+                let end = entry.raw_file_position() + entry.header().entry_size()?;
+                next_header = Some(end.next_multiple_of(512));
+                let path = (*entry.path()?).to_owned();
+                let path = path.to_string_lossy().to_string();
+                progress.message(format!("processing: {}", path));
+                progress.entry_started(&path);
+
+                ctx.check_entry(&path, entry.header().size()?)?;
+                let kind = tar_entry_kind(entry.header().entry_type());
+                let mtime = entry.header().mtime().ok();
+                if ctx.take_match(filter_list, &path) {
+                    // listed, not written
+                } else if kind != EntryKind::File
+                    && ctx.decide(&path, kind, mtime, None)? == Decision::Remove
+                {
+                    progress.message(format!("removed by filter: {}", path));
+                } else {
+                    let xattrs = if ctx.strip_xattrs {
+                        Vec::new()
+                    } else {
+                        tar_xattrs(&mut entry)?
+                    };
+                    let mut header = entry.header().clone();
+                    ctx.owners.apply(&mut header)?;
+                    if let Some(mtime) = mtime {
+                        header.set_mtime(ctx.clamped_mtime(mtime));
+                    }
+                    match entry.header().entry_type() {
+                        tar::EntryType::Directory => {
+                            progress.message(format!("adding directory: {}", path));
+                            header.set_size(0);
+                            append_tar_entry(
+                                &mut tar_writer,
+                                ctx.tar_format,
+                                &header,
+                                &ctx.entry_name(&path),
+                                None,
+                                xattrs,
+                                std::io::empty(),
                             )?;
-                            let mut header = entry.header().clone();
-                            if is_archive {
-                                header.set_size(inner_entry.len().try_into()?);
+                        }
+                        tar::EntryType::Regular
+                        | tar::EntryType::GNUSparse
+                        | tar::EntryType::Continuous
+                        | tar::EntryType::GNULongName
+                        | tar::EntryType::XGlobalHeader
+                        | tar::EntryType::XHeader => {
+                            progress.message(format!("adding file: {}", path));
+
+                            // read exactly the size of the current entry
+                            let size = entry.header().size()?;
+                            if !read_entry(ctx, &path, &mut entry, size, &mut buffer)? {
+                                progress.entry_finished(&path);
+                                continue;
+                            }
+                            let mut inner_entry = Cow::Borrowed(buffer.as_slice());
+
+                            if kind == EntryKind::File {
+                                match ctx.decide(&path, kind, mtime, Some(&inner_entry))? {
+                                    Decision::Keep => {}
+                                    Decision::Remove => {
+                                        progress.message(format!("removed by filter: {}", path));
+                                        progress.entry_finished(&path);
+                                        continue;
+                                    }
+                                    Decision::Replace(data) => inner_entry = Cow::Owned(data),
+                                }
                             }
-                            tar_writer.append_data(&mut header, &path, &*inner_entry)?;
+                            let mut inner_entry =
+                                tar_handle_inner_archive(ctx, inner_entry, filter_list, &path)?;
+                            header.set_size(inner_entry.len());
+                            append_tar_entry(
+                                &mut tar_writer,
+                                ctx.tar_format,
+                                &header,
+                                &ctx.entry_name(&path),
+                                None,
+                                xattrs,
+                                inner_entry.reader(),
+                            )?;
                         }
                         tar::EntryType::Symlink
                         | tar::EntryType::Link
                         | tar::EntryType::GNULongLink => {
-                            progress_bar.set_message(format!("adding link: {}", path));
-                            tar_writer.append_link(
-                                entry.header().clone().borrow_mut(),
-                                &path,
-                                entry
-                                    .header()
-                                    .link_name()?
-                                    .unwrap_or(entry.header().path()?),
+                            let link_name = entry.link_name()?.unwrap_or(entry.path()?);
+                            if entry.header().entry_type() == tar::EntryType::Symlink
+                                && !ctx.keep_symlink(&path, &link_name)?
+                            {
+                                progress.entry_finished(&path);
+                                continue;
+                            }
+                            progress.message(format!("adding link: {}", path));
+                            append_tar_entry(
+                                &mut tar_writer,
+                                ctx.tar_format,
+                                &header,
+                                &ctx.entry_name(&path),
+                                Some(&link_name),
+                                xattrs,
+                                std::io::empty(),
                             )?;
                         }
-                        _ => progress_bar.set_message(format!("unhandled type: {}", path)),
+                        tar::EntryType::Fifo | tar::EntryType::Char | tar::EntryType::Block => {
+                            // the header carries the device numbers, there is no data
+                            progress.message(format!("adding special file: {}", path));
+                            header.set_size(0);
+                            append_tar_entry(
+                                &mut tar_writer,
+                                ctx.tar_format,
+                                &header,
+                                &ctx.entry_name(&path),
+                                None,
+                                xattrs,
+                                std::io::empty(),
+                            )?;
+                        }
+                        entry_type if entry_type.as_byte() == GNU_DUMPDIR => {
+                            progress.message(format!("adding directory: {}", path));
+                            let size = entry.header().size()?;
+                            if !read_entry(ctx, &path, &mut entry, size, &mut buffer)? {
+                                progress.entry_finished(&path);
+                                continue;
+                            }
+                            let listing = if ctx.drop_incremental_metadata {
+                                header.set_entry_type(tar::EntryType::Directory);
+                                Vec::new()
+                            } else {
+                                scrub_dumpdir(&path, &buffer, filter_list, ctx.depth)
+                            };
+                            header.set_size(listing.len().try_into()?);
+                            append_tar_entry(
+                                &mut tar_writer,
+                                ctx.tar_format,
+                                &header,
+                                &ctx.entry_name(&path),
+                                None,
+                                xattrs,
+                                listing.as_slice(),
+                            )?;
+                        }
+                        _ if ctx.untrusted.is_some() => Err(anyhow!(
+                            "Untrusted Archive: The entry '{}' has an unsupported type.",
+                            path
+                        ))?,
+                        _ => progress.message(format!("unhandled type: {}", path)),
                     }
                 }
+                progress.entry_finished(&path);
+            }
+            Err(err) if ctx.untrusted.is_some() => {
+                Err(anyhow!("Untrusted Archive: Invalid tar entry ({}).", err))?;
             }
-            Err(_) => {
-                prompt_error(progress_bar)?;
+            Err(err) => {
+                next_header = None;
+                ctx.entry_error(anyhow!(
+                    "Invalid Entry: Failed to read a tar entry ({}).",
+                    err
+                ))?;
             }
         }
+    };
+    if let (true, Some(next_header)) = (copy_rest, next_header) {
+        progress.message("copying the remaining entries".to_string());
+        let Counted(mut decoder, read) = tar_archive.into_inner();
+        // the rest of the last entry read, and its padding
+        std::io::copy(
+            &mut (&mut decoder).take(next_header - read),
+            &mut std::io::sink(),
+        )?;
+        copy_tar_blocks(ctx, decoder, tar_writer.get_mut())?;
+    }
+    if let Some(record) = provenance_record(ctx, ProvenanceMode::Entry, filter_list)? {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(record.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            ctx.provenance()
+                .map_or(0, |provenance| provenance.timestamp),
+        );
+        append_tar_entry(
+            &mut tar_writer,
+            ctx.tar_format,
+            &header,
+            PROVENANCE_ENTRY,
+            None,
+            Vec::new(),
+            record.as_bytes(),
+        )?;
+    }
+    Ok(tar_writer.into_inner()?)
+}
+
+/// Whether the entries of a tar left to read can be copied as they are:
+/// every listed path was found, nothing else rewrites entries and no
+/// timeout has to be checked on each of them.
+fn tar_rest_unchanged(ctx: &Context, filter_list: &[PathBuf]) -> bool {
+    filter_list.is_empty()
+        && ctx.deadline.is_none()
+        && ctx.entry_timeout.is_none()
+        && ctx.filters.is_empty()
+        && ctx.owners.is_empty()
+        && ctx.tar_format.is_none()
+        && ctx.clamp_mtime.is_none()
+        && !ctx.strip_xattrs
+        && !ctx.windows_safe
+        && !ctx.drop_incremental_metadata
+        && !ctx.symlinks.checks_entries()
+        && ctx.untrusted.is_none()
+}
+
+/// Reader keeping count of the bytes read from it.
+struct Counted<R>(R, u64);
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.0.read(buf)?;
+        self.1 += read as u64;
+        Ok(read)
+    }
+}
+
+/// Copies the headers and data blocks of a tar from `reader` to `writer`
+/// without parsing the entries, up to the end of archive marker, which is
+/// left for the [`tar::Builder`] to write.
+fn copy_tar_blocks<R: Read, W: Write>(ctx: &Context, mut reader: R, writer: &mut W) -> Result<()> {
+    let mut block = [0; 512];
+    loop {
+        ctx.check()?;
+        match reader.read_exact(&mut block) {
+            Ok(()) => {}
+            // archives without an end marker
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => Err(err)?,
+        }
+        if block.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        let header = tar::Header::from_byte_slice(&block);
+        let size = header.entry_size()?.next_multiple_of(512);
+        // old GNU sparse headers are followed by blocks not counted in the size
+        let mut extended = header.entry_type().is_gnu_sparse()
+            && header.as_gnu().is_some_and(|gnu| gnu.is_extended());
+        writer.write_all(&block)?;
+        while extended {
+            reader.read_exact(&mut block)?;
+            writer.write_all(&block)?;
+            extended = block[504] == 1;
+        }
+        let copied = std::io::copy(&mut (&mut reader).take(size), writer)?;
+        if copied != size {
+            Err(anyhow!(
+                "Truncated Entry: The archive ended before the entry was complete."
+            ))?;
+        }
+        ctx.progress.bytes_processed(size);
+    }
+}
+
+/// Whether the output tar has to be assembled in memory, to sort its entries
+/// or dereference its symlinks.
+fn tar_in_memory(ctx: &Context) -> bool {
+    ctx.entry_order != EntryOrder::Unsorted || ctx.symlinks == Symlinks::Dereference
+}
+
+/// Same as [`encode_tar`], but with the symlinks dereferenced and the
+/// entries sorted by `ctx.entry_order`, as requested. The uncompressed
+/// output is rewritten in memory, then compressed into `writer`.
+fn encode_tar_in_memory<W: Write>(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+    mime_type: &str,
+    writer: W,
+) -> Result<W> {
+    let mut decoded = Vec::new();
+    tar_decoder(ctx, &input_bytes, mime_type)?.read_to_end(&mut decoded)?;
+    drop(input_bytes);
+    let mut tar = encode_tar(ctx, decoded, filter_list, "application/x-tar", Vec::new())?;
+    if ctx.symlinks == Symlinks::Dereference {
+        tar = dereference_tar_links(ctx, &tar)?;
+    }
+    if ctx.entry_order != EntryOrder::Unsorted {
+        tar = sort_tar_entries(&tar, ctx.entry_order)?;
     }
-    let encoder = tar_writer.into_inner()?;
-    let result = encoder.inner().unwrap();
+
+    let mut encoder = tar_encoder(ctx, mime_type, writer)?;
+    encoder.write_all(&tar)?;
+    encoder.inner()
+}
+
+/// Replaces each symlink of an uncompressed tar by a copy of the regular
+/// file it points to, under the name of the link. Links to anything else,
+/// e.g. directories, missing entries or paths outside the archive, are
+/// dropped.
+fn dereference_tar_links(ctx: &Context, tar: &[u8]) -> Result<Vec<u8>> {
+    let mut links = HashMap::new();
+    let mut files = HashMap::new();
+    // extents of the entries, each with the extension headers preceding it
+    let mut extents = Vec::new();
+    let mut start = 0;
+    let mut archive = tar::Archive::new(tar);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let size = entry.header().entry_size()?;
+        let data = usize::try_from(entry.raw_file_position())?;
+        let end = usize::try_from((entry.raw_file_position() + size).next_multiple_of(512))?;
+        let path = entry.path()?.into_owned();
+        match (entry.header().entry_type(), normalize(&path)) {
+            (tar::EntryType::Symlink, normalized) => {
+                if let Some(normalized) = normalized {
+                    let target = entry.link_name()?.unwrap_or_default().into_owned();
+                    links.insert(normalized, target);
+                }
+                extents.push((start..end, Some(path)));
+            }
+            (tar::EntryType::Regular | tar::EntryType::Continuous, Some(normalized)) => {
+                let data = tar
+                    .get(data..data + usize::try_from(size)?)
+                    .ok_or_else(|| anyhow!("Invalid Tar Archive: An entry is truncated."))?;
+                files.insert(normalized, (entry.header().clone(), data));
+                extents.push((start..end, None));
+            }
+            _ => extents.push((start..end, None)),
+        }
+        start = end;
+    }
+    if links.is_empty() {
+        return Ok(tar.to_vec());
+    }
+
+    let mut tar_writer = tar::Builder::new(Vec::with_capacity(tar.len()));
+    for (extent, link) in extents {
+        let Some(link) = link else {
+            tar_writer.get_mut().extend_from_slice(&tar[extent]);
+            continue;
+        };
+        let path = link.to_string_lossy();
+        let Some((header, data)) = normalize(&link)
+            .and_then(|link| follow(&links, &link))
+            .and_then(|target| files.get(&target))
+        else {
+            ctx.drop_symlink(&path);
+            continue;
+        };
+        ctx.progress
+            .message(format!("dereferencing symlink: {}", path));
+        append_tar_entry(
+            &mut tar_writer,
+            ctx.tar_format,
+            header,
+            &path,
+            None,
+            Vec::new(),
+            *data,
+        )?;
+    }
+    Ok(tar_writer.into_inner()?)
+}
+
+/// Reorders the entries of an uncompressed tar, each moved with the
+/// extension headers preceding it. Global headers stay first and hard links
+/// last, so that their targets are extracted before them.
+fn sort_tar_entries(tar: &[u8], order: EntryOrder) -> Result<Vec<u8>> {
+    let mut global = Vec::new();
+    let mut sorted = Vec::new();
+    let mut links = Vec::new();
+    let mut keys = Vec::new();
+    let mut start = 0;
+    let mut archive = tar::Archive::new(tar);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let size = entry.header().entry_size()?;
+        let end = usize::try_from((entry.raw_file_position() + size).next_multiple_of(512))?;
+        let extent = tar
+            .get(start..end)
+            .ok_or_else(|| anyhow!("Invalid Tar Archive: An entry is truncated."))?;
+        match entry.header().entry_type() {
+            tar::EntryType::XGlobalHeader => global.push(extent),
+            tar::EntryType::Link => links.push(extent),
+            _ => {
+                let path = entry.path()?.to_string_lossy().into_owned();
+                keys.push((path, entry.size()));
+                sorted.push(extent);
+            }
+        }
+        start = end;
+    }
+
+    let mut result = Vec::with_capacity(tar.len());
+    for extent in global {
+        result.extend_from_slice(extent);
+    }
+    for index in order.sort(&keys) {
+        result.extend_from_slice(sorted[index]);
+    }
+    for extent in links {
+        result.extend_from_slice(extent);
+    }
+    // the end of archive marker, two empty blocks
+    result.resize(result.len() + 1024, 0);
     Ok(result)
 }
 
+fn encode_cpio(
+    ctx: &Context,
+    input_bytes: Vec<u8>,
+    filter_list: &mut Vec<PathBuf>,
+) -> Result<Vec<u8>> {
+    let progress = ctx.progress;
+    let (entries, consumed) = read_cpio(&input_bytes)?;
+    let mut kept = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        ctx.check()?;
+        let path = entry.path().to_owned();
+        progress.message(format!("processing: {}", path));
+        progress.entry_started(&path);
+        ctx.check_entry(&path, entry.data.len() as u64)?;
+
+        if ctx.take_match(filter_list, &path) {
+            progress.entry_finished(&path);
+            continue;
+        }
+        let kind = cpio_entry_kind(&entry);
+        let mtime = Some(entry.mtime().into());
+        if entry.is_file() {
+            progress.bytes_processed(entry.data.len() as u64);
+            let mut data = std::mem::take(&mut entry.data);
+            match ctx.decide(&path, kind, mtime, Some(&data))? {
+                Decision::Keep => {}
+                Decision::Remove => {
+                    progress.entry_finished(&path);
+                    continue;
+                }
+                Decision::Replace(replacement) => data = replacement,
+            }
+            let data = tar_handle_inner_archive(ctx, Cow::Owned(data), filter_list, &path)?;
+            entry.set_data(data.into_bytes()?)?;
+        } else if ctx.decide(&path, kind, mtime, None)? == Decision::Remove {
+            progress.entry_finished(&path);
+            continue;
+        } else if entry.is_symlink() {
+            let target = String::from_utf8_lossy(&entry.data).into_owned();
+            if !ctx.keep_symlink(&path, Path::new(&target))? {
+                progress.entry_finished(&path);
+                continue;
+            }
+        }
+        let name = ctx.entry_name(&path);
+        if name != path {
+            entry.set_path(&name)?;
+        }
+        let mtime = ctx.clamped_mtime(entry.mtime().into());
+        entry.set_mtime(mtime.try_into()?);
+        progress.entry_finished(&path);
+        kept.push(entry);
+    }
+    if ctx.symlinks == Symlinks::Dereference {
+        kept = dereference_cpio_links(ctx, kept)?;
+    }
+    if ctx.entry_order != EntryOrder::Unsorted {
+        let entries: Vec<_> = kept
+            .iter()
+            .map(|entry| (entry.path().to_owned(), entry.data.len() as u64))
+            .collect();
+        let mut unsorted: Vec<_> = kept.into_iter().map(Some).collect();
+        kept = ctx
+            .entry_order
+            .sort(&entries)
+            .into_iter()
+            .filter_map(|index| unsorted[index].take())
+            .collect();
+    }
+
+    if let Some(record) = provenance_record(ctx, ProvenanceMode::Entry, filter_list)? {
+        let mtime = ctx
+            .provenance()
+            .map_or(0, |provenance| provenance.timestamp);
+        kept.push(CpioEntry::file(
+            PROVENANCE_ENTRY,
+            record.into_bytes(),
+            mtime.try_into()?,
+        )?);
+    }
+    let mut result = write_cpio(&kept)?;
+    // anything after the first archive is carried over untouched
+    result.extend_from_slice(&input_bytes[consumed..]);
+    Ok(result)
+}
+
+/// Same as [`dereference_tar_links`], for the entries of a cpio archive.
+fn dereference_cpio_links(ctx: &Context, entries: Vec<CpioEntry>) -> Result<Vec<CpioEntry>> {
+    let links: HashMap<PathBuf, PathBuf> = entries
+        .iter()
+        .filter(|entry| entry.is_symlink())
+        .filter_map(|entry| {
+            let target = String::from_utf8_lossy(&entry.data).into_owned();
+            Some((normalize(Path::new(entry.path()))?, PathBuf::from(target)))
+        })
+        .collect();
+    if links.is_empty() {
+        return Ok(entries);
+    }
+    // only the targets are copied
+    let targets: HashSet<PathBuf> = links
+        .keys()
+        .filter_map(|link| follow(&links, link))
+        .collect();
+    let files: HashMap<PathBuf, (u32, Vec<u8>)> = entries
+        .iter()
+        .filter(|entry| entry.is_file())
+        .filter_map(|entry| {
+            let path = normalize(Path::new(entry.path()))?;
+            targets
+                .contains(&path)
+                .then(|| (path, (entry.mode(), entry.data.clone())))
+        })
+        .collect();
+
+    let mut dereferenced = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        if entry.is_symlink() {
+            let path = entry.path().to_owned();
+            let Some((mode, data)) = normalize(Path::new(&path))
+                .and_then(|link| follow(&links, &link))
+                .and_then(|target| files.get(&target))
+            else {
+                ctx.drop_symlink(&path);
+                continue;
+            };
+            ctx.progress
+                .message(format!("dereferencing symlink: {}", path));
+            entry.set_mode(*mode);
+            entry.set_data(data.clone())?;
+        }
+        dereferenced.push(entry);
+    }
+    Ok(dereferenced)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::OnError;
+    use crate::progress::NoProgress;
+    use crate::report::Occurrence;
+    use crate::untrusted::Untrusted;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn test_create_tar_encoder() {
         assert!(TarEncoder::new("application/gzip", 6).is_ok());
         assert!(TarEncoder::new("application/x-bzip2", 6).is_ok());
         assert!(TarEncoder::new("application/x-xz", 6).is_ok());
+        assert!(TarEncoder::new("application/zstd", 6).is_ok());
         assert!(TarEncoder::new("application/x-tar", 6).is_ok());
         assert!(TarEncoder::new("invalid", 6).is_err());
     }
@@ -355,17 +2263,19 @@ mod tests {
         assert!(create_tar_decoder(&input, "application/gzip").is_ok());
         assert!(create_tar_decoder(&input, "application/x-bzip2").is_ok());
         assert!(create_tar_decoder(&input, "application/x-xz").is_ok());
+        assert!(create_tar_decoder(&input, "application/zstd").is_ok());
         assert!(create_tar_decoder(&input, "application/x-tar").is_ok());
         assert!(create_tar_decoder(&input, "invalid").is_err());
     }
 
     #[test]
     fn test_retain_inner_vec() {
-        let mut input = Vec::new();
-        input.push(PathBuf::from("1/2"));
-        input.push(PathBuf::from("2/2"));
-        input.push(PathBuf::from("3/3"));
-        input.push(PathBuf::from("3/4"));
+        let mut input = vec![
+            PathBuf::from("1/2"),
+            PathBuf::from("2/2"),
+            PathBuf::from("3/3"),
+            PathBuf::from("3/4"),
+        ];
 
         let output = retain_inner_vec(&mut input, "3").unwrap();
 
@@ -376,4 +2286,1197 @@ mod tests {
         assert_eq!(output[0].to_str().unwrap(), "3/3");
         assert_eq!(output[1].to_str().unwrap(), "3/4");
     }
+
+    #[test]
+    fn test_pack_initramfs() {
+        use crate::cpio::CpioEntry;
+
+        let entry = |name: &str, data: &[u8]| {
+            let mut fields = [0u32; 13];
+            fields[1] = 0o100644;
+            fields[4] = 1;
+            CpioEntry {
+                magic: *b"070701",
+                fields,
+                name: name.to_string(),
+                data: data.to_vec(),
+            }
+        };
+        let microcode =
+            write_cpio(&[entry("kernel/x86/microcode/GenuineIntel.bin", b"ucode")]).unwrap();
+        let main_image =
+            write_cpio(&[entry("./init", b"#!/bin/sh"), entry("./secret", b"key")]).unwrap();
+        let mut encoder = TarEncoder::new("application/gzip", 6).unwrap().encoder();
+        encoder.write_all(&main_image).unwrap();
+
+        let mut input = microcode.clone();
+        input.extend(encoder.inner().unwrap());
+
+        let mut filter_list = vec![PathBuf::from("secret")];
+        let output =
+            pack_initramfs(&Context::new(&NoProgress, 6), input, &mut filter_list).unwrap();
+        assert!(filter_list.is_empty());
+        assert!(output.starts_with(&microcode));
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(&output[microcode.len()..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        let (entries, _) = read_cpio(&decoded).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path(), "init");
+    }
+
+    #[test]
+    fn test_pack_archive_cancelled() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let ctx = Context::new(&NoProgress, 6);
+        ctx.cancel.cancel();
+        let err = pack_archive(&ctx, input, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().starts_with("Cancelled"));
+    }
+
+    #[test]
+    fn test_pack_archive_entry_filter() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let ctx = Context::new(&NoProgress, 6).with_filter(
+            |entry: &crate::EntryInfo, _: Option<&[u8]>| -> Result<Decision> {
+                Ok(
+                    match entry.path.file_name().and_then(|name| name.to_str()) {
+                        Some("1.txt") => Decision::Remove,
+                        Some("2.txt") => Decision::Replace(b"replaced".to_vec()),
+                        _ => Decision::Keep,
+                    },
+                )
+            },
+        );
+        let output = pack_archive(&ctx, input, &mut Vec::new()).unwrap();
+
+        let entries = crate::ArchiveReader::new(output)
+            .entries()
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(entries.iter().all(|e| !e.path.ends_with("1.txt")));
+        let replaced: Vec<_> = entries
+            .iter()
+            .filter(|e| e.path.ends_with("2.txt"))
+            .collect();
+        assert!(!replaced.is_empty());
+        assert!(replaced.iter().all(|e| e.size == 8));
+    }
+
+    #[test]
+    fn test_pack_reader_into() {
+        let filter_list = || {
+            vec![
+                PathBuf::from("tar-test/file_2.txt"),
+                PathBuf::from("tar-test/nested/nested.zip/zip-test/zippedfile.dat"),
+            ]
+        };
+        // sorted entries are read into memory first
+        let sorted = Context::new(&NoProgress, 6).with_entry_order(EntryOrder::Path);
+        for (archive, ctx) in [
+            (
+                "tests/archives/tar-test.tar.gz",
+                Context::new(&NoProgress, 6),
+            ),
+            (
+                "tests/archives/tar-test.tar.xz",
+                Context::new(&NoProgress, 6),
+            ),
+            ("tests/archives/tar-test.tar.gz", sorted),
+        ] {
+            let input = std::fs::read(archive).unwrap();
+            let mut expected_list = filter_list();
+            let expected = pack_archive(&ctx, input, &mut expected_list).unwrap();
+
+            let file = std::fs::File::open(archive).unwrap();
+            let mut list = filter_list();
+            let output = pack_reader_into(&ctx, file, &mut list, Vec::new()).unwrap();
+            assert_eq!(output, expected, "{}", archive);
+            assert_eq!(list, expected_list);
+        }
+    }
+
+    #[test]
+    fn test_pack_reader_into_zip() {
+        let zip_of = |entries: &[(&str, &[u8])]| {
+            let mut bytes = Vec::new();
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut bytes));
+            for (name, data) in entries {
+                zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(data).unwrap();
+            }
+            zip.finish().unwrap();
+            bytes
+        };
+        let nested = zip_of(&[("inner.txt", b"inner"), ("keep.txt", b"keep")]);
+        let input = zip_of(&[("a.txt", b"a"), ("b.txt", b"b"), ("nested.zip", &nested)]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.zip");
+        std::fs::write(&path, &input).unwrap();
+
+        let ctx = Context::new(&NoProgress, 6);
+        // removed by the directory alone, then by rewriting the entries
+        for filter in ["b.txt", "nested.zip/inner.txt"] {
+            let mut expected_list = vec![PathBuf::from(filter)];
+            let expected = pack_archive(&ctx, input.clone(), &mut expected_list).unwrap();
+
+            // spooled to a temporary file
+            let mut list = vec![PathBuf::from(filter)];
+            let file = std::fs::File::open(&path).unwrap();
+            let output = pack_reader_into(&ctx, file, &mut list, Vec::new()).unwrap();
+            assert_eq!(output, expected, "{}", filter);
+            assert_eq!(list, expected_list);
+
+            // written into the output file directly
+            let output_path = dir.path().join("output.zip");
+            let mut list = vec![PathBuf::from(filter)];
+            let file = std::fs::File::open(&path).unwrap();
+            let sink = crate::sink::FileSink::create(&output_path).unwrap();
+            pack_reader_into(&ctx, file, &mut list, sink).unwrap();
+            assert_eq!(std::fs::read(&output_path).unwrap(), expected, "{}", filter);
+            std::fs::remove_file(&output_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encode_zip_selective() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            for name in ["a.txt", "b.txt", "c.txt"] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(name.repeat(100).as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let ctx = Context::new(&NoProgress, 6);
+        // the directory is read from the end of the file alone
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&input).unwrap();
+        let mut selective = |filter_list: &mut Vec<PathBuf>| {
+            let directory = ZipDirectory::read(&mut file);
+            let mut output = Vec::new();
+            encode_zip_selective(&ctx, &mut file, directory, filter_list, &mut output)
+                .unwrap()
+                .then_some(output)
+        };
+        let mut filter_list = vec![PathBuf::from("b.txt")];
+        let output = selective(&mut filter_list).unwrap();
+        assert!(filter_list.is_empty());
+        // the kept entries are copied verbatim
+        assert!(output.len() < input.len());
+        assert_eq!(&output[..40], &input[..40]);
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut content = String::new();
+        archive
+            .by_name("c.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "c.txt".repeat(100));
+
+        let mut filter_list = vec![PathBuf::from("a.txt/inner.txt")];
+        assert!(selective(&mut filter_list).is_none());
+    }
+
+    #[test]
+    fn test_pack_archive_untrusted() {
+        let zip_with = |name: &str, data: &[u8]| {
+            let mut input = Vec::new();
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+            zip.finish().unwrap();
+            input
+        };
+        let ctx = Context::new(&NoProgress, 6).with_untrusted(Untrusted::default());
+
+        let input = zip_with("a.txt", b"content");
+        assert!(pack_archive(&ctx, input, &mut Vec::new()).is_ok());
+
+        let input = zip_with("../escape.txt", b"content");
+        let err = pack_archive(&ctx, input, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().starts_with("Untrusted Archive"));
+
+        let input = zip_with("bomb.txt", &vec![0u8; 1 << 20]);
+        let err = pack_archive(&ctx, input, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("compression ratio"));
+
+        // a local header signature followed by garbage
+        let mut input = zip_with("a.txt", b"content");
+        input.truncate(input.len() - 8);
+        let err = pack_archive(&ctx, input, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid Zip Archive"));
+    }
+
+    #[test]
+    fn test_pack_archive_memory_limit() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let filter_list = || {
+            vec![
+                PathBuf::from("tar-test/nested/nested.zip/zip-test/zippedfile.dat"),
+                PathBuf::from("tar-test/nested/nestedtar.tar.xz/nestedtar/2.txt"),
+            ]
+        };
+        let ctx = Context::new(&NoProgress, 6);
+        let expected = pack_archive(&ctx, input.clone(), &mut filter_list()).unwrap();
+
+        // the 916 byte nested.zip fits, its rewritten copy is spooled
+        let ctx = Context::new(&NoProgress, 6).with_max_memory(Some(1000));
+        let mut list = filter_list();
+        let output = pack_archive(&ctx, input.clone(), &mut list).unwrap();
+        assert!(list.is_empty());
+        assert_eq!(output, expected);
+
+        // spooled to the workspace, the copy is stored by content
+        let root = assert_fs::TempDir::new().unwrap();
+        let spooling = ctx.clone().with_temp_dir(root.path(), None).unwrap();
+        let output = pack_archive(&spooling, input.clone(), &mut filter_list()).unwrap();
+        assert_eq!(output, expected);
+        // the lock and the stored copy, in the workspace of the run
+        let workspace = std::fs::read_dir(root.path()).unwrap().next().unwrap();
+        assert_eq!(
+            std::fs::read_dir(workspace.unwrap().path())
+                .unwrap()
+                .count(),
+            2
+        );
+        let capped = ctx.with_temp_dir(root.path(), Some(100)).unwrap();
+        let err = pack_archive(&capped, input.clone(), &mut filter_list()).unwrap_err();
+        assert!(err.to_string().starts_with("Workspace Full"));
+
+        let ctx = Context::new(&NoProgress, 6).with_max_memory(Some(500));
+        let err = pack_archive(&ctx, input, &mut filter_list()).unwrap_err();
+        assert!(err.to_string().starts_with("Memory Limit"));
+    }
+
+    #[test]
+    fn test_pack_archive_timeout() {
+        let mut tar = tar::Builder::new(Vec::new());
+        for name in ["a.txt", "b.txt"] {
+            let data = name.repeat(10);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            tar.append_data(&mut header, name, data.as_bytes()).unwrap();
+        }
+        let input = tar.into_inner().unwrap();
+        let ctx = Context::new(&NoProgress, 6)
+            .with_buffer_size(1)
+            .with_entry_timeout(Some(Duration::ZERO))
+            .with_on_error(OnError::Skip);
+        let output = pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap();
+        let mut archive = tar::Archive::new(output.as_slice());
+        assert_eq!(archive.entries().unwrap().count(), 0);
+
+        // aborting is the default, library callers are never prompted
+        let ctx = Context::new(&NoProgress, 6)
+            .with_buffer_size(1)
+            .with_entry_timeout(Some(Duration::ZERO));
+        let err = pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().starts_with("Entry Timeout"));
+
+        let ctx = Context::new(&NoProgress, 6).with_timeout(Some(Duration::ZERO));
+        let err = pack_archive(&ctx, input, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().starts_with("Timeout"));
+    }
+
+    #[test]
+    fn test_scrub_dumpdir() {
+        let listing = b"Ykeep.txt\0Ysecret.txt\0Dsub\0\0";
+        let filter_list = vec![PathBuf::from("dir/secret.txt")];
+        assert_eq!(
+            scrub_dumpdir("dir/", listing, &filter_list, 0),
+            b"Ykeep.txt\0Dsub\0\0"
+        );
+        assert_eq!(scrub_dumpdir("dir/", listing, &[], 0), listing);
+    }
+
+    #[test]
+    fn test_pack_archive_dumpdir() {
+        let listing = b"Ya.txt\0Yb.txt\0\0";
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::new(GNU_DUMPDIR));
+        header.set_mode(0o755);
+        header.set_size(listing.len() as u64);
+        tar.append_data(&mut header, "dir/", &listing[..]).unwrap();
+        for name in ["dir/a.txt", "dir/b.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            tar.append_data(&mut header, name, &b"x"[..]).unwrap();
+        }
+        let input = tar.into_inner().unwrap();
+
+        let dumpdir = |ctx: &Context| {
+            let mut filter_list = vec![PathBuf::from("dir/b.txt")];
+            let output = pack_archive(ctx, input.clone(), &mut filter_list).unwrap();
+            let mut archive = tar::Archive::new(output.as_slice());
+            let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            (entry.header().entry_type(), data)
+        };
+        let ctx = Context::new(&NoProgress, 6);
+        assert_eq!(
+            dumpdir(&ctx),
+            (tar::EntryType::new(GNU_DUMPDIR), b"Ya.txt\0\0".to_vec())
+        );
+        let ctx = ctx.with_drop_incremental_metadata(true);
+        assert_eq!(dumpdir(&ctx), (tar::EntryType::Directory, Vec::new()));
+    }
+
+    #[test]
+    fn test_pack_archive_special_files() {
+        let mut tar = tar::Builder::new(Vec::new());
+        for (name, entry_type) in [
+            ("dev/tty1", tar::EntryType::Char),
+            ("dev/sda", tar::EntryType::Block),
+            ("run/fifo", tar::EntryType::Fifo),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_device_major(4).unwrap();
+            header.set_device_minor(1).unwrap();
+            header.set_size(0);
+            tar.append_data(&mut header, name, std::io::empty())
+                .unwrap();
+        }
+        let input = tar.into_inner().unwrap();
+
+        let mut filter_list = vec![PathBuf::from("dev/sda")];
+        let output = pack_archive(&Context::new(&NoProgress, 6), input, &mut filter_list).unwrap();
+        let mut archive = tar::Archive::new(output.as_slice());
+        let headers: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().header().clone())
+            .collect();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].entry_type(), tar::EntryType::Char);
+        assert_eq!(headers[0].device_major().unwrap(), Some(4));
+        assert_eq!(headers[0].device_minor().unwrap(), Some(1));
+        assert_eq!(headers[1].entry_type(), tar::EntryType::Fifo);
+        assert_eq!(headers[1].size().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pack_archive_xattrs() {
+        let mut tar = tar::Builder::new(Vec::new());
+        tar.append_pax_extensions([
+            (
+                "SCHILY.xattr.security.selinux",
+                &b"system_u:object_r:bin_t:s0"[..],
+            ),
+            ("comment", &b"dropped"[..]),
+        ])
+        .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        tar.append_data(&mut header, "bin/sh", &b"x"[..]).unwrap();
+        let input = tar.into_inner().unwrap();
+
+        let xattrs = |ctx: &Context| {
+            let output = pack_archive(ctx, input.clone(), &mut Vec::new()).unwrap();
+            let mut archive = tar::Archive::new(output.as_slice());
+            let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+            tar_xattrs(&mut entry).unwrap()
+        };
+        let ctx = Context::new(&NoProgress, 6);
+        assert_eq!(
+            xattrs(&ctx),
+            vec![(
+                "SCHILY.xattr.security.selinux".to_string(),
+                b"system_u:object_r:bin_t:s0".to_vec()
+            )]
+        );
+        assert!(xattrs(&ctx.with_strip_xattrs(true)).is_empty());
+    }
+
+    #[test]
+    fn test_pack_archive_tar_format() {
+        let long_name = format!("dir/{}.txt", "a".repeat(120));
+        let mut tar = tar::Builder::new(Vec::new());
+        tar.append_pax_extensions([("SCHILY.xattr.user.test", &b"value"[..])])
+            .unwrap();
+        for name in ["short.txt", long_name.as_str()] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_username("builder").unwrap();
+            tar.append_data(&mut header, name, &b"x"[..]).unwrap();
+        }
+        let input = tar.into_inner().unwrap();
+
+        let pack = |format: TarFormat| {
+            let ctx = Context::new(&NoProgress, 6).with_tar_format(Some(format));
+            pack_archive(&ctx, input.clone(), &mut Vec::new())
+        };
+        let output = pack(TarFormat::Pax).unwrap();
+        let mut archive = tar::Archive::new(output.as_slice());
+        let mut entries = archive.entries().unwrap().map(|entry| entry.unwrap());
+        let mut short = entries.next().unwrap();
+        assert!(short.header().as_ustar().is_some());
+        assert_eq!(short.header().username().unwrap(), Some("builder"));
+        assert_eq!(tar_xattrs(&mut short).unwrap().len(), 1);
+        let long = entries.next().unwrap();
+        assert_eq!(long.path().unwrap().to_str(), Some(long_name.as_str()));
+        // the long name is carried by a PAX record, not a GNU extension
+        assert!(!output.windows(13).any(|w| w == b"././@LongLink"));
+
+        let output = pack(TarFormat::Gnu).unwrap();
+        let mut archive = tar::Archive::new(output.as_slice());
+        let mut short = archive.entries().unwrap().next().unwrap().unwrap();
+        assert!(short.header().as_gnu().is_some());
+        assert!(tar_xattrs(&mut short).unwrap().is_empty());
+
+        let err = pack(TarFormat::Ustar).unwrap_err();
+        assert!(err.to_string().starts_with("Tar Format Error"));
+    }
+
+    #[test]
+    fn test_pack_archive_zip_align() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            let stored =
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            for name in ["a.bin", "assets/bb.bin", "ccc.bin"] {
+                zip.start_file(name, stored).unwrap();
+                zip.write_all(name.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let ctx = Context::new(&NoProgress, 6).with_zip_align(Some(4096));
+        let mut filter_list = vec![PathBuf::from("a.bin")];
+        let output = pack_archive(&ctx, input, &mut filter_list).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), 2);
+        for i in 0..archive.len() {
+            assert_eq!(archive.by_index(i).unwrap().data_start() % 4096, 0);
+        }
+    }
+
+    #[test]
+    fn test_pack_archive_zip_comment() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            zip.set_comment("original");
+            for name in ["a.txt", "b.txt"] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(name.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let comment = |ctx: &Context| {
+            let mut filter_list = vec![PathBuf::from("a.txt")];
+            let output = pack_archive(ctx, input.clone(), &mut filter_list).unwrap();
+            let archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+            String::from_utf8(archive.comment().to_vec()).unwrap()
+        };
+        let ctx = Context::new(&NoProgress, 6);
+        assert_eq!(comment(&ctx), "original");
+        // rewritten instead of copied
+        assert_eq!(comment(&ctx.clone().with_windows_safe(true)), "original");
+
+        let ctx = ctx.with_archive_comment(Some("scrubbed".to_string()));
+        assert_eq!(comment(&ctx), "scrubbed");
+        assert_eq!(comment(&ctx.with_windows_safe(true)), "scrubbed");
+    }
+
+    #[test]
+    fn test_pack_archive_provenance() {
+        use crate::provenance::{Provenance, StoredRecord};
+
+        let provenance = |mode| Some(Provenance::new(mode, b"secret.txt", 1, 0, 0));
+        let record = |bytes: &[u8]| -> serde_json::Value { serde_json::from_slice(bytes).unwrap() };
+
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let ctx = Context::new(&NoProgress, 6).with_provenance(provenance(ProvenanceMode::Entry));
+        let output = pack_archive(&ctx, input, &mut vec![PathBuf::from("missing.txt")]).unwrap();
+        assert_eq!(StoredRecord::read(&output).unwrap().rules, 1);
+        let entries = crate::ArchiveReader::new(output)
+            .entries()
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let last = entries.last().unwrap();
+        assert_eq!(last.path, PathBuf::from(PROVENANCE_ENTRY));
+
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            for name in ["a.txt", "secret.txt"] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(name.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let output =
+            pack_archive(&ctx, input.clone(), &mut vec![PathBuf::from("secret.txt")]).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut content = Vec::new();
+        archive
+            .by_name(PROVENANCE_ENTRY)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(record(&content)["removed"], 1);
+
+        let ctx = ctx.with_provenance(provenance(ProvenanceMode::Comment));
+        let output = pack_archive(&ctx, input, &mut vec![PathBuf::from("secret.txt")]).unwrap();
+        assert_eq!(StoredRecord::read(&output).unwrap().tool, "expurgator");
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(archive.len(), 1);
+        assert_eq!(record(archive.comment())["tool"], "expurgator");
+    }
+
+    #[test]
+    fn test_pack_archive_duplicates() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            for (name, content) in [("a.txt", "first"), ("b.txt", "b"), ("c.txt", "last")] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(content.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        // the zip writer refuses duplicates, c.txt is renamed in place
+        for start in 0..input.len() - 5 {
+            if &input[start..start + 5] == b"c.txt" {
+                input[start..start + 5].copy_from_slice(b"a.txt");
+            }
+        }
+        let names = |output: &[u8]| -> Vec<String> {
+            let directory = ZipDirectory::parse(output).unwrap();
+            directory
+                .records
+                .into_iter()
+                .map(|record| record.name)
+                .collect()
+        };
+        let content = |output: Vec<u8>| {
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+            let mut content = String::new();
+            archive
+                .by_name("a.txt")
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        let pack = |duplicates, windows_safe, filter: &[&str]| {
+            let entries = Arc::default();
+            let ctx = Context::new(&NoProgress, 6)
+                .with_windows_safe(windows_safe)
+                .with_duplicates(duplicates, Arc::clone(&entries));
+            let mut filter_list = filter.iter().map(PathBuf::from).collect();
+            let output = pack_archive(&ctx, input.clone(), &mut filter_list);
+            let entries = entries.lock().unwrap().clone();
+            output.map(|output| (output, entries))
+        };
+
+        let (output, entries) = pack(Duplicates::KeepFirst, false, &[]).unwrap();
+        assert_eq!(names(&output), ["a.txt", "b.txt"]);
+        assert_eq!(content(output), "first");
+        assert_eq!(entries, [PathBuf::from("a.txt")]);
+        let (output, _) = pack(Duplicates::KeepLast, true, &[]).unwrap();
+        assert_eq!(names(&output), ["b.txt", "a.txt"]);
+        assert_eq!(content(output), "last");
+
+        // the other copies are appended as they are
+        let (output, entries) = pack(Duplicates::KeepAll, true, &[]).unwrap();
+        assert_eq!(names(&output), ["b.txt", "a.txt", "a.txt"]);
+        assert_eq!(entries, [PathBuf::from("a.txt")]);
+        let (output, entries) = pack(Duplicates::KeepAll, false, &["a.txt"]).unwrap();
+        assert_eq!(names(&output), ["b.txt"]);
+        assert!(entries.is_empty());
+
+        assert!(pack(Duplicates::Error, false, &[]).is_err());
+    }
+
+    #[test]
+    fn test_pack_archive_match_all() {
+        let names = |output: Vec<u8>| -> Vec<PathBuf> {
+            crate::ArchiveReader::new(output)
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path)
+                .collect()
+        };
+        let pack = |input: &[u8], match_mode, filter: &[&str]| {
+            let occurrences = Arc::default();
+            let ctx =
+                Context::new(&NoProgress, 6).with_match_mode(match_mode, Arc::clone(&occurrences));
+            let mut filter_list = filter.iter().map(PathBuf::from).collect();
+            let output = pack_archive(&ctx, input.to_vec(), &mut filter_list).unwrap();
+            let paths: Vec<_> = occurrences
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|occurrence: &Occurrence| occurrence.path.clone())
+                .collect();
+            (output, ctx.unmatched(filter_list), paths)
+        };
+
+        // tar archives may hold a path twice
+        let mut tar = tar::Builder::new(Vec::new());
+        for name in ["a.txt", "b.txt", "a.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            tar.append_data(&mut header, name, &b"x"[..]).unwrap();
+        }
+        let input = tar.into_inner().unwrap();
+        let (output, unmatched, paths) = pack(&input, MatchMode::First, &["a.txt", "c.txt"]);
+        assert_eq!(names(output), ["b.txt", "a.txt"].map(PathBuf::from));
+        assert_eq!(unmatched, [PathBuf::from("c.txt")]);
+        assert!(paths.is_empty());
+        let (output, unmatched, paths) = pack(&input, MatchMode::All, &["a.txt", "c.txt"]);
+        assert_eq!(names(output), [PathBuf::from("b.txt")]);
+        assert_eq!(unmatched, [PathBuf::from("c.txt")]);
+        assert_eq!(paths, ["a.txt", "a.txt"].map(PathBuf::from));
+
+        // the rule ends with the path of the top level entry too
+        let mut inner = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut inner));
+            zip.start_file("file.txt", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(b"inner").unwrap();
+            zip.finish().unwrap();
+        }
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            for (name, content) in [("file.txt", &b"outer"[..]), ("inner.zip", &inner)] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(content).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let rule = "inner.zip/file.txt";
+        let (output, unmatched, _) = pack(&input, MatchMode::First, &[rule]);
+        assert!(unmatched.is_empty());
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), ["inner.zip"]);
+        let mut nested = Vec::new();
+        archive
+            .by_name("inner.zip")
+            .unwrap()
+            .read_to_end(&mut nested)
+            .unwrap();
+        assert_eq!(names(nested), [PathBuf::from("file.txt")]);
+
+        let (output, unmatched, paths) = pack(&input, MatchMode::All, &[rule]);
+        assert!(unmatched.is_empty());
+        assert_eq!(paths, ["file.txt", rule].map(PathBuf::from));
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        let mut nested = Vec::new();
+        archive
+            .by_name("inner.zip")
+            .unwrap()
+            .read_to_end(&mut nested)
+            .unwrap();
+        assert!(names(nested).is_empty());
+    }
+
+    #[test]
+    fn test_pack_archive_depth_modifier() {
+        let zip_of = |entries: &[(&str, &[u8])]| {
+            let mut output = Vec::new();
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut output));
+            for (name, content) in entries {
+                zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(content).unwrap();
+            }
+            zip.finish().unwrap();
+            output
+        };
+        let inner = zip_of(&[("LICENSE", b"inner"), ("a.txt", b"a")]);
+        let input = zip_of(&[("LICENSE", b"outer"), ("lib/inner.zip", &inner)]);
+        let pack = |row: &str| {
+            let occurrences = Arc::default();
+            let ctx = Context::new(&NoProgress, 6)
+                .with_match_mode(MatchMode::First, Arc::clone(&occurrences));
+            let mut filter_list = vec![PathBuf::from(row)];
+            let output = pack_archive(&ctx, input.clone(), &mut filter_list).unwrap();
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+            let outer: Vec<_> = archive.file_names().map(String::from).collect();
+            let mut nested = Vec::new();
+            archive
+                .by_name("lib/inner.zip")
+                .unwrap()
+                .read_to_end(&mut nested)
+                .unwrap();
+            let nested = zip::ZipArchive::new(std::io::Cursor::new(nested)).unwrap();
+            let inner: Vec<_> = nested.file_names().map(String::from).collect();
+            let paths: Vec<_> = occurrences
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|occurrence: &Occurrence| occurrence.path.clone())
+                .collect();
+            (outer, inner, ctx.unmatched(filter_list), paths)
+        };
+
+        let (outer, inner, unmatched, paths) = pack("depth:0 LICENSE");
+        assert_eq!(outer, ["lib/inner.zip"]);
+        assert_eq!(inner.len(), 2);
+        assert!(unmatched.is_empty());
+        assert_eq!(paths, [PathBuf::from("LICENSE")]);
+
+        let (outer, inner, unmatched, paths) = pack("depth:>=1 LICENSE");
+        assert_eq!(outer, ["LICENSE", "lib/inner.zip"]);
+        assert_eq!(inner, ["a.txt"]);
+        assert!(unmatched.is_empty());
+        assert_eq!(paths, [PathBuf::from("lib/inner.zip/LICENSE")]);
+
+        let (_, inner, unmatched, _) = pack("depth:2 LICENSE");
+        assert_eq!(inner.len(), 2);
+        assert_eq!(unmatched, [PathBuf::from("depth:2 LICENSE")]);
+    }
+
+    #[test]
+    fn test_pack_archive_symlinks() {
+        let entries = |output: Vec<u8>| -> Vec<(PathBuf, EntryKind, u64)> {
+            crate::ArchiveReader::new(output)
+                .entries()
+                .unwrap()
+                .map(Result::unwrap)
+                .filter(|entry| entry.depth == 0)
+                .map(|entry| (entry.path, entry.kind, entry.size))
+                .collect()
+        };
+        let link = Path::new("tar-test/nested/file_link.txt");
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let ctx = Context::new(&NoProgress, 6).with_symlinks(Symlinks::Drop, dropped.clone());
+        let output = entries(pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap());
+        assert!(!output.iter().any(|(path, _, _)| path == link));
+        assert_eq!(*dropped.lock().unwrap(), [link]);
+
+        // the link points to ../file_3.txt
+        let ctx = Context::new(&NoProgress, 6).with_symlinks(Symlinks::Dereference, Arc::default());
+        let output = entries(pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap());
+        assert!(output.contains(&(link.to_path_buf(), EntryKind::File, 20)));
+
+        // removed targets are not inlined
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let ctx =
+            Context::new(&NoProgress, 6).with_symlinks(Symlinks::Dereference, dropped.clone());
+        let mut filter_list = vec![PathBuf::from("tar-test/file_3.txt")];
+        let output = entries(pack_archive(&ctx, input.clone(), &mut filter_list).unwrap());
+        assert!(!output.iter().any(|(path, _, _)| path == link));
+        assert_eq!(*dropped.lock().unwrap(), [link]);
+
+        let ctx =
+            Context::new(&NoProgress, 6).with_symlinks(Symlinks::RejectExternal, Arc::default());
+        assert!(pack_archive(&ctx, input, &mut Vec::new()).is_ok());
+
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            let options = SimpleFileOptions::default();
+            zip.start_file("lib/libz.so.1", options).unwrap();
+            zip.write_all(b"library").unwrap();
+            zip.add_symlink("lib/libz.so", "libz.so.1", options)
+                .unwrap();
+            zip.add_symlink("etc/passwd", "/etc/passwd", options)
+                .unwrap();
+            zip.finish().unwrap();
+        }
+        // rewritten entries keep their links
+        let ctx = Context::new(&NoProgress, 6).with_entry_order(EntryOrder::Path);
+        let output = pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap();
+        let mut output = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        let links: Vec<(String, bool)> = (0..output.len())
+            .map(|i| {
+                let entry = output.by_index(i).unwrap();
+                (entry.name().to_owned(), entry.is_symlink())
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("etc/passwd".to_owned(), true),
+                ("lib/libz.so".to_owned(), true),
+                ("lib/libz.so.1".to_owned(), false),
+            ]
+        );
+
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let ctx = Context::new(&NoProgress, 6).with_symlinks(Symlinks::Drop, dropped.clone());
+        let output = entries(pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap());
+        assert_eq!(
+            output,
+            [(PathBuf::from("lib/libz.so.1"), EntryKind::File, 7)]
+        );
+        assert_eq!(
+            *dropped.lock().unwrap(),
+            [Path::new("lib/libz.so"), Path::new("etc/passwd")]
+        );
+
+        let ctx =
+            Context::new(&NoProgress, 6).with_symlinks(Symlinks::RejectExternal, Arc::default());
+        let err = pack_archive(&ctx, input.clone(), &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "External Symlink: 'etc/passwd' points to '/etc/passwd', outside the archive."
+        );
+
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let ctx =
+            Context::new(&NoProgress, 6).with_symlinks(Symlinks::Dereference, dropped.clone());
+        let output = entries(pack_archive(&ctx, input, &mut Vec::new()).unwrap());
+        assert_eq!(
+            output,
+            [
+                (PathBuf::from("lib/libz.so.1"), EntryKind::File, 7),
+                (PathBuf::from("lib/libz.so"), EntryKind::File, 7),
+            ]
+        );
+        assert_eq!(*dropped.lock().unwrap(), [Path::new("etc/passwd")]);
+    }
+
+    #[test]
+    fn test_pack_archive_entry_order() {
+        let top_level = |output: Vec<u8>| -> Vec<(PathBuf, u64)> {
+            crate::ArchiveReader::new(output)
+                .entries()
+                .unwrap()
+                .map(Result::unwrap)
+                .filter(|entry| entry.depth == 0)
+                .map(|entry| (entry.path, entry.size))
+                .collect()
+        };
+
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let ctx = Context::new(&NoProgress, 6).with_entry_order(EntryOrder::Path);
+        let unsorted = top_level(
+            pack_archive(
+                &Context::new(&NoProgress, 6),
+                input.clone(),
+                &mut Vec::new(),
+            )
+            .unwrap(),
+        );
+        let sorted = top_level(pack_archive(&ctx, input, &mut Vec::new()).unwrap());
+        let mut expected = unsorted.clone();
+        expected.sort();
+        assert_ne!(unsorted, expected);
+        assert_eq!(sorted, expected);
+
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            for (name, size) in [("c.txt", 1), ("a.txt", 3), ("b.txt", 2)] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(&vec![b'x'; size]).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let ctx = ctx.with_entry_order(EntryOrder::Size);
+        let output = pack_archive(&ctx, input, &mut Vec::new()).unwrap();
+        let names: Vec<_> = top_level(output)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(names, ["c.txt", "b.txt", "a.txt"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_pack_archive_clamp_mtime() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            let modified = zip::DateTime::from_date_and_time(2023, 3, 7, 8, 5, 0).unwrap();
+            let options = SimpleFileOptions::default().last_modified_time(modified);
+            zip.start_file("a.txt", options).unwrap();
+            zip.write_all(b"a").unwrap();
+            zip.finish().unwrap();
+        }
+        let mtime = |ctx: &Context| {
+            let output = pack_archive(ctx, input.clone(), &mut Vec::new()).unwrap();
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+            let modified = archive.by_index(0).unwrap().last_modified().unwrap();
+            zip_datetime_to_unix(modified)
+        };
+        // rewritten entries keep their time
+        let ctx = Context::new(&NoProgress, 6).with_windows_safe(true);
+        assert_eq!(mtime(&ctx), 1678176300);
+        assert_eq!(
+            mtime(&ctx.clone().with_clamp_mtime(Some(1577836800))),
+            1577836800
+        );
+        assert_eq!(mtime(&ctx.with_clamp_mtime(Some(1700000000))), 1678176300);
+    }
+
+    #[test]
+    fn test_pack_archive_parallel_nested() {
+        let zip_of = |entries: &[(String, Vec<u8>)]| {
+            let mut output = Vec::new();
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut output));
+            for (name, content) in entries {
+                zip.start_file(name.as_str(), SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(content).unwrap();
+            }
+            zip.finish().unwrap();
+            output
+        };
+        let mut entries = Vec::new();
+        for i in 0..8 {
+            let inner = zip_of(&[
+                ("a.txt".to_string(), b"a".repeat(1000)),
+                ("b.txt".to_string(), b"b".repeat(1000)),
+            ]);
+            entries.push((format!("lib/{}.jar", i), inner));
+            entries.push((format!("{}.txt", i), i.to_string().into_bytes()));
+        }
+        let input = zip_of(&entries);
+        let pack = |jobs| {
+            let ctx = Context::new(&NoProgress, 6).with_jobs(jobs);
+            let mut filter_list = (0..8)
+                .map(|i| PathBuf::from(format!("lib/{}.jar/b.txt", i)))
+                .collect();
+            let output = pack_archive(&ctx, input.clone(), &mut filter_list).unwrap();
+            assert!(filter_list.is_empty());
+            output
+        };
+
+        let output = pack(4);
+        // the entries keep their order, each nested archive lost its listed entry
+        let directory = ZipDirectory::parse(&output).unwrap();
+        let names: Vec<_> = directory
+            .records
+            .into_iter()
+            .map(|record| record.name)
+            .collect();
+        let expected: Vec<_> = entries.iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(names, expected);
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&output)).unwrap();
+        for i in 0..8 {
+            let mut inner = Vec::new();
+            archive
+                .by_name(&format!("lib/{}.jar", i))
+                .unwrap()
+                .read_to_end(&mut inner)
+                .unwrap();
+            let inner = zip::ZipArchive::new(std::io::Cursor::new(inner)).unwrap();
+            assert_eq!(inner.file_names().collect::<Vec<_>>(), ["a.txt"]);
+        }
+        assert_eq!(output, pack(1));
+    }
+
+    #[test]
+    fn test_pack_archive_copy_rest() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let long_name = format!("{}/b.txt", "dir".repeat(50));
+        for (name, content) in [("a.txt", "a"), (long_name.as_str(), "b"), ("c.txt", "c")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        let input = builder.into_inner().unwrap();
+
+        let ctx = Context::new(&NoProgress, 6);
+        let mut filter_list = vec![PathBuf::from("a.txt")];
+        let output = pack_archive(&ctx, input.clone(), &mut filter_list).unwrap();
+        // the header and data block of a.txt are gone, the rest is copied
+        assert_eq!(output, input[1024..]);
+        let mut archive = tar::Archive::new(output.as_slice());
+        let paths: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(paths, [long_name.as_str(), "c.txt"]);
+    }
+
+    #[test]
+    fn test_stream_content() {
+        let gzip = |data: &[u8]| {
+            let mut encoder = TarEncoder::new("application/gzip", 6).unwrap().encoder();
+            encoder.write_all(data).unwrap();
+            encoder.inner().unwrap()
+        };
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "a.txt", &b"a"[..])
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        let mut zip = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut zip));
+            writer
+                .start_file("a.txt", SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(b"a").unwrap();
+            writer.finish().unwrap();
+        }
+        let json = gzip(br#"{"name": "expurgator"}"#);
+        assert_eq!(
+            stream_content(&gzip(&tar), "application/gzip").unwrap(),
+            StreamContent::Tar
+        );
+        assert_eq!(
+            stream_content(&gzip(&zip), "application/gzip").unwrap(),
+            StreamContent::Archive
+        );
+        assert_eq!(
+            stream_content(&json, "application/gzip").unwrap(),
+            StreamContent::Data
+        );
+        // an empty tar is only its end of archive blocks
+        assert!(holds_archive(&gzip(&[0; 1024])));
+        assert!(!holds_archive(&json));
+        assert!(holds_archive(&zip));
+    }
+
+    #[test]
+    fn test_pack_archive_compressed_files() {
+        let gzip = |data: &[u8]| {
+            let mut encoder = TarEncoder::new("application/gzip", 6).unwrap().encoder();
+            encoder.write_all(data).unwrap();
+            encoder.inner().unwrap()
+        };
+        let mut inner = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut inner));
+            for name in ["a.txt", "b.txt"] {
+                zip.start_file(name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(name.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let json = gzip(br#"{"name": "expurgator"}"#);
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            zip.start_file("data.json.gz", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&json).unwrap();
+            zip.start_file("inner.zip.gz", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&gzip(&inner)).unwrap();
+            zip.finish().unwrap();
+        }
+
+        let ctx = Context::new(&NoProgress, 6);
+        let mut filter_list = vec![
+            PathBuf::from("data.json.gz/name"),
+            PathBuf::from("inner.zip.gz/b.txt"),
+        ];
+        let output = pack_archive(&ctx, input, &mut filter_list).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        // the compressed single file is kept as it is
+        let mut data = Vec::new();
+        archive
+            .by_name("data.json.gz")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, json);
+        // the zip inside the gzip is filtered
+        let mut data = Vec::new();
+        archive
+            .by_name("inner.zip.gz")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let mut decoded = Vec::new();
+        GzDecoder::new(data.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        let inner = zip::ZipArchive::new(std::io::Cursor::new(decoded)).unwrap();
+        assert_eq!(inner.file_names().collect::<Vec<_>>(), ["a.txt"]);
+        assert_eq!(filter_list, [PathBuf::from("data.json.gz/name")]);
+
+        assert!(pack_archive(&ctx, json, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_pack_archive_zstd_dict() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for index in 0..300 {
+            let content = format!("{{\"id\": {}, \"license\": \"MIT\"}}", index);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("{}.json", index), content.as_bytes())
+                .unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+        let dict = crate::dictionary::train(&tar).unwrap();
+        let mut encoder = ZstdEncoder::with_dictionary(Vec::new(), 6, &dict).unwrap();
+        encoder.write_all(&tar).unwrap();
+        let input = encoder.finish().unwrap();
+
+        let ctx = Context::new(&NoProgress, 6);
+        let mut filter_list = vec![PathBuf::from("0.json")];
+        assert!(pack_archive(&ctx, input.clone(), &mut filter_list).is_err());
+        let ctx = ctx.with_zstd_dict(Some(dict.clone()));
+        let output = pack_archive(&ctx, input, &mut filter_list).unwrap();
+        let mut decoded = Vec::new();
+        ZstdDecoder::with_dictionary(output.as_slice(), &dict)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        let mut archive = tar::Archive::new(decoded.as_slice());
+        assert_eq!(archive.entries().unwrap().count(), 299);
+    }
+
+    #[test]
+    fn test_pack_archive_zstd_long() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let content = b"expurgator ".repeat(1000);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, content.as_slice())
+                .unwrap();
+        }
+        let mut encoder = TarEncoder::new("application/zstd", 3).unwrap().encoder();
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        let input = encoder.inner().unwrap();
+
+        let ctx = Context::new(&NoProgress, 3).with_zstd_long(Some(30));
+        let mut filter_list = vec![PathBuf::from("b.txt")];
+        let output = pack_archive(&ctx, input, &mut filter_list).unwrap();
+        // a window above the default limit of decoders
+        let mut decoded = Vec::new();
+        assert!(ZstdDecoder::new(output.as_slice())
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .is_err());
+        let mut archive =
+            tar::Archive::new(create_tar_decoder(&output, "application/zstd").unwrap());
+        assert_eq!(archive.entries().unwrap().count(), 2);
+    }
 }
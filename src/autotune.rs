@@ -0,0 +1,202 @@
+use std::{
+    io::{Cursor, Read, Write},
+    time::Instant,
+};
+
+use anyhow::Result;
+
+use crate::archive::{create_tar_decoder, TarEncoder};
+use crate::util::infer_input_file;
+
+/// Level used when compression is not tuned, or the output is not compressed.
+pub const DEFAULT_LEVEL: u32 = 6;
+
+/// Bytes of entry content compressed at each level.
+const SAMPLE_SIZE: usize = 4 * 1024 * 1024;
+/// Number of zip entries the sample is taken from, spread over the archive.
+const SAMPLE_ENTRIES: usize = 8;
+
+/// What `--compression auto` aims for.
+#[derive(Debug, Clone, Copy)]
+pub struct Target {
+    /// Slowest acceptable compression speed, in bytes per second.
+    pub min_speed: f64,
+    /// Fraction of the best measured ratio a level may give up.
+    pub max_ratio_loss: f64,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target {
+            min_speed: 100_000_000.0,
+            max_ratio_loss: 0.02,
+        }
+    }
+}
+
+/// Ratio and speed of a compression level on the sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub level: u32,
+    pub ratio: f64,
+    /// Bytes per second.
+    pub speed: f64,
+}
+
+/// Picks the compression level for rewriting `input_bytes` by compressing a
+/// sample of its entries at increasing levels with the encoder of the
+/// output, until one is slower than `target`. Outputs that are not
+/// compressed get [`DEFAULT_LEVEL`].
+pub fn autotune(input_bytes: &[u8], target: &Target) -> Result<u32> {
+    let mime_type = infer_input_file(input_bytes)?;
+    // zip entries are deflated like gzip streams
+    let (encoder, levels) = match mime_type.as_str() {
+        "application/zip" | "application/gzip" => ("application/gzip", 1..=9),
+        "application/x-bzip2" => ("application/x-bzip2", 1..=9),
+        "application/x-xz" => ("application/x-xz", 0..=9),
+        _ => return Ok(DEFAULT_LEVEL),
+    };
+    let sample = sample(input_bytes, &mime_type)?;
+    if sample.is_empty() {
+        return Ok(DEFAULT_LEVEL);
+    }
+    let mut measurements = Vec::new();
+    for level in levels {
+        let measurement = measure(encoder, level, &sample)?;
+        measurements.push(measurement);
+        // higher levels are only slower
+        if measurement.speed < target.min_speed {
+            break;
+        }
+    }
+    Ok(choose(&measurements, target))
+}
+
+/// Up to [`SAMPLE_SIZE`] bytes of the entries of the archive: the start of
+/// the stream of a tar, parts of entries spread over a zip.
+fn sample(input_bytes: &[u8], mime_type: &str) -> Result<Vec<u8>> {
+    let mut sample = Vec::new();
+    if mime_type != "application/zip" {
+        create_tar_decoder(input_bytes, mime_type)?
+            .take(SAMPLE_SIZE as u64)
+            .read_to_end(&mut sample)?;
+        return Ok(sample);
+    }
+    let mut zip = zip::ZipArchive::new(Cursor::new(input_bytes))?;
+    let step = (zip.len() / SAMPLE_ENTRIES).max(1);
+    for index in (0..zip.len()).step_by(step).take(SAMPLE_ENTRIES) {
+        // encrypted entries cannot be read without the password
+        let Ok(entry) = zip.by_index(index) else {
+            continue;
+        };
+        if entry.is_file() {
+            entry
+                .take((SAMPLE_SIZE / SAMPLE_ENTRIES) as u64)
+                .read_to_end(&mut sample)?;
+        }
+    }
+    Ok(sample)
+}
+
+fn measure(mime_type: &str, level: u32, sample: &[u8]) -> Result<Measurement> {
+    let start = Instant::now();
+    let mut encoder = TarEncoder::new(mime_type, level)?.encoder();
+    encoder.write_all(sample)?;
+    let compressed = encoder.inner()?;
+    let elapsed = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    Ok(Measurement {
+        level,
+        ratio: sample.len() as f64 / compressed.len().max(1) as f64,
+        speed: sample.len() as f64 / elapsed,
+    })
+}
+
+/// The lowest level fast enough and within the ratio loss of the best
+/// measured ratio, else the best ratio among the fast enough levels, else
+/// the fastest level.
+pub fn choose(measurements: &[Measurement], target: &Target) -> u32 {
+    let best_ratio = measurements
+        .iter()
+        .map(|measurement| measurement.ratio)
+        .fold(0.0, f64::max);
+    let fast: Vec<_> = measurements
+        .iter()
+        .filter(|measurement| measurement.speed >= target.min_speed)
+        .collect();
+    if let Some(measurement) = fast
+        .iter()
+        .find(|measurement| measurement.ratio >= best_ratio * (1.0 - target.max_ratio_loss))
+    {
+        return measurement.level;
+    }
+    if let Some(measurement) = fast.iter().max_by(|a, b| a.ratio.total_cmp(&b.ratio)) {
+        return measurement.level;
+    }
+    measurements
+        .iter()
+        .max_by(|a, b| a.speed.total_cmp(&b.speed))
+        .map_or(DEFAULT_LEVEL, |measurement| measurement.level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose() {
+        let measure = |level, ratio, speed| Measurement {
+            level,
+            ratio,
+            speed,
+        };
+        let target = Target::default();
+        let measurements = [
+            measure(1, 3.0, 300e6),
+            measure(2, 3.5, 200e6),
+            measure(3, 3.56, 120e6),
+            measure(4, 3.6, 90e6),
+        ];
+        // level 3 is within 2% of the ratio of level 4, which is too slow
+        assert_eq!(choose(&measurements, &target), 3);
+        // the best ratio among the fast enough levels
+        assert_eq!(
+            choose(
+                &measurements[..2],
+                &Target {
+                    max_ratio_loss: 0.0,
+                    ..target
+                }
+            ),
+            2
+        );
+        // nothing is fast enough
+        assert_eq!(choose(&measurements[3..], &target), 4);
+        assert_eq!(choose(&[], &target), DEFAULT_LEVEL);
+    }
+
+    #[test]
+    fn test_autotune() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let content = b"expurgator ".repeat(10_000);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "a.txt", content.as_slice())
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+        assert_eq!(autotune(&tar, &Target::default()).unwrap(), DEFAULT_LEVEL);
+
+        let mut encoder = TarEncoder::new("application/gzip", 6).unwrap().encoder();
+        encoder.write_all(&tar).unwrap();
+        let gzip = encoder.inner().unwrap();
+        // no level is fast enough, tuning stops at the first one
+        let slow = Target {
+            min_speed: f64::MAX,
+            ..Target::default()
+        };
+        assert_eq!(autotune(&gzip, &slow).unwrap(), 1);
+        let level = autotune(&gzip, &Target::default()).unwrap();
+        assert!((1..=9).contains(&level));
+    }
+}
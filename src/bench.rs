@@ -0,0 +1,176 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use zip::write::SimpleFileOptions;
+
+use crate::archive::{pack_archive_into, TarEncoder};
+use crate::context::Context;
+use crate::sink::{Clobber, FileSink, OutputOptions};
+
+pub const BENCH_FORMATS: [&str; 6] = ["zip", "tar", "tar.gz", "tar.bz2", "tar.xz", "tar.zst"];
+
+/// Every n-th generated entry is removed by the benchmark scrub.
+const REMOVE_EVERY: usize = 10;
+
+pub(crate) fn tar_mime_type(format: &str) -> Option<&'static str> {
+    match format {
+        "tar" => Some("application/x-tar"),
+        "tar.gz" => Some("application/gzip"),
+        "tar.bz2" => Some("application/x-bzip2"),
+        "tar.xz" => Some("application/x-xz"),
+        "tar.zst" => Some("application/zstd"),
+        _ => None,
+    }
+}
+
+fn entry_name(index: usize) -> String {
+    format!("bench/{:04}/{:08}.dat", index / 1000, index)
+}
+
+/// Deterministic filler of 16 distinct letters, compressing to about half.
+fn fill(buffer: &mut [u8], seed: u64) {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    for byte in buffer {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *byte = b'a' + (state % 16) as u8;
+    }
+}
+
+/// Builds an archive of `entries` files sharing `size` bytes of content.
+pub fn generate_archive(format: &str, entries: usize, size: u64) -> Result<Vec<u8>> {
+    let entry_size = size / entries.max(1) as u64;
+    let mut data = vec![0u8; entry_size.try_into()?];
+    if format == "zip" {
+        let mut result = Vec::new();
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut result));
+        let options = SimpleFileOptions::default().large_file(entry_size > u32::MAX as u64);
+        for index in 0..entries {
+            fill(&mut data, index as u64);
+            zip.start_file(entry_name(index), options)?;
+            zip.write_all(&data)?;
+        }
+        zip.finish()?;
+        return Ok(result);
+    }
+
+    let mime_type = tar_mime_type(format).ok_or_else(|| {
+        anyhow!(
+            "Unsupported Format: The benchmark format '{}' is not supported, expected one of: {}.",
+            format,
+            BENCH_FORMATS.join(", ")
+        )
+    })?;
+    let mut tar = tar::Builder::new(TarEncoder::new(mime_type, 6)?.encoder());
+    for index in 0..entries {
+        fill(&mut data, index as u64);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(entry_size);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        tar.append_data(&mut header, entry_name(index), data.as_slice())?;
+    }
+    tar.into_inner()?.inner()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    pub entries: usize,
+    pub removed: usize,
+    pub content_size: u64,
+    pub input_size: u64,
+    pub output_size: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    /// Uncompressed content scrubbed per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        self.content_size as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn entries_per_second(&self) -> f64 {
+        self.entries as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Generates an archive in `dir`, then measures a scrub removing every
+/// tenth entry with the settings of `ctx`.
+pub fn run_bench(
+    ctx: &Context,
+    format: &str,
+    entries: usize,
+    size: u64,
+    dir: &Path,
+) -> Result<BenchResult> {
+    let input = dir.join(format!("input.{}", format));
+    std::fs::write(&input, generate_archive(format, entries, size)?)?;
+    let input_bytes = std::fs::read(&input)?;
+    let input_size = input_bytes.len() as u64;
+
+    let mut filter_list: Vec<PathBuf> = (0..entries)
+        .step_by(REMOVE_EVERY)
+        .map(|index| PathBuf::from(entry_name(index)))
+        .collect();
+    let removed = filter_list.len();
+    let output = dir.join(format!("output.{}", format));
+    let options = OutputOptions {
+        clobber: Clobber::Overwrite,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    pack_archive_into(
+        ctx,
+        input_bytes,
+        &mut filter_list,
+        FileSink::with_options(ctx.buffer_size, &output, &options)?,
+    )?;
+    let elapsed = start.elapsed();
+
+    Ok(BenchResult {
+        entries,
+        removed: removed - filter_list.len(),
+        content_size: size / entries.max(1) as u64 * entries as u64,
+        input_size,
+        output_size: std::fs::metadata(&output)?.len(),
+        elapsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::reader::ArchiveReader;
+
+    #[test]
+    fn test_generate_archive() {
+        for format in BENCH_FORMATS {
+            let archive = generate_archive(format, 20, 20 * 100).unwrap();
+            let entries = ArchiveReader::new(archive)
+                .entries()
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(entries.len(), 20, "{}", format);
+            assert_eq!(entries[3].size, 100);
+        }
+        assert!(generate_archive("rar", 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_run_bench() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let progress = NoProgress;
+        let ctx = Context::new(&progress, 1);
+        let result = run_bench(&ctx, "tar.gz", 25, 25 * 64, dir.path()).unwrap();
+        assert_eq!(result.removed, 3);
+        assert!(result.output_size > 0);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use sha2::{Digest, Sha256};
+
+/// Rewritten nested archives by key.
+type Entries = HashMap<[u8; 32], Arc<[u8]>>;
+
+/// Rewritten nested archives of a scrub by content and filter list, so
+/// identical copies vendored in several places are only rewritten once.
+/// Clones share the entries, so every nested archive uses the same cache.
+#[derive(Debug, Clone, Default)]
+pub struct InnerCache(Arc<Mutex<Entries>>);
+
+impl InnerCache {
+    /// Key of the archive `content` stored at `path`, rewritten with
+    /// `filter_list`, the entries of the filter list within it, at nesting
+    /// `depth`, which decides what rules with a depth range match.
+    pub fn key(content: &[u8], path: &str, depth: usize, filter_list: &[PathBuf]) -> [u8; 32] {
+        let mut relative: Vec<&Path> = filter_list
+            .iter()
+            .map(|e| e.strip_prefix(path).unwrap_or(e))
+            .collect();
+        relative.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(Sha256::digest(content));
+        hasher.update((depth as u64).to_le_bytes());
+        for e in relative {
+            hasher.update(e.to_string_lossy().as_bytes());
+            hasher.update([0]);
+        }
+        hasher.finalize().into()
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<Arc<[u8]>> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: [u8; 32], result: &[u8]) {
+        self.0.lock().unwrap().insert(key, result.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inner_cache() {
+        let cache = InnerCache::default();
+        let filter_list = [PathBuf::from("a/lib.jar/x"), PathBuf::from("a/lib.jar/y")];
+        let key = InnerCache::key(b"jar", "a/lib.jar", 1, &filter_list);
+        // the same archive elsewhere, with the same entries listed
+        let moved = [PathBuf::from("b/lib.jar/y"), PathBuf::from("b/lib.jar/x")];
+        assert_eq!(InnerCache::key(b"jar", "b/lib.jar", 1, &moved), key);
+        assert_ne!(InnerCache::key(b"jar", "b/lib.jar", 1, &moved[..1]), key);
+        assert_ne!(InnerCache::key(b"war", "a/lib.jar", 1, &filter_list), key);
+        // nested deeper, rules with a depth range may match differently
+        assert_ne!(InnerCache::key(b"jar", "a/lib.jar", 2, &filter_list), key);
+
+        assert!(cache.get(&key).is_none());
+        cache.clone().insert(key, b"rewritten");
+        assert_eq!(&*cache.get(&key).unwrap(), b"rewritten");
+    }
+}
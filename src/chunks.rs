@@ -0,0 +1,120 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::util::sha256_hex;
+
+/// Appended to the output name for the file holding its chunk hashes.
+pub const CHUNK_HASHES_SUFFIX: &str = ".chunks.json";
+
+/// The sha256 of every consecutive block of a file, so that a partial copy
+/// of it can be verified and its download resumed after the last good block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkHashes {
+    pub file: String,
+    pub size: u64,
+    pub chunk_size: u64,
+    pub algorithm: String,
+    pub chunks: Vec<String>,
+}
+
+impl ChunkHashes {
+    /// Hashes `reader` in blocks of `chunk_size` bytes, the last one may be
+    /// shorter.
+    pub fn compute<R: Read>(file: &str, mut reader: R, chunk_size: u64) -> Result<Self> {
+        if chunk_size == 0 {
+            Err(anyhow!(
+                "Invalid Chunk Size: The chunk size cannot be zero."
+            ))?;
+        }
+        let mut chunks = Vec::new();
+        let mut size = 0;
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let mut hasher = Sha256::new();
+            let mut chunk_len = 0;
+            while chunk_len < chunk_size {
+                let len = buffer.len().min((chunk_size - chunk_len) as usize);
+                let read = reader.read(&mut buffer[..len])?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                chunk_len += read as u64;
+            }
+            if chunk_len == 0 {
+                break;
+            }
+            chunks.push(format!("{:x}", hasher.finalize()));
+            size += chunk_len;
+        }
+        Ok(ChunkHashes {
+            file: file.to_string(),
+            size,
+            chunk_size,
+            algorithm: "sha256".to_string(),
+            chunks,
+        })
+    }
+
+    pub fn from_file(path: &Path, chunk_size: u64) -> Result<Self> {
+        let file = File::open(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        ChunkHashes::compute(&name, BufReader::new(file), chunk_size)
+    }
+
+    /// Length of the leading part of `partial` matching the chunk hashes,
+    /// the offset a download can resume from.
+    pub fn verified_len(&self, partial: &[u8]) -> u64 {
+        let mut verified = 0;
+        for hash in &self.chunks {
+            let end = (verified + self.chunk_size).min(self.size);
+            let Some(chunk) = partial.get(verified as usize..end as usize) else {
+                break;
+            };
+            if sha256_hex(chunk) != *hash {
+                break;
+            }
+            verified = end;
+        }
+        verified
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_hashes() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let hashes = ChunkHashes::compute("app.tar.gz", data.as_slice(), 1000).unwrap();
+        assert_eq!(hashes.size, 2500);
+        assert_eq!(hashes.chunks.len(), 3);
+        assert_eq!(hashes.chunks[2], sha256_hex(&data[2000..]));
+
+        assert_eq!(hashes.verified_len(&data), 2500);
+        // a partial block is not verified yet
+        assert_eq!(hashes.verified_len(&data[..1500]), 1000);
+        let mut corrupt = data.clone();
+        corrupt[1200] ^= 1;
+        assert_eq!(hashes.verified_len(&corrupt), 1000);
+
+        let json: ChunkHashes = serde_json::from_str(&hashes.to_json().unwrap()).unwrap();
+        assert_eq!(json, hashes);
+        assert!(ChunkHashes::compute("empty", &b""[..], 0).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::filter::{Decision, EntryFilter};
+use crate::reader::EntryInfo;
+use crate::signature::quarantine;
+
+/// Size of the chunks of an INSTREAM scan, well below the default
+/// StreamMaxLength of clamd.
+const CHUNK_SIZE: usize = 64 << 10;
+/// clamd answers once the whole entry is scanned.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Address of a clamd daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClamdAddress {
+    /// `unix:/run/clamd.sock`
+    Unix(PathBuf),
+    /// `tcp:host:port`
+    Tcp(String),
+}
+
+impl ClamdAddress {
+    pub fn parse(address: &str) -> Result<Self> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Ok(ClamdAddress::Unix(PathBuf::from(path)));
+        }
+        if let Some(host) = address.strip_prefix("tcp:") {
+            return Ok(ClamdAddress::Tcp(host.to_string()));
+        }
+        Err(anyhow!(
+            "Invalid ClamAV Address: '{}', use unix:<socket> or tcp:<host>:<port>.",
+            address
+        ))
+    }
+
+    /// Scans `content` in a new session, returns the name of the signature
+    /// found, if any.
+    fn scan(&self, content: &[u8]) -> Result<Option<String>> {
+        match self {
+            #[cfg(unix)]
+            ClamdAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                instream(stream, content)
+            }
+            #[cfg(not(unix))]
+            ClamdAddress::Unix(_) => Err(anyhow!(
+                "Invalid ClamAV Address: Unix sockets are not supported on this platform, use tcp:<host>:<port>."
+            )),
+            ClamdAddress::Tcp(host) => {
+                let stream = TcpStream::connect(host)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                instream(stream, content)
+            }
+        }
+    }
+}
+
+/// Streams `content` to clamd with the INSTREAM command: chunks prefixed by
+/// their big-endian length, ended by an empty one.
+fn instream<S: Read + Write>(mut stream: S, content: &[u8]) -> Result<Option<String>> {
+    stream.write_all(b"zINSTREAM\0")?;
+    for chunk in content.chunks(CHUNK_SIZE) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes())?;
+        stream.write_all(chunk)?;
+    }
+    stream.write_all(&0u32.to_be_bytes())?;
+    stream.flush()?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    parse_reply(&String::from_utf8_lossy(&reply))
+}
+
+/// Parses `stream: OK` or `stream: <signature> FOUND`.
+fn parse_reply(reply: &str) -> Result<Option<String>> {
+    let reply = reply.trim_end_matches(['\0', '\n']);
+    let verdict = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if verdict == "OK" {
+        return Ok(None);
+    }
+    match verdict.strip_suffix(" FOUND") {
+        Some(signature) => Ok(Some(signature.to_string())),
+        None => Err(anyhow!("{}", verdict)),
+    }
+}
+
+/// An entry found infected by clamd.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Detection {
+    pub path: PathBuf,
+    /// Name of the signature found.
+    pub signature: String,
+    /// Where the content was quarantined, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantined: Option<PathBuf>,
+}
+
+/// An [`EntryFilter`] streaming each file to clamd and removing the infected
+/// ones, kept in the quarantine directory if there is one.
+///
+/// Clones share the recorded detections.
+#[derive(Clone)]
+pub struct ClamavFilter {
+    address: ClamdAddress,
+    quarantine: Option<PathBuf>,
+    detections: Arc<Mutex<Vec<Detection>>>,
+}
+
+impl ClamavFilter {
+    pub fn new(address: ClamdAddress, quarantine: Option<PathBuf>) -> Self {
+        ClamavFilter {
+            address,
+            quarantine,
+            detections: Arc::default(),
+        }
+    }
+
+    /// The infected entries, by path.
+    pub fn detections(&self) -> Vec<Detection> {
+        let mut detections = self.detections.lock().unwrap().clone();
+        // nested archives may be rewritten in parallel
+        detections.sort_by(|a, b| a.path.cmp(&b.path));
+        detections
+    }
+
+    fn scan(&self, path: &Path, content: &[u8]) -> Result<Option<String>> {
+        self.address.scan(content).map_err(|err| {
+            anyhow!(
+                "ClamAV Scan Failed: '{}' could not be scanned by clamd ({}).",
+                path.display(),
+                err
+            )
+        })
+    }
+}
+
+impl EntryFilter for ClamavFilter {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let Some(content) = content else {
+            return Ok(Decision::Keep);
+        };
+        let Some(signature) = self.scan(&entry.path, content)? else {
+            return Ok(Decision::Keep);
+        };
+        let quarantined = self
+            .quarantine
+            .as_deref()
+            .map(|dir| quarantine(dir, &entry.path, content))
+            .transpose()?;
+        self.detections.lock().unwrap().push(Detection {
+            path: entry.path.clone(),
+            signature,
+            quarantined,
+        });
+        Ok(Decision::Remove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamd_address() {
+        assert_eq!(
+            ClamdAddress::parse("unix:/run/clamd.sock").unwrap(),
+            ClamdAddress::Unix(PathBuf::from("/run/clamd.sock"))
+        );
+        assert_eq!(
+            ClamdAddress::parse("tcp:localhost:3310").unwrap(),
+            ClamdAddress::Tcp("localhost:3310".to_string())
+        );
+        assert!(ClamdAddress::parse("/run/clamd.sock").is_err());
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("stream: OK\0").unwrap(), None);
+        assert_eq!(
+            parse_reply("stream: Eicar-Test-Signature FOUND\0").unwrap(),
+            Some("Eicar-Test-Signature".to_string())
+        );
+        let err = parse_reply("INSTREAM size limit exceeded. ERROR\0").unwrap_err();
+        assert_eq!(err.to_string(), "INSTREAM size limit exceeded. ERROR");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clamav_filter() {
+        use crate::reader::EntryKind;
+        use std::os::unix::net::UnixListener;
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        let socket = dir.path().join("clamd.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        // a clamd flagging content holding "EICAR"
+        let clamd = std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut command = [0; 10];
+                stream.read_exact(&mut command).unwrap();
+                assert_eq!(&command, b"zINSTREAM\0");
+                let mut content = Vec::new();
+                loop {
+                    let mut len = [0; 4];
+                    stream.read_exact(&mut len).unwrap();
+                    let mut chunk = vec![0; u32::from_be_bytes(len) as usize];
+                    if chunk.is_empty() {
+                        break;
+                    }
+                    stream.read_exact(&mut chunk).unwrap();
+                    content.extend(chunk);
+                }
+                let infected = content.windows(5).any(|window| window == b"EICAR");
+                let reply: &[u8] = if infected {
+                    b"stream: Eicar-Test-Signature FOUND\0"
+                } else {
+                    b"stream: OK\0"
+                };
+                stream.write_all(reply).unwrap();
+            }
+        });
+
+        let quarantine = dir.path().join("quarantine");
+        let filter = ClamavFilter::new(ClamdAddress::Unix(socket), Some(quarantine.clone()));
+        let entry = |path: &str| EntryInfo {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: None,
+            kind: EntryKind::File,
+            depth: 0,
+            mime_type: None,
+        };
+        assert_eq!(
+            filter
+                .decide(&entry("bin/eicar.com"), Some(b"X5O!P%@AP EICAR".as_slice()))
+                .unwrap(),
+            Decision::Remove
+        );
+        assert_eq!(
+            filter
+                .decide(&entry("README"), Some(b"hello\n".as_slice()))
+                .unwrap(),
+            Decision::Keep
+        );
+        clamd.join().unwrap();
+
+        assert_eq!(
+            filter.detections(),
+            [Detection {
+                path: PathBuf::from("bin/eicar.com"),
+                signature: "Eicar-Test-Signature".to_string(),
+                quarantined: Some(quarantine.join("bin/eicar.com")),
+            }]
+        );
+        assert!(quarantine.join("bin/eicar.com").is_file());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+use crate::filter::{Decision, EntryFilter};
+use crate::reader::EntryInfo;
+
+/// Files are classified by their first bytes only.
+const SAMPLE_SIZE: usize = 8 << 10;
+
+/// Kind of content of a regular file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentClass {
+    Text,
+    /// Binary content, the name of the executable format if recognized.
+    Binary(&'static str),
+}
+
+impl ContentClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentClass::Text => "text",
+            ContentClass::Binary(format) => format,
+        }
+    }
+}
+
+fn executable_format(content: &[u8]) -> Option<&'static str> {
+    match content.get(..4)? {
+        b"\x7fELF" => Some("ELF"),
+        [0xfe, 0xed, 0xfa, 0xce | 0xcf] | [0xce | 0xcf, 0xfa, 0xed, 0xfe] => Some("Mach-O"),
+        [b'M', b'Z', ..] => {
+            // the PE header offset is stored at 0x3c
+            let offset = u32::from_le_bytes(content.get(0x3c..0x40)?.try_into().ok()?) as usize;
+            (content.get(offset..offset.checked_add(4)?)? == b"PE\0\0").then_some("PE")
+        }
+        _ => None,
+    }
+}
+
+/// Classifies content as an executable by its magic, as text if it is valid
+/// UTF-8 without NUL bytes, else as binary data.
+pub fn classify(content: &[u8]) -> ContentClass {
+    if let Some(format) = executable_format(content) {
+        return ContentClass::Binary(format);
+    }
+    let sample = &content[..content.len().min(SAMPLE_SIZE)];
+    let utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        // a character cut off by the sample size
+        Err(err) => err.error_len().is_none() && sample.len() < content.len(),
+    };
+    if utf8 && !sample.contains(&0) {
+        ContentClass::Text
+    } else {
+        ContentClass::Binary("binary")
+    }
+}
+
+/// An [`EntryFilter`] removing binary or text files.
+///
+/// Clones share the recorded removals.
+#[derive(Clone)]
+pub struct ClassFilter {
+    exclude_binaries: bool,
+    exclude_text: bool,
+    removed: Arc<Mutex<Vec<(PathBuf, ContentClass)>>>,
+}
+
+impl ClassFilter {
+    pub fn new(exclude_binaries: bool, exclude_text: bool) -> Self {
+        ClassFilter {
+            exclude_binaries,
+            exclude_text,
+            removed: Arc::default(),
+        }
+    }
+
+    /// The removed entries and their class.
+    pub fn removed(&self) -> Vec<(PathBuf, ContentClass)> {
+        self.removed.lock().unwrap().clone()
+    }
+}
+
+impl EntryFilter for ClassFilter {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let Some(content) = content else {
+            return Ok(Decision::Keep);
+        };
+        let class = classify(content);
+        let excluded = match class {
+            ContentClass::Text => self.exclude_text,
+            ContentClass::Binary(_) => self.exclude_binaries,
+        };
+        if !excluded {
+            return Ok(Decision::Keep);
+        }
+        self.removed
+            .lock()
+            .unwrap()
+            .push((entry.path.clone(), class));
+        Ok(Decision::Remove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let mut pe = vec![0u8; 0x84];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x80;
+        pe[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(
+            classify(b"\x7fELF\x02\x01\x01"),
+            ContentClass::Binary("ELF")
+        );
+        assert_eq!(classify(&pe), ContentClass::Binary("PE"));
+        assert_eq!(
+            classify(&[0xcf, 0xfa, 0xed, 0xfe, 7, 0, 0, 1]),
+            ContentClass::Binary("Mach-O")
+        );
+        assert_eq!(
+            classify("fn main() {} // é\n".as_bytes()),
+            ContentClass::Text
+        );
+        assert_eq!(classify(b""), ContentClass::Text);
+        assert_eq!(classify(b"MZ but text"), ContentClass::Text);
+        assert_eq!(classify(b"text\0"), ContentClass::Binary("binary"));
+        assert_eq!(
+            classify(&[0x89, b'P', b'N', b'G']),
+            ContentClass::Binary("binary")
+        );
+
+        let mut long_text = vec![b'a'; SAMPLE_SIZE - 1];
+        long_text.extend_from_slice("é".as_bytes());
+        assert_eq!(classify(&long_text), ContentClass::Text);
+    }
+}
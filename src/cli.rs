@@ -1,41 +1,857 @@
-use clap::{ArgAction, Parser};
+use std::{ffi::OsString, io::IsTerminal, path::Path};
+
+use anyhow::Result;
+use clap::{ArgAction, Args as ClapArgs, CommandFactory, Parser, Subcommand, ValueEnum};
+use expurgator::internal::{
+    ConflictPolicy, BENCH_FORMATS, DEFAULT_OUTPUT_DIR, DUPLICATES_POLICIES, ENCRYPTED_POLICIES,
+    ENTRY_ORDERS, ON_ERROR_POLICIES, PROVENANCE_MODES, SYMLINK_POLICIES, TAR_FORMATS,
+};
+
+use crate::config::load_profile;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "expurgator", long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file with named profiles [default: ./expurgator.toml, then
+    /// $XDG_CONFIG_HOME/expurgator/config.toml]
+    #[arg(long, value_name = "PATH", env = "EXPURGATOR_CONFIG")]
+    pub config: Option<String>,
+
+    /// Apply the settings of a profile from the config file, command line
+    /// arguments and environment variables take precedence
+    #[arg(long, value_name = "NAME", env = "EXPURGATOR_PROFILE")]
+    pub profile: Option<String>,
+
+    /// When to use colors, `auto` also honors NO_COLOR
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto, env = "EXPURGATOR_COLOR")]
+    pub color: ColorChoice,
+
+    /// Language of the prompts, e.g. `de` or `pt_BR` [default: from
+    /// LC_ALL, LC_MESSAGES or LANG, else English]
+    #[arg(long, global = true, value_name = "LANG", env = "EXPURGATOR_LANG")]
+    pub lang: Option<String>,
+
+    /// Input archive file, `-` reads the archive from stdin, a directory is
+    /// packed into the --output archive, an http(s):// or s3:// URL is
+    /// downloaded with curl or the aws CLI
+    #[arg(long, short, required = true, env = "EXPURGATOR_INPUT")]
+    pub input: Option<String>,
+
+    /// CSV file containing the list of files to be removed, or the JSON
+    /// report of a previous run with --filter-format report
+    #[arg(
+        long,
+        required_unless_present_any = [
+            "plan",
+            "policy",
+            "manifest",
+            "exclude_licenses",
+            "exclude_binaries",
+            "exclude_text",
+            "allow_ext",
+            "deny_ext",
+            "yara",
+            "clamav",
+            "compare_structure"
+        ],
+        conflicts_with = "plan",
+        env = "EXPURGATOR_CSV"
+    )]
+    pub csv: Option<String>,
+
+    /// Index of the field in CSV containing the list of files to be removed
+    #[arg(
+        long,
+        required_unless_present_any = [
+            "filter_format",
+            "plan",
+            "policy",
+            "manifest",
+            "exclude_licenses",
+            "exclude_binaries",
+            "exclude_text",
+            "allow_ext",
+            "deny_ext",
+            "yara",
+            "clamav",
+            "compare_structure"
+        ],
+        env = "EXPURGATOR_INDEX"
+    )]
+    pub index: Option<usize>,
+
+    /// Format of the filter file: csv, or report to remove the entries a
+    /// previous run removed according to its --output-json report
+    /// [default: csv]
+    #[arg(
+        long,
+        value_parser = ["csv", "report"],
+        env = "EXPURGATOR_FILTER_FORMAT"
+    )]
+    pub filter_format: Option<String>,
+
+    /// Specify this flag if the CSV contains a header record [default: false]
+    #[arg(long, action=ArgAction::SetFalse)]
+    pub with_headers: bool,
+
+    /// Remove exactly the entries of a reviewed plan file written by `plan`,
+    /// instead of resolving a filter list
+    #[arg(long, value_name = "FILE")]
+    pub plan: Option<String>,
+
+    /// Refuse the plan unless it carries a valid minisign signature by this
+    /// public key
+    #[arg(
+        long,
+        value_name = "KEY",
+        requires = "plan",
+        env = "EXPURGATOR_REQUIRE_SIGNED_BY"
+    )]
+    pub require_signed_by: Option<String>,
+
+    /// Detached signature of the plan [default: <PLAN>.minisig]
+    #[arg(long, value_name = "FILE", requires = "require_signed_by")]
+    pub plan_signature: Option<String>,
+
+    /// Remove the entries matching the `remove` rules of a YAML policy, as
+    /// evaluated by `check`, and report the other matches
+    #[arg(long, value_name = "FILE", env = "EXPURGATOR_POLICY")]
+    pub policy: Option<String>,
+
+    /// Remove every top level file missing from this sha256sum manifest or
+    /// whose content does not match its hash
+    #[arg(long, value_name = "FILE", env = "EXPURGATOR_MANIFEST")]
+    pub manifest: Option<String>,
+
+    /// Output file, `-` writes the archive to stdout, an http(s):// or s3://
+    /// URL is written to --output-dir and uploaded [default: --input]
+    #[arg(long, short, env = "EXPURGATOR_OUTPUT")]
+    pub output: Option<String>,
+
+    /// Directory the output files are written to
+    #[arg(long, value_name = "DIR", default_value = DEFAULT_OUTPUT_DIR, env = "EXPURGATOR_OUTPUT_DIR")]
+    pub output_dir: String,
+
+    /// Rewrite the input file where it is, overwriting it. The file is locked
+    /// for the run through a `<input>.lock` file next to it, another
+    /// expurgator process rewriting it fails
+    #[arg(long, conflicts_with_all = ["output", "output_dir", "extract", "split_size"], env = "EXPURGATOR_IN_PLACE")]
+    pub in_place: bool,
+
+    /// Extract the kept entries into this directory instead of writing an
+    /// archive
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "split_size", "initramfs"], env = "EXPURGATOR_EXTRACT")]
+    pub extract: Option<String>,
+
+    /// Encrypt the output: the entries of zip outputs with AES-256, other
+    /// outputs as a whole with age or GPG
+    #[arg(long, conflicts_with_all = ["extract", "compare_structure"], env = "EXPURGATOR_ENCRYPT_OUTPUT")]
+    pub encrypt_output: bool,
+
+    /// File holding the password of encrypted zip inputs and outputs, else
+    /// it is read from EXPURGATOR_PASSWORD or prompted for
+    #[arg(long, value_name = "FILE", env = "EXPURGATOR_PASSWORD_FILE")]
+    pub password_file: Option<String>,
+
+    /// Fetch the password from the OS keyring (keyring://SERVICE/ACCOUNT) or
+    /// a KMS encrypted data key (kms://KEY_ID?ciphertext=FILE) instead
+    #[arg(
+        long,
+        value_name = "URI",
+        conflicts_with = "password_file",
+        env = "EXPURGATOR_KEY"
+    )]
+    pub key: Option<String>,
+
+    /// What to do with the encrypted entries of a zip input: decrypt them with
+    /// the password, keep them as they are without filtering
+    /// (skip-encrypted), or fail
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_parser = ENCRYPTED_POLICIES,
+        default_value = "decrypt",
+        env = "EXPURGATOR_ENCRYPTED"
+    )]
+    pub encrypted: String,
+
+    /// Which of the zip entries sharing a path to write: the first, the last
+    /// (which extractors end up with), all of them, or fail with error
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_parser = DUPLICATES_POLICIES,
+        default_value = "keep-last",
+        env = "EXPURGATOR_DUPLICATES"
+    )]
+    pub duplicates: String,
+
+    /// What to do with symlinks: keep them, drop them, replace them by the
+    /// file they point to (dereference), or fail on one pointing outside the
+    /// archive (reject-external)
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_parser = SYMLINK_POLICIES,
+        default_value = "keep",
+        env = "EXPURGATOR_SYMLINKS"
+    )]
+    pub symlinks: String,
+
+    /// Remove only the first entry each filter list rule matches, the
+    /// default
+    #[arg(long, conflicts_with = "match_all", env = "EXPURGATOR_MATCH_FIRST")]
+    pub match_first: bool,
+
+    /// Remove every entry each filter list rule matches, in every directory
+    /// and nested archive, and report each one
+    #[arg(long, env = "EXPURGATOR_MATCH_ALL")]
+    pub match_all: bool,
+
+    /// Order to write the entries of the output archive in: by path, by size
+    /// (smallest first), or none to keep the input's. Sorted archives
+    /// compress better and diff smaller between releases
+    /// [default: none, path with --rsyncable]
+    #[arg(
+        long,
+        value_name = "ORDER",
+        value_parser = ENTRY_ORDERS,
+        env = "EXPURGATOR_SORT_ENTRIES"
+    )]
+    pub sort_entries: Option<String>,
+
+    /// Write the output so that consecutive scrubs diff small with rsync,
+    /// zsync or casync: gzip is reset at content defined boundaries, entries
+    /// are sorted by path and their modification times clamped to
+    /// SOURCE_DATE_EPOCH, if set
+    #[arg(long, env = "EXPURGATOR_RSYNCABLE")]
+    pub rsyncable: bool,
+
+    /// Set the modification time of entries modified later to this one, as
+    /// seconds since the epoch or a YYYY-MM-DD[THH:MM:SS] UTC date
+    #[arg(long, value_name = "TIMESTAMP", env = "EXPURGATOR_CLAMP_MTIME")]
+    pub clamp_mtime: Option<String>,
+
+    /// Recipient of encrypted non-zip outputs: age or SSH public keys are
+    /// encrypted to with `age`, anything else is a GPG key looked up by `gpg`
+    #[arg(
+        long,
+        value_name = "RECIPIENT",
+        requires = "encrypt_output",
+        value_delimiter = ',',
+        env = "EXPURGATOR_ENCRYPT_RECIPIENTS"
+    )]
+    pub encrypt_recipient: Vec<String>,
+
+    /// Compression level, or auto to pick the lowest one within 2% of the
+    /// best ratio on a sample of the input while compressing at 100 MB/s
+    #[arg(long, default_value = "6", env = "EXPURGATOR_COMPRESSION")]
+    pub compression: String,
+
+    /// Compress xz outputs with the slower extreme variant of the level
+    #[arg(long, env = "EXPURGATOR_XZ_EXTREME")]
+    pub xz_extreme: bool,
+
+    /// Filter chain of xz outputs, e.g. delta:dist=4,lzma2:dict=64M or
+    /// x86,lzma2:preset=9e, ending with lzma2
+    #[arg(long, value_name = "CHAIN", env = "EXPURGATOR_XZ_FILTERS")]
+    pub xz_filters: Option<String>,
+
+    /// Dictionary of zstd outputs: train:<FILE> trains one on the files of
+    /// the input and saves it, use:<FILE> reads one
+    #[arg(long, value_name = "MODE:FILE", env = "EXPURGATOR_ZSTD_DICT")]
+    pub zstd_dict: Option<String>,
+
+    /// Compress zstd outputs with long distance matching over a window of
+    /// 2^N bytes, like zstd --long [default: 27]
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(10..=31),
+        num_args = 0..=1,
+        default_missing_value = "27",
+        env = "EXPURGATOR_ZSTD_LONG"
+    )]
+    pub zstd_long: Option<u32>,
+
+    /// Split the output into parts of at most this size (e.g. 2GB)
+    #[arg(long, env = "EXPURGATOR_SPLIT_SIZE")]
+    pub split_size: Option<String>,
+
+    /// Also write the sha256 of every block of this size (e.g. 64M) of the
+    /// output to <OUTPUT>.chunks.json, to verify and resume partial downloads
+    #[arg(
+        long,
+        value_name = "SIZE",
+        conflicts_with = "split_size",
+        env = "EXPURGATOR_CHUNK_HASHES"
+    )]
+    pub chunk_hashes: Option<String>,
+
+    /// Size of the read and write buffers (e.g. 64K)
+    #[arg(long, default_value = "1M", env = "EXPURGATOR_BUFFER_SIZE")]
+    pub buffer_size: String,
+
+    /// How far ahead of the entry being filtered tar data is decompressed,
+    /// and behind it compressed (e.g. 16M)
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "4M",
+        env = "EXPURGATOR_PREFETCH"
+    )]
+    pub prefetch: String,
+
+    /// Limit reading the input and writing the outputs to this rate (e.g.
+    /// 50MB/s)
+    #[arg(long, value_name = "RATE", env = "EXPURGATOR_BWLIMIT")]
+    pub bwlimit: Option<String>,
+
+    /// Retry failed downloads of remote inputs and uploads of remote outputs
+    /// this many times, downloads resume where they stopped
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 3,
+        env = "EXPURGATOR_RETRIES"
+    )]
+    pub retries: u32,
+
+    /// Wait before the first retry (e.g. 2s), doubled before each further one
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1s",
+        env = "EXPURGATOR_RETRY_DELAY"
+    )]
+    pub retry_delay: String,
+
+    /// Cap on the memory buffering archives (e.g. 2G), nested archives are
+    /// spooled to temporary files beyond it
+    #[arg(long, value_name = "SIZE", env = "EXPURGATOR_MAX_MEMORY")]
+    pub max_memory: Option<String>,
+
+    /// Directory the temporary workspace of the run is created in, workspaces
+    /// left behind by killed runs are removed from it [default: the system's
+    /// temporary directory]
+    #[arg(long, value_name = "DIR", env = "EXPURGATOR_TEMP_DIR")]
+    pub temp_dir: Option<String>,
+
+    /// Cap on the disk space of the temporary workspace (e.g. 20G)
+    #[arg(long, value_name = "SIZE", env = "EXPURGATOR_TEMP_MAX_SIZE")]
+    pub temp_max_size: Option<String>,
+
+    /// Number of nested archives of a zip rewritten in parallel
+    /// [default: the number of CPUs]
+    #[arg(long, short, value_name = "N", env = "EXPURGATOR_JOBS")]
+    pub jobs: Option<usize>,
+
+    /// Abort the scrub if it takes longer than this (e.g. 30m)
+    #[arg(long, value_name = "DURATION", env = "EXPURGATOR_TIMEOUT")]
+    pub timeout: Option<String>,
+
+    /// Give up reading a single entry after this long (e.g. 90s), the entry
+    /// is then handled by --on-error
+    #[arg(long, value_name = "DURATION", env = "EXPURGATOR_ENTRY_TIMEOUT")]
+    pub entry_timeout: Option<String>,
+
+    /// What happens to entries that fail to process: prompt, skip or abort
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_parser = ON_ERROR_POLICIES,
+        default_value = "prompt",
+        env = "EXPURGATOR_ON_ERROR"
+    )]
+    pub on_error: String,
+
+    /// Store an index of the input entries at this path and reuse it on later
+    /// runs against the same input to report filter matches up front
+    #[arg(long, value_name = "PATH", env = "EXPURGATOR_INDEX_CACHE")]
+    pub index_cache: Option<String>,
+
+    /// Report what would be removed and the estimated output size without
+    /// writing anything
+    #[arg(long, env = "EXPURGATOR_DRY_RUN")]
+    pub dry_run: bool,
+
+    /// Show what --dry-run would remove as a tree of the archive, removed
+    /// entries struck through in red, directories and nested archives
+    /// without removals collapsed to their entry counts
+    #[arg(
+        long,
+        requires = "dry_run",
+        conflicts_with = "preview",
+        env = "EXPURGATOR_TREE"
+    )]
+    pub tree: bool,
+
+    /// Show the first N bytes of the removed entries with --dry-run, and of
+    /// the first listed entry when asking for confirmation, as text or as a
+    /// hexdump
+    #[arg(long, value_name = "N", env = "EXPURGATOR_PREVIEW")]
+    pub preview: Option<usize>,
+
+    /// Rewrite the input without removing anything and report every archive
+    /// or entry field the rewrite did not preserve, without writing anything
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "extract", "dry_run", "output_json", "split_size"],
+        env = "EXPURGATOR_COMPARE_STRUCTURE"
+    )]
+    pub compare_structure: bool,
+
+    /// Skip the confirmation prompt
+    #[arg(long, visible_alias = "yes", env = "EXPURGATOR_NON_INTERACTIVE")]
+    pub non_interactive: bool,
+
+    /// Skip the confirmation prompt and print only a JSON summary (status,
+    /// outputs with checksums, counts) on stdout
+    #[arg(long, conflicts_with = "dry_run", env = "EXPURGATOR_OUTPUT_JSON")]
+    pub output_json: bool,
+
+    /// After the run, open its JSON report in the default application of the
+    /// desktop, or else in $PAGER when stdout is a terminal
+    #[arg(long, conflicts_with_all = ["dry_run", "compare_structure"], env = "EXPURGATOR_OPEN_REPORT")]
+    pub open_report: bool,
+
+    /// Permissions of the output files in octal (e.g. 0640), the umask still applies
+    #[arg(long, value_name = "MODE", env = "EXPURGATOR_OUTPUT_MODE")]
+    pub output_mode: Option<String>,
+
+    /// Refuse to create world-writable output files
+    #[arg(long, env = "EXPURGATOR_HARDENED")]
+    pub hardened: bool,
+
+    /// Overwrite existing output files
+    #[arg(long, conflicts_with = "backup", env = "EXPURGATOR_FORCE")]
+    pub force: bool,
+
+    /// Move existing output files aside to <name>.bak before replacing them
+    #[arg(long, env = "EXPURGATOR_BACKUP")]
+    pub backup: bool,
+
+    /// Copy the modification time of the input onto the output
+    /// [default: with --in-place]
+    #[arg(long, conflicts_with = "touch", env = "EXPURGATOR_PRESERVE_MTIME")]
+    pub preserve_mtime: bool,
+
+    /// Set the modification time of the output, as seconds since the epoch or
+    /// a YYYY-MM-DD[THH:MM:SS] UTC date
+    #[arg(long, value_name = "TIMESTAMP", env = "EXPURGATOR_TOUCH")]
+    pub touch: Option<String>,
+
+    /// Treat the input as a Linux initramfs image (concatenated cpio segments)
+    #[arg(long, env = "EXPURGATOR_INITRAMFS")]
+    pub initramfs: bool,
+
+    /// Load an entry filter plugin (shared library), may be repeated
+    #[arg(
+        long = "plugin",
+        value_name = "PATH",
+        env = "EXPURGATOR_PLUGINS",
+        value_delimiter = ','
+    )]
+    pub plugins: Vec<String>,
+
+    /// Apply a built-in transform to kept entries matching a glob (or mime:<type>),
+    /// e.g. minify-json=*.json, may be repeated
+    #[arg(
+        long = "transform",
+        value_name = "NAME=GLOB",
+        env = "EXPURGATOR_TRANSFORMS"
+    )]
+    pub transforms: Vec<String>,
+
+    /// Remove text files whose license header is this SPDX id or a variant of
+    /// it (e.g. AGPL-3.0), or `proprietary`, may be repeated
+    #[arg(
+        long = "exclude-license",
+        value_name = "LICENSE",
+        env = "EXPURGATOR_EXCLUDE_LICENSES",
+        value_delimiter = ','
+    )]
+    pub exclude_licenses: Vec<String>,
+
+    /// Remove binary files: executables (ELF, PE, Mach-O) and any content
+    /// that is not UTF-8 text
+    #[arg(long, env = "EXPURGATOR_EXCLUDE_BINARIES")]
+    pub exclude_binaries: bool,
+
+    /// Remove text files, i.e. valid UTF-8 without NUL bytes
+    #[arg(long, env = "EXPURGATOR_EXCLUDE_TEXT")]
+    pub exclude_text: bool,
+
+    /// Keep only files with one of these extensions, e.g. rs,toml
+    #[arg(
+        long,
+        value_name = "EXT",
+        env = "EXPURGATOR_ALLOW_EXT",
+        value_delimiter = ','
+    )]
+    pub allow_ext: Vec<String>,
+
+    /// Remove files with one of these extensions, e.g. pdb,map,log
+    #[arg(
+        long,
+        value_name = "EXT",
+        env = "EXPURGATOR_DENY_EXT",
+        value_delimiter = ','
+    )]
+    pub deny_ext: Vec<String>,
+
+    /// Remove files whose content matches a rule of this YARA rule file,
+    /// may be repeated
+    #[arg(
+        long,
+        value_name = "RULES",
+        env = "EXPURGATOR_YARA",
+        value_delimiter = ','
+    )]
+    pub yara: Vec<String>,
+
+    /// Keep a copy of the files removed by --yara in this directory
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "yara",
+        env = "EXPURGATOR_YARA_QUARANTINE"
+    )]
+    pub yara_quarantine: Option<String>,
+
+    /// Stream each file to clamd at this address, unix:<socket> or
+    /// tcp:<host>:<port>, and remove the infected ones
+    #[arg(long, value_name = "ADDRESS", env = "EXPURGATOR_CLAMAV")]
+    pub clamav: Option<String>,
+
+    /// Keep a copy of the files removed by --clamav in this directory
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "clamav",
+        env = "EXPURGATOR_CLAMAV_QUARANTINE"
+    )]
+    pub clamav_quarantine: Option<String>,
+
+    /// Report kept entries with unusually high entropy for their extension,
+    /// e.g. a .txt holding encrypted data, as candidates for human review
+    #[arg(long, env = "EXPURGATOR_ENTROPY_REPORT")]
+    pub entropy_report: bool,
+
+    /// Record tool version, filter file hash, time and rule counts in the
+    /// output, as a .expurgator.json entry or as the zip comment
+    #[arg(
+        long,
+        value_name = "WHERE",
+        value_parser = PROVENANCE_MODES,
+        num_args = 0..=1,
+        default_missing_value = "entry",
+        env = "EXPURGATOR_PROVENANCE"
+    )]
+    pub provenance: Option<String>,
+
+    /// Set or replace the comment of the output zip, which is otherwise
+    /// preserved
+    #[arg(long, value_name = "TEXT", env = "EXPURGATOR_ARCHIVE_COMMENT")]
+    pub archive_comment: Option<String>,
+
+    /// Pad stored zip entries so their data starts at a multiple of N bytes,
+    /// like zipalign (4 for APKs, 4096 for memory-mapped assets)
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        env = "EXPURGATOR_ZIP_ALIGN"
+    )]
+    pub zip_align: Option<u16>,
+
+    /// Header format of the output tar: pax, gnu or ustar [default: as in the
+    /// input]. gnu and ustar drop extended attributes
+    #[arg(long, value_parser = TAR_FORMATS, env = "EXPURGATOR_TAR_FORMAT")]
+    pub tar_format: Option<String>,
+
+    /// Rewrite the owner of kept tar entries, e.g. builder:0 or
+    /// builder:root:0, matching a user name or uid, may be repeated
+    #[arg(
+        long = "map-owner",
+        value_name = "FROM:TO",
+        env = "EXPURGATOR_MAP_OWNER",
+        value_delimiter = ','
+    )]
+    pub map_owner: Vec<String>,
+
+    /// Rewrite the group of kept tar entries, like --map-owner
+    #[arg(
+        long = "map-group",
+        value_name = "FROM:TO",
+        env = "EXPURGATOR_MAP_GROUP",
+        value_delimiter = ','
+    )]
+    pub map_group: Vec<String>,
+
+    /// Rename kept entries with reserved names (CON, NUL, ...), invalid
+    /// characters or overly long paths so they extract on Windows
+    #[arg(long, env = "EXPURGATOR_WINDOWS_SAFE")]
+    pub windows_safe: bool,
+
+    /// Convert the line endings of text entries to lf or crlf, optionally only
+    /// of those matching a glob (e.g. crlf=*.bat), may be repeated
+    #[arg(
+        long,
+        value_name = "EOL[=GLOB]",
+        env = "EXPURGATOR_NORMALIZE_EOL",
+        value_delimiter = ','
+    )]
+    pub normalize_eol: Vec<String>,
+
+    /// Losslessly recompress PNG entries, same as
+    /// --transform optimize-images=**
+    #[arg(long, env = "EXPURGATOR_OPTIMIZE_IMAGES")]
+    pub optimize_images: bool,
+
+    /// Drop the comments, XMP and Photoshop metadata of JPEG entries, same as
+    /// --transform strip-image-metadata=**
+    #[arg(long, env = "EXPURGATOR_STRIP_IMAGE_METADATA")]
+    pub strip_image_metadata: bool,
+
+    /// Drop the extended attributes (SELinux labels, capabilities) and ACLs
+    /// of tar entries instead of preserving them
+    #[arg(long, env = "EXPURGATOR_STRIP_XATTRS")]
+    pub strip_xattrs: bool,
+
+    /// Write the directory listings of GNU incremental archives
+    /// (`tar --listed-incremental`) as plain directories
+    #[arg(long, env = "EXPURGATOR_DROP_INCREMENTAL_METADATA")]
+    pub drop_incremental_metadata: bool,
+
+    /// Treat the input as hostile: enforce entry count, size, compression
+    /// ratio and nesting limits and fail on any malformed entry
+    #[arg(long, env = "EXPURGATOR_UNTRUSTED")]
+    pub untrusted: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Merge several archives of the same kind into one
+    Merge(MergeArgs),
+    /// List the entries of an archive, including nested archives
+    List(ListArgs),
+    /// Summarize files by extension, MIME type, age and top-level directory
+    Stats(StatsArgs),
+    /// Print the content of an entry, entries of nested archives are named
+    /// like lib/app.jar!META-INF/MANIFEST.MF
+    Cat(CatArgs),
+    /// Resolve a filter list against an archive and write the exact entries
+    /// to remove as a plan for review, applied with --plan
+    Plan(PlanArgs),
+    /// Evaluate a policy against an archive without writing anything, for
+    /// CI gates; violations are printed as GitHub Actions annotations
+    Check(CheckArgs),
+    /// Print the man page in roff format
+    Man,
+    /// Measure scrub throughput on a generated archive
+    Bench(BenchArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// `auto` colors when stderr is a terminal and `NO_COLOR` is not set.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::io::stderr().is_terminal()
+                    && !matches!(std::env::var_os("NO_COLOR"), Some(value) if !value.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct BenchArgs {
+    /// Number of generated entries (e.g. 100k)
+    #[arg(long, default_value = "10k")]
+    pub entries: String,
+
+    /// Total uncompressed size of the entries (e.g. 1G)
+    #[arg(long, default_value = "100M")]
+    pub size: String,
+
+    /// Archive format
+    #[arg(long, default_value = "tar.gz", value_parser = BENCH_FORMATS)]
+    pub format: String,
+
+    /// Compression level
+    #[arg(long, default_value_t = 6, env = "EXPURGATOR_COMPRESSION")]
+    pub compression: u32,
+
+    /// Size of the read and write buffers (e.g. 64K)
+    #[arg(long, default_value = "1M", env = "EXPURGATOR_BUFFER_SIZE")]
+    pub buffer_size: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct PlanArgs {
     /// Input archive file
-    #[arg(long, short)]
     pub input: String,
 
-    /// CSV file containing the list of files to be removed
+    /// CSV file containing the list of files to be removed, or the JSON
+    /// report of a previous run with --filter-format report
     #[arg(long)]
     pub csv: String,
 
     /// Index of the field in CSV containing the list of files to be removed
-    #[arg(long)]
-    pub index: usize,
+    #[arg(long, required_unless_present = "filter_format")]
+    pub index: Option<usize>,
+
+    /// Format of the filter file: csv or report [default: csv]
+    #[arg(long, value_parser = ["csv", "report"])]
+    pub filter_format: Option<String>,
 
     /// Specify this flag if the CSV contains a header record [default: false]
     #[arg(long, action=ArgAction::SetFalse)]
     pub with_headers: bool,
 
-    /// Output file [default: --input]
+    /// Plan file [default: stdout]
     #[arg(long, short)]
     pub output: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CheckArgs {
+    /// Input archive file
+    pub input: String,
+
+    /// YAML policy with forbidden-paths, forbidden-content, max-entry-size,
+    /// max-total-size and rules
+    #[arg(long, value_name = "FILE")]
+    pub policy: String,
+
+    /// Accepted findings, one `<rule> <path> <expires>` per line
+    /// [default: .expurgatorignore if present]
+    #[arg(long, value_name = "FILE")]
+    pub ignore_file: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct StatsArgs {
+    /// Input archive file
+    pub input: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CatArgs {
+    /// Input archive file
+    pub input: String,
+
+    /// Path of the entry, with a ! after each nested archive
+    pub entry: String,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ListArgs {
+    /// Input archive file
+    pub input: String,
+
+    /// Render a directory tree with cumulative sizes and entry counts
+    #[arg(long)]
+    pub tree: bool,
+
+    /// Report the N largest files across all nesting levels as CSV, usable as
+    /// a filter (--index 1)
+    #[arg(long, value_name = "N", conflicts_with = "tree")]
+    pub largest: Option<usize>,
+
+    /// Dump the raw metadata of every entry as JSON: zip header fields,
+    /// comments and extra field IDs, tar header fields and PAX records
+    #[arg(long, conflicts_with_all = ["tree", "largest"])]
+    pub metadata: bool,
+
+    /// Report which archives contain which, with their sizes, as a DOT or
+    /// JSON graph
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = ["dot", "json"],
+        num_args = 0..=1,
+        default_missing_value = "dot",
+        conflicts_with_all = ["tree", "largest", "metadata"]
+    )]
+    pub nested_graph: Option<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct MergeArgs {
+    /// Input archive files, merged in the given order
+    #[arg(required = true, num_args = 2..)]
+    pub inputs: Vec<String>,
+
+    /// Output file
+    #[arg(long, short)]
+    pub output: String,
+
+    /// How to resolve entries present in more than one input
+    #[arg(long, value_enum, default_value_t = ConflictPolicy::Error)]
+    pub conflict: ConflictPolicy,
 
     /// Compression level
     #[arg(long, default_value_t = 6)]
     pub compression: u32,
+
+    /// Overwrite an existing output file
+    #[arg(long)]
+    pub force: bool,
 }
 
 impl Args {
-    pub fn from() -> Args {
-        let mut args = Args::parse();
+    pub fn from() -> Result<Args> {
+        // the profile has to be known before parsing, as it may provide
+        // required arguments
+        let command = Args::command();
+        let matches = command.clone().ignore_errors(true).get_matches();
+        let mut argv: Vec<OsString> = std::env::args_os().collect();
+        if let (Some(name), None) = (
+            matches.get_one::<String>("profile"),
+            matches.subcommand_name(),
+        ) {
+            let profile = load_profile(
+                matches.get_one::<String>("config").map(|s| s.as_str()),
+                name,
+            )?;
+            let (settings, unknown) = profile.args(&command, &matches)?;
+            for key in unknown {
+                eprintln!(
+                    "warning: ignoring the setting '{}' of the profile '{}', it is not an option of this version",
+                    key, name
+                );
+            }
+            let program = argv.len().min(1);
+            argv.splice(program..program, settings.into_iter().map(OsString::from));
+        }
+
+        let mut args = Args::parse_from(argv);
 
         if args.output.is_none() {
-            args.output = Some(args.input.clone());
+            args.output = args.input.clone();
+        }
+        if let (true, Some(input)) = (args.in_place, &args.input) {
+            args.output_dir = match Path::new(input).parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.display().to_string(),
+                _ => ".".to_string(),
+            };
         }
 
-        args
+        Ok(args)
     }
 }
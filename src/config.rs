@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use clap::{parser::ValueSource, ArgMatches, Command};
+use serde::Deserialize;
+
+pub const CONFIG_FILE: &str = "expurgator.toml";
+
+/// Contents of the config file, named profiles bundle the settings of a
+/// standard job.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// Settings of a profile, keyed by the long name of their options, e.g.
+/// `output-dir = "dist"` or `transform = ["minify-json=*.json"]`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Profile(BTreeMap<String, toml::Value>);
+
+impl Profile {
+    /// The settings as command line arguments of `command`, except those
+    /// already given on the command line or in the environment, which take
+    /// precedence. Keys naming no option are returned separately, so a
+    /// profile written for a newer version still applies.
+    pub fn args(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+    ) -> Result<(Vec<String>, Vec<&str>)> {
+        let mut args = Vec::new();
+        let mut unknown = Vec::new();
+        for (key, value) in &self.0 {
+            let id = key.replace('-', "_");
+            let arg = command.get_arguments().find(|arg| {
+                arg.get_long().is_some()
+                    && (arg.get_long() == Some(key.as_str()) || arg.get_id() == id.as_str())
+                    && !matches!(arg.get_id().as_str(), "config" | "profile")
+            });
+            let Some(arg) = arg else {
+                unknown.push(key.as_str());
+                continue;
+            };
+            let given = matches!(
+                matches.value_source(arg.get_id().as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            );
+            if given {
+                continue;
+            }
+            let long = arg.get_long().unwrap_or_default();
+            let values = match value {
+                toml::Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            for value in values {
+                match value {
+                    toml::Value::Boolean(set) if !arg.get_action().takes_values() => {
+                        if *set {
+                            args.push(format!("--{}", long));
+                        }
+                    }
+                    toml::Value::String(value) => args.push(format!("--{}={}", long, value)),
+                    toml::Value::Array(_) | toml::Value::Table(_) => Err(anyhow!(
+                        "Config Error: The profile setting '{}' must be a value or a list of values.",
+                        key
+                    ))?,
+                    value => args.push(format!("--{}={}", long, value)),
+                }
+            }
+        }
+        Ok((args, unknown))
+    }
+}
+
+/// `expurgator.toml` in the working directory, then the user config
+/// directory (`$XDG_CONFIG_HOME/expurgator/config.toml`).
+pub fn default_path() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.exists() {
+        return Some(local);
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    let user = config_home.join("expurgator").join("config.toml");
+    user.exists().then_some(user)
+}
+
+pub fn load_profile(path: Option<&str>, name: &str) -> Result<Profile> {
+    let path = path
+        .map(PathBuf::from)
+        .or_else(default_path)
+        .ok_or_else(|| {
+            anyhow!(
+                "Config Error: The profile '{}' was requested but no {} was found.",
+                name,
+                CONFIG_FILE
+            )
+        })?;
+    let content = std::fs::read_to_string(&path)?;
+    let mut config: Config = toml::from_str(&content).map_err(|err| {
+        anyhow!(
+            "Config Error: The config file '{}' is invalid ({}).",
+            path.display(),
+            err.message()
+        )
+    })?;
+    config.profile.remove(name).ok_or_else(|| {
+        anyhow!(
+            "Unknown Profile: The profile '{}' is not defined in '{}'.",
+            name,
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::FileWriteStr;
+    use clap::{Arg, ArgAction};
+
+    fn command() -> Command {
+        Command::new("expurgator")
+            .arg(Arg::new("config").long("config"))
+            .arg(Arg::new("csv").long("csv").env("EXPURGATOR_TEST_CSV"))
+            .arg(Arg::new("compression").long("compression"))
+            .arg(
+                Arg::new("non_interactive")
+                    .long("non-interactive")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("transforms")
+                    .long("transform")
+                    .action(ArgAction::Append),
+            )
+    }
+
+    #[test]
+    fn test_load_profile() {
+        let file = assert_fs::NamedTempFile::new("expurgator.toml").unwrap();
+        file.write_str(
+            r#"
+[profile.quick]
+compression = 1
+non-interactive = true
+from-a-newer-version = true
+
+[profile.release-scrub]
+csv = "release.csv"
+transforms = ["minify-json=*.json", "strip-comments=*.properties"]
+"#,
+        )
+        .unwrap();
+        let path = file.path().to_str();
+
+        let quick = load_profile(path, "quick").unwrap();
+        let matches = command().get_matches_from(["expurgator"]);
+        let (args, unknown) = quick.args(&command(), &matches).unwrap();
+        assert_eq!(args, ["--compression=1", "--non-interactive"]);
+        assert_eq!(unknown, ["from-a-newer-version"]);
+
+        // the command line takes precedence
+        let release = load_profile(path, "release-scrub").unwrap();
+        let matches = command().get_matches_from(["expurgator", "--csv", "other.csv"]);
+        let (args, _) = release.args(&command(), &matches).unwrap();
+        assert_eq!(
+            args,
+            [
+                "--transform=minify-json=*.json",
+                "--transform=strip-comments=*.properties"
+            ]
+        );
+        assert!(load_profile(path, "missing").is_err());
+    }
+}
@@ -0,0 +1,711 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::archive::TarFormat;
+use crate::cache::InnerCache;
+use crate::cancel::CancellationToken;
+use crate::filter::{Decision, EntryFilter};
+use crate::memory::MemoryLimit;
+use crate::owner::OwnerMap;
+use crate::progress::Progress;
+use crate::provenance::Provenance;
+use crate::reader::{EntryInfo, EntryKind};
+use crate::report::Occurrence;
+use crate::rule::Rule;
+use crate::symlink::resolve;
+use crate::untrusted::Untrusted;
+use crate::util::prompt_error;
+use crate::windows::safe_path;
+use crate::workspace::Workspace;
+use crate::xz::XzOptions;
+
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+pub const DEFAULT_PREFETCH: usize = 4 * 1024 * 1024;
+
+pub const ON_ERROR_POLICIES: [&str; 3] = ["prompt", "skip", "abort"];
+
+/// What happens to an entry that fails to process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// Ask whether to skip the entry and continue, on the terminal.
+    Prompt,
+    Skip,
+    /// Fail the scrub, the default, as library callers may have no terminal.
+    #[default]
+    Abort,
+}
+
+impl OnError {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "prompt" => Ok(OnError::Prompt),
+            "skip" => Ok(OnError::Skip),
+            "abort" => Ok(OnError::Abort),
+            _ => Err(anyhow!(
+                "Invalid Error Policy: '{}' is not one of: {}.",
+                name,
+                ON_ERROR_POLICIES.join(", ")
+            )),
+        }
+    }
+}
+
+pub const ENCRYPTED_POLICIES: [&str; 3] = ["decrypt", "skip-encrypted", "fail"];
+
+/// What happens to the encrypted entries of a zip.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encrypted {
+    /// Decrypt them with the password, they are written back encrypted.
+    #[default]
+    Decrypt,
+    /// Copy them as they are, without running the entry filters.
+    SkipEncrypted,
+    Fail,
+}
+
+impl Encrypted {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "decrypt" => Ok(Encrypted::Decrypt),
+            "skip-encrypted" => Ok(Encrypted::SkipEncrypted),
+            "fail" => Ok(Encrypted::Fail),
+            _ => Err(anyhow!(
+                "Invalid Encrypted Policy: '{}' is not one of: {}.",
+                name,
+                ENCRYPTED_POLICIES.join(", ")
+            )),
+        }
+    }
+}
+
+pub const DUPLICATES_POLICIES: [&str; 4] = ["keep-first", "keep-last", "keep-all", "error"];
+
+/// Which of the zip entries sharing a path are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Duplicates {
+    KeepFirst,
+    /// The copy extractors end up with, as later ones overwrite earlier ones.
+    #[default]
+    KeepLast,
+    /// Every copy, the ones before the last are copied as they are, without
+    /// running the entry filters.
+    KeepAll,
+    Error,
+}
+
+impl Duplicates {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "keep-first" => Ok(Duplicates::KeepFirst),
+            "keep-last" => Ok(Duplicates::KeepLast),
+            "keep-all" => Ok(Duplicates::KeepAll),
+            "error" => Ok(Duplicates::Error),
+            _ => Err(anyhow!(
+                "Invalid Duplicates Policy: '{}' is not one of: {}.",
+                name,
+                DUPLICATES_POLICIES.join(", ")
+            )),
+        }
+    }
+}
+
+pub const ENTRY_ORDERS: [&str; 3] = ["path", "size", "none"];
+
+/// Order the entries of rewritten archives are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EntryOrder {
+    Path,
+    /// Smallest first, entries of the same size by path.
+    Size,
+    /// The order of the input.
+    #[default]
+    Unsorted,
+}
+
+impl EntryOrder {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "path" => Ok(EntryOrder::Path),
+            "size" => Ok(EntryOrder::Size),
+            "none" => Ok(EntryOrder::Unsorted),
+            _ => Err(anyhow!(
+                "Invalid Entry Order: '{}' is not one of: {}.",
+                name,
+                ENTRY_ORDERS.join(", ")
+            )),
+        }
+    }
+
+    /// Indices of `entries`, given as path and size, in the order to write
+    /// them.
+    pub fn sort(self, entries: &[(String, u64)]) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..entries.len()).collect();
+        match self {
+            EntryOrder::Path => indices.sort_by(|&a, &b| entries[a].0.cmp(&entries[b].0)),
+            EntryOrder::Size => indices.sort_by(|&a, &b| {
+                (entries[a].1, &entries[a].0).cmp(&(entries[b].1, &entries[b].0))
+            }),
+            EntryOrder::Unsorted => {}
+        }
+        indices
+    }
+}
+
+pub const SYMLINK_POLICIES: [&str; 4] = ["keep", "drop", "dereference", "reject-external"];
+
+/// What happens to the symlinks of the archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    #[default]
+    Keep,
+    Drop,
+    /// Replace each link by a copy of the file it points to in the scrubbed
+    /// archive, links to anything else are dropped.
+    Dereference,
+    /// Fail on a link pointing outside the archive root.
+    RejectExternal,
+}
+
+impl Symlinks {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "keep" => Ok(Symlinks::Keep),
+            "drop" => Ok(Symlinks::Drop),
+            "dereference" => Ok(Symlinks::Dereference),
+            "reject-external" => Ok(Symlinks::RejectExternal),
+            _ => Err(anyhow!(
+                "Invalid Symlinks Policy: '{}' is not one of: {}.",
+                name,
+                SYMLINK_POLICIES.join(", ")
+            )),
+        }
+    }
+
+    /// Whether each link has to be checked as it is read, links are
+    /// dereferenced once the archive is written.
+    pub fn checks_entries(self) -> bool {
+        matches!(self, Symlinks::Drop | Symlinks::RejectExternal)
+    }
+}
+
+/// Which of the entries a filter list rule matches are removed. A rule
+/// matches every entry whose path it ends with, the same path may be in
+/// several directories or nested archives, or appear twice in a tar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The first entry, the rule is used up by it.
+    #[default]
+    First,
+    /// Every entry, each one is recorded as an occurrence.
+    All,
+}
+
+/// Settings shared by every stage of a scrub.
+#[derive(Clone)]
+pub struct Context<'a> {
+    pub(crate) progress: &'a dyn Progress,
+    pub(crate) cancel: CancellationToken,
+    pub(crate) compression_level: u32,
+    /// Granularity of entry reads and size of the output write buffer.
+    pub(crate) buffer_size: usize,
+    /// Bytes decompression of a tar may run ahead of filtering, and
+    /// compression behind it.
+    pub(crate) prefetch: usize,
+    pub(crate) filters: Vec<Arc<dyn EntryFilter>>,
+    /// Rename kept entries so they extract on Windows.
+    pub(crate) windows_safe: bool,
+    /// Record of the scrub stored in the output.
+    pub(crate) provenance: Option<Provenance>,
+    /// Comment replacing the one of the output zip.
+    pub(crate) archive_comment: Option<String>,
+    /// Boundary stored zip entries are aligned to, like zipalign.
+    pub(crate) zip_align: Option<u16>,
+    /// Password of encrypted zip entries, which are written back encrypted
+    /// with it, and of the output zip if `encrypt_output` is set.
+    pub(crate) password: Option<String>,
+    /// AES-256 encrypt every entry of the output zip.
+    pub(crate) encrypt_output: bool,
+    pub(crate) encrypted: Encrypted,
+    /// Encrypted entries copied as they are, shared with nested contexts.
+    pub(crate) skipped_encrypted: Arc<Mutex<Vec<PathBuf>>>,
+    pub(crate) duplicates: Duplicates,
+    /// Copies of duplicate zip entries dropped, or with `keep-all` copied as
+    /// they are, shared with nested contexts.
+    pub(crate) duplicate_entries: Arc<Mutex<Vec<PathBuf>>>,
+    pub(crate) entry_order: EntryOrder,
+    pub(crate) symlinks: Symlinks,
+    /// Symlinks dropped by the `--symlinks` policy, shared with nested
+    /// contexts.
+    pub(crate) dropped_symlinks: Arc<Mutex<Vec<PathBuf>>>,
+    pub(crate) match_mode: MatchMode,
+    /// Entries removed by a filter list rule with `MatchMode::All` or a depth
+    /// modifier, shared with nested contexts.
+    pub(crate) occurrences: Arc<Mutex<Vec<Occurrence>>>,
+    /// Write gzip outputs that rsync can transfer incrementally.
+    pub(crate) rsyncable: bool,
+    /// Latest modification time, as seconds since the epoch, of the entries
+    /// written.
+    pub(crate) clamp_mtime: Option<u64>,
+    /// Extreme preset and filter chain of xz outputs.
+    pub(crate) xz: XzOptions,
+    /// Dictionary zstd inputs are decoded and outputs encoded with.
+    pub(crate) zstd_dict: Option<Arc<[u8]>>,
+    /// Window of zstd outputs in bits, with long distance matching.
+    pub(crate) zstd_long: Option<u32>,
+    /// Header format of rewritten tar archives, `None` keeps the input's.
+    pub(crate) tar_format: Option<TarFormat>,
+    /// Owner rewrites applied to kept tar entries.
+    pub(crate) owners: OwnerMap,
+    /// Drop the extended attributes and ACLs of tar entries.
+    pub(crate) strip_xattrs: bool,
+    /// Write GNU incremental directory listings as plain directories.
+    pub(crate) drop_incremental_metadata: bool,
+    /// Limits and strict checks for archives from external users.
+    pub(crate) untrusted: Option<Untrusted>,
+    /// Budget for buffering nested archives, beyond it they are spooled to
+    /// temporary files.
+    pub(crate) memory_limit: Option<MemoryLimit>,
+    /// Directory nested archives are spooled to, the system's temporary
+    /// directory if not set.
+    pub(crate) workspace: Option<Workspace>,
+    /// Time the whole scrub has to finish by.
+    pub(crate) deadline: Option<Instant>,
+    /// Longest time reading the data of a single entry may take.
+    pub(crate) entry_timeout: Option<Duration>,
+    pub(crate) on_error: OnError,
+    /// Nested archives already rewritten, shared with nested contexts.
+    pub(crate) inner_cache: InnerCache,
+    /// Worker threads rewriting the nested archives of a zip in parallel.
+    pub(crate) jobs: usize,
+    /// Path of the enclosing archive entry when processing nested archives.
+    pub(crate) parent: PathBuf,
+    pub(crate) depth: usize,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(progress: &'a dyn Progress, compression_level: u32) -> Self {
+        Context {
+            progress,
+            cancel: CancellationToken::default(),
+            compression_level,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            prefetch: DEFAULT_PREFETCH,
+            filters: Vec::new(),
+            windows_safe: false,
+            provenance: None,
+            archive_comment: None,
+            zip_align: None,
+            password: None,
+            encrypt_output: false,
+            encrypted: Encrypted::default(),
+            skipped_encrypted: Arc::default(),
+            duplicates: Duplicates::default(),
+            duplicate_entries: Arc::default(),
+            entry_order: EntryOrder::default(),
+            symlinks: Symlinks::default(),
+            dropped_symlinks: Arc::default(),
+            match_mode: MatchMode::default(),
+            occurrences: Arc::default(),
+            rsyncable: false,
+            clamp_mtime: None,
+            xz: XzOptions::default(),
+            zstd_dict: None,
+            zstd_long: None,
+            tar_format: None,
+            owners: OwnerMap::default(),
+            strip_xattrs: false,
+            drop_incremental_metadata: false,
+            untrusted: None,
+            memory_limit: None,
+            workspace: None,
+            deadline: None,
+            entry_timeout: None,
+            on_error: OnError::default(),
+            inner_cache: InnerCache::default(),
+            jobs: 1,
+            parent: PathBuf::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn with_prefetch(mut self, prefetch: usize) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    pub fn with_filter<F: EntryFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    pub fn with_windows_safe(mut self, windows_safe: bool) -> Self {
+        self.windows_safe = windows_safe;
+        self
+    }
+
+    pub fn with_provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Provenance to store in the archive being written, nested archives get
+    /// none.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref().filter(|_| self.depth == 0)
+    }
+
+    pub fn with_archive_comment(mut self, archive_comment: Option<String>) -> Self {
+        self.archive_comment = archive_comment;
+        self
+    }
+
+    /// Comment to set on the zip being written, nested archives keep theirs.
+    pub fn archive_comment(&self) -> Option<&str> {
+        self.archive_comment.as_deref().filter(|_| self.depth == 0)
+    }
+
+    pub fn with_zip_align(mut self, zip_align: Option<u16>) -> Self {
+        self.zip_align = zip_align;
+        self
+    }
+
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    pub fn with_encrypt_output(mut self, encrypt_output: bool) -> Self {
+        self.encrypt_output = encrypt_output;
+        self
+    }
+
+    /// Sets the `--encrypted` policy, skipped entries are recorded in
+    /// `skipped`.
+    pub fn with_encrypted(
+        mut self,
+        encrypted: Encrypted,
+        skipped: Arc<Mutex<Vec<PathBuf>>>,
+    ) -> Self {
+        self.encrypted = encrypted;
+        self.skipped_encrypted = skipped;
+        self
+    }
+
+    /// The encrypted entries copied without being processed, as full paths.
+    pub fn skipped_encrypted(&self) -> Vec<PathBuf> {
+        self.skipped_encrypted.lock().unwrap().clone()
+    }
+
+    /// Sets the `--duplicates` policy, the copies it drops or skips are
+    /// recorded in `entries`.
+    pub fn with_duplicates(
+        mut self,
+        duplicates: Duplicates,
+        entries: Arc<Mutex<Vec<PathBuf>>>,
+    ) -> Self {
+        self.duplicates = duplicates;
+        self.duplicate_entries = entries;
+        self
+    }
+
+    pub fn with_entry_order(mut self, entry_order: EntryOrder) -> Self {
+        self.entry_order = entry_order;
+        self
+    }
+
+    /// Sets the `--symlinks` policy, the links it drops are recorded in
+    /// `dropped`.
+    pub fn with_symlinks(mut self, symlinks: Symlinks, dropped: Arc<Mutex<Vec<PathBuf>>>) -> Self {
+        self.symlinks = symlinks;
+        self.dropped_symlinks = dropped;
+        self
+    }
+
+    /// Applies the `--symlinks` policy to the link at `path` of the current
+    /// archive pointing to `target`, returns whether it is written.
+    pub fn keep_symlink(&self, path: &str, target: &Path) -> Result<bool> {
+        match self.symlinks {
+            Symlinks::Keep | Symlinks::Dereference => Ok(true),
+            Symlinks::Drop => {
+                self.drop_symlink(path);
+                Ok(false)
+            }
+            Symlinks::RejectExternal => match resolve(Path::new(path), target) {
+                Some(_) => Ok(true),
+                None => Err(anyhow!(
+                    "External Symlink: '{}' points to '{}', outside the archive.",
+                    self.parent.join(path).display(),
+                    target.display()
+                )),
+            },
+        }
+    }
+
+    /// Records the link at `path` of the current archive as dropped.
+    pub fn drop_symlink(&self, path: &str) {
+        self.progress.message(format!("dropped symlink: {}", path));
+        self.dropped_symlinks
+            .lock()
+            .unwrap()
+            .push(self.parent.join(path));
+    }
+
+    /// Sets the filter list match mode, with `MatchMode::All` the removed
+    /// entries are recorded in `occurrences`.
+    pub fn with_match_mode(
+        mut self,
+        match_mode: MatchMode,
+        occurrences: Arc<Mutex<Vec<Occurrence>>>,
+    ) -> Self {
+        self.match_mode = match_mode;
+        self.occurrences = occurrences;
+        self
+    }
+
+    /// Whether the entry at `path` of the current archive is listed in
+    /// `filter_list`. With `MatchMode::First` the matching rule is used up,
+    /// with `MatchMode::All` it is kept for the other occurrences. Matches
+    /// of rules with a depth modifier are recorded in both modes, as copies
+    /// of them are handed to every nested archive.
+    pub fn take_match(&self, filter_list: &mut Vec<PathBuf>, path: &str) -> bool {
+        let Some(found_file) = filter_list
+            .iter()
+            .position(|e| Rule::new(e).matches(path, self.depth))
+        else {
+            return false;
+        };
+        if self.match_mode == MatchMode::All || Rule::new(&filter_list[found_file]).depth.is_some()
+        {
+            self.occurrences.lock().unwrap().push(Occurrence {
+                rule: filter_list[found_file].clone(),
+                path: self.parent.join(path),
+            });
+        }
+        if self.match_mode == MatchMode::First {
+            filter_list.swap_remove(found_file);
+        }
+        true
+    }
+
+    /// Entries removed by a filter list rule with `MatchMode::All` or a depth
+    /// modifier so far.
+    pub fn occurrences(&self) -> Vec<Occurrence> {
+        self.occurrences.lock().unwrap().clone()
+    }
+
+    /// The rules of `filter_list` that matched no entry, leaving out the
+    /// ones kept in the list after matching.
+    pub fn unmatched(&self, mut filter_list: Vec<PathBuf>) -> Vec<PathBuf> {
+        let occurrences = self.occurrences.lock().unwrap();
+        filter_list.retain(|rule| !occurrences.iter().any(|o| &o.rule == rule));
+        filter_list
+    }
+
+    pub fn with_rsyncable(mut self, rsyncable: bool) -> Self {
+        self.rsyncable = rsyncable;
+        self
+    }
+
+    pub fn with_clamp_mtime(mut self, clamp_mtime: Option<u64>) -> Self {
+        self.clamp_mtime = clamp_mtime;
+        self
+    }
+
+    /// Modification time to write for an entry modified at `mtime`.
+    pub fn clamped_mtime(&self, mtime: u64) -> u64 {
+        self.clamp_mtime.map_or(mtime, |clamp| mtime.min(clamp))
+    }
+
+    /// Password to encrypt a zip entry with when writing it: encrypted
+    /// entries stay encrypted, with --encrypt-output the entries of the top
+    /// level zip are encrypted too, nested archives only as part of theirs.
+    pub fn entry_password(&self, encrypted: bool) -> Option<&str> {
+        let encrypt = encrypted || (self.encrypt_output && self.depth == 0);
+        self.password.as_deref().filter(|_| encrypt)
+    }
+
+    pub fn with_xz(mut self, xz: XzOptions) -> Self {
+        self.xz = xz;
+        self
+    }
+
+    pub fn with_zstd_dict(mut self, zstd_dict: Option<Vec<u8>>) -> Self {
+        self.zstd_dict = zstd_dict.map(Arc::from);
+        self
+    }
+
+    pub fn with_zstd_long(mut self, zstd_long: Option<u32>) -> Self {
+        self.zstd_long = zstd_long;
+        self
+    }
+
+    pub fn with_tar_format(mut self, tar_format: Option<TarFormat>) -> Self {
+        self.tar_format = tar_format;
+        self
+    }
+
+    pub fn with_owners(mut self, owners: OwnerMap) -> Self {
+        self.owners = owners;
+        self
+    }
+
+    pub fn with_strip_xattrs(mut self, strip_xattrs: bool) -> Self {
+        self.strip_xattrs = strip_xattrs;
+        self
+    }
+
+    pub fn with_drop_incremental_metadata(mut self, drop_incremental_metadata: bool) -> Self {
+        self.drop_incremental_metadata = drop_incremental_metadata;
+        self
+    }
+
+    pub fn with_untrusted(mut self, untrusted: Untrusted) -> Self {
+        self.untrusted = Some(untrusted);
+        self
+    }
+
+    /// Caps the bytes of nested archives buffered in memory at `max_memory`,
+    /// larger ones are spooled to disk.
+    pub fn with_max_memory(mut self, max_memory: Option<u64>) -> Self {
+        self.memory_limit = max_memory.map(MemoryLimit::new);
+        self
+    }
+
+    /// Spools nested archives to a directory of their own under `root`,
+    /// holding at most `max_size` bytes, instead of the system's temporary
+    /// directory. It is removed once the context and its clones are dropped.
+    pub fn with_temp_dir(mut self, root: &Path, max_size: Option<u64>) -> Result<Self> {
+        self.workspace = Some(Workspace::create(root, max_size)?);
+        Ok(self)
+    }
+
+    /// Sets the deadline of the scrub `timeout` from now.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self
+    }
+
+    pub fn with_entry_timeout(mut self, entry_timeout: Option<Duration>) -> Self {
+        self.entry_timeout = entry_timeout;
+        self
+    }
+
+    pub fn with_on_error(mut self, on_error: OnError) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Fails once the scrub was cancelled or ran past its deadline.
+    pub fn check(&self) -> Result<()> {
+        self.cancel.check()?;
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() > deadline)
+        {
+            Err(anyhow!(
+                "Timeout: The scrub did not finish within --timeout."
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Applies the `--on-error` policy to an entry that failed to process,
+    /// `Ok` means the entry is skipped.
+    pub fn entry_error(&self, err: anyhow::Error) -> Result<()> {
+        match self.on_error {
+            OnError::Prompt => {
+                self.progress.message(err.to_string());
+                prompt_error(self.progress)
+            }
+            OnError::Skip => {
+                self.progress.message(format!("skipped: {}", err));
+                Ok(())
+            }
+            OnError::Abort => Err(err),
+        }
+    }
+
+    /// Applies the untrusted input limits to an entry, if enabled.
+    pub fn check_entry(&self, path: &str, size: u64) -> Result<()> {
+        match &self.untrusted {
+            Some(untrusted) => untrusted.check_entry(path, size),
+            None => Ok(()),
+        }
+    }
+
+    /// Name a kept entry is written under.
+    pub fn entry_name<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        if !self.windows_safe {
+            return Cow::Borrowed(path);
+        }
+        let name = safe_path(path);
+        if name != path {
+            self.progress
+                .message(format!("renaming: {} -> {}", path, name));
+        }
+        name
+    }
+
+    /// Context for the archive stored at `path` within the current one.
+    pub fn nested(&self, path: &str) -> Self {
+        Context {
+            parent: self.parent.join(path),
+            depth: self.depth + 1,
+            ..self.clone()
+        }
+    }
+
+    /// Runs the registered filters on an entry of the current archive.
+    pub fn decide(
+        &self,
+        path: &str,
+        kind: EntryKind,
+        mtime: Option<u64>,
+        content: Option<&[u8]>,
+    ) -> Result<Decision> {
+        if self.filters.is_empty() {
+            return Ok(Decision::Keep);
+        }
+        let entry = EntryInfo {
+            path: self.parent.join(path),
+            size: content.map_or(0, |content| content.len() as u64),
+            mtime,
+            kind,
+            depth: self.depth,
+            mime_type: content
+                .and_then(infer::get)
+                .map(|kind| kind.mime_type().to_string()),
+        };
+        let mut replaced: Option<Vec<u8>> = None;
+        for filter in &self.filters {
+            match filter.decide(&entry, replaced.as_deref().or(content))? {
+                Decision::Keep => {}
+                Decision::Remove => return Ok(Decision::Remove),
+                Decision::Replace(data) => replaced = Some(data),
+            }
+        }
+        Ok(replaced.map_or(Decision::Keep, Decision::Replace))
+    }
+}
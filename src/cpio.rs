@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const NEWC_CRC_MAGIC: &[u8] = b"070702";
+const HEADER_LEN: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// A single `newc` cpio entry, header fields are kept verbatim so the
+/// entry can be written back unchanged.
+#[derive(Debug, Clone)]
+pub struct CpioEntry {
+    pub magic: [u8; 6],
+    pub fields: [u32; 13],
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+// field indices inside the newc header
+const MODE: usize = 1;
+const NLINK: usize = 4;
+const MTIME: usize = 5;
+const FILESIZE: usize = 6;
+const NAMESIZE: usize = 11;
+
+impl CpioEntry {
+    /// A regular file owned by root with mode 0644.
+    pub fn file(name: &str, data: Vec<u8>, mtime: u32) -> Result<Self> {
+        let mut fields = [0u32; 13];
+        fields[MODE] = S_IFREG | 0o644;
+        fields[NLINK] = 1;
+        fields[MTIME] = mtime;
+        let mut entry = CpioEntry {
+            magic: NEWC_MAGIC.try_into()?,
+            fields,
+            name: String::new(),
+            data: Vec::new(),
+        };
+        entry.set_path(name)?;
+        entry.set_data(data)?;
+        Ok(entry)
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.fields[MODE]
+    }
+
+    /// Sets the file type and permission bits.
+    pub fn set_mode(&mut self, mode: u32) {
+        self.fields[MODE] = mode;
+    }
+
+    pub fn mtime(&self) -> u32 {
+        self.fields[MTIME]
+    }
+
+    pub fn set_mtime(&mut self, mtime: u32) {
+        self.fields[MTIME] = mtime;
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode() & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode() & S_IFMT == S_IFLNK
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode() & S_IFMT == S_IFREG
+    }
+
+    /// Entry name without the leading `./` most cpio producers emit.
+    pub fn path(&self) -> &str {
+        self.name.trim_start_matches("./")
+    }
+
+    /// Replaces the name, keeping a leading `./` of the original.
+    pub fn set_path(&mut self, path: &str) -> Result<()> {
+        let prefix = &self.name[..self.name.len() - self.path().len()];
+        self.name = format!("{}{}", prefix, path);
+        self.fields[NAMESIZE] = (self.name.len() + 1).try_into()?;
+        Ok(())
+    }
+
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        self.fields[FILESIZE] = data.len().try_into()?;
+        self.data = data;
+        Ok(())
+    }
+}
+
+pub fn is_cpio(bytes: &[u8]) -> bool {
+    bytes.starts_with(NEWC_MAGIC) || bytes.starts_with(NEWC_CRC_MAGIC)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn parse_hex(field: &[u8]) -> Result<u32> {
+    let field = std::str::from_utf8(field)?;
+    Ok(u32::from_str_radix(field, 16)?)
+}
+
+/// Parses a single cpio archive starting at the beginning of `bytes`.
+///
+/// Returns the entries (without the trailer) and the number of bytes the
+/// archive occupies, including the trailer and its padding.
+pub fn read_cpio(bytes: &[u8]) -> Result<(Vec<CpioEntry>, usize)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let header = bytes.get(offset..offset + HEADER_LEN).ok_or_else(|| {
+            anyhow!("Invalid CPIO Archive: Unexpected end of data while reading header.")
+        })?;
+        if !is_cpio(header) {
+            Err(anyhow!(
+                "Invalid CPIO Archive: Only the newc format is supported."
+            ))?;
+        }
+        let mut magic = [0u8; 6];
+        magic.copy_from_slice(&header[..6]);
+        let mut fields = [0u32; 13];
+        for (i, field) in fields.iter_mut().enumerate() {
+            let start = 6 + i * 8;
+            *field = parse_hex(&header[start..start + 8])?;
+        }
+
+        let name_start = offset + HEADER_LEN;
+        let name_end = name_start + fields[NAMESIZE] as usize;
+        let name = bytes
+            .get(name_start..name_end)
+            .ok_or_else(|| anyhow!("Invalid CPIO Archive: Truncated entry name."))?;
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(name)).to_string();
+
+        let data_start = align4(name_end);
+        let data_end = data_start + fields[FILESIZE] as usize;
+        let data = bytes
+            .get(data_start..data_end)
+            .ok_or_else(|| anyhow!("Invalid CPIO Archive: Truncated entry data."))?;
+        offset = align4(data_end);
+
+        if name == TRAILER {
+            break;
+        }
+        entries.push(CpioEntry {
+            magic,
+            fields,
+            name,
+            data: data.to_vec(),
+        });
+    }
+
+    // skip the zero padding between concatenated archives
+    while offset < bytes.len() && bytes[offset] == 0 {
+        offset += 1;
+    }
+    Ok((entries, offset.min(bytes.len())))
+}
+
+fn write_entry(out: &mut Vec<u8>, magic: &[u8], fields: &[u32; 13], name: &str, data: &[u8]) {
+    out.extend_from_slice(magic);
+    for field in fields {
+        out.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.resize(align4(out.len()), 0);
+    out.extend_from_slice(data);
+    out.resize(align4(out.len()), 0);
+}
+
+/// Serializes entries back into a newc archive, padded to 512 bytes like
+/// GNU cpio does.
+pub fn write_cpio(entries: &[CpioEntry]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for entry in entries {
+        let mut fields = entry.fields;
+        fields[NAMESIZE] = (entry.name.len() + 1).try_into()?;
+        fields[FILESIZE] = entry.data.len().try_into()?;
+        write_entry(&mut out, &entry.magic, &fields, &entry.name, &entry.data);
+    }
+    let mut fields = [0u32; 13];
+    fields[NAMESIZE] = (TRAILER.len() + 1).try_into()?;
+    fields[4] = 1; // nlink
+    write_entry(&mut out, NEWC_MAGIC, &fields, TRAILER, &[]);
+    out.resize((out.len() + 511) & !511, 0);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, mode: u32, data: &[u8]) -> CpioEntry {
+        let mut fields = [0u32; 13];
+        fields[MODE] = mode;
+        fields[4] = 1;
+        CpioEntry {
+            magic: *b"070701",
+            fields,
+            name: name.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_cpio_round_trip() {
+        let entries = vec![
+            entry("./etc", S_IFDIR | 0o755, b""),
+            entry("./etc/hostname", S_IFREG | 0o644, b"expurgator\n"),
+        ];
+        let bytes = write_cpio(&entries).unwrap();
+        assert!(is_cpio(&bytes));
+        assert_eq!(bytes.len() % 512, 0);
+
+        let (parsed, consumed) = read_cpio(&bytes).unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].is_dir());
+        assert!(parsed[1].is_file());
+        assert_eq!(parsed[1].path(), "etc/hostname");
+        assert_eq!(parsed[1].data, b"expurgator\n");
+    }
+
+    #[test]
+    fn test_read_cpio_invalid() {
+        assert!(read_cpio(b"not a cpio archive at all").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    io::{Cursor, Read},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::archive::create_tar_decoder;
+use crate::util::infer_input_file;
+
+/// Size of trained dictionaries, the default of `zstd --train`.
+pub const DICT_SIZE: usize = 110 * 1024;
+/// Bytes of an entry used as a training sample.
+const SAMPLE_SIZE: usize = 128 * 1024;
+/// Bytes of all samples, zstd wants about a hundred times the dictionary.
+const SAMPLES_SIZE: usize = 100 * DICT_SIZE;
+
+/// What `--zstd-dict` does with its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZstdDict {
+    /// Train a dictionary on the entries of the input and write it there.
+    Train(PathBuf),
+    /// Read the dictionary from there.
+    Use(PathBuf),
+}
+
+impl ZstdDict {
+    /// Parses `train:<FILE>` or `use:<FILE>`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec.split_once(':') {
+            Some(("train", path)) if !path.is_empty() => Ok(ZstdDict::Train(path.into())),
+            Some(("use", path)) if !path.is_empty() => Ok(ZstdDict::Use(path.into())),
+            _ => Err(anyhow!(
+                "Invalid Zstd Dictionary: '{}' is not train:<FILE> or use:<FILE>.",
+                spec
+            )),
+        }
+    }
+
+    /// The dictionary for rewriting `input_bytes`: trained on its entries
+    /// and saved to the file, or read from it.
+    pub fn load(&self, input_bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            ZstdDict::Train(path) => {
+                let dict = train(input_bytes)?;
+                std::fs::write(path, &dict)?;
+                Ok(dict)
+            }
+            ZstdDict::Use(path) => std::fs::read(path).map_err(|err| {
+                anyhow!(
+                    "Invalid Zstd Dictionary: Cannot read '{}': {}.",
+                    path.display(),
+                    err
+                )
+            }),
+        }
+    }
+}
+
+/// Trains a dictionary on the start of the files of the archive, nested
+/// archives are sampled as they are.
+pub fn train(input_bytes: &[u8]) -> Result<Vec<u8>> {
+    let (data, sizes) = samples(input_bytes)?;
+    zstd::dict::from_continuous(&data, &sizes, DICT_SIZE).map_err(|err| {
+        anyhow!(
+            "Dictionary Training Failed: {} ({} samples); the archive may have too few files to train on.",
+            err,
+            sizes.len()
+        )
+    })
+}
+
+/// Up to [`SAMPLE_SIZE`] bytes of every file, one after the other, and
+/// their sizes.
+fn samples(input_bytes: &[u8]) -> Result<(Vec<u8>, Vec<usize>)> {
+    let mut data = Vec::new();
+    let mut sizes = Vec::new();
+    let mut push = |entry: &mut dyn Read| -> Result<bool> {
+        let start = data.len();
+        entry.take(SAMPLE_SIZE as u64).read_to_end(&mut data)?;
+        if data.len() > start {
+            sizes.push(data.len() - start);
+        }
+        Ok(data.len() < SAMPLES_SIZE)
+    };
+    let mime_type = infer_input_file(input_bytes)?;
+    if mime_type == "application/zip" {
+        let mut zip = zip::ZipArchive::new(Cursor::new(input_bytes))?;
+        for index in 0..zip.len() {
+            // encrypted entries cannot be read without the password
+            let Ok(mut entry) = zip.by_index(index) else {
+                continue;
+            };
+            if entry.is_file() && !push(&mut entry)? {
+                break;
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(create_tar_decoder(input_bytes, &mime_type)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() && !push(&mut entry)? {
+                break;
+            }
+        }
+    }
+    Ok((data, sizes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zstd::stream::{read::Decoder, write::Encoder};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ZstdDict::parse("train:dict.bin").unwrap(),
+            ZstdDict::Train("dict.bin".into())
+        );
+        assert_eq!(
+            ZstdDict::parse("use:/tmp/dict.bin").unwrap(),
+            ZstdDict::Use("/tmp/dict.bin".into())
+        );
+        assert!(ZstdDict::parse("use:").is_err());
+        assert!(ZstdDict::parse("dict.bin").is_err());
+        assert!(ZstdDict::parse("load:dict.bin").is_err());
+    }
+
+    #[test]
+    fn test_train() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for index in 0..500 {
+            let content = format!(
+                "{{\"id\": {}, \"name\": \"package-{}\", \"version\": \"1.{}.0\", \"license\": \"MIT\"}}",
+                index,
+                index * 7,
+                index % 13
+            );
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("{}.json", index), content.as_bytes())
+                .unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+        let dict = train(&tar).unwrap();
+        assert!(!dict.is_empty() && dict.len() <= DICT_SIZE);
+
+        let mut encoder = Encoder::with_dictionary(Vec::new(), 3, &dict).unwrap();
+        encoder.write_all(&tar).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        Decoder::with_dictionary(encoded.as_slice(), &dict)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, tar);
+
+        // a single file is not enough to train on
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "a", &b"a"[..]).unwrap();
+        assert!(train(&builder.into_inner().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use zip::write::SimpleFileOptions;
+
+use crate::archive::TarEncoder;
+use crate::bench::{tar_mime_type, BENCH_FORMATS};
+
+/// The archive format named by the extension of `output`.
+pub fn archive_format(output: &str) -> Result<&'static str> {
+    let output = output.to_lowercase();
+    let format = match output.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("zip") => "zip",
+        Some("tar") => "tar",
+        Some("tgz") => "tar.gz",
+        Some("gz") if output.ends_with(".tar.gz") => "tar.gz",
+        Some("bz2") if output.ends_with(".tar.bz2") => "tar.bz2",
+        Some("xz") if output.ends_with(".tar.xz") => "tar.xz",
+        Some("tzst") => "tar.zst",
+        Some("zst") if output.ends_with(".tar.zst") => "tar.zst",
+        _ => Err(anyhow!(
+            "Invalid Output: Packing a directory needs an --output named after one of the formats: {}.",
+            BENCH_FORMATS.join(", ")
+        ))?,
+    };
+    Ok(format)
+}
+
+/// The files below `dir`, sorted so that the archive is reproducible.
+fn walk(dir: &Path, prefix: &Path, entries: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    let mut children = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = prefix.join(child.file_name());
+        entries.push((child.path(), name.clone()));
+        if child.file_type()?.is_dir() {
+            walk(&child.path(), &name, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Packs the content of `dir` into an archive of `format`, with entry paths
+/// relative to `dir`. Symlinks are stored as links.
+pub fn pack_directory(dir: &Path, format: &str, compression_level: u32) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    walk(dir, Path::new(""), &mut entries)?;
+
+    if format == "zip" {
+        let mut result = Vec::new();
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut result));
+        // kept entries are copied as they are, so they get the final level
+        let options =
+            SimpleFileOptions::default().compression_level(Some(compression_level.into()));
+        for (path, name) in entries {
+            let metadata = std::fs::symlink_metadata(&path)?;
+            let name = name.to_string_lossy().replace('\\', "/");
+            #[cfg(unix)]
+            let options = {
+                use std::os::unix::fs::PermissionsExt;
+                options.unix_permissions(metadata.permissions().mode())
+            };
+            if metadata.is_symlink() {
+                let target = std::fs::read_link(&path)?;
+                zip.add_symlink(name, target.to_string_lossy(), options)?;
+            } else if metadata.is_dir() {
+                zip.add_directory(name, options)?;
+            } else {
+                zip.start_file(name, options)?;
+                zip.write_all(&std::fs::read(&path)?)?;
+            }
+        }
+        zip.finish()?;
+        return Ok(result);
+    }
+
+    let mime_type = tar_mime_type(format).ok_or_else(|| {
+        anyhow!(
+            "Unsupported Format: '{}' is not one of: {}.",
+            format,
+            BENCH_FORMATS.join(", ")
+        )
+    })?;
+    // the scrub compresses the tar again at the requested level
+    let mut tar = tar::Builder::new(TarEncoder::new(mime_type, 1)?.encoder());
+    tar.follow_symlinks(false);
+    for (path, name) in entries {
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            tar.append_dir(name, &path)?;
+        } else {
+            tar.append_path_with_name(&path, name)?;
+        }
+    }
+    tar.into_inner()?.inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::EntryIndex;
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(archive_format("dist.tar.gz").unwrap(), "tar.gz");
+        assert_eq!(archive_format("dist.TGZ").unwrap(), "tar.gz");
+        assert_eq!(archive_format("dist.zip").unwrap(), "zip");
+        assert_eq!(archive_format("dist.tzst").unwrap(), "tar.zst");
+        assert!(archive_format("dist.gz").is_err());
+        assert!(archive_format("dist").is_err());
+    }
+
+    #[test]
+    fn test_pack_directory() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("bin")).unwrap();
+        std::fs::write(dir.path().join("bin/app"), b"app").unwrap();
+        std::fs::write(dir.path().join("README"), b"readme").unwrap();
+
+        for format in ["zip", "tar.gz"] {
+            let archive = pack_directory(dir.path(), format, 6).unwrap();
+            let paths: Vec<_> = EntryIndex::build(&archive)
+                .unwrap()
+                .entries
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect();
+            assert_eq!(
+                paths,
+                vec![
+                    PathBuf::from("README"),
+                    PathBuf::from("bin"),
+                    PathBuf::from("bin/app")
+                ],
+                "{}",
+                format
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+
+const SHF_ALLOC: u64 = 0x2;
+const SHF_INFO_LINK: u64 = 0x40;
+const SHT_RELA: u64 = 4;
+const SHT_NOBITS: u64 = 8;
+const SHT_REL: u64 = 9;
+/// Section counts from here on are stored out of line, not supported.
+const SHN_LORESERVE: u64 = 0xff00;
+
+/// Byte order and word size of an ELF file.
+#[derive(Clone, Copy)]
+struct Layout {
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Layout {
+    fn read(self, bytes: &[u8], offset: usize, size: usize) -> Result<u64> {
+        let field = offset
+            .checked_add(size)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| anyhow!("Invalid ELF: A header field is out of bounds."))?;
+        let mut value = 0u64;
+        for index in 0..size {
+            let byte = if self.big_endian {
+                field[index]
+            } else {
+                field[size - 1 - index]
+            };
+            value = value << 8 | byte as u64;
+        }
+        Ok(value)
+    }
+
+    fn write(self, bytes: &mut [u8], offset: usize, size: usize, value: u64) {
+        for index in 0..size {
+            let shift = if self.big_endian {
+                (size - 1 - index) * 8
+            } else {
+                index * 8
+            };
+            bytes[offset + index] = (value >> shift) as u8;
+        }
+    }
+
+    /// Size of an address or offset field.
+    fn word(self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+}
+
+/// The fields of a section header that are read or rewritten.
+struct Section {
+    name: u64,
+    kind: u64,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    info: u64,
+    align: u64,
+    header: Vec<u8>,
+}
+
+fn read_sections(content: &[u8], layout: Layout) -> Result<(Vec<Section>, u64)> {
+    let (shoff, shentsize, shnum, shstrndx) = if layout.is_64 {
+        (0x28, 0x3a, 0x3c, 0x3e)
+    } else {
+        (0x20, 0x2e, 0x30, 0x32)
+    };
+    let shoff = layout.read(content, shoff, layout.word())? as usize;
+    let shentsize = layout.read(content, shentsize, 2)? as usize;
+    let shnum = layout.read(content, shnum, 2)?;
+    let shstrndx = layout.read(content, shstrndx, 2)?;
+    if shnum == 0 || shnum >= SHN_LORESERVE || shstrndx >= shnum {
+        Err(anyhow!(
+            "Invalid ELF: The section header table is not supported."
+        ))?;
+    }
+
+    let word = layout.word();
+    let mut sections = Vec::new();
+    for index in 0..shnum as usize {
+        let start = shoff.saturating_add(index * shentsize);
+        let header = content
+            .get(start..start.saturating_add(shentsize))
+            .ok_or_else(|| anyhow!("Invalid ELF: A section header is out of bounds."))?;
+        // sh_name, sh_type, then word sized flags, addr, offset and size
+        let field = |index: usize| layout.read(header, 8 + index * word, word);
+        sections.push(Section {
+            name: layout.read(header, 0, 4)?,
+            kind: layout.read(header, 4, 4)?,
+            flags: field(0)?,
+            offset: field(2)?,
+            size: field(3)?,
+            info: layout.read(header, 8 + 4 * word + 4, 4)?,
+            align: layout.read(header, 8 + 4 * word + 8, word)?,
+            header: header.to_vec(),
+        });
+    }
+    Ok((sections, shstrndx))
+}
+
+fn section_name<'a>(content: &'a [u8], names: &Section, section: &Section) -> &'a [u8] {
+    let start = names.offset.saturating_add(section.name) as usize;
+    let names_end = names.offset.saturating_add(names.size) as usize;
+    let name = content.get(start..names_end).unwrap_or_default();
+    name.split(|&byte| byte == 0).next().unwrap_or_default()
+}
+
+/// Removes the `.debug*` and `.zdebug*` sections of an ELF file, as
+/// `strip --strip-debug` does. The section headers are kept, empty, so that
+/// section indices stay valid. Returns `None` if `content` is not ELF or
+/// has no debug sections.
+pub fn strip_debug(content: &[u8]) -> Result<Option<Vec<u8>>> {
+    if !content.starts_with(b"\x7fELF") || content.len() < 0x40 {
+        return Ok(None);
+    }
+    let layout = Layout {
+        is_64: content[4] == 2,
+        big_endian: content[5] == 2,
+    };
+    let (mut sections, shstrndx) = read_sections(content, layout)?;
+
+    let names = &sections[shstrndx as usize];
+    let mut removed: Vec<bool> = sections
+        .iter()
+        .map(|section| {
+            let name = section_name(content, names, section);
+            section.flags & SHF_ALLOC == 0
+                && section.size > 0
+                && (name.starts_with(b".debug") || name.starts_with(b".zdebug"))
+        })
+        .collect();
+    // relocations of removed sections, in object files
+    for index in 0..sections.len() {
+        let section = &sections[index];
+        if matches!(section.kind, SHT_REL | SHT_RELA)
+            && section.flags & SHF_ALLOC == 0
+            && (section.flags & SHF_INFO_LINK != 0 || section.info != 0)
+            && section.size > 0
+            && removed.get(section.info as usize) == Some(&true)
+        {
+            removed[index] = true;
+        }
+    }
+    if !removed.contains(&true) {
+        return Ok(None);
+    }
+
+    // everything up to the end of the loaded data stays in place
+    let (phoff, phentsize, phnum) = if layout.is_64 {
+        (0x20, 0x36, 0x38)
+    } else {
+        (0x1c, 0x2a, 0x2c)
+    };
+    let phoff = layout.read(content, phoff, layout.word())?;
+    let phentsize = layout.read(content, phentsize, 2)?;
+    let phnum = layout.read(content, phnum, 2)?;
+    let mut end = if layout.is_64 { 0x40 } else { 0x34 };
+    if phnum > 0 {
+        end = end.max(phoff.saturating_add(phentsize * phnum));
+    }
+    for index in 0..phnum {
+        let header = phoff.saturating_add(index * phentsize) as usize;
+        let (offset, filesz) = if layout.is_64 {
+            (
+                layout.read(content, header + 8, 8)?,
+                layout.read(content, header + 32, 8)?,
+            )
+        } else {
+            (
+                layout.read(content, header + 4, 4)?,
+                layout.read(content, header + 16, 4)?,
+            )
+        };
+        end = end.max(offset.saturating_add(filesz));
+    }
+    let mut in_file: Vec<usize> = (1..sections.len())
+        .filter(|&index| !removed[index] && sections[index].kind != SHT_NOBITS)
+        .collect();
+    in_file.sort_by_key(|&index| sections[index].offset);
+    let data_end = |index: usize| sections[index].offset.saturating_add(sections[index].size);
+    for &index in &in_file {
+        if sections[index].flags & SHF_ALLOC != 0 {
+            end = end.max(data_end(index));
+        }
+    }
+    for &index in &in_file {
+        if sections[index].offset < end {
+            end = end.max(data_end(index));
+        }
+    }
+    let end = usize::try_from(end)?;
+    let mut output = content
+        .get(..end)
+        .ok_or_else(|| anyhow!("Invalid ELF: A segment is out of bounds."))?
+        .to_vec();
+
+    // the remaining sections are packed after it
+    for &index in &in_file {
+        let section = &mut sections[index];
+        if (section.offset as usize) < end {
+            continue;
+        }
+        let data = content
+            .get(section.offset as usize..section.offset.saturating_add(section.size) as usize)
+            .ok_or_else(|| anyhow!("Invalid ELF: A section is out of bounds."))?;
+        let align = section.align.max(1) as usize;
+        output.resize(output.len().next_multiple_of(align), 0);
+        section.offset = output.len() as u64;
+        output.extend_from_slice(data);
+    }
+    output.resize(output.len().next_multiple_of(layout.word()), 0);
+
+    let word = layout.word();
+    let shoff = output.len() as u64;
+    for (index, section) in sections.iter_mut().enumerate() {
+        if removed[index] {
+            section.offset = shoff;
+            section.size = 0;
+        }
+        let mut header = std::mem::take(&mut section.header);
+        layout.write(&mut header, 8 + 2 * word, word, section.offset);
+        layout.write(&mut header, 8 + 3 * word, word, section.size);
+        output.extend_from_slice(&header);
+    }
+    let shoff_field = if layout.is_64 { 0x28 } else { 0x20 };
+    layout.write(&mut output, shoff_field, word, shoff);
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little endian ELF64 file without segments, with sections
+    /// (null, .text, .debug_info, .shstrtab) and the header table at the end.
+    fn elf(debug_info: &[u8]) -> Vec<u8> {
+        let names = b"\0.text\0.debug_info\0.shstrtab\0";
+        let mut file = vec![0u8; 0x40];
+        file[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        let text_offset = file.len() as u64;
+        file.extend_from_slice(b"code");
+        let debug_offset = file.len() as u64;
+        file.extend_from_slice(debug_info);
+        let names_offset = file.len() as u64;
+        file.extend_from_slice(names);
+        file.resize(file.len().next_multiple_of(8), 0);
+        let shoff = file.len() as u64;
+        let sections = [
+            (0, 0, 0, 0, 0),
+            (1, 1, SHF_ALLOC, text_offset, 4),
+            (7, 1, 0, debug_offset, debug_info.len() as u64),
+            (19, 3, 0, names_offset, names.len() as u64),
+        ];
+        for (name, kind, flags, offset, size) in sections {
+            let mut header = [0u8; 64];
+            header[0..4].copy_from_slice(&(name as u32).to_le_bytes());
+            header[4..8].copy_from_slice(&(kind as u32).to_le_bytes());
+            header[8..16].copy_from_slice(&flags.to_le_bytes());
+            header[24..32].copy_from_slice(&offset.to_le_bytes());
+            header[32..40].copy_from_slice(&size.to_le_bytes());
+            header[48..56].copy_from_slice(&1u64.to_le_bytes());
+            file.extend_from_slice(&header);
+        }
+        file[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        file[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        file[0x3c..0x3e].copy_from_slice(&4u16.to_le_bytes());
+        file[0x3e..0x40].copy_from_slice(&3u16.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn test_strip_debug() {
+        let layout = Layout {
+            is_64: true,
+            big_endian: false,
+        };
+        let input = elf(&[0xdb; 1000]);
+        let output = strip_debug(&input).unwrap().unwrap();
+        assert!(output.len() < input.len() - 900);
+
+        let (sections, shstrndx) = read_sections(&output, layout).unwrap();
+        assert_eq!(sections.len(), 4);
+        let names = &sections[shstrndx as usize];
+        assert_eq!(section_name(&output, names, &sections[1]), b".text");
+        assert_eq!(section_name(&output, names, &sections[2]), b".debug_info");
+        assert_eq!(sections[2].size, 0);
+        let text = &sections[1];
+        assert_eq!(
+            &output[text.offset as usize..(text.offset + text.size) as usize],
+            b"code"
+        );
+
+        assert!(strip_debug(&output).unwrap().is_none());
+        assert!(strip_debug(b"not an elf file").unwrap().is_none());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    io::{Read, Seek, Write},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+
+/// Tool encrypting non-zip outputs as a whole, chosen by the recipients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamCipher {
+    /// `age`, for `age1…` and SSH public keys.
+    Age,
+    /// `gpg`, for key IDs, fingerprints and user IDs of the keyring.
+    Gpg,
+}
+
+fn is_age_recipient(recipient: &str) -> bool {
+    recipient.starts_with("age1") || recipient.starts_with("ssh-")
+}
+
+impl StreamCipher {
+    pub fn for_recipients(recipients: &[String]) -> Result<Self> {
+        if recipients.is_empty() {
+            Err(anyhow!(
+                "Invalid Encryption: Encrypting a non-zip output needs at least one --encrypt-recipient."
+            ))?;
+        }
+        let age = recipients.iter().filter(|r| is_age_recipient(r)).count();
+        match age {
+            0 => Ok(StreamCipher::Gpg),
+            age if age == recipients.len() => Ok(StreamCipher::Age),
+            _ => Err(anyhow!(
+                "Invalid Encryption: age and GPG recipients cannot be mixed."
+            ))?,
+        }
+    }
+
+    fn command(self, recipients: &[String]) -> Command {
+        let mut command = match self {
+            StreamCipher::Age => Command::new("age"),
+            StreamCipher::Gpg => {
+                let mut command = Command::new("gpg");
+                command.args(["--batch", "--yes", "--output", "-", "--encrypt"]);
+                command
+            }
+        };
+        for recipient in recipients {
+            command.arg("--recipient").arg(recipient);
+        }
+        command
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            StreamCipher::Age => "age",
+            StreamCipher::Gpg => "gpg",
+        }
+    }
+}
+
+/// Encrypts `content` to the recipients with `age` or `gpg`, which have to
+/// be installed.
+pub fn encrypt_stream(content: Vec<u8>, recipients: &[String]) -> Result<Vec<u8>> {
+    let cipher = StreamCipher::for_recipients(recipients)?;
+    let mut child = cipher
+        .command(recipients)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            anyhow!(
+                "Encryption Failed: Could not run '{}' ({}).",
+                cipher.name(),
+                err
+            )
+        })?;
+    // written from a thread, the output pipe would fill up otherwise
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(&content));
+    let output = child.wait_with_output()?;
+    let written = writer.join().unwrap();
+    if !output.status.success() {
+        Err(anyhow!(
+            "Encryption Failed: '{}' exited with {}: {}",
+            cipher.name(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))?;
+    }
+    written?;
+    Ok(output.stdout)
+}
+
+/// Environment variable holding the password of encrypted zip inputs and
+/// outputs. It is read directly rather than through an argument, so that
+/// the password never shows up in `--help` or process listings.
+pub const PASSWORD_ENV: &str = "EXPURGATOR_PASSWORD";
+
+/// The password of encrypted zip inputs and outputs from `file`, else from
+/// [`PASSWORD_ENV`]. A trailing line break is not part of it.
+pub fn load_password(file: Option<&str>) -> Result<Option<String>> {
+    let password = match file {
+        Some(path) => std::fs::read_to_string(path).map_err(|err| {
+            anyhow!(
+                "Invalid Password: Could not read the password file '{}' ({}).",
+                path,
+                err
+            )
+        })?,
+        None => match std::env::var(PASSWORD_ENV) {
+            Ok(password) => password,
+            Err(_) => return Ok(None),
+        },
+    };
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        Err(anyhow!("Invalid Password: The password is empty."))?;
+    }
+    Ok(Some(password.to_string()))
+}
+
+/// Where `--key` fetches the password of encrypted zip inputs and outputs
+/// from, instead of a file on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// `keyring://SERVICE/ACCOUNT`, a secret of the OS keyring, looked up
+    /// with `secret-tool` on Linux and `security` on macOS.
+    Keyring { service: String, account: String },
+    /// `kms://KEY_ID?ciphertext=FILE`, a data key encrypted under a KMS key,
+    /// decrypted with the `aws` CLI. The base64 encoded plaintext is the
+    /// password.
+    Kms { key_id: String, ciphertext: String },
+}
+
+impl KeySource {
+    pub fn parse(uri: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid Key: '{}' is neither keyring://SERVICE/ACCOUNT nor kms://KEY_ID?ciphertext=FILE.",
+                uri
+            )
+        };
+        if let Some(rest) = uri.strip_prefix("keyring://") {
+            let (service, account) = rest.split_once('/').ok_or_else(invalid)?;
+            if service.is_empty() || account.is_empty() {
+                Err(invalid())?;
+            }
+            return Ok(KeySource::Keyring {
+                service: service.to_string(),
+                account: account.to_string(),
+            });
+        }
+        let rest = uri.strip_prefix("kms://").ok_or_else(invalid)?;
+        let (key_id, query) = rest.split_once('?').ok_or_else(invalid)?;
+        let ciphertext = query.strip_prefix("ciphertext=").ok_or_else(invalid)?;
+        if key_id.is_empty() || ciphertext.is_empty() {
+            Err(invalid())?;
+        }
+        Ok(KeySource::Kms {
+            key_id: key_id.to_string(),
+            ciphertext: ciphertext.to_string(),
+        })
+    }
+
+    fn command(&self) -> Command {
+        match self {
+            KeySource::Keyring { service, account } if cfg!(target_os = "macos") => {
+                let mut command = Command::new("security");
+                command.args(["find-generic-password", "-w", "-s", service, "-a", account]);
+                command
+            }
+            KeySource::Keyring { service, account } => {
+                let mut command = Command::new("secret-tool");
+                command.args(["lookup", "service", service, "account", account]);
+                command
+            }
+            KeySource::Kms { key_id, ciphertext } => {
+                let mut command = Command::new("aws");
+                command
+                    .args(["kms", "decrypt", "--key-id", key_id])
+                    .arg("--ciphertext-blob")
+                    .arg(format!("fileb://{}", ciphertext))
+                    .args(["--query", "Plaintext", "--output", "text"]);
+                command
+            }
+        }
+    }
+
+    /// Fetches the password, the key material never touches the disk.
+    pub fn fetch(&self) -> Result<String> {
+        let mut command = self.command();
+        let name = command.get_program().to_string_lossy().into_owned();
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| anyhow!("Key Lookup Failed: Could not run '{}' ({}).", name, err))?;
+        if !output.status.success() {
+            Err(anyhow!(
+                "Key Lookup Failed: '{}' exited with {}: {}",
+                name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))?;
+        }
+        let key = String::from_utf8(output.stdout)
+            .map_err(|_| anyhow!("Key Lookup Failed: The key is not UTF-8."))?;
+        let key = key.trim_end_matches(['\r', '\n']);
+        if key.is_empty() {
+            Err(anyhow!("Key Lookup Failed: The key is empty."))?;
+        }
+        Ok(key.to_string())
+    }
+}
+
+/// The names of the encrypted entries of the archive `reader`, if it is a
+/// zip.
+pub fn encrypted_entries<R: Read + Seek>(reader: R) -> Vec<String> {
+    let Ok(mut zip) = zip::ZipArchive::new(reader) else {
+        return Vec::new();
+    };
+    (0..zip.len())
+        .filter_map(|index| {
+            let entry = zip.by_index_raw(index).ok()?;
+            entry.encrypted().then(|| entry.name().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_cipher() {
+        let recipients =
+            |list: &[&str]| -> Vec<String> { list.iter().map(ToString::to_string).collect() };
+        assert_eq!(
+            StreamCipher::for_recipients(&recipients(&[
+                "age1qyqszqgpqyqszqgp",
+                "ssh-ed25519 AAAA"
+            ]))
+            .unwrap(),
+            StreamCipher::Age
+        );
+        assert_eq!(
+            StreamCipher::for_recipients(&recipients(&["ops@example.com"])).unwrap(),
+            StreamCipher::Gpg
+        );
+        assert!(StreamCipher::for_recipients(&recipients(&["age1x", "ops@example.com"])).is_err());
+        assert!(StreamCipher::for_recipients(&[]).is_err());
+    }
+
+    #[test]
+    fn test_key_source() {
+        assert_eq!(
+            KeySource::parse("keyring://expurgator/release").unwrap(),
+            KeySource::Keyring {
+                service: "expurgator".to_string(),
+                account: "release".to_string()
+            }
+        );
+        assert_eq!(
+            KeySource::parse("kms://alias/scrubber?ciphertext=/etc/expurgator/key.bin").unwrap(),
+            KeySource::Kms {
+                key_id: "alias/scrubber".to_string(),
+                ciphertext: "/etc/expurgator/key.bin".to_string()
+            }
+        );
+        assert!(KeySource::parse("kms://alias/scrubber").is_err());
+        assert!(KeySource::parse("keyring://expurgator").is_err());
+        assert!(KeySource::parse("/etc/key").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::filter::{Decision, EntryFilter};
+use crate::reader::EntryInfo;
+
+/// Shorter files do not have enough bytes to tell.
+const MIN_SIZE: usize = 256;
+
+/// Text formats: base64, the densest encoding found in them, stays below 6
+/// bits per byte, encrypted or compressed data comes close to 8.
+const TEXT_EXTENSIONS: [&str; 30] = [
+    "txt",
+    "md",
+    "rst",
+    "csv",
+    "tsv",
+    "log",
+    "json",
+    "xml",
+    "html",
+    "htm",
+    "css",
+    "svg",
+    "yml",
+    "yaml",
+    "toml",
+    "ini",
+    "cfg",
+    "conf",
+    "properties",
+    "sh",
+    "py",
+    "rb",
+    "pl",
+    "js",
+    "ts",
+    "c",
+    "h",
+    "cpp",
+    "java",
+    "rs",
+];
+const TEXT_MAX_ENTROPY: f64 = 6.5;
+
+/// Executables and object files: code and symbols stay well below 8 bits per
+/// byte unless packed or carrying an encrypted payload.
+const BINARY_EXTENSIONS: [&str; 8] = ["exe", "dll", "so", "dylib", "o", "a", "class", "dex"];
+const BINARY_MAX_ENTROPY: f64 = 7.2;
+
+/// Shannon entropy of `content` in bits per byte, from 0 to 8.
+pub fn entropy(content: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in content {
+        counts[byte as usize] += 1;
+    }
+    let len = content.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The highest entropy expected of a file with this extension, if known.
+pub fn expected_entropy(path: &Path) -> Option<f64> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if TEXT_EXTENSIONS.contains(&extension.as_str()) {
+        Some(TEXT_MAX_ENTROPY)
+    } else if BINARY_EXTENSIONS.contains(&extension.as_str()) {
+        Some(BINARY_MAX_ENTROPY)
+    } else {
+        None
+    }
+}
+
+/// A kept entry whose content is denser than its extension suggests, e.g. a
+/// `.txt` holding encrypted data.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub path: PathBuf,
+    /// Entropy of the content, in bits per byte.
+    pub entropy: f64,
+    /// Highest entropy expected of the extension.
+    pub expected: f64,
+}
+
+/// An [`EntryFilter`] keeping every entry, recording those with unusually
+/// high entropy for their extension as candidates for human review.
+///
+/// Clones share the recorded anomalies.
+#[derive(Clone, Default)]
+pub struct EntropyCheck {
+    anomalies: Arc<Mutex<Vec<Anomaly>>>,
+}
+
+impl EntropyCheck {
+    pub fn new() -> Self {
+        EntropyCheck::default()
+    }
+
+    /// The recorded anomalies, by path.
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        let mut anomalies = self.anomalies.lock().unwrap().clone();
+        // nested archives may be rewritten in parallel
+        anomalies.sort_by(|a, b| a.path.cmp(&b.path));
+        anomalies
+    }
+}
+
+impl EntryFilter for EntropyCheck {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let (Some(content), Some(expected)) = (content, expected_entropy(&entry.path)) else {
+            return Ok(Decision::Keep);
+        };
+        if content.len() < MIN_SIZE {
+            return Ok(Decision::Keep);
+        }
+        let entropy = entropy(content);
+        if entropy > expected {
+            self.anomalies.lock().unwrap().push(Anomaly {
+                path: entry.path.clone(),
+                entropy,
+                expected,
+            });
+        }
+        Ok(Decision::Keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::EntryKind;
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all), 8.0);
+
+        assert_eq!(
+            expected_entropy(Path::new("docs/README.TXT")),
+            Some(TEXT_MAX_ENTROPY)
+        );
+        assert_eq!(
+            expected_entropy(Path::new("lib/libz.so")),
+            Some(BINARY_MAX_ENTROPY)
+        );
+        assert_eq!(expected_entropy(Path::new("image.png")), None);
+        assert_eq!(expected_entropy(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn test_entropy_check() {
+        let check = EntropyCheck::new();
+        let entry = |path: &str| EntryInfo {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: None,
+            kind: EntryKind::File,
+            depth: 0,
+            mime_type: None,
+        };
+        let random: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let text = "lorem ipsum dolor sit amet\n".repeat(20);
+
+        for (path, content) in [
+            ("notes.txt", random.as_slice()),
+            ("image.png", random.as_slice()),
+            ("readme.txt", text.as_bytes()),
+            ("short.txt", &random[..64]),
+        ] {
+            assert_eq!(
+                check.decide(&entry(path), Some(content)).unwrap(),
+                Decision::Keep
+            );
+        }
+        let anomalies = check.anomalies();
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].path, Path::new("notes.txt"));
+        assert!(anomalies[0].entropy > 7.5);
+    }
+}
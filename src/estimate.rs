@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    io::{Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+
+use crate::reader::{read_head, ArchiveReader, EntryInfo, EntryKind};
+use crate::rule::Rule;
+use crate::util::infer_input_file;
+use crate::zipfmt::{CENTRAL_HEADER_LEN, LOCAL_HEADER_LEN};
+
+const TAR_BLOCK_LEN: u64 = 512;
+
+/// Projected effect of a filter list on an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEstimate {
+    pub removed: Vec<EntryInfo>,
+    pub input_size: u64,
+    pub output_size: u64,
+}
+
+impl SizeEstimate {
+    pub fn removed_bytes(&self) -> u64 {
+        self.removed.iter().map(|entry| entry.size).sum()
+    }
+
+    pub fn savings(&self) -> u64 {
+        self.input_size.saturating_sub(self.output_size)
+    }
+}
+
+fn tar_stored_size(size: u64) -> u64 {
+    TAR_BLOCK_LEN + size.div_ceil(TAR_BLOCK_LEN) * TAR_BLOCK_LEN
+}
+
+/// Estimates the output size without rewriting anything.
+///
+/// Removed zip entries account for their compressed size and headers. Other
+/// formats, and entries of nested archives, scale the uncompressed size by
+/// the compression ratio of the input.
+pub fn estimate_output(input_bytes: &[u8], filter_list: &[PathBuf]) -> Result<SizeEstimate> {
+    estimate_reader(&ArchiveReader::new(input_bytes.to_vec()), filter_list)
+}
+
+/// Same as [`estimate_output`], for the archive of `reader`.
+pub fn estimate_reader(reader: &ArchiveReader, filter_list: &[PathBuf]) -> Result<SizeEstimate> {
+    let entries = reader.entries()?.collect::<Result<Vec<_>>>()?;
+    let mut source = reader.source()?;
+    let input_size = source.seek(SeekFrom::End(0))?;
+    source.rewind()?;
+
+    let stored_size: u64 = entries
+        .iter()
+        .filter(|entry| entry.depth == 0)
+        .map(|entry| tar_stored_size(entry.size))
+        .sum();
+    let ratio = if stored_size == 0 {
+        1.0
+    } else {
+        input_size as f64 / stored_size as f64
+    };
+
+    let mut zip_sizes = HashMap::new();
+    if infer_input_file(&read_head(&mut source)?)? == "application/zip" {
+        let mut archive = zip::ZipArchive::new(source)?;
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            let headers = LOCAL_HEADER_LEN + CENTRAL_HEADER_LEN + 2 * entry.name_raw().len();
+            zip_sizes.insert(
+                PathBuf::from(entry.name()),
+                entry.compressed_size() + headers as u64,
+            );
+        }
+    }
+
+    let removed: Vec<EntryInfo> = entries
+        .into_iter()
+        .filter(|entry| {
+            filter_list
+                .iter()
+                .any(|filter| Rule::new(filter).matches_listed(&entry.path, entry.depth))
+        })
+        .collect();
+    let removed_size: f64 = removed
+        .iter()
+        .map(|entry| match zip_sizes.get(&entry.path) {
+            Some(&size) if entry.depth == 0 => size as f64,
+            _ if entry.kind == EntryKind::File => tar_stored_size(entry.size) as f64 * ratio,
+            _ => TAR_BLOCK_LEN as f64 * ratio,
+        })
+        .sum();
+
+    Ok(SizeEstimate {
+        removed,
+        input_size,
+        output_size: input_size.saturating_sub(removed_size as u64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_estimate_output_zip() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            for name in ["a.txt", "b.txt"] {
+                zip.start_file(name, options).unwrap();
+                zip.write_all(&[b'x'; 1000]).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let estimate = estimate_output(&input, &[PathBuf::from("b.txt")]).unwrap();
+        assert_eq!(estimate.removed.len(), 1);
+        assert_eq!(estimate.removed_bytes(), 1000);
+        assert_eq!(
+            estimate.savings(),
+            1000 + (LOCAL_HEADER_LEN + CENTRAL_HEADER_LEN + 10) as u64
+        );
+    }
+
+    #[test]
+    fn test_estimate_output_tar() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let estimate = estimate_output(&input, &[PathBuf::from("tar-test/file_1.txt")]).unwrap();
+        assert_eq!(estimate.removed.len(), 1);
+        assert!(estimate.output_size < estimate.input_size);
+    }
+}
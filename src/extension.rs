@@ -0,0 +1,133 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+use expurgator::{Decision, EntryFilter, EntryInfo, EntryKind};
+
+/// Extensions as given on the command line, with or without the dot, e.g.
+/// `pdb`, `.map` or `tar.gz`, compared case-insensitively.
+fn normalize(extensions: &[String]) -> Vec<String> {
+    extensions
+        .iter()
+        .map(|ext| format!(".{}", ext.trim().trim_start_matches('.').to_lowercase()))
+        .collect()
+}
+
+/// The extension of `extensions` the file name of `path` ends with, if any.
+fn find<'a>(extensions: &'a [String], path: &Path) -> Option<&'a str> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    extensions
+        .iter()
+        .find(|ext| name.len() > ext.len() && name.ends_with(ext.as_str()))
+        .map(String::as_str)
+}
+
+/// An [`EntryFilter`] removing files by extension: those with a denied one
+/// and, given allowed extensions, those with none of them. Nested archives
+/// are files too, directories are kept.
+///
+/// Clones share the recorded removals.
+#[derive(Clone)]
+pub struct ExtensionFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    removed: Arc<Mutex<Vec<(PathBuf, String)>>>,
+}
+
+impl ExtensionFilter {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        ExtensionFilter {
+            allow: normalize(allow),
+            deny: normalize(deny),
+            removed: Arc::default(),
+        }
+    }
+
+    /// The removed entries and why.
+    pub fn removed(&self) -> Vec<(PathBuf, String)> {
+        self.removed.lock().unwrap().clone()
+    }
+
+    fn reason(&self, path: &Path) -> Option<String> {
+        if let Some(ext) = find(&self.deny, path) {
+            return Some(format!("denied {}", ext));
+        }
+        (!self.allow.is_empty() && find(&self.allow, path).is_none())
+            .then(|| "extension not allowed".to_string())
+    }
+}
+
+impl EntryFilter for ExtensionFilter {
+    fn decide(&self, entry: &EntryInfo, _content: Option<&[u8]>) -> Result<Decision> {
+        if entry.kind == EntryKind::Directory {
+            return Ok(Decision::Keep);
+        }
+        let Some(reason) = self.reason(&entry.path) else {
+            return Ok(Decision::Keep);
+        };
+        self.removed
+            .lock()
+            .unwrap()
+            .push((entry.path.clone(), reason));
+        Ok(Decision::Remove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, kind: EntryKind) -> EntryInfo {
+        EntryInfo {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: None,
+            kind,
+            depth: 0,
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn test_extension_filter() {
+        let deny = ExtensionFilter::new(&[], &["pdb".to_string(), ".MAP".to_string()]);
+        for (path, decision) in [
+            ("bin/app.pdb", Decision::Remove),
+            ("dist/app.js.map", Decision::Remove),
+            ("dist/APP.JS.MAP", Decision::Remove),
+            ("dist/app.js", Decision::Keep),
+            // the whole name is not an extension
+            ("dist/.map", Decision::Keep),
+        ] {
+            assert_eq!(
+                deny.decide(&entry(path, EntryKind::File), None).unwrap(),
+                decision,
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            deny.removed()[0],
+            (PathBuf::from("bin/app.pdb"), "denied .pdb".to_string())
+        );
+
+        let allow = ExtensionFilter::new(&["rs".to_string(), "tar.gz".to_string()], &[]);
+        for (path, kind, decision) in [
+            ("src/main.rs", EntryKind::File, Decision::Keep),
+            ("vendor/deps.tar.gz", EntryKind::File, Decision::Keep),
+            ("vendor/deps.gz", EntryKind::File, Decision::Remove),
+            ("Makefile", EntryKind::File, Decision::Remove),
+            ("src", EntryKind::Directory, Decision::Keep),
+        ] {
+            assert_eq!(
+                allow.decide(&entry(path, kind), None).unwrap(),
+                decision,
+                "{}",
+                path
+            );
+        }
+    }
+}
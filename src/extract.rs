@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::archive::create_tar_decoder;
+use crate::util::infer_input_file;
+
+/// Extracts an archive into `dir`, keeping permissions and symlinks.
+///
+/// Entries that would resolve outside of `dir`, through `..`, absolute paths
+/// or symlinks, are not written.
+pub fn extract_archive(archive_bytes: &[u8], dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mime_type = infer_input_file(archive_bytes)?;
+    if mime_type == "application/zip" {
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive_bytes))?;
+        return zip.extract(dir).map_err(|err| {
+            anyhow!(
+                "Extraction Failed: The archive could not be extracted to '{}' ({}).",
+                dir.display(),
+                err
+            )
+        });
+    }
+
+    let mut tar = tar::Archive::new(create_tar_decoder(archive_bytes, &mime_type)?);
+    tar.set_preserve_permissions(true);
+    tar.set_overwrite(true);
+    tar.unpack(dir).map_err(|err| {
+        anyhow!(
+            "Extraction Failed: The archive could not be extracted to '{}' ({}).",
+            dir.display(),
+            err
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::generate_archive;
+
+    #[test]
+    fn test_extract_archive() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let archive = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        extract_archive(&archive, dir.path()).unwrap();
+        assert!(dir.path().join("tar-test/nested/nested.zip").is_file());
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        extract_archive(&generate_archive("zip", 2, 20).unwrap(), dir.path()).unwrap();
+        assert!(dir.path().join("bench/0000/00000001.dat").is_file());
+    }
+
+    #[test]
+    fn test_extract_archive_outside() {
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        // bypasses the path checks of Header::set_path
+        header.as_gnu_mut().unwrap().name[..10].copy_from_slice(b"../escaped");
+        header.set_cksum();
+        tar.append(&header, b"data".as_slice()).unwrap();
+        let archive = tar.into_inner().unwrap();
+
+        let parent = assert_fs::TempDir::new().unwrap();
+        let dir = parent.path().join("out");
+        // tar skips such entries
+        extract_archive(&archive, &dir).unwrap();
+        assert!(!parent.path().join("escaped").exists());
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+
+use crate::reader::EntryInfo;
+
+/// Outcome of an [`EntryFilter`] for a single entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Keep,
+    Remove,
+    /// Keep the entry with the given content instead of the original one.
+    Replace(Vec<u8>),
+}
+
+/// Custom policy deciding what happens to each entry.
+///
+/// `content` is only provided for regular files. Filters run after the
+/// filter list, in the order they were registered; the first filter
+/// removing an entry wins and replacements are passed on to the next one.
+pub trait EntryFilter: Send + Sync {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision>;
+}
+
+impl<F> EntryFilter for F
+where
+    F: Fn(&EntryInfo, Option<&[u8]>) -> Result<Decision> + Send + Sync,
+{
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        self(entry, content)
+    }
+}
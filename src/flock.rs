@@ -0,0 +1,85 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+/// Advisory lock on a file, held until dropped or the process ends. Other
+/// processes taking it fail instead of waiting. Locks are only taken on
+/// Unix, elsewhere acquiring always succeeds.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Locks the file at `path` through its `<path>.lock` sidecar, failing if
+    /// another process holds the lock. The file itself is replaced by a
+    /// rename when rewritten, a lock on it would not outlive the rewrite.
+    /// The sidecar is left in place, removing it would race with the next
+    /// process opening it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sidecar(path))?;
+        if !try_lock(&file)? {
+            Err(anyhow!(
+                "File Locked: '{}' is being rewritten by another expurgator process.",
+                path.display()
+            ))?;
+        }
+        Ok(FileLock { _file: file })
+    }
+}
+
+/// The lock file of `path`, `<path>.lock`.
+fn sidecar(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".lock");
+    PathBuf::from(sidecar)
+}
+
+/// Takes the exclusive lock of `file` without waiting, false if it is held
+/// through another open file.
+#[cfg(unix)]
+pub fn try_lock(file: &File) -> Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.kind() {
+        std::io::ErrorKind::WouldBlock => Ok(false),
+        _ => Err(err)?,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn try_lock(_: &File) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_lock() {
+        let file = assert_fs::NamedTempFile::new("artifact.zip").unwrap();
+        std::fs::write(file.path(), b"zip").unwrap();
+        let lock = FileLock::acquire(file.path()).unwrap();
+        // locks are held by open files, a second one conflicts in-process too
+        let err = FileLock::acquire(file.path()).unwrap_err();
+        assert!(err.to_string().starts_with("File Locked"));
+        // the lock survives the file being replaced
+        std::fs::write(file.path().with_extension("new"), b"zip").unwrap();
+        std::fs::rename(file.path().with_extension("new"), file.path()).unwrap();
+        assert!(FileLock::acquire(file.path()).is_err());
+        drop(lock);
+        assert!(FileLock::acquire(file.path()).is_ok());
+    }
+}
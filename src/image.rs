@@ -0,0 +1,207 @@
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+fn png_chunks(content: &[u8]) -> Result<Vec<(&[u8], &[u8])>> {
+    let mut chunks = Vec::new();
+    let mut rest = &content[PNG_SIGNATURE.len()..];
+    while !rest.is_empty() {
+        let invalid = || anyhow!("Invalid PNG: A chunk is truncated.");
+        let length = u32::from_be_bytes(rest.get(..4).ok_or_else(invalid)?.try_into()?) as usize;
+        let end = length.checked_add(12).ok_or_else(invalid)?;
+        let chunk = rest.get(..end).ok_or_else(invalid)?;
+        chunks.push((&chunk[4..8], &chunk[8..8 + length]));
+        rest = &rest[end..];
+    }
+    Ok(chunks)
+}
+
+fn write_png_chunk(output: &mut Vec<u8>, kind: &[u8], data: &[u8]) -> Result<()> {
+    output.extend_from_slice(&u32::try_from(data.len())?.to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    output.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(())
+}
+
+/// Recompresses the image data of a PNG at the best zlib level, merged into
+/// a single IDAT chunk. The pixels and the other chunks are left unchanged.
+pub fn optimize_png(content: &[u8]) -> Result<Vec<u8>> {
+    let chunks = png_chunks(content)?;
+    let compressed: Vec<u8> = chunks
+        .iter()
+        .filter(|(kind, _)| *kind == b"IDAT")
+        .flat_map(|(_, data)| data.iter().copied())
+        .collect();
+    let mut pixels = Vec::new();
+    ZlibDecoder::new(compressed.as_slice())
+        .read_to_end(&mut pixels)
+        .map_err(|err| anyhow!("Invalid PNG: The image data is corrupt ({}).", err))?;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&pixels)?;
+    let recompressed = encoder.finish()?;
+
+    let mut output = PNG_SIGNATURE.to_vec();
+    let mut idat_written = false;
+    for (kind, data) in chunks {
+        if kind != b"IDAT" {
+            write_png_chunk(&mut output, kind, data)?;
+        } else if !idat_written {
+            write_png_chunk(&mut output, kind, &recompressed)?;
+            idat_written = true;
+        }
+    }
+    Ok(output)
+}
+
+/// Drops the comments and the XMP and Photoshop metadata of a JPEG. Markers
+/// affecting how it renders (JFIF, Exif, ICC profiles, Adobe) and the
+/// compressed image data are kept as they are, the entropy coding is not
+/// optimized.
+pub fn strip_jpeg_metadata(content: &[u8]) -> Result<Vec<u8>> {
+    let mut output = content[..2].to_vec();
+    let mut rest = &content[2..];
+    loop {
+        let invalid = || anyhow!("Invalid JPEG: A segment is truncated.");
+        let marker = *rest
+            .get(1)
+            .filter(|_| rest[0] == 0xff)
+            .ok_or_else(invalid)?;
+        // the entropy coded data following the scan header is kept as is
+        if marker == 0xda || marker == 0xd9 {
+            output.extend_from_slice(rest);
+            return Ok(output);
+        }
+        // markers without a segment
+        if matches!(marker, 0x01 | 0xd0..=0xd7) {
+            output.extend_from_slice(&rest[..2]);
+            rest = &rest[2..];
+            continue;
+        }
+        let length = u16::from_be_bytes(rest.get(2..4).ok_or_else(invalid)?.try_into()?) as usize;
+        let segment = rest.get(..2 + length).ok_or_else(invalid)?;
+        let metadata = match marker {
+            0xfe => true,
+            0xe1 => segment
+                .get(4..)
+                .is_some_and(|data| data.starts_with(XMP_NAMESPACE)),
+            0xed => true,
+            _ => false,
+        };
+        if !metadata {
+            output.extend_from_slice(segment);
+        }
+        rest = &rest[2 + length..];
+    }
+}
+
+/// Optimizes PNG images losslessly, other content and images that would not
+/// shrink are kept as they are.
+pub fn optimize_image(content: &[u8]) -> Result<Vec<u8>> {
+    if !content.starts_with(PNG_SIGNATURE) {
+        return Ok(content.to_vec());
+    }
+    let optimized = optimize_png(content)?;
+    if optimized.len() < content.len() {
+        Ok(optimized)
+    } else {
+        Ok(content.to_vec())
+    }
+}
+
+/// Strips the metadata of JPEG images, other content is kept as it is.
+pub fn strip_image_metadata(content: &[u8]) -> Result<Vec<u8>> {
+    if content.starts_with(b"\xff\xd8\xff") {
+        strip_jpeg_metadata(content)
+    } else {
+        Ok(content.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 64x64 grayscale PNG with its image data stored uncompressed in two
+    /// IDAT chunks.
+    fn png() -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for _ in 0..64 {
+            pixels.push(0);
+            pixels.extend(0..64u8);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&pixels).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        let header = [0, 0, 0, 64, 0, 0, 0, 64, 8, 0, 0, 0, 0];
+        write_png_chunk(&mut png, b"IHDR", &header).unwrap();
+        write_png_chunk(&mut png, b"tEXt", b"Comment\0kept").unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        write_png_chunk(&mut png, b"IDAT", first).unwrap();
+        write_png_chunk(&mut png, b"IDAT", second).unwrap();
+        write_png_chunk(&mut png, b"IEND", b"").unwrap();
+        png
+    }
+
+    fn png_pixels(content: &[u8]) -> Vec<u8> {
+        let compressed: Vec<u8> = png_chunks(content)
+            .unwrap()
+            .into_iter()
+            .filter(|(kind, _)| *kind == b"IDAT")
+            .flat_map(|(_, data)| data.to_vec())
+            .collect();
+        let mut pixels = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut pixels)
+            .unwrap();
+        pixels
+    }
+
+    #[test]
+    fn test_optimize_png() {
+        let input = png();
+        let output = optimize_image(&input).unwrap();
+        assert!(output.len() < input.len());
+        assert_eq!(png_pixels(&output), png_pixels(&input));
+        let kinds: Vec<_> = png_chunks(&output)
+            .unwrap()
+            .into_iter()
+            .map(|(kind, _)| String::from_utf8_lossy(kind).to_string())
+            .collect();
+        assert_eq!(kinds, ["IHDR", "tEXt", "IDAT", "IEND"]);
+        // already optimal
+        assert_eq!(optimize_image(&output).unwrap(), output);
+    }
+
+    #[test]
+    fn test_strip_jpeg_metadata() {
+        let mut input = b"\xff\xd8".to_vec();
+        input.extend_from_slice(b"\xff\xe0\x00\x06JFIF");
+        input.extend_from_slice(b"\xff\xfe\x00\x0aComment!");
+        let mut xmp = b"\xff\xe1\x00\x00".to_vec();
+        xmp.extend_from_slice(XMP_NAMESPACE);
+        xmp.extend_from_slice(b"<x:xmpmeta/>");
+        let length = (xmp.len() - 2) as u16;
+        xmp[2..4].copy_from_slice(&length.to_be_bytes());
+        input.extend_from_slice(&xmp);
+        input.extend_from_slice(b"\xff\xda\x00\x02\x12\x34\xff\xd9");
+
+        let output = strip_image_metadata(&input).unwrap();
+        assert_eq!(
+            output,
+            b"\xff\xd8\xff\xe0\x00\x06JFIF\xff\xda\x00\x02\x12\x34\xff\xd9"
+        );
+        assert_eq!(strip_image_metadata(b"plain text").unwrap(), b"plain text");
+        // metadata is only dropped when asked for
+        assert_eq!(optimize_image(&input).unwrap(), input);
+    }
+}
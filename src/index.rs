@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::reader::{ArchiveReader, EntryInfo};
+use crate::rule::Rule;
+use crate::util::sha256_hex;
+
+/// Sidecar index of an archive's entries.
+///
+/// The index is keyed by the hash of the input, so a cached index is only
+/// reused for the exact same archive.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntryIndex {
+    pub input_sha256: String,
+    pub entries: Vec<EntryInfo>,
+}
+
+impl EntryIndex {
+    pub fn build(input_bytes: &[u8]) -> Result<Self> {
+        let entries = ArchiveReader::new(input_bytes.to_vec())
+            .entries()?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(EntryIndex {
+            input_sha256: sha256_hex(input_bytes),
+            entries,
+        })
+    }
+
+    /// Loads the index stored at `path` if it was built from `input_bytes`,
+    /// otherwise scans the input and stores a fresh index there.
+    ///
+    /// The flag tells whether the cached index was used.
+    pub fn cached<P: AsRef<Path>>(path: P, input_bytes: &[u8]) -> Result<(Self, bool)> {
+        let path = path.as_ref();
+        let input_sha256 = sha256_hex(input_bytes);
+        if let Ok(bytes) = std::fs::read(path) {
+            match serde_json::from_slice::<EntryIndex>(&bytes) {
+                Ok(index) if index.input_sha256 == input_sha256 => return Ok((index, true)),
+                _ => {}
+            }
+        }
+        let index = EntryIndex::build(input_bytes)?;
+        index.save(path)?;
+        Ok((index, false))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_vec(self)?).map_err(|err| {
+            anyhow!(
+                "Index Cache Error: Failed to write '{}': {}",
+                path.display(),
+                err
+            )
+        })
+    }
+
+    /// Splits the filter list into entries found in the archive and entries
+    /// that would not match anything.
+    pub fn matches<'a>(&self, filter_list: &'a [PathBuf]) -> (Vec<&'a PathBuf>, Vec<&'a PathBuf>) {
+        filter_list.iter().partition(|filter| {
+            self.entries
+                .iter()
+                .any(|e| Rule::new(filter).matches_listed(&e.path, e.depth))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_index_cache() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let file = assert_fs::NamedTempFile::new("index.json").unwrap();
+
+        let (index, cached) = EntryIndex::cached(file.path(), &input).unwrap();
+        assert!(!cached);
+        let (cached_index, cached) = EntryIndex::cached(file.path(), &input).unwrap();
+        assert!(cached);
+        assert_eq!(cached_index.entries, index.entries);
+
+        let filter_list = vec![
+            PathBuf::from("tar-test/file_1.txt"),
+            PathBuf::from("tar-test/missing.txt"),
+        ];
+        let (found, missing) = index.matches(&filter_list);
+        assert_eq!(found, vec![&filter_list[0]]);
+        assert_eq!(missing, vec![&filter_list[1]]);
+
+        let other = std::fs::read("tests/archives/tar-test.tar.xz").unwrap();
+        assert!(!EntryIndex::cached(file.path(), &other).unwrap().1);
+    }
+}
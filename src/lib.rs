@@ -0,0 +1,133 @@
+//! Removes unwanted files from archives: zip, tar in any of its compressed
+//! forms, cpio and initramfs images, nested archives included, rewriting
+//! them in a single pass.
+//!
+//! [`Expurgator`] runs a scrub the way the command line tool does, given the
+//! input archive, the paths to remove and the options. [`pack_archive`] with
+//! a [`Context`] is the lower level entry point, [`EntryFilter`]s remove or
+//! rewrite entries by custom policies.
+//!
+//! ```no_run
+//! use expurgator::Expurgator;
+//!
+//! let scrubbed = Expurgator::new(std::fs::read("app.zip")?)
+//!     .with_exclusions(["app/debug.log"])
+//!     .run()?;
+//! std::fs::write("app-clean.zip", scrubbed.archive)?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+mod archive;
+mod autotune;
+mod bench;
+mod cache;
+mod cancel;
+mod chunks;
+mod clamav;
+mod classify;
+mod context;
+mod cpio;
+mod dictionary;
+mod directory;
+mod elf;
+mod entropy;
+mod estimate;
+mod extract;
+mod filter;
+mod flock;
+mod image;
+mod index;
+mod list;
+mod locale;
+mod memory;
+mod merge;
+mod metadata;
+mod minify;
+mod owner;
+mod pipeline;
+mod plan;
+mod progress;
+mod provenance;
+mod ratelimit;
+mod reader;
+mod report;
+mod rsyncable;
+mod rule;
+mod scrub;
+mod signature;
+mod sink;
+mod split;
+mod symlink;
+mod transform;
+mod untrusted;
+mod util;
+mod windows;
+mod workspace;
+mod xz;
+mod zipfmt;
+
+pub use archive::{pack_archive, pack_reader_into};
+pub use cancel::CancellationToken;
+pub use context::Context;
+pub use filter::{Decision, EntryFilter};
+pub use progress::{NoProgress, Progress};
+pub use reader::{ArchiveReader, EntryInfo, EntryKind, NESTED_SEPARATOR};
+pub use scrub::{Expurgator, Scrubbed};
+pub use sink::{FileSink, SeekWrite, Sink, WriteSink};
+pub use util::parse_csv;
+
+// named by the signatures of the items above
+pub use archive::TarFormat;
+pub use context::{Duplicates, Encrypted, EntryOrder, MatchMode, OnError, Symlinks};
+pub use owner::{OwnerMap, OwnerRule};
+pub use provenance::{Provenance, ProvenanceMode, StoredRecord};
+pub use ratelimit::RateLimit;
+pub use reader::Entries;
+pub use report::Occurrence;
+pub use sink::{Clobber, OutputOptions};
+pub use untrusted::Untrusted;
+pub use xz::XzOptions;
+
+/// What the `expurgator` binary builds on besides the library API, the parts
+/// only it needs are modules of the binary. Not part of the API, it changes
+/// without notice.
+#[doc(hidden)]
+pub mod internal {
+    pub use crate::archive::{pack_archive_into, pack_initramfs, TAR_FORMATS};
+    pub use crate::autotune::{autotune, Target, DEFAULT_LEVEL};
+    pub use crate::bench::{run_bench, BENCH_FORMATS};
+    pub use crate::chunks::{ChunkHashes, CHUNK_HASHES_SUFFIX};
+    pub use crate::clamav::{ClamavFilter, ClamdAddress, Detection};
+    pub use crate::classify::ClassFilter;
+    pub use crate::context::{
+        DUPLICATES_POLICIES, ENCRYPTED_POLICIES, ENTRY_ORDERS, ON_ERROR_POLICIES, SYMLINK_POLICIES,
+    };
+    pub use crate::dictionary::ZstdDict;
+    pub use crate::directory::{archive_format, pack_directory};
+    pub use crate::entropy::{Anomaly, EntropyCheck};
+    pub use crate::estimate::{estimate_output, estimate_reader};
+    pub use crate::extract::extract_archive;
+    pub use crate::flock::FileLock;
+    pub use crate::index::EntryIndex;
+    pub use crate::list::{
+        format_size, largest, render_diff_tree, render_tree, write_csv, NestedGraph,
+    };
+    pub use crate::locale::{messages, set_messages, Messages};
+    pub use crate::merge::{merge_archives, ConflictPolicy};
+    pub use crate::metadata::{compare_metadata, read_metadata};
+    pub use crate::plan::Plan;
+    pub use crate::provenance::PROVENANCE_MODES;
+    pub use crate::ratelimit::Throttled;
+    pub use crate::report::{parse_report, RunReport, Status};
+    pub use crate::rule::Rule;
+    pub use crate::signature::YaraFilter;
+    pub use crate::sink::DEFAULT_OUTPUT_DIR;
+    pub use crate::split::{is_zip_volume, read_zip_volumes, read_zip_volumes_limited, split_zip};
+    pub use crate::transform::{parse_normalize_eol, Transformer};
+    pub use crate::util::{
+        infer_input_file, output_file, output_path, parse_compression, parse_compression_setting,
+        parse_count, parse_duration, parse_mode, parse_size, parse_timestamp, prompt_password,
+        prompt_summary, set_mtime, sha256_hex, to_bytes, to_file, to_split_files, to_zip_volumes,
+    };
+    pub use crate::windows::check_entries;
+}
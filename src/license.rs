@@ -0,0 +1,171 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use regex::Regex;
+
+use expurgator::{Decision, EntryFilter, EntryInfo};
+
+/// License headers are looked for in the first bytes of a file only.
+const HEADER_SIZE: usize = 8 << 10;
+
+fn spdx_identifier() -> &'static Regex {
+    static SPDX: OnceLock<Regex> = OnceLock::new();
+    SPDX.get_or_init(|| Regex::new(r"SPDX-License-Identifier:\s*([A-Za-z0-9.+-]+)").unwrap())
+}
+
+/// Version suffix of a GNU license header, e.g. `-3.0-or-later`.
+fn gnu_version(header: &str) -> String {
+    let version = if header.contains("version 3") {
+        "3.0"
+    } else if header.contains("version 2.1") {
+        "2.1"
+    } else if header.contains("version 2") {
+        "2.0"
+    } else {
+        return String::new();
+    };
+    if header.contains("any later version") {
+        format!("-{}-or-later", version)
+    } else {
+        format!("-{}-only", version)
+    }
+}
+
+/// Classifies text content by its license header: the SPDX identifier if
+/// present, else GNU license notices as their SPDX id, else `proprietary`
+/// for proprietary or confidential markers.
+pub fn detect_license(content: &[u8]) -> Option<String> {
+    let header = &content[..content.len().min(HEADER_SIZE)];
+    if header.contains(&0) {
+        return None;
+    }
+    let header = String::from_utf8_lossy(header);
+    if let Some(captures) = spdx_identifier().captures(&header) {
+        return Some(captures[1].to_string());
+    }
+
+    let header = header.to_lowercase();
+    let gnu_license = if header.contains("gnu affero general public license") {
+        Some("AGPL")
+    } else if header.contains("gnu lesser general public license")
+        || header.contains("gnu library general public license")
+    {
+        Some("LGPL")
+    } else if header.contains("gnu general public license") {
+        Some("GPL")
+    } else {
+        None
+    };
+    if let Some(license) = gnu_license {
+        return Some(format!("{}{}", license, gnu_version(&header)));
+    }
+    if header.contains("proprietary") || header.contains("confidential") {
+        return Some("proprietary".to_string());
+    }
+    None
+}
+
+/// Whether `license` is excluded by `rule`, which also covers its versions
+/// and variants: `GPL` excludes `GPL-2.0-only` and `GPL-3.0` excludes
+/// `GPL-3.0-or-later`, but `GPL` does not exclude `AGPL-3.0-only`.
+fn excludes(rule: &str, license: &str) -> bool {
+    let (rule, license) = (rule.to_lowercase(), license.to_lowercase());
+    let Some(rest) = license.strip_prefix(&rule) else {
+        return false;
+    };
+    rest.is_empty()
+        || rest == "+"
+        || rest.starts_with("-only")
+        || rest.starts_with("-or-later")
+        || rest.strip_prefix('-').is_some_and(|rest| {
+            // a version such as 3.0, unlike the separate license MIT-0
+            let version = rest.split(['-', '+']).next().unwrap_or_default();
+            version.starts_with(|c: char| c.is_ascii_digit()) && version.contains('.')
+        })
+}
+
+/// An [`EntryFilter`] removing the files whose license header matches one
+/// of the excluded licenses.
+///
+/// Clones share the recorded removals.
+#[derive(Clone)]
+pub struct LicenseFilter {
+    excluded: Arc<Vec<String>>,
+    removed: Arc<Mutex<Vec<(PathBuf, String)>>>,
+}
+
+impl LicenseFilter {
+    pub fn new(excluded: Vec<String>) -> Self {
+        LicenseFilter {
+            excluded: Arc::new(excluded),
+            removed: Arc::default(),
+        }
+    }
+
+    /// The removed entries and their detected license.
+    pub fn removed(&self) -> Vec<(PathBuf, String)> {
+        self.removed.lock().unwrap().clone()
+    }
+}
+
+impl EntryFilter for LicenseFilter {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let Some(license) = content.and_then(detect_license) else {
+            return Ok(Decision::Keep);
+        };
+        if !self.excluded.iter().any(|rule| excludes(rule, &license)) {
+            return Ok(Decision::Keep);
+        }
+        self.removed
+            .lock()
+            .unwrap()
+            .push((entry.path.clone(), license));
+        Ok(Decision::Remove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_license() {
+        assert_eq!(
+            detect_license(b"// SPDX-License-Identifier: MIT\nfn main() {}\n").as_deref(),
+            Some("MIT")
+        );
+        assert_eq!(
+            detect_license(
+                b"# This program is free software: you can redistribute it under the terms\n\
+                  # of the GNU Affero General Public License as published by the Free\n\
+                  # Software Foundation, either version 3 of the License, or (at your\n\
+                  # option) any later version.\n"
+            )
+            .as_deref(),
+            Some("AGPL-3.0-or-later")
+        );
+        assert_eq!(
+            detect_license(b"/* GNU General Public License version 2 */").as_deref(),
+            Some("GPL-2.0-only")
+        );
+        assert_eq!(
+            detect_license(b"// Copyright ACME. Proprietary and confidential.").as_deref(),
+            Some("proprietary")
+        );
+        assert_eq!(detect_license(b"fn main() {}\n"), None);
+        assert_eq!(detect_license(b"\0ELF GNU General Public License"), None);
+    }
+
+    #[test]
+    fn test_excludes() {
+        assert!(excludes("AGPL-3.0", "AGPL-3.0-or-later"));
+        assert!(excludes("agpl-3.0-only", "AGPL-3.0-only"));
+        assert!(excludes("GPL", "GPL-2.0-only"));
+        assert!(!excludes("GPL", "AGPL-3.0-only"));
+        assert!(excludes("GPL-2.0", "GPL-2.0+"));
+        assert!(!excludes("MIT", "MIT-0"));
+    }
+}
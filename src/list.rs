@@ -0,0 +1,466 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::reader::{EntryInfo, EntryKind};
+
+/// Style of removed entries in colored trees: red, struck through.
+const REMOVED_STYLE: &str = "\x1b[9;31m";
+/// Style of counts of removed entries in colored trees: red.
+const COUNT_STYLE: &str = "\x1b[31m";
+const RESET_STYLE: &str = "\x1b[0m";
+
+#[derive(Default)]
+struct TreeNode {
+    kind: Option<EntryKind>,
+    size: u64,
+    /// Removed by the scrub, for diff trees.
+    removed: bool,
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    fn insert(&mut self, entry: &EntryInfo) {
+        self.insert_diff(entry, false);
+    }
+
+    fn insert_diff(&mut self, entry: &EntryInfo, removed: bool) {
+        let mut node = self;
+        for component in entry.path.components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name).or_default();
+        }
+        node.kind = Some(entry.kind);
+        node.size = entry.size;
+        node.removed = removed;
+    }
+
+    fn is_archive(&self) -> bool {
+        self.kind == Some(EntryKind::File) && !self.children.is_empty()
+    }
+
+    /// Cumulative size and entry count below this node. Nested archives
+    /// count with their own size, their content is rolled up separately.
+    fn rollup(&self) -> (u64, usize) {
+        self.children.values().fold((0, 0), |(size, count), child| {
+            let (child_size, child_count) = if child.is_archive() {
+                (0, 0)
+            } else {
+                child.rollup()
+            };
+            let own_size = match child.kind {
+                Some(EntryKind::File) => child.size,
+                _ => 0,
+            };
+            (size + own_size + child_size, count + 1 + child_count)
+        })
+    }
+
+    fn label(&self, name: &str) -> String {
+        let (size, count) = self.rollup();
+        if self.is_archive() {
+            format!(
+                "{} [archive] {} ({} entries, {} unpacked)",
+                name,
+                format_size(self.size),
+                count,
+                format_size(size)
+            )
+        } else if !self.children.is_empty() || self.kind == Some(EntryKind::Directory) {
+            format!("{}/ ({} entries, {})", name, count, format_size(size))
+        } else {
+            match self.kind {
+                Some(EntryKind::Symlink) => format!("{} [symlink]", name),
+                Some(EntryKind::HardLink) => format!("{} [hard link]", name),
+                _ => format!("{} {}", name, format_size(self.size)),
+            }
+        }
+    }
+
+    /// Removed entries below this node and all entries below it, those of
+    /// nested archives included.
+    fn removals(&self) -> (usize, usize) {
+        self.children
+            .values()
+            .fold((0, 0), |(removed, total), child| {
+                let (child_removed, child_total) = child.removals();
+                (
+                    removed + usize::from(child.removed) + child_removed,
+                    total + 1 + child_total,
+                )
+            })
+    }
+
+    /// Whether the children are listed in diff trees: only where some of
+    /// them, but not all, are removed.
+    fn expanded(&self) -> bool {
+        let (removed, total) = self.removals();
+        !self.removed && removed > 0 && removed < total
+    }
+
+    fn diff_label(&self, name: &str, color: bool) -> String {
+        let (removed, total) = self.removals();
+        let style = |text: String, style: &str| match color {
+            true => format!("{}{}{}", style, text, RESET_STYLE),
+            false => text,
+        };
+        let name = if self.is_archive() {
+            format!("{} [archive]", name)
+        } else if !self.children.is_empty() || self.kind == Some(EntryKind::Directory) {
+            format!("{}/", name)
+        } else {
+            self.label(name)
+        };
+        if self.removed {
+            let label = match total {
+                0 => name,
+                _ => format!("{} ({} entries)", name, total),
+            };
+            return match color {
+                true => style(label, REMOVED_STYLE),
+                false => format!("{} [removed]", label),
+            };
+        }
+        match (removed, total) {
+            (_, 0) => name,
+            (0, _) => format!("{} ({} entries)", name, total),
+            _ if removed == total => format!(
+                "{} {}",
+                name,
+                style(format!("(all {} entries removed)", total), COUNT_STYLE)
+            ),
+            _ => format!(
+                "{} {}",
+                name,
+                style(
+                    format!("({} of {} entries removed)", removed, total),
+                    COUNT_STYLE
+                )
+            ),
+        }
+    }
+
+    fn render_diff(&self, prefix: &str, color: bool, output: &mut String) {
+        let last = self.children.len().saturating_sub(1);
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            let (branch, indent) = if index == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let _ = writeln!(
+                output,
+                "{}{}{}",
+                prefix,
+                branch,
+                child.diff_label(name, color)
+            );
+            if child.expanded() {
+                child.render_diff(&format!("{}{}", prefix, indent), color, output);
+            }
+        }
+    }
+
+    fn render(&self, prefix: &str, output: &mut String) {
+        let last = self.children.len().saturating_sub(1);
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            let (branch, indent) = if index == last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let _ = writeln!(output, "{}{}{}", prefix, branch, child.label(name));
+            child.render(&format!("{}{}", prefix, indent), output);
+        }
+    }
+}
+
+/// Renders the entries as a directory tree with cumulative sizes and entry
+/// counts per directory; nested archives show up as nodes of their own.
+pub fn render_tree(entries: &[EntryInfo]) -> String {
+    let mut root = TreeNode::default();
+    for entry in entries {
+        root.insert(entry);
+    }
+    let mut output = String::new();
+    for (name, node) in &root.children {
+        let _ = writeln!(output, "{}", node.label(name));
+        node.render("", &mut output);
+    }
+    output
+}
+
+/// Renders the entries as a directory tree marking the `removed` ones, in
+/// red and struck through with `color`, else with `[removed]`. Directories
+/// and nested archives are collapsed to their entry counts unless some of
+/// their entries, but not all, are removed.
+pub fn render_diff_tree(entries: &[EntryInfo], removed: &[EntryInfo], color: bool) -> String {
+    let removed: HashSet<_> = removed.iter().map(|entry| &entry.path).collect();
+    let mut root = TreeNode::default();
+    for entry in entries {
+        root.insert_diff(entry, removed.contains(&entry.path));
+    }
+    let mut output = String::new();
+    for (name, node) in &root.children {
+        let _ = writeln!(output, "{}", node.diff_label(name, color));
+        if node.expanded() {
+            node.render_diff("", color, &mut output);
+        }
+    }
+    output
+}
+
+/// The `count` largest files, biggest first.
+pub fn largest(entries: &[EntryInfo], count: usize) -> Vec<&EntryInfo> {
+    let mut files: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.kind == EntryKind::File)
+        .collect();
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    files.truncate(count);
+    files
+}
+
+/// Writes entries as `path,size` CSV with a header record, the path column
+/// can be fed back as a filter list.
+pub fn write_csv<W: std::io::Write>(entries: &[&EntryInfo], writer: W) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["path", "size"])?;
+    for entry in entries {
+        writer.write_record([
+            entry.path.to_string_lossy().as_ref(),
+            &entry.size.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// An archive of the containment graph.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct GraphNode {
+    /// Path within the outermost archive, its file name for itself.
+    pub path: String,
+    pub size: u64,
+    /// Number of entries directly within the archive.
+    pub entries: usize,
+    /// Size of the files directly within the archive.
+    pub unpacked: u64,
+}
+
+/// `from` contains `to`, both indexes of [`NestedGraph::nodes`].
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Which archives contain which, the outermost one first.
+#[derive(Debug, Serialize)]
+pub struct NestedGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl NestedGraph {
+    /// Builds the graph of the archive `name` of `size` bytes from its
+    /// entries, in the order they are listed: the content of a nested
+    /// archive follows its entry.
+    pub fn new(name: &str, size: u64, entries: &[EntryInfo]) -> Self {
+        let mut graph = NestedGraph {
+            nodes: vec![GraphNode {
+                path: name.to_string(),
+                size,
+                entries: 0,
+                unpacked: 0,
+            }],
+            edges: Vec::new(),
+        };
+        // the archives containing the current entry, outermost first
+        let mut stack = vec![0];
+        let mut previous: Option<&EntryInfo> = None;
+        for entry in entries {
+            if let Some(archive) = previous.filter(|previous| entry.depth == previous.depth + 1) {
+                graph.nodes.push(GraphNode {
+                    path: archive.path.to_string_lossy().into_owned(),
+                    size: archive.size,
+                    entries: 0,
+                    unpacked: 0,
+                });
+                let index = graph.nodes.len() - 1;
+                graph.edges.push(GraphEdge {
+                    from: stack[stack.len() - 1],
+                    to: index,
+                });
+                stack.push(index);
+            }
+            stack.truncate(entry.depth + 1);
+            let node = &mut graph.nodes[stack[stack.len() - 1]];
+            node.entries += 1;
+            if entry.kind == EntryKind::File {
+                node.unpacked += entry.size;
+            }
+            previous = Some(entry);
+        }
+        graph
+    }
+
+    /// Renders the graph in the DOT language of Graphviz, each archive
+    /// labeled with its name, size and content.
+    pub fn render_dot(&self) -> String {
+        let mut output = String::from("digraph archives {\n    node [shape=box];\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let name = node.path.rsplit('/').next().unwrap_or_default();
+            let _ = writeln!(
+                output,
+                "    n{} [label=\"{}\\n{}, {} entries, {} unpacked\", tooltip=\"{}\"];",
+                index,
+                escape_dot(name),
+                format_size(node.size),
+                node.entries,
+                format_size(node.unpacked),
+                escape_dot(&node.path)
+            );
+        }
+        for edge in &self.edges {
+            let _ = writeln!(output, "    n{} -> n{};", edge.from, edge.to);
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::ArchiveReader;
+
+    #[test]
+    fn test_render_tree() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let entries = ArchiveReader::new(input)
+            .entries()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let tree = render_tree(&entries);
+        let lines: Vec<_> = tree.lines().collect();
+        assert!(lines[0].starts_with("tar-test/ (8 entries, "));
+        assert!(lines.contains(&"├── file_1.txt 20 B"));
+        assert!(tree.contains("nestedtar.tar.xz [archive] 199 B ("));
+        assert!(tree.contains("file_link.txt [symlink]"));
+    }
+
+    #[test]
+    fn test_render_diff_tree() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let entries = ArchiveReader::new(input.clone())
+            .entries()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let removed = crate::estimate::estimate_output(&input, &["tar-test/file_1.txt".into()])
+            .unwrap()
+            .removed;
+        let tree = render_diff_tree(&entries, &removed, false);
+        let lines: Vec<_> = tree.lines().collect();
+        assert!(lines[0].starts_with("tar-test/ (1 of "));
+        assert!(lines.contains(&"├── file_1.txt 20 B [removed]"));
+        // nothing removed below, collapsed
+        assert!(tree.contains("── nested/ ("));
+        assert!(!tree.contains("nested.zip"));
+
+        let tree = render_diff_tree(&entries, &removed, true);
+        assert!(tree.contains("├── \x1b[9;31mfile_1.txt 20 B\x1b[0m\n"));
+        assert!(tree.contains("\x1b[31m(1 of "));
+    }
+
+    #[test]
+    fn test_largest_csv() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let entries = ArchiveReader::new(input)
+            .entries()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let top = largest(&entries, 2);
+        assert_eq!(top[0].path.to_str().unwrap(), "tar-test/nested/nested.zip");
+        assert_eq!(
+            top[1].path.to_str().unwrap(),
+            "tar-test/nested/nestedtar.tar.xz"
+        );
+
+        let mut output = Vec::new();
+        write_csv(&top, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "path,size\ntar-test/nested/nested.zip,916\ntar-test/nested/nestedtar.tar.xz,199\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_graph() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let size = input.len() as u64;
+        let entries = ArchiveReader::new(input)
+            .entries()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let graph = NestedGraph::new("tar-test.tar.gz", size, &entries);
+        assert_eq!(graph.nodes[0].path, "tar-test.tar.gz");
+        assert_eq!(graph.nodes[0].size, size);
+        let paths: Vec<_> = graph.nodes[1..].iter().map(|node| &node.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "tar-test/nested/nested.zip",
+                "tar-test/nested/nestedtar.tar.xz"
+            ]
+        );
+        assert_eq!(graph.nodes[2].size, 199);
+        assert_eq!(
+            graph.edges,
+            [GraphEdge { from: 0, to: 1 }, GraphEdge { from: 0, to: 2 }]
+        );
+        // every entry is counted in the archive directly containing it
+        let total: usize = graph.nodes.iter().map(|node| node.entries).sum();
+        assert_eq!(total, entries.len());
+
+        let dot = graph.render_dot();
+        assert!(dot.starts_with("digraph archives {\n"));
+        assert!(dot.contains("    n2 [label=\"nestedtar.tar.xz\\n199 B, "));
+        assert!(dot.contains("    n0 -> n2;\n"));
+        assert_eq!(escape_dot(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(20), "20 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 << 30), "3.0 GiB");
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// The English catalog, which translations are laid over.
+const ENGLISH: &str = include_str!("../locale/en.toml");
+
+/// Directory of translations set by packagers, searched first.
+pub const LOCALE_DIR_ENV: &str = "EXPURGATOR_LOCALE_DIR";
+
+const SYSTEM_LOCALE_DIR: &str = "/usr/share/expurgator/locale";
+
+/// Text of the interactive prompts, see `locale/en.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Messages {
+    pub confirm_filter: String,
+    pub confirm_filter_help: String,
+    pub summary_title: String,
+    pub summary_rules: String,
+    pub summary_no_rules: String,
+    pub summary_archives: String,
+    pub summary_entries: String,
+    pub summary_entry_filters: String,
+    pub summary_destinations: String,
+    pub continue_on_error: String,
+    pub continue_on_error_help: String,
+    pub archive_password: String,
+    pub output_password: String,
+    pub password_confirmation: String,
+    pub password_mismatch: String,
+    pub yes: Vec<String>,
+    pub no: Vec<String>,
+    pub default_no: String,
+    pub answered_yes: String,
+    pub answered_no: String,
+    pub invalid_answer: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        toml::from_str(ENGLISH).expect("the English catalog is valid")
+    }
+}
+
+impl Messages {
+    /// Loads the catalog of `lang`, e.g. `de` or `pt_BR`, or of the language
+    /// of the environment (`LC_ALL`, `LC_MESSAGES` or `LANG`) if not given.
+    /// An unknown language of the environment falls back to English, an
+    /// unknown `lang` is an error.
+    pub fn load(lang: Option<&str>) -> Result<Self> {
+        let dirs = locale_dirs();
+        let Some(lang) = lang else {
+            let catalog = env_lang()
+                .iter()
+                .flat_map(|lang| candidates(lang))
+                .find_map(|candidate| find_catalog(&dirs, &candidate));
+            return match catalog {
+                Some(path) => Messages::from_file(&path),
+                None => Ok(Messages::default()),
+            };
+        };
+        if let Some(path) = candidates(lang)
+            .iter()
+            .find_map(|candidate| find_catalog(&dirs, candidate))
+        {
+            return Messages::from_file(&path);
+        }
+        if candidates(lang).last().map(String::as_str) == Some("en") {
+            return Ok(Messages::default());
+        }
+        Err(anyhow!(
+            "Invalid Language: There is no {}.toml in {}.",
+            lang,
+            dirs.iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ))
+    }
+
+    /// Reads a translation, keys it lacks are taken from English.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Messages::from_translation(&content).map_err(|err| {
+            anyhow!(
+                "Invalid Language: The catalog '{}' is invalid: {}",
+                path.display(),
+                err
+            )
+        })
+    }
+
+    fn from_translation(content: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(ENGLISH)?;
+        let translation: toml::Table = toml::from_str(content)?;
+        table.extend(translation);
+        Ok(table.try_into()?)
+    }
+
+    /// `text` with each `{name}` of `values` replaced.
+    pub fn format(text: &str, values: &[(&str, &str)]) -> String {
+        values.iter().fold(text.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+    }
+
+    /// Parses an answer to a yes/no question.
+    pub fn parse_answer(&self, answer: &str) -> Option<bool> {
+        let answer = answer.trim();
+        let matches = |words: &[String]| words.iter().any(|word| word.eq_ignore_ascii_case(answer));
+        if matches(&self.yes) {
+            Some(true)
+        } else if matches(&self.no) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+static MESSAGES: OnceLock<Messages> = OnceLock::new();
+
+/// Sets the catalog of the prompts, only the first call has an effect.
+pub fn set_messages(messages: Messages) {
+    let _ = MESSAGES.set(messages);
+}
+
+/// The catalog of the prompts, English unless set.
+pub fn messages() -> &'static Messages {
+    MESSAGES.get_or_init(Messages::default)
+}
+
+fn locale_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = std::env::var_os(LOCALE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs.push(PathBuf::from(SYSTEM_LOCALE_DIR));
+    dirs
+}
+
+/// The language of the environment, the first set of the variables.
+fn env_lang() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Catalog names for a locale such as `pt_BR.UTF-8@euro`, most specific
+/// first: `pt_BR`, then `pt`.
+fn candidates(lang: &str) -> Vec<String> {
+    let lang = lang.split(['.', '@']).next().unwrap_or_default();
+    let mut candidates = vec![lang.to_string()];
+    if let Some((language, _)) = lang.split_once(['_', '-']) {
+        candidates.push(language.to_string());
+    }
+    candidates.retain(|candidate| {
+        !candidate.is_empty()
+            && candidate
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    });
+    candidates
+}
+
+fn find_catalog(dirs: &[PathBuf], lang: &str) -> Option<PathBuf> {
+    dirs.iter()
+        .map(|dir| dir.join(format!("{}.toml", lang)))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let english = Messages::default();
+        assert_eq!(english.confirm_filter, "Is this correct?");
+        assert_eq!(english.parse_answer(" YES "), Some(true));
+        assert_eq!(english.parse_answer("n"), Some(false));
+        assert_eq!(english.parse_answer("ja"), None);
+        assert_eq!(
+            Messages::format(&english.confirm_filter_help, &[("count", "3")]),
+            "File contains 3 records, first value:"
+        );
+
+        let german = Messages::from_translation(
+            "confirm_filter = \"Ist das richtig?\"\nyes = [\"j\", \"ja\"]\nno = [\"n\", \"nein\"]",
+        )
+        .unwrap();
+        assert_eq!(german.confirm_filter, "Ist das richtig?");
+        assert_eq!(german.parse_answer("Ja"), Some(true));
+        // missing keys are English
+        assert_eq!(german.continue_on_error, english.continue_on_error);
+        assert!(Messages::from_translation("confirm = \"?\"").is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(candidates("pt_BR.UTF-8@euro"), ["pt_BR", "pt"]);
+        assert_eq!(candidates("de"), ["de"]);
+        assert_eq!(candidates("C"), ["C"]);
+        assert!(candidates("../../etc/passwd").is_empty());
+
+        assert_eq!(Messages::load(Some("en_US")).unwrap(), Messages::default());
+        assert!(Messages::load(Some("xx")).is_err());
+    }
+}
@@ -1,48 +1,1373 @@
-mod archive;
 mod cli;
-mod util;
+mod config;
+mod encrypt;
+mod extension;
+mod license;
+mod manifest;
+mod open;
+mod plugin;
+mod policy;
+mod preview;
+mod remote;
+mod spinner;
+mod stats;
+mod summary;
+mod waiver;
 
-use anyhow::Result;
-use archive::pack_archive;
-use indicatif::ProgressBar;
-use indicatif::ProgressStyle;
-use std::time::Duration;
-use util::{parse_compression, parse_csv, prompt_csv, to_bytes, to_file};
+use std::{
+    fs::File,
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use clap::CommandFactory;
+use cli::{BenchArgs, CatArgs, CheckArgs, Command, ListArgs, MergeArgs, PlanArgs, StatsArgs};
+use encrypt::{
+    encrypt_stream, encrypted_entries, load_password, KeySource, StreamCipher, PASSWORD_ENV,
+};
+use expurgator::internal::{
+    archive_format, autotune, check_entries, compare_metadata, estimate_output, extract_archive,
+    format_size, infer_input_file, is_zip_volume, largest, merge_archives, messages, output_file,
+    output_path, pack_archive_into, pack_directory, pack_initramfs, parse_compression,
+    parse_compression_setting, parse_count, parse_duration, parse_mode, parse_normalize_eol,
+    parse_report, parse_size, parse_timestamp, prompt_password, prompt_summary, read_metadata,
+    read_zip_volumes, read_zip_volumes_limited, render_diff_tree, render_tree, run_bench,
+    set_messages, set_mtime, split_zip, to_bytes, to_file, to_split_files, to_zip_volumes,
+    write_csv, Anomaly, ChunkHashes, ClamavFilter, ClamdAddress, ClassFilter, Detection,
+    EntropyCheck, EntryIndex, FileLock, Messages, NestedGraph, Plan, Rule, RunReport, Status,
+    Target, Throttled, Transformer, YaraFilter, ZstdDict, CHUNK_HASHES_SUFFIX, DEFAULT_LEVEL,
+};
+use expurgator::{
+    pack_archive, pack_reader_into, parse_csv, ArchiveReader, CancellationToken, Clobber, Context,
+    Duplicates, Encrypted, EntryInfo, EntryKind, EntryOrder, FileSink, MatchMode, NoProgress,
+    Occurrence, OnError, OutputOptions, OwnerMap, OwnerRule, Progress, Provenance, ProvenanceMode,
+    RateLimit, Sink, StoredRecord, Symlinks, TarFormat, Untrusted, WriteSink, XzOptions,
+};
+use extension::ExtensionFilter;
+use inquire::ui::RenderConfig;
+use license::LicenseFilter;
+use manifest::Manifest;
+use open::Viewer;
+use plugin::NativePlugin;
+use policy::{annotation, Policy, Severity};
+use preview::preview;
+use remote::{upload_outputs, Remote, Retry};
+use spinner::Spinner;
+use stats::collect_stats;
+use summary::Summary;
+use waiver::{Waived, Waivers, IGNORE_FILE};
 
 fn main() -> Result<()> {
-    let args = cli::Args::from();
+    let mut args = cli::Args::from()?;
+    let color = args.color.enabled();
+    set_messages(Messages::load(args.lang.as_deref())?);
+    if !color {
+        inquire::set_global_render_config(RenderConfig::empty());
+    }
+
+    match args.command.take() {
+        Some(Command::Merge(merge_args)) => merge(merge_args, color),
+        Some(Command::List(list_args)) => list(list_args),
+        Some(Command::Stats(stats_args)) => stats(stats_args),
+        Some(Command::Cat(cat_args)) => cat(cat_args),
+        Some(Command::Plan(plan_args)) => plan(plan_args),
+        Some(Command::Check(check_args)) => check(check_args),
+        Some(Command::Man) => man(),
+        Some(Command::Bench(bench_args)) => bench(bench_args, color),
+        None => remove(args, color),
+    }
+}
+
+/// Cancels the returned token on Ctrl-C, the pipeline then stops at the next
+/// entry and the partial output is removed.
+fn cancel_on_interrupt() -> Result<CancellationToken> {
+    let cancel = CancellationToken::new();
+    let handler = cancel.clone();
+    ctrlc::set_handler(move || handler.cancel())?;
+    Ok(cancel)
+}
+
+fn merge(args: MergeArgs, color: bool) -> Result<()> {
+    let compression_level = parse_compression(args.compression)?;
+
+    let inputs = args
+        .inputs
+        .iter()
+        .map(|input| to_bytes(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    let progress = Spinner::new(color);
+    let ctx = Context::new(&progress, compression_level).with_cancel(cancel_on_interrupt()?);
+    let result_bytes = merge_archives(&ctx, inputs, args.conflict)?;
 
+    let options = OutputOptions {
+        clobber: clobber(args.force, false),
+        ..Default::default()
+    };
+    to_file(&args.output, result_bytes, &options)
+}
+
+fn bench(args: BenchArgs, color: bool) -> Result<()> {
     let compression_level = parse_compression(args.compression)?;
+    let entries = parse_count(&args.entries)?;
+    let size = parse_size(&args.size)? as u64;
+
+    let workspace = tempfile::Builder::new()
+        .prefix("expurgator-bench-")
+        .tempdir()?;
+    let progress = Spinner::new(color);
+    progress.message(format!(
+        "generating {} entries ({})",
+        entries,
+        format_size(size)
+    ));
+    let ctx = Context::new(&progress, compression_level)
+        .with_cancel(cancel_on_interrupt()?)
+        .with_buffer_size(parse_size(&args.buffer_size)?);
+    let result = run_bench(&ctx, &args.format, entries, size, workspace.path());
+    drop(workspace);
+    let result = result?;
 
-    let input_bytes = to_bytes(&args.input)?;
-
-    let mut filter_list = parse_csv(&args.csv, args.index, args.with_headers)?;
-    prompt_csv(&filter_list)?;
-
-    let progress_bar = ProgressBar::new_spinner();
-    progress_bar.enable_steady_tick(Duration::from_millis(120));
-    progress_bar.set_style(
-        ProgressStyle::with_template("{spinner:.blue} {msg}")
-            .unwrap()
-            .tick_strings(&[
-                "▹▹▹▹▹",
-                "▸▹▹▹▹",
-                "▹▸▹▹▹",
-                "▹▹▸▹▹",
-                "▹▹▹▸▹",
-                "▹▹▹▹▸",
-                "▪▪▪▪▪",
-            ]),
+    println!(
+        "{}: {} entries, {} content, input {}, output {}",
+        args.format,
+        result.entries,
+        format_size(result.content_size),
+        format_size(result.input_size),
+        format_size(result.output_size)
     );
+    println!(
+        "removed {} entries in {:.2?}: {}/s, {:.0} entries/s",
+        result.removed,
+        result.elapsed,
+        format_size(result.bytes_per_second() as u64),
+        result.entries_per_second()
+    );
+    Ok(())
+}
+
+fn man() -> Result<()> {
+    let man = clap_mangen::Man::new(cli::Args::command());
+    man.render(&mut std::io::stdout().lock())?;
+    Ok(())
+}
 
-    let result_bytes = pack_archive(
-        &progress_bar,
+fn read_filter_list(
+    file_path: &str,
+    filter_format: Option<&str>,
+    index: Option<usize>,
+    with_headers: bool,
+) -> Result<Vec<PathBuf>> {
+    let filter_list = match (filter_format, index) {
+        (Some("report"), _) => parse_report(file_path)?,
+        (_, Some(index)) => parse_csv(file_path, index, with_headers)?,
+        (_, None) => Err(anyhow!(
+            "Missing Index: --index is required for CSV filter files."
+        ))?,
+    };
+    // rows with a malformed depth modifier would be taken as plain paths
+    for row in &filter_list {
+        Rule::parse(row)?;
+    }
+    Ok(filter_list)
+}
+
+fn plan(args: PlanArgs) -> Result<()> {
+    let input_bytes = read_zip_volumes(&args.input)?;
+    let filter_list = read_filter_list(
+        &args.csv,
+        args.filter_format.as_deref(),
+        args.index,
+        args.with_headers,
+    )?;
+    let plan = Plan::build(&args.input, &input_bytes, &filter_list)?;
+    match &args.output {
+        Some(output) => std::fs::write(output, plan.to_json()?)?,
+        None => print!("{}", plan.to_json()?),
+    }
+    eprintln!(
+        "{} entries to remove, {} filter entries not found",
+        plan.remove.len(),
+        plan.not_found.len()
+    );
+    Ok(())
+}
+
+fn check(args: CheckArgs) -> Result<()> {
+    let policy = Policy::load(&args.policy)?.without_actions();
+    let input_bytes = read_zip_volumes(&args.input)?;
+    // the scrub engine evaluates the policy, its output is discarded
+    let ctx = Context::new(&NoProgress, 1).with_filter(policy.clone());
+    pack_archive_into(
+        &ctx,
         input_bytes,
-        &mut filter_list,
-        compression_level,
+        &mut Vec::new(),
+        WriteSink(std::io::sink()),
     )?;
 
-    to_file(args.output.unwrap().as_str(), result_bytes)?;
+    let waivers = match args.ignore_file {
+        Some(path) => Waivers::load(path)?,
+        None if Path::new(IGNORE_FILE).exists() => Waivers::load(IGNORE_FILE)?,
+        None => Waivers::default(),
+    };
+    let now = SystemTime::now();
+    let mut errors = 0;
+    let mut waived = 0;
+    for mut violation in policy.violations() {
+        match waivers.waived(&violation, now) {
+            Waived::Yes => {
+                waived += 1;
+                continue;
+            }
+            Waived::Expired(expires) => {
+                violation.message += &format!(" The waiver expired on {}.", expires);
+            }
+            Waived::No => {}
+        }
+        if violation.severity == Severity::Error {
+            errors += 1;
+        }
+        println!("{}", annotation(&args.input, &violation));
+    }
+    if waived > 0 {
+        eprintln!("{} finding(s) waived", waived);
+    }
+    if errors > 0 {
+        Err(anyhow!(
+            "Policy Violation: Found {} error(s) in '{}'.",
+            errors,
+            args.input
+        ))?;
+    }
+    Ok(())
+}
+
+/// Filters of a scrub whose findings are reported once it is done.
+#[derive(Clone, Default)]
+struct ReportedFilters {
+    policy: Option<Policy>,
+    manifest: Option<Manifest>,
+    licenses: Option<LicenseFilter>,
+    classes: Option<ClassFilter>,
+    extensions: Option<ExtensionFilter>,
+    yara: Option<YaraFilter>,
+    clamav: Option<ClamavFilter>,
+    entropy: Option<EntropyCheck>,
+    /// Encrypted entries kept as they are by `--encrypted skip-encrypted`.
+    skipped_encrypted: Arc<Mutex<Vec<PathBuf>>>,
+    duplicates: Duplicates,
+    /// Copies of duplicate zip entries dropped, or skipped with `keep-all`.
+    duplicate_entries: Arc<Mutex<Vec<PathBuf>>>,
+    symlinks: Symlinks,
+    /// Symlinks dropped by `--symlinks`.
+    dropped_symlinks: Arc<Mutex<Vec<PathBuf>>>,
+    match_mode: MatchMode,
+    /// Entries removed by the filter list with `--match-all` or by rows with
+    /// a depth modifier.
+    occurrences: Arc<Mutex<Vec<Occurrence>>>,
+}
+
+impl ReportedFilters {
+    fn load(args: &cli::Args) -> Result<Self> {
+        Ok(ReportedFilters {
+            policy: args.policy.as_deref().map(Policy::load).transpose()?,
+            manifest: args.manifest.as_deref().map(Manifest::load).transpose()?,
+            licenses: (!args.exclude_licenses.is_empty())
+                .then(|| LicenseFilter::new(args.exclude_licenses.clone())),
+            classes: (args.exclude_binaries || args.exclude_text)
+                .then(|| ClassFilter::new(args.exclude_binaries, args.exclude_text)),
+            extensions: (!args.allow_ext.is_empty() || !args.deny_ext.is_empty())
+                .then(|| ExtensionFilter::new(&args.allow_ext, &args.deny_ext)),
+            yara: (!args.yara.is_empty())
+                .then(|| {
+                    YaraFilter::load(&args.yara, args.yara_quarantine.clone().map(PathBuf::from))
+                })
+                .transpose()?,
+            clamav: args
+                .clamav
+                .as_deref()
+                .map(ClamdAddress::parse)
+                .transpose()?
+                .map(|address| {
+                    ClamavFilter::new(address, args.clamav_quarantine.clone().map(PathBuf::from))
+                }),
+            entropy: args.entropy_report.then(EntropyCheck::new),
+            skipped_encrypted: Arc::default(),
+            duplicates: Duplicates::parse(&args.duplicates)?,
+            duplicate_entries: Arc::default(),
+            symlinks: Symlinks::parse(&args.symlinks)?,
+            dropped_symlinks: Arc::default(),
+            // the flags conflict, --match-first only makes the default explicit
+            match_mode: match (args.match_first, args.match_all) {
+                (false, true) => MatchMode::All,
+                _ => MatchMode::First,
+            },
+            occurrences: Arc::default(),
+        })
+    }
+
+    fn infected(&self) -> Vec<Detection> {
+        self.clamav
+            .as_ref()
+            .map(ClamavFilter::detections)
+            .unwrap_or_default()
+    }
+
+    fn anomalies(&self) -> Vec<Anomaly> {
+        self.entropy
+            .as_ref()
+            .map(EntropyCheck::anomalies)
+            .unwrap_or_default()
+    }
+
+    /// Prints the findings on stderr.
+    fn print(&self) {
+        print_policy_violations(self.policy.as_ref());
+        for (path, mismatch) in self
+            .manifest
+            .as_ref()
+            .map(Manifest::removed)
+            .unwrap_or_default()
+        {
+            eprintln!("removed: {} [{}]", path.display(), mismatch.as_str());
+        }
+        for (path, license) in self
+            .licenses
+            .as_ref()
+            .map(LicenseFilter::removed)
+            .unwrap_or_default()
+        {
+            eprintln!("removed: {} [{}]", path.display(), license);
+        }
+        for (path, reason) in self
+            .extensions
+            .as_ref()
+            .map(ExtensionFilter::removed)
+            .unwrap_or_default()
+        {
+            eprintln!("removed: {} [{}]", path.display(), reason);
+        }
+        for (path, class) in self
+            .classes
+            .as_ref()
+            .map(ClassFilter::removed)
+            .unwrap_or_default()
+        {
+            eprintln!("removed: {} [{}]", path.display(), class.name());
+        }
+        for signature in self
+            .yara
+            .as_ref()
+            .map(YaraFilter::matches)
+            .unwrap_or_default()
+        {
+            let action = match signature.quarantined {
+                Some(_) => "quarantined",
+                None => "removed",
+            };
+            eprintln!(
+                "{}: {} [{}]",
+                action,
+                signature.path.display(),
+                signature.rules.join(", ")
+            );
+        }
+        for detection in self.infected() {
+            let action = match detection.quarantined {
+                Some(_) => "quarantined",
+                None => "removed",
+            };
+            eprintln!(
+                "{}: {} [{}]",
+                action,
+                detection.path.display(),
+                detection.signature
+            );
+        }
+        for anomaly in self.anomalies() {
+            eprintln!(
+                "review: {} [entropy {:.2} bits/byte, expected at most {:.1}]",
+                anomaly.path.display(),
+                anomaly.entropy,
+                anomaly.expected
+            );
+        }
+        for path in self.skipped_encrypted.lock().unwrap().iter() {
+            eprintln!("skipped: {} [encrypted]", path.display());
+        }
+        let action = match self.duplicates {
+            Duplicates::KeepAll => "skipped",
+            _ => "removed",
+        };
+        for path in self.duplicate_entries.lock().unwrap().iter() {
+            eprintln!("{}: {} [duplicate]", action, path.display());
+        }
+        let mut dropped_symlinks = self.dropped_symlinks.lock().unwrap().clone();
+        dropped_symlinks.sort();
+        for path in dropped_symlinks {
+            eprintln!("removed: {} [symlink]", path.display());
+        }
+        // nested archives may be rewritten in parallel
+        let mut occurrences = self.occurrences.lock().unwrap().clone();
+        occurrences.sort_by(|a, b| a.path.cmp(&b.path));
+        for occurrence in occurrences {
+            eprintln!(
+                "removed: {} [{}]",
+                occurrence.path.display(),
+                occurrence.rule.display()
+            );
+        }
+    }
+}
+
+/// Reports the matches of the policy applied by a scrub on stderr.
+fn print_policy_violations(policy: Option<&Policy>) {
+    for violation in policy.map(Policy::violations).unwrap_or_default() {
+        if violation.removed {
+            eprintln!("removed: {} [{}]", violation.path.display(), violation.rule);
+        } else if violation.path.as_os_str().is_empty() {
+            eprintln!(
+                "{}: {} [{}]",
+                violation.severity.as_str(),
+                violation.message,
+                violation.rule
+            );
+        } else {
+            eprintln!(
+                "{}: {}: {} [{}]",
+                violation.severity.as_str(),
+                violation.path.display(),
+                violation.message,
+                violation.rule
+            );
+        }
+    }
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    let input_bytes = read_zip_volumes(&args.input)?;
+    if args.metadata {
+        let archives = read_metadata(&input_bytes)?;
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &archives)?;
+        println!();
+        return Ok(());
+    }
+    let input_size = input_bytes.len() as u64;
+    let entries = ArchiveReader::new(input_bytes)
+        .entries()?
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(format) = args.nested_graph {
+        let name = Path::new(&args.input)
+            .file_name()
+            .map_or(args.input.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        let graph = NestedGraph::new(&name, input_size, &entries);
+        if format == "json" {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &graph)?;
+            println!();
+        } else {
+            print!("{}", graph.render_dot());
+        }
+    } else if args.tree {
+        print!("{}", render_tree(&entries));
+    } else if let Some(count) = args.largest {
+        write_csv(&largest(&entries, count), std::io::stdout().lock())?;
+    } else {
+        for entry in entries {
+            println!("{:>12}  {}", entry.size, entry.path.display());
+        }
+    }
+    Ok(())
+}
+
+fn cat(args: CatArgs) -> Result<()> {
+    let input_bytes = read_zip_volumes(&args.input)?;
+    let data = ArchiveReader::new(input_bytes).read(&args.entry)?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn stats(args: StatsArgs) -> Result<()> {
+    let input_bytes = read_zip_volumes(&args.input)?;
+    let entries = ArchiveReader::new(input_bytes)
+        .entries()?
+        .collect::<Result<Vec<_>>>()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    for (archive, stats) in collect_stats(&entries, now) {
+        if archive.as_os_str().is_empty() {
+            println!("== {} ==", args.input);
+        } else {
+            println!("== {} ==", archive.display());
+        }
+        print!("{}", stats.render());
+    }
+    print_windows_issues(&entries);
+    Ok(())
+}
+
+fn print_windows_issues(entries: &[EntryInfo]) {
+    let issues = check_entries(entries);
+    if issues.is_empty() {
+        return;
+    }
+    println!("== windows compatibility ==");
+    for (entry, issues) in issues {
+        let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+        println!("  {}: {}", entry.path.display(), issues.join(", "));
+    }
+}
+
+/// The first `limit` bytes of the entry `path`, or why they cannot be shown.
+fn preview_entry(reader: &ArchiveReader, path: &Path, limit: usize) -> String {
+    match reader.read(&path.to_string_lossy()) {
+        Ok(data) => preview(&data, limit, "    "),
+        Err(err) => format!("    (no preview: {})\n", err),
+    }
+}
+
+/// Prints what a scrub would remove, as a list or, if `tree` is set, as a
+/// tree, colored if it is true.
+fn dry_run(
+    input_bytes: &[u8],
+    filter_list: &[PathBuf],
+    limit: Option<usize>,
+    tree: Option<bool>,
+) -> Result<()> {
+    let estimate = estimate_output(input_bytes, filter_list)?;
+    match tree {
+        Some(color) => {
+            let entries = ArchiveReader::new(input_bytes.to_vec())
+                .entries()?
+                .collect::<Result<Vec<_>>>()?;
+            print!("{}", render_diff_tree(&entries, &estimate.removed, color));
+        }
+        None => {
+            let reader = limit.map(|_| ArchiveReader::new(input_bytes.to_vec()));
+            for entry in &estimate.removed {
+                println!("would remove: {}", entry.path.display());
+                if let (Some(reader), Some(limit)) = (&reader, limit) {
+                    if entry.kind == EntryKind::File {
+                        print!("{}", preview_entry(reader, &entry.path, limit));
+                    }
+                }
+            }
+        }
+    }
+    println!(
+        "{} entries ({}) would be removed",
+        estimate.removed.len(),
+        format_size(estimate.removed_bytes())
+    );
+    println!(
+        "estimated output size: {} (input {}, saving {})",
+        format_size(estimate.output_size),
+        format_size(estimate.input_size),
+        format_size(estimate.savings())
+    );
+    Ok(())
+}
+
+/// Rewrites the input with an empty filter and reports the metadata the
+/// rewrite did not preserve.
+fn compare_structure(args: &cli::Args) -> Result<()> {
+    let input = args.input.clone().unwrap_or_default();
+    let input_bytes = read_zip_volumes(&input)?;
+    let compression_level = match parse_compression_setting(&args.compression)? {
+        Some(level) => level,
+        None => autotune(&input_bytes, &Target::default())?,
+    };
+    let ctx = Context::new(&NoProgress, compression_level);
+    let output_bytes = pack_archive(&ctx, input_bytes.clone(), &mut Vec::new())?;
+
+    let (before, after) = (read_metadata(&input_bytes)?, read_metadata(&output_bytes)?);
+    let differences = compare_metadata(&before, &after)?;
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        Err(anyhow!(
+            "Structure Mismatch: {} field(s) were not preserved by the rewrite.",
+            differences.len()
+        ))?;
+    }
+    let entries: usize = before.iter().map(|archive| archive.entries.len()).sum();
+    println!(
+        "structure preserved: {} archive(s), {} entries",
+        before.len(),
+        entries
+    );
+    Ok(())
+}
+
+fn remove(args: cli::Args, color: bool) -> Result<()> {
+    if args.compare_structure {
+        return compare_structure(&args);
+    }
+    let filters = ReportedFilters::load(&args)?;
+    let open_report = args.open_report;
+    if !args.output_json {
+        let result = scrub(args, color, filters.clone());
+        filters.print();
+        let (report, written) = result?;
+        if open_report {
+            let report = report
+                .with_infected(filters.infected())
+                .with_anomalies(filters.anomalies());
+            show_report(&report.with_outputs(&written)?)?;
+        }
+        return Ok(());
+    }
+    let input = args.input.clone().unwrap_or_default();
+    let report = scrub(args, color, filters.clone())
+        .and_then(|(report, written)| {
+            report
+                .with_infected(filters.infected())
+                .with_anomalies(filters.anomalies())
+                .with_outputs(&written)
+        })
+        .unwrap_or_else(|err| RunReport::failure(&input, &err));
+    filters.print();
+    println!("{}", report.to_json()?);
+    if open_report {
+        show_report(&report)?;
+    }
+    if report.status == Status::Error {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Writes the report to a temporary file and opens it in the default
+/// application of the desktop, or else in the pager. The run is done, a
+/// viewer that fails is only reported.
+fn show_report(report: &RunReport) -> Result<()> {
+    let path = std::env::temp_dir().join(format!("expurgator-report-{}.json", std::process::id()));
+    std::fs::write(&path, report.to_json()?)?;
+    match Viewer::detect() {
+        Some(viewer) => {
+            eprintln!("report: {}", path.display());
+            if let Err(err) = viewer.open(&path) {
+                eprintln!("{}", err);
+            }
+        }
+        None => eprintln!(
+            "report: {} (no desktop or terminal to open it in)",
+            path.display()
+        ),
+    }
+    Ok(())
+}
+
+/// Where a removal writes, shown before it is confirmed.
+fn destinations(
+    output: &str,
+    extract: Option<&str>,
+    output_dir: &str,
+    split: bool,
+    zip_output: bool,
+    chunk_hashes: bool,
+) -> Vec<String> {
+    if let Some(dir) = extract {
+        return vec![format!("{}/", dir.trim_end_matches('/'))];
+    }
+    if output == "-" {
+        return vec!["stdout".to_string()];
+    }
+    let path = output_file(output_dir, output);
+    let mut destinations = Vec::new();
+    if !split {
+        destinations.push(path.clone());
+    } else if zip_output {
+        // the volumes are .z01, .z02, ... and the .zip
+        destinations.push(Path::new(&path).with_extension("z*").display().to_string());
+    } else {
+        destinations.push(format!("{}.*", path));
+    }
+    if chunk_hashes {
+        destinations.push(format!("{}{}", path, CHUNK_HASHES_SUFFIX));
+    }
+    destinations
+}
+
+/// Runs the removal, returns the report and the written paths.
+fn scrub(
+    args: cli::Args,
+    color: bool,
+    filters: ReportedFilters,
+) -> Result<(RunReport, Vec<String>)> {
+    let compression = parse_compression_setting(&args.compression)?;
+    let zstd_dict = args.zstd_dict.as_deref().map(ZstdDict::parse).transpose()?;
+
+    let split_size = args.split_size.as_deref().map(parse_size).transpose()?;
+    let chunk_size = args.chunk_hashes.as_deref().map(parse_size).transpose()?;
+    let buffer_size = parse_size(&args.buffer_size)?;
+    let bwlimit = args.bwlimit.as_deref().map(RateLimit::parse).transpose()?;
+    let max_memory = args
+        .max_memory
+        .as_deref()
+        .map(parse_size)
+        .transpose()?
+        .map(|size| size as u64);
+    let output_options = OutputOptions {
+        dir: args.output_dir.clone(),
+        mode: args.output_mode.as_deref().map(parse_mode).transpose()?,
+        hardened: args.hardened,
+        // --in-place overwrites the input by definition
+        clobber: clobber(args.force || args.in_place, args.backup),
+        bwlimit: bwlimit.clone(),
+    };
+
+    let input = args.input.unwrap();
+    let output = args.output.unwrap();
+    if output == "-" && split_size.is_some() {
+        Err(anyhow!(
+            "Invalid Output: --split-size cannot be used when writing to stdout."
+        ))?;
+    }
+    if output == "-" && chunk_size.is_some() {
+        Err(anyhow!(
+            "Invalid Output: --chunk-hashes cannot be used when writing to stdout."
+        ))?;
+    }
+    if output == "-" && args.output_json {
+        Err(anyhow!(
+            "Invalid Output: --output-json cannot be used when writing to stdout."
+        ))?;
+    }
+    let retry = Retry {
+        retries: args.retries,
+        delay: parse_duration(&args.retry_delay)?,
+    };
+    let temp_dir = args
+        .temp_dir
+        .as_ref()
+        .map_or_else(std::env::temp_dir, PathBuf::from);
+    let temp_max_size = args
+        .temp_max_size
+        .as_deref()
+        .map(parse_size)
+        .transpose()?
+        .map(|size| size as u64);
+    let remote_input = Remote::parse(&input);
+    let remote_output = Remote::parse(&output);
+    if remote_output.is_some() && split_size.is_some() {
+        Err(anyhow!(
+            "Invalid Output: --split-size cannot be used with a remote output."
+        ))?;
+    }
+    if args.in_place && (input == "-" || remote_input.is_some() || Path::new(&input).is_dir()) {
+        Err(anyhow!(
+            "Invalid Input: --in-place needs a local archive file to rewrite."
+        ))?;
+    }
+    // held until the rewritten archive replaced the input
+    let _lock = args
+        .in_place
+        .then(|| FileLock::acquire(Path::new(&input)))
+        .transpose()?;
+    let report = RunReport::new(&input);
+    let mtime = match &args.touch {
+        Some(touch) => Some(parse_timestamp(touch)?),
+        // in-place mode keeps the artifact timestamp for downstream sync jobs
+        // stdin has no timestamp to keep
+        None if input != "-"
+            && remote_input.is_none()
+            && (args.preserve_mtime || args.in_place) =>
+        {
+            Some(std::fs::metadata(&input)?.modified()?)
+        }
+        None => None,
+    };
+
+    // local inputs nothing needs in memory before the scrub are read from
+    // disk as they are rewritten, the summary and the preview included
+    let stream_input = input != "-"
+        && remote_input.is_none()
+        && Path::new(&input).is_file()
+        && !is_zip_volume(&input)?
+        && compression.is_some()
+        && zstd_dict.is_none()
+        && args.plan.is_none()
+        && args.index_cache.is_none()
+        && args.provenance.is_none()
+        && !args.dry_run
+        && args.extract.is_none()
+        && !args.initramfs
+        && !args.encrypt_output
+        && split_size.is_none();
+    let open_input = || -> Result<_> { Ok(Throttled::new(File::open(&input)?, bwlimit.clone())) };
+
+    let input_bytes = if stream_input {
+        Vec::new()
+    } else if Path::new(&input).is_dir() {
+        pack_directory(
+            Path::new(&input),
+            archive_format(&output)?,
+            compression.unwrap_or(DEFAULT_LEVEL),
+        )?
+    } else if let Some(remote) = &remote_input {
+        std::fs::create_dir_all(&temp_dir)?;
+        let staging = tempfile::Builder::new()
+            .prefix("expurgator-download-")
+            .tempdir_in(&temp_dir)?;
+        remote.download(&retry, staging.path())?
+    } else {
+        read_zip_volumes_limited(&input, bwlimit.as_ref())?
+    };
+    let input_head = if stream_input {
+        let mut head = Vec::new();
+        File::open(&input)?.take(8 << 10).read_to_end(&mut head)?;
+        head
+    } else {
+        input_bytes[..input_bytes.len().min(8 << 10)].to_vec()
+    };
+    let compression_level = match compression {
+        Some(level) => level,
+        None => {
+            let level = autotune(&input_bytes, &Target::default())?;
+            if !args.output_json {
+                eprintln!("compression level: {} (auto)", level);
+            }
+            level
+        }
+    };
+    let zstd_dict = zstd_dict
+        .map(|zstd_dict| zstd_dict.load(&input_bytes))
+        .transpose()?;
+    // the input is buffered for the whole scrub, nested archives get the rest
+    let max_memory = max_memory
+        .map(|max_memory| {
+            max_memory
+                .checked_sub(input_bytes.len() as u64)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Memory Limit: Buffering '{}' needs {} bytes, more than is left of --max-memory {} bytes.",
+                        input,
+                        input_bytes.len(),
+                        max_memory
+                    )
+                })
+        })
+        .transpose()?;
+
+    let filter_option = if args.plan.is_some() {
+        "--plan"
+    } else {
+        "--csv"
+    };
+    let (filter_file, mut filter_list) = match args.plan {
+        Some(plan_file) => {
+            let plan = match &args.require_signed_by {
+                Some(public_key) => {
+                    let signature = args
+                        .plan_signature
+                        .clone()
+                        .unwrap_or_else(|| format!("{}.minisig", plan_file));
+                    Plan::load_signed(&plan_file, Path::new(&signature), Path::new(public_key))?
+                }
+                None => Plan::load(&plan_file)?,
+            };
+            plan.verify(&input_bytes)?;
+            (plan_file, plan.remove)
+        }
+        None => match args.csv {
+            Some(csv) => {
+                let filter_list = read_filter_list(
+                    &csv,
+                    args.filter_format.as_deref(),
+                    args.index,
+                    args.with_headers,
+                )?;
+                (csv, filter_list)
+            }
+            // the entry filters alone decide what is removed
+            None => (
+                args.policy
+                    .clone()
+                    .or(args.manifest.clone())
+                    .unwrap_or_default(),
+                Vec::new(),
+            ),
+        },
+    };
+    if let Some(index_cache) = &args.index_cache {
+        let (index, cached) = EntryIndex::cached(index_cache, &input_bytes)?;
+        let (found, missing) = index.matches(&filter_list);
+        eprintln!(
+            "{} of {} filter entries found in {} archive entries{}",
+            found.len(),
+            filter_list.len(),
+            index.entries.len(),
+            if cached { " (cached index)" } else { "" }
+        );
+        for path in missing {
+            eprintln!("not found: {}", path.display());
+        }
+    }
+    if args.dry_run {
+        // --color decides by stderr, the tree goes to stdout
+        let color = match args.color {
+            cli::ColorChoice::Auto => color && std::io::stdout().is_terminal(),
+            _ => color,
+        };
+        dry_run(
+            &input_bytes,
+            &filter_list,
+            args.preview,
+            args.tree.then_some(color),
+        )?;
+        return Ok((report, Vec::new()));
+    }
+    let requested = filter_list.clone();
+    let provenance = match args.provenance.as_deref().map(ProvenanceMode::parse) {
+        Some(mode) => {
+            let mode = mode?;
+            if mode == ProvenanceMode::Comment
+                && infer_input_file(&input_head)? != "application/zip"
+            {
+                Err(anyhow!(
+                    "Invalid Provenance: Only zip archives have a comment, use --provenance entry."
+                ))?;
+            }
+            if mode == ProvenanceMode::Comment && args.archive_comment.is_some() {
+                Err(anyhow!(
+                    "Invalid Provenance: --provenance comment cannot be used with --archive-comment."
+                ))?;
+            }
+            let filter_bytes = match filter_file.as_str() {
+                "" => Vec::new(),
+                path => to_bytes(path)?,
+            };
+            Some(
+                Provenance::new(
+                    mode,
+                    &filter_bytes,
+                    requested.len(),
+                    args.plugins.len(),
+                    args.transforms.len(),
+                )
+                .with_input(&input_bytes),
+            )
+        }
+        None => None,
+    };
+    // reruns of a pipeline leave the output they already wrote alone
+    let single_output =
+        output != "-" && args.extract.is_none() && remote_output.is_none() && split_size.is_none();
+    if let Some(provenance) = provenance.as_ref().filter(|_| single_output) {
+        let path = output_file(&output_options.dir, &output);
+        if already_scrubbed(provenance, &input, &input_bytes, &path) {
+            if !args.output_json {
+                eprintln!("already scrubbed with identical rules: {}", path);
+            }
+            return Ok((report.already_scrubbed(), vec![path]));
+        }
+    }
+    if !args.output_json && !args.non_interactive && !filter_list.is_empty() {
+        let reader = if stream_input {
+            ArchiveReader::open(&input)
+        } else {
+            ArchiveReader::new(input_bytes.clone())
+        };
+        let preview = args
+            .preview
+            .map(|limit| preview_entry(&reader, &filter_list[0], limit));
+        let mut summary = Summary::scan(&input, &reader, &filter_list)?
+            .with_rules(filter_option, filter_list.len())
+            .with_rules(
+                "--manifest",
+                filters.manifest.as_ref().map_or(0, Manifest::file_count),
+            )
+            .with_rules(
+                "--policy",
+                filters.policy.as_ref().map_or(0, Policy::rule_count),
+            )
+            .with_rules("--exclude-license", args.exclude_licenses.len())
+            .with_rules("--exclude-binaries", usize::from(args.exclude_binaries))
+            .with_rules("--exclude-text", usize::from(args.exclude_text))
+            .with_rules("--allow-ext", args.allow_ext.len())
+            .with_rules("--deny-ext", args.deny_ext.len())
+            .with_rules("--yara", args.yara.len())
+            .with_rules("--clamav", usize::from(args.clamav.is_some()))
+            .with_rules("--plugin", args.plugins.len())
+            .with_rules("--transform", args.transforms.len())
+            .with_rules("--normalize-eol", args.normalize_eol.len())
+            .with_rules("--optimize-images", usize::from(args.optimize_images))
+            .with_rules(
+                "--strip-image-metadata",
+                usize::from(args.strip_image_metadata),
+            );
+        for destination in destinations(
+            &output,
+            args.extract.as_deref(),
+            &output_options.dir,
+            split_size.is_some(),
+            !args.initramfs && infer_input_file(&input_head)? == "application/zip",
+            chunk_size.is_some(),
+        ) {
+            summary = summary.with_destination(destination);
+        }
+        if let Some(remote) = &remote_output {
+            summary = summary.with_destination(remote.url());
+        }
+        prompt_summary(&summary.render(messages(), preview.as_deref()))?;
+    }
+
+    let spinner;
+    let progress: &dyn Progress = if args.output_json {
+        &NoProgress
+    } else {
+        spinner = Spinner::new(color);
+        &spinner
+    };
+    let owners = OwnerMap {
+        users: args
+            .map_owner
+            .iter()
+            .map(|rule| OwnerRule::parse(rule))
+            .collect::<Result<_>>()?,
+        groups: args
+            .map_group
+            .iter()
+            .map(|rule| OwnerRule::parse(rule))
+            .collect::<Result<_>>()?,
+    };
+    // zip outputs encrypt their entries, others are encrypted as a whole
+    let zip_output = !args.initramfs && infer_input_file(&input_head)? == "application/zip";
+    let encrypted = Encrypted::parse(&args.encrypted)?;
+    let encrypted_names = if stream_input {
+        encrypted_entries(File::open(&input)?)
+    } else {
+        encrypted_entries(std::io::Cursor::new(&input_bytes))
+    };
+    if let (Some(name), Encrypted::Fail) = (encrypted_names.first(), encrypted) {
+        Err(anyhow!(
+            "Encrypted Archive: {} entries are encrypted, e.g. '{}'. Use --encrypted decrypt or skip-encrypted.",
+            encrypted_names.len(),
+            name
+        ))?;
+    }
+    let input_encrypted = !encrypted_names.is_empty() && encrypted == Encrypted::Decrypt;
+    let mut password = match &args.key {
+        Some(uri) => Some(KeySource::parse(uri)?.fetch()?),
+        None => load_password(args.password_file.as_deref())?,
+    };
+    if password.is_none()
+        && (input_encrypted || (args.encrypt_output && zip_output))
+        && !args.output_json
+        && !args.non_interactive
+    {
+        password = Some(if input_encrypted {
+            prompt_password(&messages().archive_password, false)?
+        } else {
+            prompt_password(&messages().output_password, true)?
+        });
+    }
+    if input_encrypted && password.is_none() {
+        Err(anyhow!(
+            "Encrypted Archive: {} entries are encrypted, e.g. '{}'. Pass the password with --password-file, --key or {}, or use --encrypted skip-encrypted.",
+            encrypted_names.len(),
+            encrypted_names[0],
+            PASSWORD_ENV
+        ))?;
+    }
+    if args.encrypt_output && zip_output && password.is_none() {
+        Err(anyhow!(
+            "Invalid Encryption: Encrypting a zip output needs a password, pass it with --password-file or {}.",
+            PASSWORD_ENV
+        ))?;
+    }
+    let stream_encryption = args.encrypt_output && !zip_output;
+    if stream_encryption {
+        StreamCipher::for_recipients(&args.encrypt_recipient)?;
+    }
+    let encrypt = |bytes: Vec<u8>| {
+        if stream_encryption {
+            encrypt_stream(bytes, &args.encrypt_recipient)
+        } else {
+            Ok(bytes)
+        }
+    };
+
+    let entry_order = match &args.sort_entries {
+        Some(order) => EntryOrder::parse(order)?,
+        None if args.rsyncable => EntryOrder::Path,
+        None => EntryOrder::Unsorted,
+    };
+    let clamp_mtime = match &args.clamp_mtime {
+        Some(timestamp) => Some(
+            parse_timestamp(timestamp)?
+                .duration_since(UNIX_EPOCH)?
+                .as_secs(),
+        ),
+        None if args.rsyncable => std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .map(|epoch| {
+                epoch.trim().parse().map_err(|_| {
+                    anyhow!(
+                        "Invalid Timestamp: SOURCE_DATE_EPOCH '{}' is not seconds since the epoch.",
+                        epoch
+                    )
+                })
+            })
+            .transpose()?,
+        None => None,
+    };
+
+    let mut ctx = Context::new(progress, compression_level)
+        .with_cancel(cancel_on_interrupt()?)
+        .with_buffer_size(buffer_size)
+        .with_prefetch(parse_size(&args.prefetch)?)
+        .with_windows_safe(args.windows_safe)
+        .with_provenance(provenance)
+        .with_archive_comment(args.archive_comment.clone())
+        .with_zip_align(args.zip_align)
+        .with_password(password)
+        .with_encrypt_output(args.encrypt_output)
+        .with_encrypted(encrypted, filters.skipped_encrypted.clone())
+        .with_duplicates(filters.duplicates, filters.duplicate_entries.clone())
+        .with_symlinks(filters.symlinks, filters.dropped_symlinks.clone())
+        .with_match_mode(filters.match_mode, filters.occurrences.clone())
+        .with_entry_order(entry_order)
+        .with_rsyncable(args.rsyncable)
+        .with_clamp_mtime(clamp_mtime)
+        .with_xz(XzOptions::new(args.xz_extreme, args.xz_filters.as_deref())?)
+        .with_zstd_dict(zstd_dict)
+        .with_zstd_long(args.zstd_long)
+        .with_tar_format(
+            args.tar_format
+                .as_deref()
+                .map(TarFormat::parse)
+                .transpose()?,
+        )
+        .with_owners(owners)
+        .with_strip_xattrs(args.strip_xattrs)
+        .with_drop_incremental_metadata(args.drop_incremental_metadata)
+        .with_max_memory(max_memory)
+        .with_temp_dir(&temp_dir, temp_max_size)?
+        .with_timeout(args.timeout.as_deref().map(parse_duration).transpose()?)
+        .with_entry_timeout(
+            args.entry_timeout
+                .as_deref()
+                .map(parse_duration)
+                .transpose()?,
+        )
+        .with_on_error(OnError::parse(&args.on_error)?)
+        .with_jobs(match args.jobs {
+            Some(jobs) => jobs,
+            None => std::thread::available_parallelism().map_or(1, usize::from),
+        });
+    if args.untrusted {
+        ctx = ctx.with_untrusted(Untrusted::default());
+    }
+    for plugin in &args.plugins {
+        ctx = ctx.with_filter(NativePlugin::load(plugin)?);
+    }
+    // removals see the original content, the policy the transformed one
+    if let Some(manifest) = filters.manifest {
+        ctx = ctx.with_filter(manifest);
+    }
+    if let Some(extensions) = filters.extensions {
+        ctx = ctx.with_filter(extensions);
+    }
+    if let Some(classes) = filters.classes {
+        ctx = ctx.with_filter(classes);
+    }
+    if let Some(licenses) = filters.licenses {
+        ctx = ctx.with_filter(licenses);
+    }
+    if let Some(yara) = filters.yara {
+        ctx = ctx.with_filter(yara);
+    }
+    if let Some(clamav) = filters.clamav {
+        ctx = ctx.with_filter(clamav);
+    }
+    for transform in &args.transforms {
+        ctx = ctx.with_filter(Transformer::parse(transform)?);
+    }
+    for spec in &args.normalize_eol {
+        ctx = ctx.with_filter(parse_normalize_eol(spec)?);
+    }
+    if args.optimize_images {
+        ctx = ctx.with_filter(Transformer::parse("optimize-images=**")?);
+    }
+    if args.strip_image_metadata {
+        ctx = ctx.with_filter(Transformer::parse("strip-image-metadata=**")?);
+    }
+    if let Some(policy) = filters.policy {
+        ctx = ctx.with_filter(policy);
+    }
+    // sees the entries as they are kept
+    if let Some(entropy) = filters.entropy {
+        ctx = ctx.with_filter(entropy);
+    }
+
+    if let Some(dir) = &args.extract {
+        let dir = Path::new(dir);
+        if !args.force
+            && dir
+                .read_dir()
+                .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            Err(anyhow!(
+                "Invalid Output: '{}' is not empty, use --force to extract into it.",
+                dir.display()
+            ))?;
+        }
+        extract_archive(&pack_archive(&ctx, input_bytes, &mut filter_list)?, dir)?;
+        return Ok((
+            finish_report(report, &ctx, &requested, filter_list),
+            Vec::new(),
+        ));
+    }
+
+    if output == "-" {
+        // progress and prompts go to stderr, stdout carries the archive only
+        let mut sink = WriteSink(Throttled::new(std::io::stdout().lock(), bwlimit.clone()));
+        if args.initramfs || stream_encryption {
+            let result_bytes = if args.initramfs {
+                pack_initramfs(&ctx, input_bytes, &mut filter_list)?
+            } else {
+                pack_archive(&ctx, input_bytes, &mut filter_list)?
+            };
+            sink.write_all(&encrypt(result_bytes)?)?;
+            sink.finish()?;
+        } else if stream_input {
+            pack_reader_into(&ctx, open_input()?, &mut filter_list, sink)?;
+        } else {
+            pack_archive_into(&ctx, input_bytes, &mut filter_list, sink)?;
+        }
+        return Ok((
+            finish_report(report, &ctx, &requested, filter_list),
+            Vec::new(),
+        ));
+    }
+
+    if !args.initramfs && !stream_encryption && split_size.is_none() {
+        let output_path = output_path(&output_options.dir, &output)?;
+        let sink = FileSink::with_options(buffer_size, &output_path, &output_options)?;
+        if stream_input {
+            pack_reader_into(&ctx, open_input()?, &mut filter_list, sink)?;
+        } else {
+            pack_archive_into(&ctx, input_bytes, &mut filter_list, sink)?;
+        }
+        let mut written = vec![output_path];
+        apply_mtime(&written, mtime)?;
+        write_chunk_hashes(&mut written, &output, chunk_size, &output_options)?;
+        if let Some(remote) = &remote_output {
+            upload_outputs(remote, &written, &retry)?;
+        }
+        return Ok((
+            finish_report(report, &ctx, &requested, filter_list),
+            written,
+        ));
+    }
+
+    let result_bytes = if args.initramfs {
+        pack_initramfs(&ctx, input_bytes, &mut filter_list)?
+    } else {
+        pack_archive(&ctx, input_bytes, &mut filter_list)?
+    };
+    let result_bytes = encrypt(result_bytes)?;
+
+    let mut written = match split_size {
+        Some(split_size) => {
+            if infer_input_file(&result_bytes)? == "application/zip" {
+                to_zip_volumes(
+                    &output,
+                    split_zip(&result_bytes, split_size)?,
+                    &output_options,
+                )?
+            } else {
+                to_split_files(&output, result_bytes, split_size, &output_options)?
+            }
+        }
+        None => {
+            to_file(&output, result_bytes, &output_options)?;
+            vec![output_path(&output_options.dir, &output)?]
+        }
+    };
+
+    apply_mtime(&written, mtime)?;
+    write_chunk_hashes(&mut written, &output, chunk_size, &output_options)?;
+    if let Some(remote) = &remote_output {
+        upload_outputs(remote, &written, &retry)?;
+    }
+    Ok((
+        finish_report(report, &ctx, &requested, filter_list),
+        written,
+    ))
+}
+
+/// Records the outcome of a scrub given the filter list rules left in
+/// `filter_list`.
+fn finish_report(
+    report: RunReport,
+    ctx: &Context,
+    requested: &[PathBuf],
+    filter_list: Vec<PathBuf>,
+) -> RunReport {
+    report
+        .finish(requested, ctx.unmatched(filter_list))
+        .with_occurrences(ctx.occurrences())
+}
+
+/// Whether the output at `path` was already scrubbed from the input with
+/// the rules of `provenance`, by the record embedded in it. An input being
+/// rewritten in place is its own output.
+fn already_scrubbed(provenance: &Provenance, input: &str, input_bytes: &[u8], path: &str) -> bool {
+    let canonical = |path: &str| std::fs::canonicalize(path).ok();
+    let in_place = canonical(path).is_some() && canonical(path) == canonical(input);
+    if in_place {
+        return StoredRecord::read(input_bytes)
+            .is_some_and(|record| provenance.same_rules(&record));
+    }
+    std::fs::read(path)
+        .ok()
+        .and_then(|output| StoredRecord::read(&output))
+        .is_some_and(|record| {
+            provenance.same_rules(&record) && record.input_sha256 == provenance.input_sha256
+        })
+}
+
+/// Writes the chunk hashes of the single written output next to it.
+fn write_chunk_hashes(
+    written: &mut Vec<String>,
+    output: &str,
+    chunk_size: Option<usize>,
+    options: &OutputOptions,
+) -> Result<()> {
+    let (Some(chunk_size), [path]) = (chunk_size, written.as_slice()) else {
+        return Ok(());
+    };
+    let hashes = ChunkHashes::from_file(Path::new(path), chunk_size as u64)?;
+    let name = format!("{}{}", output, CHUNK_HASHES_SUFFIX);
+    to_file(&name, hashes.to_json()?.into_bytes(), options)?;
+    written.push(output_path(&options.dir, &name)?);
+    Ok(())
+}
+
+fn clobber(force: bool, backup: bool) -> Clobber {
+    match (force, backup) {
+        (_, true) => Clobber::Backup,
+        (true, _) => Clobber::Overwrite,
+        _ => Clobber::Refuse,
+    }
+}
 
+fn apply_mtime(paths: &[String], mtime: Option<SystemTime>) -> Result<()> {
+    if let Some(mtime) = mtime {
+        for path in paths {
+            set_mtime(path, mtime)?;
+        }
+    }
     Ok(())
 }
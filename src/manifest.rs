@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+
+use expurgator::internal::sha256_hex;
+use expurgator::{Decision, EntryFilter, EntryInfo, EntryKind};
+
+/// Why an entry was removed by a [`Manifest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    NotInManifest,
+    HashMismatch,
+}
+
+impl Mismatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mismatch::NotInManifest => "not in manifest",
+            Mismatch::HashMismatch => "hash mismatch",
+        }
+    }
+}
+
+/// Expected top level files and their SHA-256, evaluated as an
+/// [`EntryFilter`] removing every other file and every file whose content
+/// differs. Nested archives are verified as a whole.
+///
+/// Clones share the recorded removals.
+#[derive(Clone)]
+pub struct Manifest {
+    hashes: Arc<HashMap<PathBuf, String>>,
+    removed: Arc<Mutex<Vec<(PathBuf, Mismatch)>>>,
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+impl Manifest {
+    /// Parses `sha256sum` output, lines of `<hash>  <path>` or
+    /// `<hash> *<path>`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut hashes = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (hash, path) = line
+                .split_once(' ')
+                .filter(|(hash, _)| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid Manifest: Line {} is not in the sha256sum format '<hash>  <path>'.",
+                        number + 1
+                    )
+                })?;
+            let path = path.strip_prefix([' ', '*']).unwrap_or(path);
+            hashes.insert(normalize(Path::new(path)), hash.to_lowercase());
+        }
+        Ok(Manifest {
+            hashes: Arc::new(hashes),
+            removed: Arc::default(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Manifest::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Number of files listed.
+    pub fn file_count(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// The entries removed so far.
+    pub fn removed(&self) -> Vec<(PathBuf, Mismatch)> {
+        self.removed.lock().unwrap().clone()
+    }
+}
+
+impl EntryFilter for Manifest {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        if entry.depth > 0 || entry.kind == EntryKind::Directory {
+            return Ok(Decision::Keep);
+        }
+        let path = normalize(&entry.path);
+        let mismatch = match (self.hashes.get(&path), content) {
+            (None, _) => Mismatch::NotInManifest,
+            (Some(hash), Some(content)) if *hash != sha256_hex(content) => Mismatch::HashMismatch,
+            _ => return Ok(Decision::Keep),
+        };
+        self.removed.lock().unwrap().push((path, mismatch));
+        Ok(Decision::Remove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, depth: usize) -> EntryInfo {
+        EntryInfo {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: None,
+            kind: EntryKind::File,
+            depth,
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse(&format!(
+            "{}  ./bin/app\n{} *README\n",
+            sha256_hex(b"app"),
+            sha256_hex(b"readme")
+        ))
+        .unwrap();
+        let decide = |path, depth, content: &[u8]| {
+            manifest.decide(&entry(path, depth), Some(content)).unwrap()
+        };
+        assert_eq!(decide("bin/app", 0, b"app"), Decision::Keep);
+        assert_eq!(decide("README", 0, b"readme"), Decision::Keep);
+        assert_eq!(decide("bin/app", 0, b"patched"), Decision::Remove);
+        assert_eq!(decide("bin/extra", 0, b"extra"), Decision::Remove);
+        assert_eq!(decide("lib.zip/extra", 1, b"extra"), Decision::Keep);
+        assert_eq!(
+            manifest.removed(),
+            vec![
+                (PathBuf::from("bin/app"), Mismatch::HashMismatch),
+                (PathBuf::from("bin/extra"), Mismatch::NotInManifest)
+            ]
+        );
+        assert!(Manifest::parse("app.bin\n").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use anyhow::{anyhow, Result};
+
+/// Cap on the bytes buffered in memory while scrubbing. Clones share the
+/// usage, so nested archives draw from the same budget.
+#[derive(Debug, Clone)]
+pub struct MemoryLimit {
+    pub max_memory: u64,
+    used: Arc<AtomicU64>,
+}
+
+/// Memory accounted for until dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    bytes: u64,
+    used: Arc<AtomicU64>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.used.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+impl MemoryLimit {
+    pub fn new(max_memory: u64) -> Self {
+        MemoryLimit {
+            max_memory,
+            used: Arc::default(),
+        }
+    }
+
+    /// Reserves `bytes` if they fit in the remaining budget.
+    pub fn try_reserve(&self, bytes: u64) -> Option<Reservation> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes)
+                    .filter(|total| *total <= self.max_memory)
+            })
+            .ok()?;
+        Some(Reservation {
+            bytes,
+            used: self.used.clone(),
+        })
+    }
+
+    /// Reserves the `bytes` buffered for `path`, failing if they do not fit.
+    pub fn reserve(&self, path: &str, bytes: u64) -> Result<Reservation> {
+        self.try_reserve(bytes).ok_or_else(|| {
+            anyhow!(
+                "Memory Limit: Buffering '{}' needs {} bytes, more than is left of --max-memory {} bytes.",
+                path,
+                bytes,
+                self.max_memory
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_limit() {
+        let limit = MemoryLimit::new(1024);
+        let shared = limit.clone();
+        let reservation = limit.reserve("a.zip", 600).unwrap();
+        assert!(shared.try_reserve(600).is_none());
+        assert!(shared.reserve("b.zip", 600).is_err());
+        drop(reservation);
+        assert!(shared.try_reserve(1024).is_some());
+    }
+}
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, io::Read, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::archive::{create_tar_decoder, TarEncoder};
+use crate::context::Context;
+use crate::util::infer_input_file;
+
+const TAR_MIME_TYPES: [&str; 5] = [
+    "application/gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/zstd",
+    "application/x-tar",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the entry from the first archive containing it
+    FirstWins,
+    /// Keep the entry from the last archive containing it
+    LastWins,
+    /// Abort the merge
+    Error,
+}
+
+enum Slot {
+    New,
+    Replace(usize),
+    Skip,
+}
+
+fn resolve_conflict(
+    seen: &HashMap<String, usize>,
+    path: &str,
+    is_dir: bool,
+    policy: ConflictPolicy,
+) -> Result<Slot> {
+    match seen.get(path) {
+        None => Ok(Slot::New),
+        // identical directories are expected in overlays, keep the first one
+        Some(_) if is_dir => Ok(Slot::Skip),
+        Some(&index) => match policy {
+            ConflictPolicy::FirstWins => Ok(Slot::Skip),
+            ConflictPolicy::LastWins => Ok(Slot::Replace(index)),
+            ConflictPolicy::Error => Err(anyhow!(
+                "Merge Conflict: The entry '{}' exists in more than one archive.",
+                path
+            ))?,
+        },
+    }
+}
+
+pub fn merge_archives(
+    ctx: &Context,
+    inputs: Vec<Vec<u8>>,
+    policy: ConflictPolicy,
+) -> Result<Vec<u8>> {
+    let mime_types = inputs
+        .iter()
+        .map(|input| infer_input_file(input))
+        .collect::<Result<Vec<_>>>()?;
+    let is_zip = mime_types[0] == "application/zip";
+    let compatible = mime_types.iter().all(|mime_type| {
+        if is_zip {
+            mime_type == "application/zip"
+        } else {
+            TAR_MIME_TYPES.contains(&mime_type.as_str())
+        }
+    });
+    if !compatible {
+        Err(anyhow!(
+            "Incompatible Archives: Only zip archives or tar archives can be merged together."
+        ))?;
+    }
+
+    if is_zip {
+        merge_zip(ctx, inputs, policy)
+    } else {
+        merge_tar(ctx, &inputs, &mime_types, policy)
+    }
+}
+
+struct TarMember {
+    header: tar::Header,
+    path: String,
+    link_name: Option<PathBuf>,
+    data: Vec<u8>,
+}
+
+fn merge_tar(
+    ctx: &Context,
+    inputs: &[Vec<u8>],
+    mime_types: &[String],
+    policy: ConflictPolicy,
+) -> Result<Vec<u8>> {
+    let mut members: Vec<TarMember> = Vec::new();
+    let mut seen = HashMap::new();
+    for (input, mime_type) in inputs.iter().zip(mime_types) {
+        let decoder = create_tar_decoder(input, mime_type)?;
+        let mut tar_archive = tar::Archive::new(decoder);
+        for entry in tar_archive.entries()? {
+            ctx.check()?;
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().to_string();
+            ctx.progress.message(format!("merging: {}", path));
+
+            let header = entry.header().clone();
+            let slot = resolve_conflict(&seen, &path, header.entry_type().is_dir(), policy)?;
+            if let Slot::Skip = slot {
+                continue;
+            }
+            let link_name = entry.link_name()?.map(|link| link.into_owned());
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            let member = TarMember {
+                header,
+                path: path.clone(),
+                link_name,
+                data,
+            };
+            match slot {
+                Slot::Replace(index) => members[index] = member,
+                _ => {
+                    seen.insert(path, members.len());
+                    members.push(member);
+                }
+            }
+        }
+    }
+
+    let tar_encoder = TarEncoder::new(&mime_types[0], ctx.compression_level)?;
+    let mut tar_writer = tar::Builder::new(tar_encoder.encoder());
+    for mut member in members {
+        match (member.header.entry_type(), member.link_name) {
+            (tar::EntryType::Symlink | tar::EntryType::Link, Some(link_name)) => {
+                tar_writer.append_link(&mut member.header, &member.path, link_name)?
+            }
+            _ => tar_writer.append_data(&mut member.header, &member.path, &*member.data)?,
+        }
+    }
+    tar_writer.into_inner()?.inner()
+}
+
+fn merge_zip(ctx: &Context, inputs: Vec<Vec<u8>>, policy: ConflictPolicy) -> Result<Vec<u8>> {
+    let mut archives = inputs
+        .into_iter()
+        .map(|input| zip::ZipArchive::new(std::io::Cursor::new(input)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // (archive, entry) pairs in output order
+    let mut members: Vec<(usize, usize)> = Vec::new();
+    let mut seen = HashMap::new();
+    for (archive_index, archive) in archives.iter_mut().enumerate() {
+        for entry_index in 0..archive.len() {
+            let entry = archive.by_index_raw(entry_index)?;
+            let path = entry.name().to_owned();
+            match resolve_conflict(&seen, &path, entry.is_dir(), policy)? {
+                Slot::New => {
+                    seen.insert(path, members.len());
+                    members.push((archive_index, entry_index));
+                }
+                Slot::Replace(index) => members[index] = (archive_index, entry_index),
+                Slot::Skip => {}
+            }
+        }
+    }
+
+    let mut result: Vec<u8> = Vec::new();
+    {
+        let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(&mut result));
+        for (archive_index, entry_index) in members {
+            ctx.check()?;
+            let entry = archives[archive_index].by_index_raw(entry_index)?;
+            ctx.progress.message(format!("merging: {}", entry.name()));
+            zip_writer.raw_copy_file(entry)?;
+        }
+        zip_writer.finish()?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use std::io::Write;
+
+    fn create_tar(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut tar_writer = tar::Builder::new(Vec::new());
+        for (path, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            tar_writer
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        tar_writer.into_inner().unwrap()
+    }
+
+    fn read_tar(bytes: &[u8]) -> Vec<(String, String)> {
+        let mut tar_archive = tar::Archive::new(bytes);
+        tar_archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().to_string();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                (path, content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_tar_policies() {
+        let base = create_tar(&[("a.txt", "base"), ("b.txt", "base")]);
+        let overlay = create_tar(&[("b.txt", "overlay"), ("c.txt", "overlay")]);
+        let inputs = || vec![base.clone(), overlay.clone()];
+        let ctx = Context::new(&NoProgress, 6);
+
+        let merged = merge_archives(&ctx, inputs(), ConflictPolicy::FirstWins).unwrap();
+        assert_eq!(
+            read_tar(&merged),
+            vec![
+                ("a.txt".to_string(), "base".to_string()),
+                ("b.txt".to_string(), "base".to_string()),
+                ("c.txt".to_string(), "overlay".to_string()),
+            ]
+        );
+
+        let merged = merge_archives(&ctx, inputs(), ConflictPolicy::LastWins).unwrap();
+        assert_eq!(read_tar(&merged)[1].1, "overlay");
+
+        assert!(merge_archives(&ctx, inputs(), ConflictPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn test_merge_zip() {
+        let create_zip = |name: &str, content: &[u8]| {
+            let mut result = Vec::new();
+            {
+                let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(&mut result));
+                zip_writer
+                    .start_file(name, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                zip_writer.write_all(content).unwrap();
+                zip_writer.finish().unwrap();
+            }
+            result
+        };
+        let inputs = vec![create_zip("a.txt", b"a"), create_zip("b.txt", b"b")];
+        let merged =
+            merge_archives(&Context::new(&NoProgress, 6), inputs, ConflictPolicy::Error).unwrap();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(merged)).unwrap();
+        assert_eq!(archive.file_names().count(), 2);
+    }
+
+    #[test]
+    fn test_merge_incompatible() {
+        let tar = create_tar(&[("a.txt", "a")]);
+        let zip = vec![0x50, 0x4B, 0x05, 0x06];
+        assert!(merge_archives(
+            &Context::new(&NoProgress, 6),
+            vec![tar, zip],
+            ConflictPolicy::Error
+        )
+        .is_err());
+    }
+}
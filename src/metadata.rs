@@ -0,0 +1,570 @@
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::archive::{create_tar_decoder, holds_archive, stream_content, StreamContent};
+use crate::cpio::{is_cpio, read_cpio};
+use crate::util::infer_input_file;
+use crate::zipfmt::{
+    central_records, find_eocd, read_u16, read_u32, CENTRAL_HEADER_LEN, LOCAL_HEADER_LEN,
+    LOCAL_HEADER_SIGNATURE,
+};
+
+/// The raw metadata of an archive and its entries, as stored in the
+/// archive format.
+///
+/// Nested archives are listed as archives of their own, with the path of
+/// the enclosing entry.
+#[derive(Debug, Serialize)]
+pub struct ArchiveMetadata {
+    pub path: PathBuf,
+    pub format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub entries: Vec<EntryMetadata>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EntryMetadata {
+    Zip(ZipEntry),
+    Tar(TarEntry),
+    Cpio(CpioEntryMetadata),
+}
+
+/// The fields of a central directory record; extra fields are listed by
+/// their header ID, for the central and the local header each.
+#[derive(Debug, Serialize)]
+pub struct ZipEntry {
+    pub name: String,
+    pub version_made_by: u16,
+    pub version_needed: u16,
+    pub flags: u16,
+    pub method: u16,
+    pub dos_time: u16,
+    pub dos_date: u16,
+    pub crc32: u32,
+    pub compressed_size: u32,
+    pub size: u32,
+    pub internal_attributes: u16,
+    pub external_attributes: u32,
+    pub local_header_offset: u32,
+    pub extra_fields: Vec<String>,
+    pub local_extra_fields: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub comment: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TarEntry {
+    pub path: String,
+    /// `ustar`, `gnu` or `v7`.
+    pub header: &'static str,
+    pub entry_type: String,
+    pub mode: Option<u32>,
+    pub uid: Option<u64>,
+    pub gid: Option<u64>,
+    pub uname: Option<String>,
+    pub gname: Option<String>,
+    pub size: Option<u64>,
+    pub mtime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<(u32, u32)>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub pax: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CpioEntryMetadata {
+    pub name: String,
+    pub magic: String,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub size: u32,
+    pub device: (u32, u32),
+    pub rdevice: (u32, u32),
+    pub check: u32,
+}
+
+/// Header IDs of the extra fields in `extra`, e.g. `0x5455` for extended
+/// timestamps.
+fn extra_field_ids(mut extra: &[u8]) -> Vec<String> {
+    let mut ids = Vec::new();
+    while let (Ok(id), Ok(size)) = (read_u16(extra, 0), read_u16(extra, 2)) {
+        ids.push(format!("{:#06x}", id));
+        extra = extra.get(4 + size as usize..).unwrap_or_default();
+    }
+    ids
+}
+
+fn zip_metadata(
+    input_bytes: &[u8],
+    path: &Path,
+    archives: &mut Vec<ArchiveMetadata>,
+) -> Result<()> {
+    let eocd = find_eocd(input_bytes)?;
+    let comment_len = read_u16(input_bytes, eocd.offset + 20)? as usize;
+    let comment_start = eocd.offset + 22;
+    let comment = input_bytes
+        .get(comment_start..comment_start + comment_len)
+        .unwrap_or_default();
+    let records = central_records(input_bytes, &eocd)?;
+    let mut zip_entries = zip::ZipArchive::new(std::io::Cursor::new(input_bytes))?;
+
+    let mut entries = Vec::new();
+    let mut nested = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let header = &input_bytes[record.range.clone()];
+        let name_len = read_u16(header, 28)? as usize;
+        let extra_len = read_u16(header, 30)? as usize;
+        let extra_start = CENTRAL_HEADER_LEN + name_len;
+        let local = record.local_offset;
+        if read_u32(input_bytes, local)? != LOCAL_HEADER_SIGNATURE {
+            Err(anyhow!(
+                "Invalid Zip Archive: Local file header not found at offset {}.",
+                local
+            ))?;
+        }
+        let local_extra_start =
+            local + LOCAL_HEADER_LEN + read_u16(input_bytes, local + 26)? as usize;
+        let local_extra_len = read_u16(input_bytes, local + 28)? as usize;
+        entries.push(EntryMetadata::Zip(ZipEntry {
+            name: record.name.clone(),
+            version_made_by: read_u16(header, 4)?,
+            version_needed: read_u16(header, 6)?,
+            flags: read_u16(header, 8)?,
+            method: read_u16(header, 10)?,
+            dos_time: read_u16(header, 12)?,
+            dos_date: read_u16(header, 14)?,
+            crc32: read_u32(header, 16)?,
+            compressed_size: read_u32(header, 20)?,
+            size: read_u32(header, 24)?,
+            internal_attributes: read_u16(header, 36)?,
+            external_attributes: read_u32(header, 38)?,
+            local_header_offset: read_u32(header, 42)?,
+            extra_fields: extra_field_ids(&header[extra_start..extra_start + extra_len]),
+            local_extra_fields: extra_field_ids(
+                input_bytes
+                    .get(local_extra_start..local_extra_start + local_extra_len)
+                    .unwrap_or_default(),
+            ),
+            comment: String::from_utf8_lossy(&header[extra_start + extra_len..]).into_owned(),
+        }));
+
+        let mut entry = zip_entries.by_index(index)?;
+        if entry.is_file() && !entry.is_symlink() {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            nested.push((path.join(&record.name), data));
+        }
+    }
+    archives.push(ArchiveMetadata {
+        path: path.to_path_buf(),
+        format: "zip",
+        comment: (!comment.is_empty()).then(|| String::from_utf8_lossy(comment).into_owned()),
+        entries,
+    });
+    for (path, data) in nested {
+        if holds_archive(&data) {
+            archive_metadata(&data, &path, archives)?;
+        }
+    }
+    Ok(())
+}
+
+fn tar_metadata(
+    input_bytes: &[u8],
+    mime_type: &str,
+    path: &Path,
+    archives: &mut Vec<ArchiveMetadata>,
+) -> Result<()> {
+    let decoder = create_tar_decoder(input_bytes, mime_type)?;
+    let mut tar_archive = tar::Archive::new(decoder);
+    let mut entries = Vec::new();
+    let mut nested = Vec::new();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let mut pax = BTreeMap::new();
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension?;
+                pax.insert(
+                    String::from_utf8_lossy(extension.key_bytes()).into_owned(),
+                    String::from_utf8_lossy(extension.value_bytes()).into_owned(),
+                );
+            }
+        }
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        let header = entry.header();
+        let header_format = if header.as_ustar().is_some() {
+            "ustar"
+        } else if header.as_gnu().is_some() {
+            "gnu"
+        } else {
+            "v7"
+        };
+        let device = match (header.device_major(), header.device_minor()) {
+            (Ok(Some(major)), Ok(Some(minor))) if major != 0 || minor != 0 => Some((major, minor)),
+            _ => None,
+        };
+        let text = |value: Result<Option<&str>, std::str::Utf8Error>| {
+            value.ok().flatten().map(str::to_string)
+        };
+        let is_file = header.entry_type().is_file();
+        entries.push(EntryMetadata::Tar(TarEntry {
+            path: entry_path.clone(),
+            header: header_format,
+            entry_type: (header.entry_type().as_byte() as char).to_string(),
+            mode: header.mode().ok(),
+            uid: header.uid().ok(),
+            gid: header.gid().ok(),
+            uname: text(header.username()),
+            gname: text(header.groupname()),
+            size: header.size().ok(),
+            mtime: header.mtime().ok(),
+            link_name: entry
+                .link_name()?
+                .map(|link| link.to_string_lossy().into_owned()),
+            device,
+            pax,
+        }));
+
+        if is_file {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            if holds_archive(&data) {
+                nested.push((path.join(entry_path), data));
+            }
+        }
+    }
+    archives.push(ArchiveMetadata {
+        path: path.to_path_buf(),
+        format: "tar",
+        comment: None,
+        entries,
+    });
+    for (path, data) in nested {
+        archive_metadata(&data, &path, archives)?;
+    }
+    Ok(())
+}
+
+fn cpio_metadata(
+    input_bytes: &[u8],
+    path: &Path,
+    archives: &mut Vec<ArchiveMetadata>,
+) -> Result<()> {
+    let (cpio_entries, _) = read_cpio(input_bytes)?;
+    let mut entries = Vec::new();
+    let mut nested = Vec::new();
+    for entry in cpio_entries {
+        let fields = entry.fields;
+        entries.push(EntryMetadata::Cpio(CpioEntryMetadata {
+            name: entry.name.clone(),
+            magic: String::from_utf8_lossy(&entry.magic).into_owned(),
+            ino: fields[0],
+            mode: fields[1],
+            uid: fields[2],
+            gid: fields[3],
+            nlink: fields[4],
+            mtime: fields[5],
+            size: fields[6],
+            device: (fields[7], fields[8]),
+            rdevice: (fields[9], fields[10]),
+            check: fields[12],
+        }));
+        if entry.is_file() && holds_archive(&entry.data) {
+            nested.push((path.join(entry.path()), entry.data));
+        }
+    }
+    archives.push(ArchiveMetadata {
+        path: path.to_path_buf(),
+        format: "cpio",
+        comment: None,
+        entries,
+    });
+    for (path, data) in nested {
+        archive_metadata(&data, &path, archives)?;
+    }
+    Ok(())
+}
+
+fn archive_metadata(
+    input_bytes: &[u8],
+    path: &Path,
+    archives: &mut Vec<ArchiveMetadata>,
+) -> Result<()> {
+    if is_cpio(input_bytes) {
+        return cpio_metadata(input_bytes, path, archives);
+    }
+    let mime_type = infer_input_file(input_bytes)?;
+    match mime_type.as_str() {
+        "application/zip" => zip_metadata(input_bytes, path, archives),
+        "application/gzip"
+        | "application/x-bzip2"
+        | "application/x-xz"
+        | "application/zstd"
+        | "application/x-tar" => match stream_content(input_bytes, &mime_type)? {
+            StreamContent::Archive => {
+                let mut decoded = Vec::new();
+                create_tar_decoder(input_bytes, &mime_type)?.read_to_end(&mut decoded)?;
+                archive_metadata(&decoded, path, archives)
+            }
+            _ => tar_metadata(input_bytes, &mime_type, path, archives),
+        },
+        _ => Err(anyhow!(
+            "Unsupported File Type: The file with MIME type '{}' is not supported.",
+            mime_type
+        ))?,
+    }
+}
+
+/// Reads the metadata of an archive and of the archives nested in it,
+/// outermost first.
+pub fn read_metadata(input_bytes: &[u8]) -> Result<Vec<ArchiveMetadata>> {
+    let mut archives = Vec::new();
+    archive_metadata(input_bytes, Path::new(""), &mut archives)?;
+    Ok(archives)
+}
+
+/// A field of an archive or entry that differs between two metadata dumps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// Path of the archive, then of the entry inside it, if any.
+    pub location: String,
+    pub field: String,
+    pub input: String,
+    pub output: String,
+}
+
+impl Difference {
+    fn new(location: String, field: &str, input: &str, output: &str) -> Self {
+        Difference {
+            location,
+            field: field.to_string(),
+            input: input.to_string(),
+            output: output.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}: {} -> {}",
+            self.location, self.field, self.input, self.output
+        )
+    }
+}
+
+fn entry_name(entry: &serde_json::Value) -> String {
+    entry
+        .get("name")
+        .or_else(|| entry.get("path"))
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn field_value(value: Option<&serde_json::Value>) -> String {
+    value.map_or_else(|| "(none)".to_string(), ToString::to_string)
+}
+
+fn compare_fields(
+    location: &str,
+    input: &serde_json::Value,
+    output: &serde_json::Value,
+    skip: &[&str],
+    differences: &mut Vec<Difference>,
+) {
+    let (Some(input), Some(output)) = (input.as_object(), output.as_object()) else {
+        return;
+    };
+    let mut fields: Vec<&String> = input.keys().chain(output.keys()).collect();
+    fields.sort();
+    fields.dedup();
+    for field in fields {
+        if skip.contains(&field.as_str()) || input.get(field) == output.get(field) {
+            continue;
+        }
+        differences.push(Difference::new(
+            location.to_string(),
+            field,
+            &field_value(input.get(field)),
+            &field_value(output.get(field)),
+        ));
+    }
+}
+
+/// Compares the metadata of an archive before and after a rewrite, archives
+/// by path and entries by name. Returns every field that was not preserved,
+/// including archives, entries and an entry order that changed.
+pub fn compare_metadata(
+    input: &[ArchiveMetadata],
+    output: &[ArchiveMetadata],
+) -> Result<Vec<Difference>> {
+    let mut differences = Vec::new();
+    for archive in input {
+        if !output.iter().any(|other| other.path == archive.path) {
+            differences.push(Difference::new(
+                archive.path.display().to_string(),
+                "archive",
+                "present",
+                "missing",
+            ));
+        }
+    }
+    for archive in output {
+        if !input.iter().any(|other| other.path == archive.path) {
+            differences.push(Difference::new(
+                archive.path.display().to_string(),
+                "archive",
+                "missing",
+                "present",
+            ));
+        }
+    }
+
+    for archive in input {
+        let Some(rewritten) = output.iter().find(|other| other.path == archive.path) else {
+            continue;
+        };
+        let location = archive.path.display().to_string();
+        let (archive, rewritten) = (
+            serde_json::to_value(archive)?,
+            serde_json::to_value(rewritten)?,
+        );
+        compare_fields(
+            &location,
+            &archive,
+            &rewritten,
+            &["path", "entries"],
+            &mut differences,
+        );
+
+        let entries = |archive: &serde_json::Value| -> Vec<serde_json::Value> {
+            archive["entries"].as_array().cloned().unwrap_or_default()
+        };
+        let (entries, rewritten) = (entries(&archive), entries(&rewritten));
+        let names: Vec<String> = entries.iter().map(entry_name).collect();
+        let rewritten_names: Vec<String> = rewritten.iter().map(entry_name).collect();
+        for entry in &entries {
+            let name = entry_name(entry);
+            let entry_location = Path::new(&location).join(&name).display().to_string();
+            match rewritten.iter().find(|other| entry_name(other) == name) {
+                // offsets follow from the sizes of the preceding entries
+                Some(other) => compare_fields(
+                    &entry_location,
+                    entry,
+                    other,
+                    &["local_header_offset"],
+                    &mut differences,
+                ),
+                None => differences.push(Difference::new(
+                    entry_location,
+                    "entry",
+                    "present",
+                    "missing",
+                )),
+            }
+        }
+        for name in rewritten_names.iter().filter(|name| !names.contains(name)) {
+            differences.push(Difference::new(
+                Path::new(&location).join(name).display().to_string(),
+                "entry",
+                "missing",
+                "present",
+            ));
+        }
+        let common = |names: &[String], other: &[String]| -> Vec<String> {
+            names
+                .iter()
+                .filter(|name| other.contains(name))
+                .cloned()
+                .collect()
+        };
+        if common(&names, &rewritten_names) != common(&rewritten_names, &names) {
+            differences.push(Difference::new(
+                location,
+                "entry order",
+                &names.join(", "),
+                &rewritten_names.join(", "),
+            ));
+        }
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_metadata() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let archives = read_metadata(&input).unwrap();
+        assert_eq!(archives[0].path, Path::new(""));
+        assert_eq!(archives[0].format, "tar");
+        let EntryMetadata::Tar(entry) = &archives[0].entries[0] else {
+            panic!("not a tar entry");
+        };
+        assert_eq!(entry.header, "ustar");
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.set_comment("archive comment");
+        zip.start_file("a.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, b"hello").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let archives = read_metadata(&zip).unwrap();
+        assert_eq!(archives[0].comment.as_deref(), Some("archive comment"));
+        let EntryMetadata::Zip(entry) = &archives[0].entries[0] else {
+            panic!("not a zip entry");
+        };
+        assert_eq!(entry.name, "a.txt");
+        assert_eq!(entry.method, 8);
+        assert_eq!(entry.size, 5);
+        assert!(entry.comment.is_empty());
+
+        assert_eq!(
+            extra_field_ids(b"\x55\x54\x05\x00\x01\x00\x00\x00\x00\x75\x78\x00\x00"),
+            ["0x5455", "0x7875"]
+        );
+    }
+
+    #[test]
+    fn test_compare_metadata() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let before = read_metadata(&input).unwrap();
+        assert!(compare_metadata(&before, &before).unwrap().is_empty());
+
+        let mut after = read_metadata(&input).unwrap();
+        if let EntryMetadata::Tar(entry) = &mut after[0].entries[1] {
+            entry.mode = Some(0o644);
+        }
+        after[0].entries.remove(0);
+        after.pop();
+        let differences: Vec<String> = compare_metadata(&before, &after)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(differences.len(), 3);
+        assert!(differences[0].ends_with("archive: present -> missing"));
+        assert_eq!(differences[1], "tar-test/: entry: present -> missing");
+        assert_eq!(differences[2], "tar-test/file_1.txt: mode: 511 -> 420");
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{anyhow, Result};
+
+/// Removes comments and the whitespace between tags of an XML document.
+/// Documents using `xml:space="preserve"` are returned unchanged.
+pub fn minify_xml(content: &[u8]) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(content)
+        .map_err(|_| anyhow!("Invalid XML: The document is not UTF-8."))?;
+    if text.contains("xml:space=\"preserve\"") || text.contains("xml:space='preserve'") {
+        return Ok(content.to_vec());
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let (before, markup) = rest.split_at(start);
+        // text between tags is kept unless it is whitespace only
+        if !before.trim().is_empty() {
+            output.push_str(before);
+        }
+        let (end, keep) = if markup.starts_with("<!--") {
+            (markup.find("-->").map(|end| end + 3), false)
+        } else if markup.starts_with("<![CDATA[") {
+            (markup.find("]]>").map(|end| end + 3), true)
+        } else {
+            (markup.find('>').map(|end| end + 1), true)
+        };
+        let end = end.ok_or_else(|| anyhow!("Invalid XML: A tag is not closed."))?;
+        if keep {
+            output.push_str(&markup[..end]);
+        }
+        rest = &markup[end..];
+    }
+    if !rest.trim().is_empty() {
+        output.push_str(rest);
+    }
+    Ok(output.into_bytes())
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+/// Whether a `/` following `output` starts a regular expression rather
+/// than a division.
+fn regex_allowed(output: &str) -> bool {
+    let trimmed = output.trim_end();
+    match trimmed.chars().last() {
+        None => true,
+        Some(c) if "(,=:[!&|?{};+-*%<>~^".contains(c) => true,
+        Some(c) if is_word(c) => {
+            let word_start = trimmed
+                .rfind(|c: char| !is_word(c))
+                .map_or(0, |index| index + 1);
+            matches!(
+                &trimmed[word_start..],
+                "return"
+                    | "typeof"
+                    | "instanceof"
+                    | "in"
+                    | "of"
+                    | "new"
+                    | "delete"
+                    | "void"
+                    | "throw"
+                    | "case"
+                    | "do"
+                    | "else"
+                    | "yield"
+                    | "await"
+            )
+        }
+        _ => false,
+    }
+}
+
+/// Copies a string, template or regular expression literal starting at
+/// `chars[start]`, returns the index following it.
+fn copy_literal(chars: &[char], start: usize, output: &mut String) -> usize {
+    let quote = chars[start];
+    let mut in_class = false;
+    let mut index = start + 1;
+    output.push(quote);
+    while index < chars.len() {
+        let c = chars[index];
+        output.push(c);
+        index += 1;
+        match c {
+            '\\' if index < chars.len() => {
+                output.push(chars[index]);
+                index += 1;
+            }
+            '[' if quote == '/' => in_class = true,
+            ']' if quote == '/' => in_class = false,
+            c if c == quote && !in_class => break,
+            // unterminated, ASI keeps the newline
+            '\n' if quote != '`' => break,
+            _ => {}
+        }
+    }
+    index
+}
+
+/// Conservatively minifies JavaScript: removes comments, except `/*!`
+/// license comments, indentation and blank lines. Line breaks are kept where
+/// automatic semicolon insertion may depend on them.
+pub fn minify_js(content: &[u8]) -> Result<Vec<u8>> {
+    let text = std::str::from_utf8(content)
+        .map_err(|_| anyhow!("Invalid JavaScript: The script is not UTF-8."))?;
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut index = 0;
+    // whitespace seen since the last output, and whether it had a newline
+    let mut space = false;
+    let mut newline = false;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        if c.is_whitespace() {
+            space = true;
+            newline |= c == '\n';
+            index += 1;
+            continue;
+        }
+        if c == '/' && next == Some('/') {
+            while index < chars.len() && chars[index] != '\n' {
+                index += 1;
+            }
+            continue;
+        }
+        if c == '/' && next == Some('*') && chars.get(index + 2) != Some(&'!') {
+            let end = (index + 2..chars.len().saturating_sub(1))
+                .find(|&end| chars[end] == '*' && chars[end + 1] == '/')
+                .map_or(chars.len(), |end| end + 2);
+            space = true;
+            newline |= chars[index..end].contains(&'\n');
+            index = end;
+            continue;
+        }
+
+        if space {
+            let last = output.chars().last();
+            if newline && last.is_some_and(|last| !"{;,([".contains(last)) {
+                output.push('\n');
+            } else if last.is_some_and(|last| {
+                (is_word(last) && is_word(c)) || (last == c && (c == '+' || c == '-'))
+            }) {
+                output.push(' ');
+            }
+            space = false;
+            newline = false;
+        }
+        if c == '"'
+            || c == '\''
+            || c == '`'
+            || (c == '/' && next != Some('*') && regex_allowed(&output))
+        {
+            index = copy_literal(&chars, index, &mut output);
+        } else if c == '/' && next == Some('*') {
+            // a /*! comment
+            let end = (index + 2..chars.len().saturating_sub(1))
+                .find(|&end| chars[end] == '*' && chars[end + 1] == '/')
+                .map_or(chars.len(), |end| end + 2);
+            output.extend(&chars[index..end]);
+            index = end;
+        } else {
+            output.push(c);
+            index += 1;
+        }
+    }
+    Ok(output.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xml(text: &str) -> String {
+        String::from_utf8(minify_xml(text.as_bytes()).unwrap()).unwrap()
+    }
+
+    fn js(text: &str) -> String {
+        String::from_utf8(minify_js(text.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_minify_xml() {
+        assert_eq!(
+            xml("<?xml version=\"1.0\"?>\n<a>\n  <!-- note -->\n  <b x=\"1\"> text </b>\n  <c><![CDATA[ <kept> ]]></c>\n</a>\n"),
+            "<?xml version=\"1.0\"?><a><b x=\"1\"> text </b><c><![CDATA[ <kept> ]]></c></a>"
+        );
+        let preserved = "<a xml:space=\"preserve\">\n  <b/>\n</a>";
+        assert_eq!(xml(preserved), preserved);
+        assert!(minify_xml(b"<a><b").is_err());
+    }
+
+    #[test]
+    fn test_minify_js() {
+        assert_eq!(
+            js("/*! keep me */\n// comment\nfunction add(a, b) {\n    return a + +b; /* sum */\n}\n\nlet s = \"a // b\", r = /[/]\\//g;\nlet t = `x\n  y`\nx = a / b / c\n"),
+            "/*! keep me */\nfunction add(a,b){return a+ +b;}\nlet s=\"a // b\",r=/[/]\\//g;let t=`x\n  y`\nx=a/b/c"
+        );
+        assert_eq!(js("a\n++b"), "a\n++b");
+        assert_eq!(js("return /x/.test(y)"), "return/x/.test(y)");
+    }
+}
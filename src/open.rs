@@ -0,0 +1,133 @@
+use std::{
+    io::IsTerminal,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+
+/// Pager used when `PAGER` is not set.
+const DEFAULT_PAGER: &str = "less";
+
+/// What a file is shown with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Viewer {
+    /// The opener of the desktop, which picks the default application.
+    Desktop(&'static str),
+    /// A pager in the terminal.
+    Pager(String),
+}
+
+impl Viewer {
+    /// The viewer of this session: the desktop opener if there is a desktop,
+    /// else `PAGER` if stdout is a terminal, else none.
+    pub fn detect() -> Option<Self> {
+        let display = ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()));
+        Viewer::choose(
+            std::env::consts::OS,
+            display,
+            std::io::stdout().is_terminal(),
+            std::env::var("PAGER").ok(),
+        )
+    }
+
+    /// macOS and Windows always have a desktop, other systems when a display
+    /// server is set.
+    fn choose(os: &str, display: bool, terminal: bool, pager: Option<String>) -> Option<Self> {
+        match os {
+            "macos" => Some(Viewer::Desktop("open")),
+            "windows" => Some(Viewer::Desktop("start")),
+            _ if display => Some(Viewer::Desktop("xdg-open")),
+            _ if terminal => Some(Viewer::Pager(
+                pager
+                    .filter(|pager| !pager.trim().is_empty())
+                    .unwrap_or_else(|| DEFAULT_PAGER.to_string()),
+            )),
+            _ => None,
+        }
+    }
+
+    fn command(&self, path: &Path) -> Command {
+        match self {
+            // start is built into cmd, its first quoted argument is the title
+            Viewer::Desktop("start") => {
+                let mut command = Command::new("cmd");
+                command.args(["/C", "start", ""]).arg(path);
+                command
+            }
+            Viewer::Desktop(opener) => {
+                let mut command = Command::new(opener);
+                command.arg(path);
+                command
+            }
+            // PAGER may carry arguments, e.g. `less -R`
+            Viewer::Pager(pager) => {
+                let mut words = pager.split_whitespace();
+                let mut command = Command::new(words.next().unwrap_or(DEFAULT_PAGER));
+                command.args(words).arg(path);
+                command
+            }
+        }
+    }
+
+    /// Shows the file, waiting for the pager to be closed.
+    pub fn open(&self, path: &Path) -> Result<()> {
+        let mut command = self.command(path);
+        let name = command.get_program().to_string_lossy().into_owned();
+        if let Viewer::Desktop(_) = self {
+            // the opener may log, it must not garble the terminal
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        let status = command
+            .status()
+            .map_err(|err| anyhow!("Open Failed: Could not run '{}' ({}).", name, err))?;
+        if !status.success() {
+            Err(anyhow!(
+                "Open Failed: '{}' exited with {} opening '{}'.",
+                name,
+                status,
+                path.display()
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose() {
+        assert_eq!(
+            Viewer::choose("macos", false, false, None),
+            Some(Viewer::Desktop("open"))
+        );
+        assert_eq!(
+            Viewer::choose("linux", true, true, None),
+            Some(Viewer::Desktop("xdg-open"))
+        );
+        assert_eq!(
+            Viewer::choose("linux", false, true, Some("less -R".to_string())),
+            Some(Viewer::Pager("less -R".to_string()))
+        );
+        assert_eq!(
+            Viewer::choose("freebsd", false, true, Some(" ".to_string())),
+            Some(Viewer::Pager("less".to_string()))
+        );
+        // headless, e.g. CI or ssh with the output piped
+        assert_eq!(Viewer::choose("linux", false, false, None), None);
+    }
+
+    #[test]
+    fn test_command() {
+        let command = Viewer::Pager("less -R".to_string()).command(Path::new("report.json"));
+        assert_eq!(command.get_program(), "less");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-R", "report.json"]);
+        let command = Viewer::Desktop("start").command(Path::new("report.json"));
+        assert_eq!(command.get_program(), "cmd");
+    }
+}
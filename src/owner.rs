@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Result};
+
+/// Rewrites the owner of matching entries, parsed from `FROM:NAME[:ID]` or
+/// `FROM:ID`. `FROM` matches a name or a numeric id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerRule {
+    pub from: String,
+    pub name: Option<String>,
+    pub id: Option<u64>,
+}
+
+impl OwnerRule {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!(
+                "Invalid Owner Mapping: '{}' is not of the form FROM:NAME[:ID] or FROM:ID.",
+                rule
+            )
+        };
+        let mut parts = rule.split(':');
+        let from = parts
+            .next()
+            .filter(|from| !from.is_empty())
+            .ok_or_else(invalid)?;
+        let to = parts
+            .next()
+            .filter(|to| !to.is_empty())
+            .ok_or_else(invalid)?;
+        let id = parts.next();
+        if parts.next().is_some() {
+            Err(invalid())?;
+        }
+        let (name, id) = match (to.parse::<u64>(), id) {
+            (Ok(id), None) => (None, Some(id)),
+            (_, None) => (Some(to.to_string()), None),
+            (_, Some(id)) => (
+                Some(to.to_string()),
+                Some(id.parse().map_err(|_| invalid())?),
+            ),
+        };
+        Ok(OwnerRule {
+            from: from.to_string(),
+            name,
+            id,
+        })
+    }
+
+    fn matches(&self, name: Option<&str>, id: Option<u64>) -> bool {
+        name == Some(self.from.as_str()) || id.is_some_and(|id| self.from == id.to_string())
+    }
+}
+
+/// User and group mappings applied to the headers of kept tar entries.
+#[derive(Debug, Clone, Default)]
+pub struct OwnerMap {
+    pub users: Vec<OwnerRule>,
+    pub groups: Vec<OwnerRule>,
+}
+
+impl OwnerMap {
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.groups.is_empty()
+    }
+
+    pub fn apply(&self, header: &mut tar::Header) -> Result<()> {
+        let username = header.username().ok().flatten().map(str::to_string);
+        if let Some(rule) = self
+            .users
+            .iter()
+            .find(|rule| rule.matches(username.as_deref(), header.uid().ok()))
+        {
+            // a numeric only target clears the name so the id is used on extraction
+            header.set_username(rule.name.as_deref().unwrap_or(""))?;
+            if let Some(id) = rule.id {
+                header.set_uid(id);
+            }
+        }
+        let groupname = header.groupname().ok().flatten().map(str::to_string);
+        if let Some(rule) = self
+            .groups
+            .iter()
+            .find(|rule| rule.matches(groupname.as_deref(), header.gid().ok()))
+        {
+            header.set_groupname(rule.name.as_deref().unwrap_or(""))?;
+            if let Some(id) = rule.id {
+                header.set_gid(id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_owner_rule() {
+        assert_eq!(
+            OwnerRule::parse("builder:0").unwrap(),
+            OwnerRule {
+                from: "builder".to_string(),
+                name: None,
+                id: Some(0)
+            }
+        );
+        assert_eq!(
+            OwnerRule::parse("1000:root:0").unwrap(),
+            OwnerRule {
+                from: "1000".to_string(),
+                name: Some("root".to_string()),
+                id: Some(0)
+            }
+        );
+        assert!(OwnerRule::parse("builder").is_err());
+        assert!(OwnerRule::parse("builder:root:x").is_err());
+        assert!(OwnerRule::parse(":root").is_err());
+    }
+
+    #[test]
+    fn test_apply_owner_map() {
+        let mut header = tar::Header::new_gnu();
+        header.set_username("builder").unwrap();
+        header.set_uid(1000);
+        header.set_groupname("staff").unwrap();
+        header.set_gid(50);
+
+        let map = OwnerMap {
+            users: vec![OwnerRule::parse("builder:0").unwrap()],
+            groups: vec![OwnerRule::parse("50:users:100").unwrap()],
+        };
+        map.apply(&mut header).unwrap();
+        assert_eq!(header.username().unwrap(), Some(""));
+        assert_eq!(header.uid().unwrap(), 0);
+        assert_eq!(header.groupname().unwrap(), Some("users"));
+        assert_eq!(header.gid().unwrap(), 100);
+    }
+}
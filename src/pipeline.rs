@@ -0,0 +1,279 @@
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+    thread::{Scope, ScopedJoinHandle},
+};
+
+use anyhow::Result;
+
+/// Size of the blocks handed from one stage to the next.
+pub const BLOCK_SIZE: usize = 128 * 1024;
+
+/// Number of blocks of `prefetch` bytes a stage may run ahead of the
+/// following one, at least one.
+pub fn depth(prefetch: usize) -> usize {
+    (prefetch / BLOCK_SIZE).max(1)
+}
+
+/// Reads the blocks produced by a [`read_ahead`] thread.
+pub struct ChannelReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    /// Blocks read, handed back to be filled again.
+    recycle: Sender<Vec<u8>>,
+    block: Vec<u8>,
+    position: usize,
+}
+
+/// Runs `reader`, typically a decompressor, on its own thread of `scope`,
+/// up to `depth` blocks ahead of the returned reader. The blocks are reused
+/// once read, so no more than `depth + 2` are ever allocated.
+pub fn read_ahead<'scope, R>(
+    scope: &'scope Scope<'scope, '_>,
+    depth: usize,
+    mut reader: R,
+) -> ChannelReader
+where
+    R: Read + Send + 'scope,
+{
+    let (sender, receiver) = sync_channel(depth);
+    let (recycle, recycled) = channel::<Vec<u8>>();
+    scope.spawn(move || loop {
+        let mut block = recycled.try_recv().unwrap_or_default();
+        block.resize(BLOCK_SIZE, 0);
+        let block = match reader.read(&mut block) {
+            Ok(0) => break,
+            Ok(read) => {
+                block.truncate(read);
+                Ok(block)
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => Err(err),
+        };
+        let failed = block.is_err();
+        // the reader is gone once the consumer stopped early
+        if sender.send(block).is_err() || failed {
+            break;
+        }
+    });
+    ChannelReader {
+        receiver,
+        recycle,
+        block: Vec::new(),
+        position: 0,
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.block.len() {
+            match self.receiver.recv() {
+                Ok(block) => {
+                    let read = std::mem::replace(&mut self.block, block?);
+                    // the producing thread may be done already
+                    let _ = self.recycle.send(read);
+                    self.position = 0;
+                }
+                // the producing thread is done
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.block.len() - self.position);
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// The output side of an [`encode_behind`] thread, handing its encoded
+/// blocks back through a channel.
+pub struct BlockSender(Sender<Vec<u8>>);
+
+impl Write for BlockSender {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes through an encoder running on a thread of its own, see
+/// [`encode_behind`].
+pub struct ChannelWriter<'scope, W: Write> {
+    sender: Option<SyncSender<Vec<u8>>>,
+    encoded: Receiver<Vec<u8>>,
+    /// Blocks encoded, handed back to be filled again.
+    recycled: Receiver<Vec<u8>>,
+    handle: Option<ScopedJoinHandle<'scope, Result<()>>>,
+    block: Vec<u8>,
+    sink: W,
+}
+
+/// Runs the encoder made by `encoder` on its own thread of `scope`. What is
+/// written to the returned writer is encoded there, up to `depth` blocks
+/// behind, and the encoded output is written to `sink` on the calling
+/// thread, so that `sink` does not have to be [`Send`].
+pub fn encode_behind<'scope, W, E, F>(
+    scope: &'scope Scope<'scope, '_>,
+    depth: usize,
+    sink: W,
+    encoder: F,
+) -> ChannelWriter<'scope, W>
+where
+    W: Write,
+    E: Write + FinishEncoder,
+    F: FnOnce(BlockSender) -> Result<E> + Send + 'scope,
+{
+    let (sender, receiver) = sync_channel::<Vec<u8>>(depth);
+    let (encoded_sender, encoded) = channel();
+    let (recycle, recycled) = channel();
+    let handle = scope.spawn(move || {
+        let mut encoder = encoder(BlockSender(encoded_sender))?;
+        for mut block in receiver {
+            encoder.write_all(&block)?;
+            block.clear();
+            // the writer may be gone already
+            let _ = recycle.send(block);
+        }
+        encoder.finish_encoder()
+    });
+    ChannelWriter {
+        sender: Some(sender),
+        encoded,
+        recycled,
+        handle: Some(handle),
+        block: Vec::with_capacity(BLOCK_SIZE),
+        sink,
+    }
+}
+
+/// Ends the stream of an encoder running on an [`encode_behind`] thread.
+pub trait FinishEncoder {
+    fn finish_encoder(self) -> Result<()>;
+}
+
+impl<W: Write> FinishEncoder for Box<dyn crate::archive::WriteEncoder<W> + '_> {
+    fn finish_encoder(self) -> Result<()> {
+        self.inner().map(drop)
+    }
+}
+
+impl<W: Write> ChannelWriter<'_, W> {
+    /// The error of the encoder thread, once it stopped.
+    fn join(&mut self) -> Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+            None => Ok(()),
+        }
+    }
+
+    fn send_block(&mut self) -> io::Result<()> {
+        let next = self
+            .recycled
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(BLOCK_SIZE));
+        let block = std::mem::replace(&mut self.block, next);
+        let sent = match &self.sender {
+            Some(sender) => sender.send(block).is_ok(),
+            None => false,
+        };
+        if !sent {
+            // the encoder stopped on an error
+            self.sender = None;
+            self.join().map_err(io::Error::other)?;
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        while let Ok(encoded) = self.encoded.try_recv() {
+            self.sink.write_all(&encoded)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the encoder to end the stream and returns the sink with
+    /// all of the encoded output written to it.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.block.is_empty() {
+            self.send_block()?;
+        }
+        self.sender = None;
+        for encoded in &self.encoded {
+            self.sink.write_all(&encoded)?;
+        }
+        self.join()?;
+        Ok(self.sink)
+    }
+}
+
+impl<W: Write> Write for ChannelWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        if self.block.len() == BLOCK_SIZE {
+            self.send_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+    impl<W: Write> FinishEncoder for GzEncoder<W> {
+        fn finish_encoder(self) -> Result<()> {
+            self.finish().map(drop).map_err(Into::into)
+        }
+    }
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(depth(0), 1);
+        assert_eq!(depth(4 * 1024 * 1024), 32);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 253) as u8).collect();
+        let encoded = std::thread::scope(|scope| {
+            let mut writer = encode_behind(scope, 2, Vec::new(), |blocks| {
+                Ok(GzEncoder::new(blocks, Compression::fast()))
+            });
+            writer.write_all(&data).unwrap();
+            writer.finish().unwrap()
+        });
+        let decoded = std::thread::scope(|scope| {
+            let mut reader = read_ahead(scope, 2, GzDecoder::new(encoded.as_slice()));
+            let mut decoded = Vec::new();
+            reader.read_to_end(&mut decoded).unwrap();
+            decoded
+        });
+        assert_eq!(decoded, data);
+
+        // a decoding error reaches the reader
+        let result = std::thread::scope(|scope| {
+            let mut reader = read_ahead(scope, 2, GzDecoder::new(&encoded[..encoded.len() / 2]));
+            reader.read_to_end(&mut Vec::new())
+        });
+        assert!(result.is_err());
+
+        // so does an encoding one, on the writer
+        let result = std::thread::scope(|scope| {
+            let mut writer = encode_behind(scope, 2, Vec::new(), |_| {
+                Err::<GzEncoder<Vec<u8>>, _>(anyhow::anyhow!("Encoding Failed: Test."))
+            });
+            writer.write_all(&data)
+        });
+        assert!(result.unwrap_err().to_string().contains("Encoding Failed"));
+    }
+}
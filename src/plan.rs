@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+use crate::index::EntryIndex;
+use crate::rule::Rule;
+use crate::util::sha256_hex;
+
+/// The exact entries a scrub will remove, written by `plan` for review and
+/// executed by `--plan`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub input: String,
+    /// The plan only applies to the archive it was made for.
+    pub input_sha256: String,
+    /// Archive paths to remove, nested entries below their archive's path.
+    pub remove: Vec<PathBuf>,
+    /// Filter list entries matching nothing.
+    pub not_found: Vec<PathBuf>,
+}
+
+impl Plan {
+    /// Resolves every filter list entry to the first archive entry it
+    /// matches, as the scrub does.
+    pub fn build(input: &str, input_bytes: &[u8], filter_list: &[PathBuf]) -> Result<Self> {
+        let index = EntryIndex::build(input_bytes)?;
+        let mut remove = Vec::new();
+        let mut not_found = Vec::new();
+        for filter in filter_list {
+            let rule = Rule::new(filter);
+            match index
+                .entries
+                .iter()
+                .find(|e| rule.matches_listed(&e.path, e.depth))
+            {
+                Some(entry) => remove.push(entry.path.clone()),
+                None => not_found.push(filter.clone()),
+            }
+        }
+        remove.sort();
+        remove.dedup();
+        not_found.sort();
+        Ok(Plan {
+            input: input.to_string(),
+            input_sha256: index.input_sha256,
+            remove,
+            not_found,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        serde_json::from_slice(&std::fs::read(path)?).map_err(|err| {
+            anyhow!(
+                "Invalid Plan: '{}' is not a plan file ({}).",
+                path.display(),
+                err
+            )
+        })
+    }
+
+    /// Loads a plan only if `signature` is a valid minisign signature of the
+    /// plan file by `public_key`.
+    pub fn load_signed<P: AsRef<Path>>(
+        path: P,
+        signature: &Path,
+        public_key: &Path,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let plan_bytes = std::fs::read(path)?;
+        let invalid = |err: minisign_verify::Error| {
+            anyhow!(
+                "Invalid Signature: The plan '{}' is not signed by '{}' ({}).",
+                path.display(),
+                public_key.display(),
+                err
+            )
+        };
+        let public_key_file = PublicKey::from_file(public_key).map_err(invalid)?;
+        let signature_file = Signature::from_file(signature).map_err(invalid)?;
+        public_key_file
+            .verify(&plan_bytes, &signature_file, false)
+            .map_err(invalid)?;
+        Plan::load(path)
+    }
+
+    /// Fails unless the plan was made for `input_bytes`.
+    pub fn verify(&self, input_bytes: &[u8]) -> Result<()> {
+        if sha256_hex(input_bytes) != self.input_sha256 {
+            Err(anyhow!(
+                "Plan Mismatch: The plan was made for a different version of '{}', create a new plan.",
+                self.input
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let filter_list = vec![
+            PathBuf::from("tar-test/missing.txt"),
+            PathBuf::from("tar-test/file_1.txt"),
+            PathBuf::from("./tar-test/file_1.txt"),
+        ];
+        let plan = Plan::build("tar-test.tar.gz", &input, &filter_list).unwrap();
+        assert_eq!(plan.remove, vec![PathBuf::from("tar-test/file_1.txt")]);
+        assert_eq!(plan.not_found, vec![PathBuf::from("tar-test/missing.txt")]);
+        assert!(plan.verify(&input).is_ok());
+        assert!(plan.verify(b"other").is_err());
+
+        let file = assert_fs::NamedTempFile::new("plan.json").unwrap();
+        std::fs::write(file.path(), plan.to_json().unwrap()).unwrap();
+        assert_eq!(Plan::load(file.path()).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_load_signed_plan() {
+        let plan = Plan::load_signed(
+            "tests/assets/tar-test.plan.json",
+            Path::new("tests/assets/tar-test.plan.json.minisig"),
+            Path::new("tests/assets/plan-key.pub"),
+        )
+        .unwrap();
+        assert_eq!(plan.remove.len(), 4);
+
+        let file = assert_fs::NamedTempFile::new("plan.json").unwrap();
+        let tampered = std::fs::read_to_string("tests/assets/tar-test.plan.json")
+            .unwrap()
+            .replace("file_link", "file_1");
+        std::fs::write(file.path(), tampered).unwrap();
+        let err = Plan::load_signed(
+            file.path(),
+            Path::new("tests/assets/tar-test.plan.json.minisig"),
+            Path::new("tests/assets/plan-key.pub"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Invalid Signature"));
+    }
+}
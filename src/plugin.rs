@@ -0,0 +1,165 @@
+//! Entry filters loaded from shared libraries.
+//!
+//! A plugin is a `cdylib` exporting the following C functions:
+//!
+//! ```c
+//! uint32_t expurgator_plugin_abi_version(void);
+//! int32_t expurgator_filter(const PluginEntry *entry,
+//!                           uint8_t **replacement, size_t *replacement_len);
+//! void expurgator_free(uint8_t *ptr, size_t len);
+//! ```
+//!
+//! `expurgator_filter` returns `0` to keep, `1` to remove and `2` to replace
+//! the entry, any other value is treated as an error. On replace the plugin
+//! hands over a buffer it allocated, which is returned to it through
+//! `expurgator_free` once copied.
+
+use std::{os::raw::c_int, path::Path, ptr, slice};
+
+use anyhow::{anyhow, Result};
+use libloading::{Library, Symbol};
+
+use expurgator::{Decision, EntryFilter, EntryInfo, EntryKind};
+
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Entry metadata as passed to `expurgator_filter`.
+///
+/// `content` is null for anything but regular files.
+#[repr(C)]
+pub struct PluginEntry {
+    pub path: *const u8,
+    pub path_len: usize,
+    pub size: u64,
+    pub mtime: u64,
+    /// 0 file, 1 directory, 2 symlink, 3 hard link, 4 other
+    pub kind: u32,
+    pub depth: u32,
+    pub content: *const u8,
+    pub content_len: usize,
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type FilterFn = unsafe extern "C" fn(*const PluginEntry, *mut *mut u8, *mut usize) -> c_int;
+type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+
+pub struct NativePlugin {
+    name: String,
+    library: Library,
+}
+
+impl NativePlugin {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        // SAFETY: loading a library runs its initializers, plugins are trusted
+        // the same way the binary itself is
+        let library = unsafe { Library::new(path) }.map_err(|err| {
+            anyhow!(
+                "Plugin Error: The plugin '{}' could not be loaded ({}).",
+                name,
+                err
+            )
+        })?;
+        let version = unsafe {
+            let abi_version: Symbol<AbiVersionFn> = library
+                .get(b"expurgator_plugin_abi_version")
+                .map_err(|_| {
+                    anyhow!(
+                        "Plugin Error: The plugin '{}' does not export 'expurgator_plugin_abi_version'.",
+                        name
+                    )
+                })?;
+            abi_version()
+        };
+        if version != PLUGIN_ABI_VERSION {
+            Err(anyhow!(
+                "Plugin Error: The plugin '{}' targets ABI version {}, expected {}.",
+                name,
+                version,
+                PLUGIN_ABI_VERSION
+            ))?;
+        }
+        for symbol in ["expurgator_filter", "expurgator_free"] {
+            unsafe { library.get::<*const ()>(symbol.as_bytes()) }.map_err(|_| {
+                anyhow!(
+                    "Plugin Error: The plugin '{}' does not export '{}'.",
+                    name,
+                    symbol
+                )
+            })?;
+        }
+        Ok(NativePlugin { name, library })
+    }
+}
+
+fn kind_to_abi(kind: EntryKind) -> u32 {
+    match kind {
+        EntryKind::File => 0,
+        EntryKind::Directory => 1,
+        EntryKind::Symlink => 2,
+        EntryKind::HardLink => 3,
+        EntryKind::Other => 4,
+    }
+}
+
+impl EntryFilter for NativePlugin {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let path = entry.path.to_string_lossy();
+        let plugin_entry = PluginEntry {
+            path: path.as_ptr(),
+            path_len: path.len(),
+            size: entry.size,
+            mtime: entry.mtime.unwrap_or(0),
+            kind: kind_to_abi(entry.kind),
+            depth: entry.depth.try_into()?,
+            content: content.map_or(ptr::null(), |content| content.as_ptr()),
+            content_len: content.map_or(0, |content| content.len()),
+        };
+
+        let mut replacement: *mut u8 = ptr::null_mut();
+        let mut replacement_len = 0;
+        // SAFETY: the symbols were checked in `load`, the entry and its
+        // buffers outlive the call
+        let status = unsafe {
+            let filter: Symbol<FilterFn> = self.library.get(b"expurgator_filter")?;
+            filter(&plugin_entry, &mut replacement, &mut replacement_len)
+        };
+        match status {
+            0 => Ok(Decision::Keep),
+            1 => Ok(Decision::Remove),
+            2 if !replacement.is_null() || replacement_len == 0 => {
+                let data = if replacement.is_null() {
+                    Vec::new()
+                } else {
+                    // SAFETY: the plugin hands over `replacement_len` bytes
+                    // which stay valid until passed back to `expurgator_free`
+                    unsafe {
+                        let data = slice::from_raw_parts(replacement, replacement_len).to_vec();
+                        let free: Symbol<FreeFn> = self.library.get(b"expurgator_free")?;
+                        free(replacement, replacement_len);
+                        data
+                    }
+                };
+                Ok(Decision::Replace(data))
+            }
+            _ => Err(anyhow!(
+                "Plugin Error: The plugin '{}' failed on '{}' with status {}.",
+                self.name,
+                path,
+                status
+            ))?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_missing_plugin() {
+        let err = NativePlugin::load("does/not/exist.so").err().unwrap();
+        assert!(err.to_string().starts_with("Plugin Error"));
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher};
+use regex::bytes::Regex;
+use serde::Deserialize;
+
+use expurgator::internal::parse_size;
+use expurgator::{Decision, EntryFilter, EntryInfo, EntryKind};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PolicyFile {
+    #[serde(default)]
+    rules: Vec<RuleFile>,
+    /// Shorthand for rules matching a path glob each.
+    #[serde(default)]
+    forbidden_paths: Vec<String>,
+    /// Shorthand for rules matching a content pattern each.
+    #[serde(default)]
+    forbidden_content: Vec<String>,
+    /// Shorthand for a rule matching entries larger than the size.
+    max_entry_size: Option<Size>,
+    /// Shorthand for a rule matching entries deeper than the directories.
+    max_path_depth: Option<usize>,
+    /// Shorthand for a rule matching names longer than the characters.
+    max_name_length: Option<usize>,
+    max_total_size: Option<Size>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RuleFile {
+    id: String,
+    #[serde(rename = "match")]
+    criteria: MatchFile,
+    #[serde(default)]
+    action: Action,
+    #[serde(default)]
+    severity: Severity,
+    message: Option<String>,
+}
+
+/// Criteria of a rule, an entry matches if it meets all given ones.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct MatchFile {
+    path: Option<String>,
+    content: Option<String>,
+    larger_than: Option<Size>,
+    /// Directories above the entry in its full path.
+    deeper_than: Option<usize>,
+    /// Characters in the file name of the entry.
+    name_longer_than: Option<usize>,
+}
+
+/// A size in bytes or with a unit, e.g. `10MB`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Size {
+    Bytes(u64),
+    Text(String),
+}
+
+impl Size {
+    fn bytes(&self) -> Result<u64> {
+        match self {
+            Size::Bytes(bytes) => Ok(*bytes),
+            Size::Text(size) => Ok(parse_size(size)? as u64),
+        }
+    }
+}
+
+/// What a scrub does with the entries matching a rule, `check` only
+/// reports them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Report,
+    Remove,
+}
+
+/// Severities of the GitHub Actions annotations, only errors fail `check`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Notice,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Notice => "notice",
+        }
+    }
+}
+
+struct Rule {
+    id: String,
+    path: Option<GlobMatcher>,
+    content: Option<Regex>,
+    larger_than: Option<u64>,
+    deeper_than: Option<usize>,
+    name_longer_than: Option<usize>,
+    action: Action,
+    severity: Severity,
+    message: String,
+}
+
+impl Rule {
+    fn new(id: &str, criteria: MatchFile, message: String) -> Result<Self> {
+        let invalid = |err: &dyn std::fmt::Display| {
+            anyhow!("Invalid Policy: The rule '{}' is invalid ({}).", id, err)
+        };
+        if criteria.path.is_none()
+            && criteria.content.is_none()
+            && criteria.larger_than.is_none()
+            && criteria.deeper_than.is_none()
+            && criteria.name_longer_than.is_none()
+        {
+            Err(invalid(&"no match criteria"))?;
+        }
+        Ok(Rule {
+            id: id.to_string(),
+            path: criteria
+                .path
+                .map(|glob| Glob::new(&glob).map(|glob| glob.compile_matcher()))
+                .transpose()
+                .map_err(|err| invalid(&err))?,
+            content: criteria
+                .content
+                .map(|pattern| Regex::new(&pattern))
+                .transpose()
+                .map_err(|err| invalid(&err))?,
+            larger_than: criteria.larger_than.map(|size| size.bytes()).transpose()?,
+            deeper_than: criteria.deeper_than,
+            name_longer_than: criteria.name_longer_than,
+            action: Action::Report,
+            severity: Severity::Error,
+            message,
+        })
+    }
+
+    fn matches(&self, entry: &EntryInfo, content: Option<&[u8]>) -> bool {
+        self.path
+            .as_ref()
+            .is_none_or(|matcher| matcher.is_match(&entry.path))
+            && self
+                .larger_than
+                .is_none_or(|size| entry.kind == EntryKind::File && entry.size > size)
+            && self
+                .deeper_than
+                .is_none_or(|depth| directory_depth(&entry.path) > depth)
+            && self
+                .name_longer_than
+                .is_none_or(|length| name_length(&entry.path) > length)
+            && self
+                .content
+                .as_ref()
+                .is_none_or(|pattern| content.is_some_and(|content| pattern.is_match(content)))
+    }
+}
+
+/// Directories above the entry at `path`, through the nested archives holding
+/// it.
+fn directory_depth(path: &Path) -> usize {
+    path.components().count().saturating_sub(1)
+}
+
+/// Characters in the file name of the entry at `path`.
+fn name_length(path: &Path) -> usize {
+    path.file_name()
+        .map_or(0, |name| name.to_string_lossy().chars().count())
+}
+
+/// A policy rule matched by an entry, or by the archive as a whole if
+/// `path` is empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub path: PathBuf,
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// The entry was removed by the rule's action.
+    pub removed: bool,
+}
+
+/// Rules for the entries of an archive, evaluated as an [`EntryFilter`]
+/// that records every match and removes the entries of `remove` rules.
+///
+/// Clones share the recorded violations, so the policy can be registered
+/// with a context and read back afterwards.
+#[derive(Clone)]
+pub struct Policy {
+    rules: Arc<Vec<Rule>>,
+    /// Limit on the sum of the kept top level entry sizes.
+    max_total_size: Option<u64>,
+    apply_actions: bool,
+    total_size: Arc<Mutex<u64>>,
+    violations: Arc<Mutex<Vec<Violation>>>,
+}
+
+impl Policy {
+    /// Parses a YAML policy of `rules`, each with an `id`, `match` criteria
+    /// (`path` glob, `content` regular expression, `larger-than` size,
+    /// `deeper-than` directories, `name-longer-than` characters), an
+    /// `action`, a `severity` and a `message`. `forbidden-paths`,
+    /// `forbidden-content`, `max-entry-size`, `max-path-depth` and
+    /// `max-name-length` are shorthands for rules reporting errors,
+    /// `max-total-size` limits the whole archive.
+    pub fn parse(yaml: &str) -> Result<Self> {
+        let file: PolicyFile =
+            serde_yaml::from_str(yaml).map_err(|err| anyhow!("Invalid Policy: {}.", err))?;
+        let mut rules = Vec::new();
+        for glob in file.forbidden_paths {
+            let message = format!("The path matches the forbidden pattern '{}'.", glob);
+            let criteria = MatchFile {
+                path: Some(glob),
+                ..Default::default()
+            };
+            rules.push(Rule::new("forbidden-paths", criteria, message)?);
+        }
+        for pattern in file.forbidden_content {
+            let message = format!("The content matches the forbidden pattern '{}'.", pattern);
+            let criteria = MatchFile {
+                content: Some(pattern),
+                ..Default::default()
+            };
+            rules.push(Rule::new("forbidden-content", criteria, message)?);
+        }
+        if let Some(size) = file.max_entry_size {
+            let message = format!("The entry exceeds {} bytes.", size.bytes()?);
+            let criteria = MatchFile {
+                larger_than: Some(size),
+                ..Default::default()
+            };
+            rules.push(Rule::new("max-entry-size", criteria, message)?);
+        }
+        if let Some(depth) = file.max_path_depth {
+            let message = format!("The entry is more than {} directories deep.", depth);
+            let criteria = MatchFile {
+                deeper_than: Some(depth),
+                ..Default::default()
+            };
+            rules.push(Rule::new("max-path-depth", criteria, message)?);
+        }
+        if let Some(length) = file.max_name_length {
+            let message = format!("The entry name exceeds {} characters.", length);
+            let criteria = MatchFile {
+                name_longer_than: Some(length),
+                ..Default::default()
+            };
+            rules.push(Rule::new("max-name-length", criteria, message)?);
+        }
+        for rule in file.rules {
+            let message = rule
+                .message
+                .unwrap_or_else(|| format!("The entry matches the rule '{}'.", rule.id));
+            rules.push(Rule {
+                action: rule.action,
+                severity: rule.severity,
+                ..Rule::new(&rule.id, rule.criteria, message)?
+            });
+        }
+        Ok(Policy {
+            rules: Arc::new(rules),
+            max_total_size: file.max_total_size.map(|size| size.bytes()).transpose()?,
+            apply_actions: true,
+            total_size: Arc::default(),
+            violations: Arc::default(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Policy::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Only records matches, entries of `remove` rules are kept.
+    pub fn without_actions(mut self) -> Self {
+        self.apply_actions = false;
+        self
+    }
+
+    /// Number of rules, `max-total-size` included.
+    pub fn rule_count(&self) -> usize {
+        self.rules.len() + usize::from(self.max_total_size.is_some())
+    }
+
+    /// The violations found so far, including the archive wide ones.
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = self.violations.lock().unwrap().clone();
+        let total_size = *self.total_size.lock().unwrap();
+        if let Some(max_total_size) = self.max_total_size.filter(|max| total_size > *max) {
+            violations.push(Violation {
+                path: PathBuf::new(),
+                rule: "max-total-size".to_string(),
+                severity: Severity::Error,
+                message: format!(
+                    "The archive content of {} bytes exceeds {} bytes.",
+                    total_size, max_total_size
+                ),
+                removed: false,
+            });
+        }
+        violations
+    }
+}
+
+impl EntryFilter for Policy {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        let mut decision = Decision::Keep;
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(entry, content))
+        {
+            let removed = self.apply_actions && rule.action == Action::Remove;
+            if removed {
+                decision = Decision::Remove;
+            }
+            self.violations.lock().unwrap().push(Violation {
+                path: entry.path.clone(),
+                rule: rule.id.clone(),
+                severity: rule.severity,
+                message: rule.message.clone(),
+                removed,
+            });
+        }
+        if entry.depth == 0 && entry.kind == EntryKind::File && decision == Decision::Keep {
+            *self.total_size.lock().unwrap() += entry.size;
+        }
+        Ok(decision)
+    }
+}
+
+fn escape_annotation(value: &str, property: bool) -> String {
+    let value = value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        value.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        value
+    }
+}
+
+/// Formats a violation as a GitHub Actions workflow command of its severity.
+pub fn annotation(input: &str, violation: &Violation) -> String {
+    let message = if violation.path.as_os_str().is_empty() {
+        violation.message.clone()
+    } else {
+        format!("{}: {}", violation.path.display(), violation.message)
+    };
+    format!(
+        "::{} file={},title={}::{}",
+        violation.severity.as_str(),
+        escape_annotation(input, true),
+        escape_annotation(&format!("Policy violation ({})", violation.rule), true),
+        escape_annotation(&message, false)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, depth: usize) -> EntryInfo {
+        EntryInfo {
+            path: PathBuf::from(path),
+            size: 12,
+            mtime: None,
+            kind: EntryKind::File,
+            depth,
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::parse(
+            "forbidden-paths: ['**/*.pem']\n\
+             forbidden-content: ['BEGIN [A-Z]+ PRIVATE KEY']\n\
+             max-entry-size: 10\n\
+             max-total-size: 20B\n",
+        )
+        .unwrap();
+        assert_eq!(policy.rule_count(), 4);
+        let shared = policy.clone();
+        for (path, depth) in [("app/key.pem", 0), ("app/lib.zip/a.txt", 1), ("b.txt", 0)] {
+            let decision = policy
+                .decide(&entry(path, depth), Some(b"BEGIN RSA PRIVATE KEY"))
+                .unwrap();
+            assert_eq!(decision, Decision::Keep);
+        }
+
+        let violations = shared.violations();
+        let rules: Vec<_> = violations
+            .iter()
+            .map(|violation| violation.rule.as_str())
+            .collect();
+        assert_eq!(
+            rules
+                .iter()
+                .filter(|rule| **rule == "forbidden-content")
+                .count(),
+            3
+        );
+        assert_eq!(rules[0], "forbidden-paths");
+        assert_eq!(rules.last(), Some(&"max-total-size"));
+        assert!(Policy::parse("forbidden-paths: ['[']").is_err());
+        assert!(Policy::parse("unknown: 1").is_err());
+    }
+
+    #[test]
+    fn test_policy_rules() {
+        let yaml = r#"
+rules:
+  - id: private-keys
+    match:
+      path: "**/*.key"
+      content: "PRIVATE KEY"
+    action: remove
+    message: Private keys must not ship.
+  - id: large-assets
+    match:
+      larger-than: 10
+    severity: warning
+"#;
+        let policy = Policy::parse(yaml).unwrap();
+        let decide = |policy: &Policy, path: &str, content: &[u8]| {
+            policy.decide(&entry(path, 0), Some(content)).unwrap()
+        };
+        assert_eq!(decide(&policy, "a.key", b"PRIVATE KEY"), Decision::Remove);
+        assert_eq!(decide(&policy, "b.key", b"public"), Decision::Keep);
+
+        let violations = policy.violations();
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[0].rule, "private-keys");
+        assert_eq!(violations[0].message, "Private keys must not ship.");
+        assert!(violations[0].removed);
+        assert_eq!(violations[1].severity, Severity::Warning);
+        assert_eq!(violations[2].path, PathBuf::from("b.key"));
+
+        let policy = Policy::parse(yaml).unwrap().without_actions();
+        assert_eq!(decide(&policy, "a.key", b"PRIVATE KEY"), Decision::Keep);
+        assert!(Policy::parse("rules: [{id: all, match: {}}]").is_err());
+    }
+
+    #[test]
+    fn test_policy_path_limits() {
+        let yaml = r#"
+max-path-depth: 2
+max-name-length: 8
+rules:
+  - id: installer-paths
+    match:
+      deeper-than: 3
+    action: remove
+"#;
+        let policy = Policy::parse(yaml).unwrap();
+        assert_eq!(policy.rule_count(), 3);
+        let decide = |path: &str| policy.decide(&entry(path, 0), Some(b"")).unwrap();
+        assert_eq!(decide("a/b/c.txt"), Decision::Keep);
+        assert_eq!(decide("a/b/c/d.txt"), Decision::Keep);
+        assert_eq!(decide("a/b/c/d/e.txt"), Decision::Remove);
+        assert_eq!(decide("a/données.txt"), Decision::Keep);
+
+        let rules: Vec<_> = policy
+            .violations()
+            .into_iter()
+            .map(|violation| (violation.path, violation.rule))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                (PathBuf::from("a/b/c/d.txt"), "max-path-depth".to_string()),
+                (PathBuf::from("a/b/c/d/e.txt"), "max-path-depth".to_string()),
+                (
+                    PathBuf::from("a/b/c/d/e.txt"),
+                    "installer-paths".to_string()
+                ),
+                (
+                    PathBuf::from("a/données.txt"),
+                    "max-name-length".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotation() {
+        let violation = Violation {
+            path: PathBuf::from("a/key.pem"),
+            rule: "forbidden-paths".to_string(),
+            severity: Severity::Error,
+            message: "The path matches the forbidden pattern '**/*.pem'.".to_string(),
+            removed: false,
+        };
+        assert_eq!(
+            annotation("dist/app,1.zip", &violation),
+            "::error file=dist/app%2C1.zip,title=Policy violation (forbidden-paths)::a/key.pem: The path matches the forbidden pattern '**/*.pem'."
+        );
+    }
+}
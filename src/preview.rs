@@ -0,0 +1,91 @@
+use std::fmt::Write;
+
+/// Bytes per line of a hexdump.
+const LINE: usize = 16;
+
+/// The first `limit` bytes of `data`, as text if they are printable UTF-8,
+/// else as a hexdump in the format of `hexdump -C`. Each line is indented
+/// by `indent`.
+pub fn preview(data: &[u8], limit: usize, indent: &str) -> String {
+    let head = &data[..data.len().min(limit)];
+    let truncated = head.len() < data.len();
+    let mut output = String::new();
+    match text(head) {
+        Some(text) => {
+            for line in text.lines() {
+                let _ = writeln!(output, "{}{}", indent, line);
+            }
+        }
+        None => {
+            for (index, line) in head.chunks(LINE).enumerate() {
+                let hex: Vec<_> = line.iter().map(|b| format!("{:02x}", b)).collect();
+                let ascii: String = line
+                    .iter()
+                    .map(|&b| match b {
+                        b' '..=b'~' => b as char,
+                        _ => '.',
+                    })
+                    .collect();
+                let _ = writeln!(
+                    output,
+                    "{}{:08x}  {:<48} |{}|",
+                    indent,
+                    index * LINE,
+                    hex.join(" "),
+                    ascii
+                );
+            }
+        }
+    }
+    if truncated {
+        let _ = writeln!(
+            output,
+            "{}... ({} more bytes)",
+            indent,
+            data.len() - head.len()
+        );
+    }
+    output
+}
+
+/// `head` as text, allowing a character cut at the end, if it has no
+/// control characters besides line breaks and tabs.
+fn text(head: &[u8]) -> Option<&str> {
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    text.chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+        .then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(
+            preview(b"key = value\nother", 64, "  "),
+            "  key = value\n  other\n"
+        );
+        assert_eq!(
+            preview("héllo".as_bytes(), 2, ""),
+            "h\n... (4 more bytes)\n"
+        );
+        assert_eq!(
+            preview(
+                b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03",
+                17,
+                ""
+            ),
+            "00000000  7f 45 4c 46 02 01 01 00 00 00 00 00 00 00 00 00  |.ELF............|\n\
+             00000010  03                                               |.|\n"
+        );
+        assert_eq!(preview(b"", 16, ""), "");
+    }
+}
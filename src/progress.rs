@@ -0,0 +1,24 @@
+/// Receives progress notifications while an archive is processed.
+///
+/// All methods default to no-ops, implement the ones you care about. Nested
+/// archives may be processed on several threads at once.
+pub trait Progress: Sync {
+    fn entry_started(&self, _path: &str) {}
+
+    fn entry_finished(&self, _path: &str) {}
+
+    fn bytes_processed(&self, _bytes: u64) {}
+
+    fn message(&self, _message: String) {}
+
+    /// Runs `f` with any progress output hidden, used around prompts.
+    fn suspend(&self, f: &mut dyn FnMut()) {
+        f()
+    }
+}
+
+/// Progress implementation that ignores every notification.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
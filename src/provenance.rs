@@ -0,0 +1,179 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::reader::ArchiveReader;
+use crate::util::sha256_hex;
+
+/// Name of the entry carrying the record in `entry` mode.
+pub const PROVENANCE_ENTRY: &str = ".expurgator.json";
+
+pub const PROVENANCE_MODES: [&str; 2] = ["entry", "comment"];
+
+/// Where the provenance record is stored in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvenanceMode {
+    /// An extra `.expurgator.json` entry.
+    Entry,
+    /// The zip archive comment.
+    Comment,
+}
+
+impl ProvenanceMode {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "entry" => Ok(ProvenanceMode::Entry),
+            "comment" => Ok(ProvenanceMode::Comment),
+            _ => Err(anyhow!(
+                "Invalid Provenance: '{}' is not one of: {}.",
+                name,
+                PROVENANCE_MODES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Describes how an artifact was scrubbed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    #[serde(skip)]
+    pub mode: ProvenanceMode,
+    pub tool: String,
+    pub version: String,
+    /// Seconds since the epoch, `SOURCE_DATE_EPOCH` if set so reproducible
+    /// builds stay reproducible.
+    pub timestamp: u64,
+    pub filter_sha256: String,
+    /// Number of filter list entries.
+    pub rules: usize,
+    pub plugins: usize,
+    pub transforms: usize,
+    /// Hash of the scrubbed input, to recognize its output on later runs.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub input_sha256: String,
+}
+
+/// A record read back from a scrubbed archive.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoredRecord {
+    pub tool: String,
+    pub filter_sha256: String,
+    pub rules: usize,
+    pub plugins: usize,
+    pub transforms: usize,
+    /// Empty in records written before it was added.
+    #[serde(default)]
+    pub input_sha256: String,
+}
+
+impl StoredRecord {
+    /// The record of `archive`, stored as an entry or as the zip comment,
+    /// if any.
+    pub fn read(archive: &[u8]) -> Option<Self> {
+        let entry = ArchiveReader::new(archive.to_vec())
+            .read(PROVENANCE_ENTRY)
+            .ok();
+        let comment = || {
+            zip::ZipArchive::new(std::io::Cursor::new(archive))
+                .ok()
+                .map(|zip| zip.comment().to_vec())
+        };
+        let record = entry.or_else(comment)?;
+        serde_json::from_slice(&record).ok()
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+    removed: usize,
+}
+
+impl Provenance {
+    pub fn new(
+        mode: ProvenanceMode,
+        filter_file: &[u8],
+        rules: usize,
+        plugins: usize,
+        transforms: usize,
+    ) -> Self {
+        let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs())
+            });
+        Provenance {
+            mode,
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp,
+            filter_sha256: sha256_hex(filter_file),
+            rules,
+            plugins,
+            transforms,
+            input_sha256: String::new(),
+        }
+    }
+
+    /// Records the hash of the input being scrubbed.
+    pub fn with_input(mut self, input_bytes: &[u8]) -> Self {
+        self.input_sha256 = sha256_hex(input_bytes);
+        self
+    }
+
+    /// Whether `record` was written by a scrub with the same filter file,
+    /// rule, plugin and transform counts.
+    pub fn same_rules(&self, record: &StoredRecord) -> bool {
+        record.tool == self.tool
+            && record.filter_sha256 == self.filter_sha256
+            && record.rules == self.rules
+            && record.plugins == self.plugins
+            && record.transforms == self.transforms
+    }
+
+    /// The record as JSON, given the filter list entries left unmatched.
+    pub fn record(&self, not_found: usize) -> Result<String> {
+        Ok(serde_json::to_string(&Record {
+            provenance: self,
+            removed: self.rules.saturating_sub(not_found),
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_record() {
+        let provenance = Provenance {
+            timestamp: 1700000000,
+            ..Provenance::new(ProvenanceMode::Entry, b"path\nsecret.txt\n", 2, 0, 1)
+        };
+        let record: serde_json::Value =
+            serde_json::from_str(&provenance.record(1).unwrap()).unwrap();
+        assert_eq!(record["tool"], "expurgator");
+        assert_eq!(record["timestamp"], 1700000000);
+        assert_eq!(record["rules"], 2);
+        assert_eq!(record["removed"], 1);
+        assert_eq!(record["transforms"], 1);
+        assert_eq!(record["filter_sha256"].as_str().unwrap().len(), 64);
+        assert!(record.get("mode").is_none());
+        assert!(record.get("input_sha256").is_none());
+
+        let stored: StoredRecord = serde_json::from_str(&provenance.record(1).unwrap()).unwrap();
+        assert!(provenance.same_rules(&stored));
+        let other = Provenance::new(ProvenanceMode::Entry, b"path\nother.txt\n", 2, 0, 1);
+        assert!(!other.same_rules(&stored));
+        assert_eq!(
+            provenance.with_input(b"input").input_sha256,
+            sha256_hex(b"input")
+        );
+        assert!(ProvenanceMode::parse("header").is_err());
+    }
+}
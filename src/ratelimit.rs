@@ -0,0 +1,163 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::util::parse_size;
+
+/// Largest chunk passed through at once, keeps the throughput smooth.
+const CHUNK_SIZE: usize = 64 << 10;
+
+#[derive(Debug)]
+struct Transferred {
+    start: Instant,
+    bytes: u64,
+}
+
+/// Bandwidth limit shared by every stream throttled with a clone of it, so
+/// reads and writes together stay below the rate.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    bytes_per_second: u64,
+    transferred: Arc<Mutex<Transferred>>,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_second: u64) -> Self {
+        RateLimit {
+            bytes_per_second: bytes_per_second.max(1),
+            transferred: Arc::new(Mutex::new(Transferred {
+                start: Instant::now(),
+                bytes: 0,
+            })),
+        }
+    }
+
+    /// Parses a rate such as `50MB/s`, the `/s` is optional.
+    pub fn parse(rate: &str) -> Result<Self> {
+        let size = rate.trim().trim_end_matches("/s");
+        let bytes_per_second = parse_size(size).map_err(|_| {
+            anyhow!(
+                "Invalid Bandwidth Limit: '{}' is not a rate such as 50MB/s.",
+                rate
+            )
+        })?;
+        Ok(RateLimit::new(bytes_per_second as u64))
+    }
+
+    /// Accounts for `bytes` transferred, sleeping while ahead of the rate.
+    pub fn throttle(&self, bytes: usize) {
+        let delay = {
+            let mut transferred = self.transferred.lock().unwrap();
+            transferred.bytes += bytes as u64;
+            let due =
+                Duration::from_secs_f64(transferred.bytes as f64 / self.bytes_per_second as f64);
+            due.saturating_sub(transferred.start.elapsed())
+        };
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+/// A reader or writer limited to the rate of an optional [`RateLimit`].
+pub struct Throttled<T> {
+    inner: T,
+    limit: Option<RateLimit>,
+}
+
+impl<T> Throttled<T> {
+    pub fn new(inner: T, limit: Option<RateLimit>) -> Self {
+        Throttled { inner, limit }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn chunk_size(&self, len: usize) -> usize {
+        match self.limit {
+            Some(_) => len.min(CHUNK_SIZE),
+            None => len,
+        }
+    }
+
+    fn throttle(&self, bytes: usize) {
+        if let Some(limit) = &self.limit {
+            limit.throttle(bytes);
+        }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.chunk_size(buf.len());
+        let read = self.inner.read(&mut buf[..len])?;
+        self.throttle(read);
+        Ok(read)
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.chunk_size(buf.len());
+        let written = self.inner.write(&buf[..len])?;
+        self.throttle(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for Throttled<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Reads a file, or stdin for `-`, limited to `limit` if set.
+pub fn read_file(path: &str, limit: Option<&RateLimit>) -> Result<Vec<u8>> {
+    if path == "-" {
+        let mut bytes = Vec::new();
+        Throttled::new(std::io::stdin().lock(), limit.cloned()).read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+    let file = std::fs::File::open(path)?;
+    let mut bytes = Vec::with_capacity(file.metadata()?.len().try_into()?);
+    Throttled::new(file, limit.cloned()).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate_limit() {
+        assert_eq!(
+            RateLimit::parse("50MB/s").unwrap().bytes_per_second,
+            50 << 20
+        );
+        assert_eq!(RateLimit::parse("512").unwrap().bytes_per_second, 512);
+        assert!(RateLimit::parse("fast").is_err());
+    }
+
+    #[test]
+    fn test_throttled() {
+        let limit = RateLimit::new(100_000);
+        let start = Instant::now();
+        let mut output = Throttled::new(Vec::new(), Some(limit.clone()));
+        output.write_all(&[0; 10_000]).unwrap();
+        let mut read = Vec::new();
+        Throttled::new(output.get_ref().as_slice(), Some(limit))
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read.len(), 10_000);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,620 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{
+    create_tar_decoder, holds_archive, sniff_stream, stream_content, stream_decoder, StreamContent,
+};
+use crate::cpio::{is_cpio, read_cpio, CpioEntry};
+use crate::util::{civil_time, infer_input_file, unix_time};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    Other,
+}
+
+/// Metadata of a single archive entry.
+///
+/// Entries of nested archives carry the full path through the enclosing
+/// archives (e.g. `dist/lib.zip/lib/a.txt`), the same form the filter list
+/// uses, and a `depth` counting the levels of nesting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub mtime: Option<u64>,
+    pub kind: EntryKind,
+    pub depth: usize,
+    /// Inferred from the content of regular files, if recognized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// Separates the path of a nested archive from the path of an entry within
+/// it, as in `lib/app.jar!META-INF/MANIFEST.MF`.
+pub const NESTED_SEPARATOR: char = '!';
+
+/// Bytes read from the start of an archive to tell its format.
+const SNIFF_SIZE: u64 = 8 << 10;
+
+/// A [`Read`] that can [`Seek`] as well.
+pub(crate) trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Where an [`ArchiveReader`] reads the archive from.
+enum Source {
+    Memory(Vec<u8>),
+    File(PathBuf),
+}
+
+/// Read-only access to the entries of an archive.
+pub struct ArchiveReader {
+    source: Source,
+}
+
+impl ArchiveReader {
+    pub fn new(input_bytes: Vec<u8>) -> Self {
+        ArchiveReader {
+            source: Source::Memory(input_bytes),
+        }
+    }
+
+    /// Reads the archive in the file at `path` as it is needed, without
+    /// holding it in memory: zip entries are read at their offsets and tar
+    /// streams decoded as they are listed. Cpio archives and compressed
+    /// streams of other archives than tars are read into memory, as are the
+    /// nested archives of a tar until they are listed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        ArchiveReader {
+            source: Source::File(path.as_ref().to_path_buf()),
+        }
+    }
+
+    /// The archive, read from the start.
+    pub(crate) fn source(&self) -> Result<Box<dyn ReadSeek + '_>> {
+        match &self.source {
+            Source::Memory(input_bytes) => Ok(Box::new(Cursor::new(input_bytes.as_slice()))),
+            Source::File(path) => Ok(Box::new(BufReader::new(File::open(path)?))),
+        }
+    }
+
+    /// Iterates over all entries, descending into nested archives.
+    ///
+    /// Each archive is listed when the iterator first reaches it, so nested
+    /// archives are only decoded if iteration gets that far.
+    pub fn entries(&self) -> Result<Entries> {
+        let path = match &self.source {
+            Source::Memory(input_bytes) => {
+                let level = list_archive(input_bytes, Path::new(""), 0)?;
+                return Ok(Entries {
+                    levels: vec![level],
+                    zip: None,
+                });
+            }
+            Source::File(path) => path,
+        };
+        let mut reader = BufReader::new(File::open(path)?);
+        let head = read_head(&mut reader)?;
+        let mime_type = (!is_cpio(&head))
+            .then(|| infer_input_file(&head))
+            .transpose()?;
+        let level = match mime_type.as_deref() {
+            Some("application/zip") => {
+                let mut zip_entries = zip::ZipArchive::new(reader)?;
+                let level = list_zip(&mut zip_entries, Path::new(""), 0, true)?;
+                return Ok(Entries {
+                    levels: vec![level],
+                    zip: Some(zip_entries),
+                });
+            }
+            Some(
+                mime_type @ ("application/gzip"
+                | "application/x-bzip2"
+                | "application/x-xz"
+                | "application/zstd"
+                | "application/x-tar"),
+            ) if sniff_stream(
+                mime_type,
+                stream_decoder(BufReader::new(&mut reader), mime_type)?,
+            )? == StreamContent::Tar =>
+            {
+                reader.rewind()?;
+                list_tar(stream_decoder(reader, mime_type)?, Path::new(""), 0)?
+            }
+            // told apart by their content once read
+            _ => {
+                reader.rewind()?;
+                let mut input_bytes = Vec::new();
+                reader.read_to_end(&mut input_bytes)?;
+                list_archive(&input_bytes, Path::new(""), 0)?
+            }
+        };
+        Ok(Entries {
+            levels: vec![level],
+            zip: None,
+        })
+    }
+
+    /// Reads the content of the file at `entry_path`, descending into nested
+    /// archives at every [`NESTED_SEPARATOR`], or along the path where it
+    /// leads through one.
+    pub fn read(&self, entry_path: &str) -> Result<Vec<u8>> {
+        let mut data: Option<Vec<u8>> = None;
+        let mut segments = entry_path.split(NESTED_SEPARATOR).peekable();
+        while let Some(segment) = segments.next() {
+            let found = match &data {
+                Some(data) => read_entry(Cursor::new(data.as_slice()), segment)?,
+                None => read_entry(self.source()?, segment)?,
+            };
+            let found = found.ok_or_else(|| {
+                anyhow!(
+                    "Entry Not Found: '{}' is not a file of the archive.",
+                    entry_path
+                )
+            })?;
+            if segments.peek().is_some() && !holds_archive(&found) {
+                Err(anyhow!(
+                    "Not An Archive: '{}' of '{}' is not an archive.",
+                    segment,
+                    entry_path
+                ))?;
+            }
+            data = Some(found);
+        }
+        Ok(data.unwrap_or_default())
+    }
+}
+
+/// The start of `reader`, which is rewound.
+pub(crate) fn read_head<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut head = Vec::new();
+    reader.take(SNIFF_SIZE).read_to_end(&mut head)?;
+    reader.rewind()?;
+    Ok(head)
+}
+
+/// An entry path without the leading `./` or `/` some archivers write.
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// The content of the file `path` of the archive, `None` if there is no
+/// such regular file. Paths through nested archives, as listed by
+/// [`ArchiveReader::entries`], are looked up within them.
+fn read_entry<R: Read + Seek + Send>(reader: R, path: &str) -> Result<Option<Vec<u8>>> {
+    let path = normalize(path);
+    let found = visit_files(reader, &mut |name, entry| {
+        let rest = match path.strip_prefix(normalize(name)) {
+            Some("") => None,
+            Some(rest) => match rest.strip_prefix('/') {
+                Some(rest) => Some(rest.to_string()),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if rest.is_some() && !holds_archive(&data) {
+            return Ok(None);
+        }
+        Ok(Some((data, rest)))
+    })?;
+    match found {
+        Some((data, Some(rest))) => read_entry(Cursor::new(data.as_slice()), &rest),
+        Some((data, None)) => Ok(Some(data)),
+        None => Ok(None),
+    }
+}
+
+/// Callback of [`visit_files`], given the name and content of a file.
+type Visit<'a, T> = dyn FnMut(&str, &mut dyn Read) -> Result<Option<T>> + 'a;
+
+/// Calls `visit` with the name and content of the regular files of the
+/// archive, until it returns a value. The content of compressed streams
+/// holding another archive than a tar, like a `.cpio.gz`, is visited.
+fn visit_files<R: Read + Seek + Send, T>(mut reader: R, visit: &mut Visit<T>) -> Result<Option<T>> {
+    let head = read_head(&mut reader)?;
+    if is_cpio(&head) {
+        let mut input_bytes = Vec::new();
+        reader.read_to_end(&mut input_bytes)?;
+        let (entries, _) = read_cpio(&input_bytes)?;
+        for entry in entries.iter().filter(|entry| entry.is_file()) {
+            if let Some(found) = visit(entry.path(), &mut entry.data.as_slice())? {
+                return Ok(Some(found));
+            }
+        }
+        return Ok(None);
+    }
+    let mime_type = infer_input_file(&head)?;
+    if mime_type == "application/zip" {
+        let mut zip_entries = zip::ZipArchive::new(reader)?;
+        for i in 0..zip_entries.len() {
+            let mut entry = zip_entries.by_index(i)?;
+            if entry.is_file() {
+                let name = entry.name().to_string();
+                if let Some(found) = visit(&name, &mut entry)? {
+                    return Ok(Some(found));
+                }
+            }
+        }
+        return Ok(None);
+    }
+    let content = sniff_stream(
+        &mime_type,
+        stream_decoder(BufReader::new(&mut reader), &mime_type)?,
+    )?;
+    reader.rewind()?;
+    if content == StreamContent::Archive {
+        let mut decoded = Vec::new();
+        stream_decoder(BufReader::new(reader), &mime_type)?.read_to_end(&mut decoded)?;
+        return visit_files(Cursor::new(decoded.as_slice()), visit);
+    }
+    let mut tar_archive = tar::Archive::new(stream_decoder(BufReader::new(reader), &mime_type)?);
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if tar_entry_kind(entry.header().entry_type()) == EntryKind::File {
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if let Some(found) = visit(&name, &mut entry)? {
+                return Ok(Some(found));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// A nested archive, listed once the iteration reaches it.
+enum Nested {
+    Bytes(Vec<u8>),
+    /// The entry at this index of the zip read from a file.
+    ZipEntry(usize),
+}
+
+type Level = VecDeque<(EntryInfo, Option<Nested>)>;
+
+pub struct Entries {
+    levels: Vec<Level>,
+    /// The zip read from a file, if it is one.
+    zip: Option<zip::ZipArchive<BufReader<File>>>,
+}
+
+impl Entries {
+    fn list_nested(&mut self, nested: Nested, info: &EntryInfo) -> Result<Level> {
+        let input_bytes = match nested {
+            Nested::Bytes(input_bytes) => input_bytes,
+            Nested::ZipEntry(index) => {
+                let zip_entries = self.zip.as_mut().expect("listed from a zip");
+                let mut input_bytes = Vec::new();
+                zip_entries.by_index(index)?.read_to_end(&mut input_bytes)?;
+                input_bytes
+            }
+        };
+        list_archive(&input_bytes, &info.path, info.depth + 1)
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<EntryInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let level = self.levels.last_mut()?;
+            let Some((info, nested)) = level.pop_front() else {
+                self.levels.pop();
+                continue;
+            };
+            if let Some(nested) = nested {
+                match self.list_nested(nested, &info) {
+                    Ok(level) => self.levels.push(level),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            return Some(Ok(info));
+        }
+    }
+}
+
+/// Adds the entry with the content `data` to `level`. A nested archive is
+/// kept to be listed, or read again from the entry `index` of the zip read
+/// from a file, if given.
+fn push_entry(level: &mut Level, mut info: EntryInfo, data: Vec<u8>, index: Option<usize>) {
+    if info.kind == EntryKind::File {
+        info.mime_type = infer::get(&data).map(|kind| kind.mime_type().to_string());
+    }
+    let nested = (info.kind == EntryKind::File && holds_archive(&data)).then_some(match index {
+        Some(index) => Nested::ZipEntry(index),
+        None => Nested::Bytes(data),
+    });
+    level.push_back((info, nested));
+}
+
+fn list_archive(input_bytes: &[u8], parent: &Path, depth: usize) -> Result<Level> {
+    if is_cpio(input_bytes) {
+        return list_cpio(input_bytes, parent, depth);
+    }
+    let mime_type = infer_input_file(input_bytes)?;
+    match mime_type.as_str() {
+        "application/zip" => {
+            let mut zip_entries = zip::ZipArchive::new(Cursor::new(input_bytes))?;
+            list_zip(&mut zip_entries, parent, depth, false)
+        }
+        "application/gzip"
+        | "application/x-bzip2"
+        | "application/x-xz"
+        | "application/zstd"
+        | "application/x-tar" => match stream_content(input_bytes, &mime_type)? {
+            // the entries of a .cpio.gz are listed as if it was not compressed
+            StreamContent::Archive => {
+                let mut decoded = Vec::new();
+                create_tar_decoder(input_bytes, &mime_type)?.read_to_end(&mut decoded)?;
+                list_archive(&decoded, parent, depth)
+            }
+            _ => list_tar(create_tar_decoder(input_bytes, &mime_type)?, parent, depth),
+        },
+        _ => Err(anyhow!(
+            "Unsupported File Type: The file with MIME type '{}' is not supported.",
+            mime_type
+        ))?,
+    }
+}
+
+/// Lists the entries of a zip, with `lazy` its nested archives are read
+/// again once listed instead of being held.
+fn list_zip<R: Read + Seek>(
+    zip_entries: &mut zip::ZipArchive<R>,
+    parent: &Path,
+    depth: usize,
+    lazy: bool,
+) -> Result<Level> {
+    let mut level = Level::new();
+    for i in 0..zip_entries.len() {
+        let mut entry = zip_entries.by_index(i)?;
+        let kind = if entry.is_dir() {
+            EntryKind::Directory
+        } else if entry.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        };
+        let info = EntryInfo {
+            path: parent.join(entry.name()),
+            size: entry.size(),
+            mtime: entry.last_modified().map(zip_datetime_to_unix),
+            kind,
+            depth,
+            mime_type: None,
+        };
+        let mut data = Vec::new();
+        if kind == EntryKind::File {
+            entry.read_to_end(&mut data)?;
+        }
+        push_entry(&mut level, info, data, lazy.then_some(i));
+    }
+    Ok(level)
+}
+
+fn list_tar(decoder: impl Read, parent: &Path, depth: usize) -> Result<Level> {
+    let mut tar_archive = tar::Archive::new(decoder);
+    let mut level = Level::new();
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        let kind = tar_entry_kind(entry.header().entry_type());
+        let info = EntryInfo {
+            path: parent.join(&*entry.path()?),
+            size: entry.header().size()?,
+            mtime: entry.header().mtime().ok(),
+            kind,
+            depth,
+            mime_type: None,
+        };
+        let mut data = Vec::new();
+        if kind == EntryKind::File {
+            entry.read_to_end(&mut data)?;
+        }
+        push_entry(&mut level, info, data, None);
+    }
+    Ok(level)
+}
+
+/// Type flag of the directory listings written by `tar --listed-incremental`.
+pub(crate) const GNU_DUMPDIR: u8 = b'D';
+
+pub(crate) fn tar_entry_kind(entry_type: tar::EntryType) -> EntryKind {
+    match entry_type {
+        tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+            EntryKind::File
+        }
+        tar::EntryType::Directory => EntryKind::Directory,
+        entry_type if entry_type.as_byte() == GNU_DUMPDIR => EntryKind::Directory,
+        tar::EntryType::Symlink => EntryKind::Symlink,
+        tar::EntryType::Link => EntryKind::HardLink,
+        _ => EntryKind::Other,
+    }
+}
+
+pub(crate) fn cpio_entry_kind(entry: &CpioEntry) -> EntryKind {
+    if entry.is_dir() {
+        EntryKind::Directory
+    } else if entry.is_symlink() {
+        EntryKind::Symlink
+    } else if entry.is_file() {
+        EntryKind::File
+    } else {
+        EntryKind::Other
+    }
+}
+
+fn list_cpio(input_bytes: &[u8], parent: &Path, depth: usize) -> Result<Level> {
+    let (entries, _) = read_cpio(input_bytes)?;
+    let mut level = Level::new();
+    for entry in entries {
+        let kind = cpio_entry_kind(&entry);
+        let info = EntryInfo {
+            path: parent.join(entry.path()),
+            size: entry.data.len() as u64,
+            mtime: Some(entry.mtime().into()),
+            kind,
+            depth,
+            mime_type: None,
+        };
+        push_entry(&mut level, info, entry.data, None);
+    }
+    Ok(level)
+}
+
+pub(crate) fn zip_datetime_to_unix(datetime: zip::DateTime) -> u64 {
+    let seconds = unix_time(
+        datetime.year().into(),
+        datetime.month().into(),
+        datetime.day().into(),
+        datetime.hour().into(),
+        datetime.minute().into(),
+        datetime.second().into(),
+    );
+    seconds.max(0) as u64
+}
+
+/// The zip date and time of `seconds` since the epoch, 1980-01-01 if a zip
+/// cannot store it.
+pub(crate) fn unix_to_zip_datetime(seconds: u64) -> zip::DateTime {
+    let (year, month, day, hour, minute, second) = civil_time(seconds.min(u32::MAX.into()) as i64);
+    let datetime = zip::DateTime::from_date_and_time(
+        year.try_into().unwrap_or_default(),
+        month as u8,
+        day as u8,
+        hour as u8,
+        minute as u8,
+        second as u8,
+    );
+    datetime.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_reader_read() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let reader = ArchiveReader::new(input);
+        assert_eq!(reader.read("tar-test/nested/nested.dat").unwrap().len(), 11);
+        assert_eq!(
+            reader
+                .read("./tar-test/nested/nestedtar.tar.xz!nestedtar/2.txt")
+                .unwrap()
+                .len(),
+            11
+        );
+        assert_eq!(
+            reader
+                .read("tar-test/nested/nested.zip!zip-test/zippedfile.dat")
+                .unwrap()
+                .len(),
+            11
+        );
+        // the path of the entry as listed
+        assert_eq!(
+            reader
+                .read("tar-test/nested/nested.zip/zip-test/zippedfile.dat")
+                .unwrap()
+                .len(),
+            11
+        );
+        // directories and missing entries are not files
+        assert!(reader.read("tar-test/nested").is_err());
+        assert!(reader.read("tar-test/missing.txt").is_err());
+        assert!(reader.read("tar-test/file_1.txt!a.txt").is_err());
+    }
+
+    #[test]
+    fn test_archive_reader_entries() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let entries = ArchiveReader::new(input)
+            .entries()
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        let outer: Vec<_> = entries.iter().filter(|e| e.depth == 0).collect();
+        assert_eq!(outer.len(), 9);
+        assert_eq!(outer[0].path, PathBuf::from("tar-test/"));
+        assert_eq!(outer[0].kind, EntryKind::Directory);
+
+        let link = entries
+            .iter()
+            .find(|e| e.path == Path::new("tar-test/nested/file_link.txt"))
+            .unwrap();
+        assert_eq!(link.kind, EntryKind::Symlink);
+
+        let nested = entries
+            .iter()
+            .find(|e| e.path == Path::new("tar-test/nested/nestedtar.tar.xz/nestedtar/2.txt"))
+            .unwrap();
+        assert_eq!(nested.depth, 1);
+        assert_eq!(nested.kind, EntryKind::File);
+    }
+
+    #[test]
+    fn test_archive_reader_open() {
+        let path = "tests/archives/tar-test.tar.gz";
+        let input = std::fs::read(path).unwrap();
+        let entries = |reader: &ArchiveReader| {
+            reader
+                .entries()
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert_eq!(
+            entries(&ArchiveReader::open(path)),
+            entries(&ArchiveReader::new(input))
+        );
+
+        // the nested archives of a zip file are read again once listed
+        let nested = ArchiveReader::open(path)
+            .read("tar-test/nested/nested.zip")
+            .unwrap();
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut input));
+            let options = zip::write::SimpleFileOptions::default();
+            zip.start_file("a.txt", options).unwrap();
+            std::io::Write::write_all(&mut zip, b"a").unwrap();
+            zip.start_file("lib/nested.zip", options).unwrap();
+            std::io::Write::write_all(&mut zip, &nested).unwrap();
+            zip.finish().unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("outer.zip");
+        std::fs::write(&zip_path, &input).unwrap();
+        let listed = entries(&ArchiveReader::open(&zip_path));
+        assert!(listed
+            .iter()
+            .any(|e| e.path == Path::new("lib/nested.zip/zip-test/zippedfile.dat")));
+        assert_eq!(listed, entries(&ArchiveReader::new(input)));
+        assert_eq!(
+            ArchiveReader::open(&zip_path)
+                .read("lib/nested.zip!zip-test/zippedfile.dat")
+                .unwrap()
+                .len(),
+            11
+        );
+    }
+
+    #[test]
+    fn test_zip_datetime_to_unix() {
+        let datetime = zip::DateTime::from_date_and_time(2023, 3, 7, 8, 5, 0).unwrap();
+        assert_eq!(zip_datetime_to_unix(datetime), 1678176300);
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+
+use expurgator::internal::sha256_hex;
+
+/// Longest wait between two attempts, however many failed before.
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// curl's exit code when the server does not serve the requested range.
+const CURL_RANGE_ERROR: i32 = 33;
+
+/// How often and how patiently failed transfers are repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    /// Attempts after the first one.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each further one.
+    pub delay: Duration,
+}
+
+impl Retry {
+    /// Wait before the retry following `attempt`, counted from zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.delay
+            .checked_mul(1 << attempt.min(16))
+            .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+    }
+
+    /// Runs `attempt` until it succeeds or the retries are used up, `what`
+    /// names the transfer in the messages.
+    fn run<T>(&self, what: &str, mut attempt: impl FnMut() -> Result<Attempt<T>>) -> Result<T> {
+        let mut failures = 0;
+        loop {
+            let error = match attempt()? {
+                Attempt::Done(value) => return Ok(value),
+                Attempt::Failed { error, .. } => error,
+            };
+            if failures == self.retries {
+                Err(anyhow!(
+                    "Transfer Failed: {} failed {} time(s), the last time with: {}",
+                    what,
+                    failures + 1,
+                    error
+                ))?;
+            }
+            let delay = self.backoff(failures);
+            eprintln!(
+                "{} failed ({}), retrying in {:?} ({} of {})",
+                what,
+                error,
+                delay,
+                failures + 1,
+                self.retries
+            );
+            sleep(delay);
+            failures += 1;
+        }
+    }
+}
+
+/// Outcome of a transfer command: failures by exit status are retried,
+/// commands that cannot be run are errors right away.
+enum Attempt<T> {
+    Done(T),
+    Failed { code: Option<i32>, error: String },
+}
+
+/// An input or output location transferred with `curl` or the `aws` CLI,
+/// which have to be installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remote {
+    /// `http://` or `https://` URL, uploaded with PUT.
+    Http(String),
+    /// `s3://BUCKET/KEY`.
+    S3 { bucket: String, key: String },
+}
+
+impl Remote {
+    /// The remote location of `location`, none for local paths.
+    pub fn parse(location: &str) -> Option<Self> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Some(Remote::Http(location.to_string()));
+        }
+        let (bucket, key) = location.strip_prefix("s3://")?.split_once('/')?;
+        (!bucket.is_empty() && !key.is_empty()).then(|| Remote::S3 {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            Remote::Http(url) => url.clone(),
+            Remote::S3 { bucket, key } => format!("s3://{}/{}", bucket, key),
+        }
+    }
+
+    /// The location of the file `name` next to this one.
+    pub fn sibling(&self, name: &str) -> Self {
+        let replace = |path: &str| match path.rsplit_once('/') {
+            Some((parent, _)) => format!("{}/{}", parent, name),
+            None => name.to_string(),
+        };
+        match self {
+            Remote::Http(url) => {
+                // the query, if any, is not part of the path
+                let (path, _) = url.split_once('?').unwrap_or((url, ""));
+                Remote::Http(replace(path))
+            }
+            Remote::S3 { bucket, key } => Remote::S3 {
+                bucket: bucket.clone(),
+                key: replace(key),
+            },
+        }
+    }
+
+    /// Downloads the content, staged in the directory `staging`. A failed
+    /// attempt leaves what it received in a partial file, the next attempt
+    /// requests only the rest.
+    pub fn download(&self, retry: &Retry, staging: &Path) -> Result<Vec<u8>> {
+        let partial = staging.join(format!(
+            "download-{}.part",
+            &sha256_hex(self.url().as_bytes())[..16]
+        ));
+        let result = retry
+            .run(&format!("Downloading {}", self.url()), || {
+                self.download_rest(&partial)
+            })
+            .and_then(|()| std::fs::read(&partial).map_err(Into::into));
+        let _ = std::fs::remove_file(&partial);
+        result
+    }
+
+    fn download_rest(&self, partial: &Path) -> Result<Attempt<()>> {
+        let offset = std::fs::metadata(partial).map_or(0, |metadata| metadata.len());
+        match self {
+            Remote::Http(url) => {
+                let mut command = Command::new("curl");
+                command
+                    .args(["--fail", "--silent", "--show-error", "--location"])
+                    .args(["--continue-at", "-", "--output"])
+                    .arg(partial)
+                    .arg(url);
+                let attempt = run(command)?;
+                // the server cannot resume, the next attempt starts over
+                if let Attempt::Failed {
+                    code: Some(CURL_RANGE_ERROR),
+                    ..
+                } = attempt
+                {
+                    let _ = std::fs::remove_file(partial);
+                }
+                Ok(attempt)
+            }
+            Remote::S3 { bucket, key } if offset > 0 => {
+                let rest = partial.with_extension("rest");
+                let mut command = Command::new("aws");
+                command
+                    .args(["s3api", "get-object", "--bucket", bucket, "--key", key])
+                    .arg("--range")
+                    .arg(format!("bytes={}-", offset))
+                    .arg(&rest);
+                let attempt = run(command)?;
+                if let Attempt::Done(()) = attempt {
+                    let mut file = OpenOptions::new().append(true).open(partial)?;
+                    file.write_all(&std::fs::read(&rest)?)?;
+                }
+                let _ = std::fs::remove_file(&rest);
+                Ok(attempt)
+            }
+            Remote::S3 { .. } => {
+                let mut command = Command::new("aws");
+                command
+                    .args(["s3", "cp", "--only-show-errors", &self.url()])
+                    .arg(partial);
+                run(command)
+            }
+        }
+    }
+
+    /// Uploads the file at `path`, repeating the whole upload if it fails.
+    /// `aws s3 cp` splits large files into parts and retries those itself.
+    pub fn upload(&self, path: &Path, retry: &Retry) -> Result<()> {
+        retry.run(&format!("Uploading {}", self.url()), || {
+            let command = match self {
+                Remote::Http(url) => {
+                    let mut command = Command::new("curl");
+                    command
+                        .args(["--fail", "--silent", "--show-error", "--upload-file"])
+                        .arg(path)
+                        .arg(url);
+                    command
+                }
+                Remote::S3 { .. } => {
+                    let mut command = Command::new("aws");
+                    command
+                        .args(["s3", "cp", "--only-show-errors"])
+                        .arg(path)
+                        .arg(self.url());
+                    command
+                }
+            };
+            run(command)
+        })
+    }
+}
+
+/// Uploads the written files next to `remote`, each under its file name.
+pub fn upload_outputs(remote: &Remote, written: &[String], retry: &Retry) -> Result<()> {
+    for path in written {
+        let path = PathBuf::from(path);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        remote.sibling(&name).upload(&path, retry)?;
+    }
+    Ok(())
+}
+
+fn run(mut command: Command) -> Result<Attempt<()>> {
+    let name = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|err| anyhow!("Transfer Failed: Could not run '{}' ({}).", name, err))?;
+    if output.status.success() {
+        return Ok(Attempt::Done(()));
+    }
+    Ok(Attempt::Failed {
+        code: output.status.code(),
+        error: format!(
+            "'{}' exited with {}: {}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote() {
+        assert_eq!(
+            Remote::parse("https://example.com/a.zip"),
+            Some(Remote::Http("https://example.com/a.zip".to_string()))
+        );
+        assert_eq!(
+            Remote::parse("s3://bucket/dir/a.tar.gz"),
+            Some(Remote::S3 {
+                bucket: "bucket".to_string(),
+                key: "dir/a.tar.gz".to_string()
+            })
+        );
+        assert_eq!(Remote::parse("s3://bucket"), None);
+        assert_eq!(Remote::parse("dir/a.zip"), None);
+
+        let remote = Remote::parse("s3://bucket/dir/a.zip").unwrap();
+        assert_eq!(remote.sibling("a.z01").url(), "s3://bucket/dir/a.z01");
+        let remote = Remote::parse("https://example.com/up/a.zip?token=1").unwrap();
+        assert_eq!(
+            remote.sibling("a.zip.chunks.json").url(),
+            "https://example.com/up/a.zip.chunks.json"
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        let retry = Retry {
+            retries: 3,
+            delay: Duration::from_secs(2),
+        };
+        assert_eq!(retry.backoff(0), Duration::from_secs(2));
+        assert_eq!(retry.backoff(2), Duration::from_secs(8));
+        assert_eq!(retry.backoff(40), MAX_DELAY);
+
+        let retry = Retry {
+            retries: 2,
+            delay: Duration::ZERO,
+        };
+        let mut attempts = 0;
+        let result = retry.run("Testing", || {
+            attempts += 1;
+            Ok(if attempts < 3 {
+                Attempt::Failed {
+                    code: Some(1),
+                    error: "flaky".to_string(),
+                }
+            } else {
+                Attempt::Done(attempts)
+            })
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<()> = retry.run("Testing", || {
+            attempts += 1;
+            Ok(Attempt::Failed {
+                code: Some(1),
+                error: "down".to_string(),
+            })
+        });
+        assert_eq!(attempts, 3);
+        assert!(result.unwrap_err().to_string().contains("failed 3 time(s)"));
+    }
+}
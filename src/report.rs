@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::clamav::Detection;
+use crate::entropy::Anomaly;
+use crate::util::sha256_hex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+/// A written output file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl OutputFile {
+    pub fn read(path: &str) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Ok(OutputFile {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+            sha256: sha256_hex(&bytes),
+        })
+    }
+}
+
+/// An entry removed by a filter list rule matching every occurrence, or
+/// limited to some nesting levels.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Occurrence {
+    pub rule: PathBuf,
+    /// Full path of the entry, through the nested archives holding it.
+    pub path: PathBuf,
+}
+
+/// Result of a scrub, printed as a single JSON object by `--output-json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub status: Status,
+    pub input: String,
+    pub outputs: Vec<OutputFile>,
+    /// Number of filter list entries found and removed.
+    pub removed: usize,
+    /// The filter list entries found and removed, usable as the filter of
+    /// another run with `--filter-format report`.
+    pub removed_entries: Vec<PathBuf>,
+    /// Filter list entries not present in the archive.
+    pub not_found: Vec<PathBuf>,
+    /// Entries removed by each filter list rule, with `--match-all` or a
+    /// depth modifier.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub occurrences: Vec<Occurrence>,
+    /// Entries found infected by clamd with `--clamav`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub infected: Vec<Detection>,
+    /// Kept entries with unusually high entropy for their extension, with
+    /// `--entropy-report`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<Anomaly>,
+    /// The output was left as is, a previous run scrubbed it with the same
+    /// rules.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub already_scrubbed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    pub fn new(input: &str) -> Self {
+        RunReport {
+            status: Status::Ok,
+            input: input.to_string(),
+            outputs: Vec::new(),
+            removed: 0,
+            removed_entries: Vec::new(),
+            not_found: Vec::new(),
+            occurrences: Vec::new(),
+            infected: Vec::new(),
+            anomalies: Vec::new(),
+            already_scrubbed: false,
+            error: None,
+        }
+    }
+
+    /// Records the outcome given the filter list entries left unmatched.
+    pub fn finish(mut self, requested: &[PathBuf], not_found: Vec<PathBuf>) -> Self {
+        self.removed_entries = requested
+            .iter()
+            .filter(|path| !not_found.contains(path))
+            .cloned()
+            .collect();
+        self.removed = self.removed_entries.len();
+        self.not_found = not_found;
+        self
+    }
+
+    pub fn with_occurrences(mut self, occurrences: Vec<Occurrence>) -> Self {
+        self.occurrences = occurrences;
+        self
+    }
+
+    pub fn with_infected(mut self, infected: Vec<Detection>) -> Self {
+        self.infected = infected;
+        self
+    }
+
+    pub fn with_anomalies(mut self, anomalies: Vec<Anomaly>) -> Self {
+        self.anomalies = anomalies;
+        self
+    }
+
+    pub fn already_scrubbed(mut self) -> Self {
+        self.already_scrubbed = true;
+        self
+    }
+
+    /// Adds size and checksum of the written files.
+    pub fn with_outputs(mut self, paths: &[String]) -> Result<Self> {
+        self.outputs = paths
+            .iter()
+            .map(|path| OutputFile::read(path))
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    pub fn failure(input: &str, error: &anyhow::Error) -> Self {
+        RunReport {
+            status: Status::Error,
+            error: Some(format!("{:#}", error)),
+            ..RunReport::new(input)
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportFilter {
+    removed_entries: Vec<PathBuf>,
+}
+
+/// Reads the entries removed by a previous run from its JSON report.
+pub fn parse_report(file_path: &str) -> Result<Vec<PathBuf>> {
+    let content = std::fs::read_to_string(file_path)?;
+    let report: ReportFilter = serde_json::from_str(&content).map_err(|err| {
+        anyhow!(
+            "Invalid Report: '{}' is not a JSON report of expurgator ({}).",
+            file_path,
+            err
+        )
+    })?;
+    Ok(report.removed_entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::FileWriteStr;
+
+    #[test]
+    fn test_run_report_json() {
+        let requested = ["a.txt", "missing.txt", "b/c.txt"].map(PathBuf::from);
+        let report = RunReport::new("in.zip").finish(&requested, vec![requested[1].clone()]);
+        assert_eq!(
+            report.to_json().unwrap(),
+            r#"{"status":"ok","input":"in.zip","outputs":[],"removed":2,"removed_entries":["a.txt","b/c.txt"],"not_found":["missing.txt"]}"#
+        );
+
+        let report = RunReport::failure("in.zip", &anyhow::anyhow!("Broken"));
+        assert_eq!(
+            report.to_json().unwrap(),
+            r#"{"status":"error","input":"in.zip","outputs":[],"removed":0,"removed_entries":[],"not_found":[],"error":"Broken"}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let file = assert_fs::NamedTempFile::new("report.json").unwrap();
+        let requested = [PathBuf::from("a.txt")];
+        let report = RunReport::new("in.zip").finish(&requested, Vec::new());
+        file.write_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            parse_report(file.path().to_str().unwrap()).unwrap(),
+            requested
+        );
+
+        file.write_str("path\na.txt\n").unwrap();
+        assert!(parse_report(file.path().to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{self, Write};
+
+use flate2::{Compress, Compression, Crc, FlushCompress, Status};
+
+/// The rolling hash picking block boundaries, as in `pigz --rsyncable`: it
+/// depends on the last 12 bytes and hits once every 4 KiB on average.
+const HASH_MASK: u32 = (1 << 12) - 1;
+const HASH_HIT: u32 = HASH_MASK >> 1;
+/// Input compressed at once between boundaries.
+const CHUNK: usize = 64 * 1024;
+
+/// A gzip encoder whose compressor is reset at boundaries picked by the
+/// content, so that a change only affects the compressed blocks around it
+/// and rsync, zsync or casync deltas between similar outputs stay small.
+///
+/// The output is a single regular gzip member.
+pub struct RsyncableGzEncoder<W: Write> {
+    writer: W,
+    compress: Compress,
+    crc: Crc,
+    hash: u32,
+    pending: Vec<u8>,
+    header_written: bool,
+}
+
+impl<W: Write> RsyncableGzEncoder<W> {
+    pub fn new(writer: W, compression_level: u32) -> Self {
+        RsyncableGzEncoder {
+            writer,
+            compress: Compress::new(Compression::new(compression_level), false),
+            crc: Crc::new(),
+            hash: HASH_HIT,
+            pending: Vec::with_capacity(CHUNK),
+            header_written: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            // no name and no modification time, the OS is unknown
+            self.writer
+                .write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    /// Compresses the pending input, `FlushCompress::Full` ends the blocks
+    /// at a byte boundary and starts over with a fresh compressor, whose
+    /// output depends on the following input alone.
+    fn deflate(&mut self, flush: FlushCompress) -> io::Result<()> {
+        let mut input = self.pending.as_slice();
+        let mut output = Vec::with_capacity(input.len() / 2 + 1024);
+        loop {
+            output.clear();
+            let consumed = self.compress.total_in();
+            let status = self
+                .compress
+                .compress_vec(input, &mut output, flush)
+                .map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - consumed) as usize..];
+            self.writer.write_all(&output)?;
+            let done = match flush {
+                FlushCompress::Finish => status == Status::StreamEnd,
+                _ => input.is_empty() && output.len() < output.capacity(),
+            };
+            if done {
+                break;
+            }
+        }
+        self.pending.clear();
+        if matches!(flush, FlushCompress::Full) {
+            // a full flush alone keeps some of the compressor's state
+            self.compress.reset();
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining data and the gzip trailer, returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        self.deflate(FlushCompress::Finish)?;
+        self.writer.write_all(&self.crc.sum().to_le_bytes())?;
+        self.writer.write_all(&self.crc.amount().to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for RsyncableGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+        self.crc.update(buf);
+        for &byte in buf {
+            self.hash = ((self.hash << 1) ^ u32::from(byte)) & HASH_MASK;
+            self.pending.push(byte);
+            if self.hash == HASH_HIT {
+                self.deflate(FlushCompress::Full)?;
+            }
+        }
+        if self.pending.len() >= CHUNK {
+            self.deflate(FlushCompress::None)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = RsyncableGzEncoder::new(Vec::new(), 6);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_rsyncable_gz_encoder() {
+        // pseudo random words, compressible but without long repeats
+        let mut state = 1u32;
+        let mut data = Vec::new();
+        while data.len() < 256 * 1024 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            data.extend_from_slice(format!("{} ", state >> 20).as_bytes());
+        }
+        let output = compress(&data);
+        let mut decoded = Vec::new();
+        GzDecoder::new(output.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // a change near the start leaves the rest of the stream alike
+        let mut changed = data.clone();
+        changed.insert(1000, b'!');
+        let changed = compress(&changed);
+        let body = |output: &[u8]| output[..output.len() - 8].to_vec();
+        let common = body(&output)
+            .iter()
+            .rev()
+            .zip(body(&changed).iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert!(common > output.len() / 2);
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+/// Prefix of the depth modifier of a filter list row.
+const DEPTH_MODIFIER: &str = "depth:";
+
+/// Nesting levels a rule applies to, 0 being the entries of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthRange {
+    pub min: usize,
+    /// Unbounded if not set.
+    pub max: Option<usize>,
+}
+
+impl DepthRange {
+    /// Parses `N`, `>=N`, `>N`, `<=N` or `<N`.
+    fn parse(text: &str) -> Option<Self> {
+        let number = |text: &str| text.parse::<usize>().ok();
+        if let Some(min) = text.strip_prefix(">=") {
+            return Some(DepthRange {
+                min: number(min)?,
+                max: None,
+            });
+        }
+        if let Some(min) = text.strip_prefix('>') {
+            return Some(DepthRange {
+                min: number(min)?.checked_add(1)?,
+                max: None,
+            });
+        }
+        if let Some(max) = text.strip_prefix("<=") {
+            return Some(DepthRange {
+                min: 0,
+                max: Some(number(max)?),
+            });
+        }
+        if let Some(max) = text.strip_prefix('<') {
+            return Some(DepthRange {
+                min: 0,
+                max: Some(number(max)?.checked_sub(1)?),
+            });
+        }
+        let depth = number(text)?;
+        Some(DepthRange {
+            min: depth,
+            max: Some(depth),
+        })
+    }
+
+    pub fn contains(&self, depth: usize) -> bool {
+        depth >= self.min && self.max.is_none_or(|max| depth <= max)
+    }
+}
+
+/// A row of the filter list: the path of the entries it removes, optionally
+/// preceded by a modifier limiting it to some nesting levels, as in
+/// `depth:0 LICENSE` for the entries of the input only or `depth:>=1 LICENSE`
+/// for those of the archives nested in it.
+///
+/// Rows reach a nested archive when their path leads into it. Rows with a
+/// modifier reaching deeper levels are handed to every nested archive
+/// besides, a generic name then matches its copies in all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule<'a> {
+    pub path: &'a Path,
+    /// Levels the rule applies to, every level if not set.
+    pub depth: Option<DepthRange>,
+}
+
+impl<'a> Rule<'a> {
+    pub fn parse(row: &'a Path) -> Result<Self> {
+        let Some((modifier, path)) = row
+            .to_str()
+            .and_then(|row| row.strip_prefix(DEPTH_MODIFIER))
+            .and_then(|row| row.split_once(' '))
+        else {
+            return Ok(Rule {
+                path: row,
+                depth: None,
+            });
+        };
+        let depth = DepthRange::parse(modifier).ok_or_else(|| {
+            anyhow!(
+                "Invalid Rule: '{}' has a malformed depth modifier, use e.g. depth:0 or depth:>=1.",
+                row.display()
+            )
+        })?;
+        Ok(Rule {
+            path: Path::new(path),
+            depth: Some(depth),
+        })
+    }
+
+    /// The rule of a row checked by [`Rule::parse`] when it was read, rows
+    /// with a malformed modifier are taken as plain paths.
+    pub fn new(row: &'a Path) -> Self {
+        Rule::parse(row).unwrap_or(Rule {
+            path: row,
+            depth: None,
+        })
+    }
+
+    /// Whether the rule removes the entry at `path` of an archive nested
+    /// `depth` levels deep.
+    pub fn matches<P: AsRef<Path>>(&self, path: P, depth: usize) -> bool {
+        self.depth.is_none_or(|range| range.contains(depth)) && self.path.ends_with(path)
+    }
+
+    /// Whether the rule removes `path`, the full path of an entry nested
+    /// `depth` levels deep as listed by [`ArchiveReader`]. Rules handed to
+    /// every nested archive are matched against the end of the path.
+    ///
+    /// [`ArchiveReader`]: crate::reader::ArchiveReader
+    pub fn matches_listed<P: AsRef<Path>>(&self, path: P, depth: usize) -> bool {
+        let path = path.as_ref();
+        match self.depth {
+            Some(range) if depth > 0 && range.max.is_none_or(|max| max > 0) => {
+                range.contains(depth) && (self.path.ends_with(path) || path.ends_with(self.path))
+            }
+            _ => self.matches(path, depth),
+        }
+    }
+
+    /// Whether the rule is handed to the archive nested at `path` within one
+    /// `depth` levels deep, without leading into it.
+    pub fn reaches_every_nested(&self, path: &str, depth: usize) -> bool {
+        self.depth
+            .is_some_and(|range| range.max.is_none_or(|max| max > depth))
+            && !self.path.starts_with(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule() {
+        let rule = Rule::parse(Path::new("dir/LICENSE")).unwrap();
+        assert_eq!(rule.depth, None);
+        assert!(rule.matches("LICENSE", 3));
+
+        let rule = Rule::parse(Path::new("depth:0 LICENSE")).unwrap();
+        assert_eq!(rule.path, Path::new("LICENSE"));
+        assert!(rule.matches("LICENSE", 0));
+        assert!(!rule.matches("LICENSE", 1));
+        assert!(!rule.reaches_every_nested("lib/a.jar", 0));
+
+        let rule = Rule::parse(Path::new("depth:>=1 LICENSE")).unwrap();
+        assert!(!rule.matches("LICENSE", 0));
+        assert!(rule.matches("LICENSE", 2));
+        assert!(rule.reaches_every_nested("lib/a.jar", 0));
+        assert!(rule.matches_listed("lib/a.jar/LICENSE", 1));
+        assert!(!rule.matches_listed("LICENSE", 0));
+
+        let rule = Rule::parse(Path::new("depth:<2 lib/a.jar/LICENSE")).unwrap();
+        assert_eq!(
+            rule.depth,
+            Some(DepthRange {
+                min: 0,
+                max: Some(1)
+            })
+        );
+        assert!(!rule.reaches_every_nested("lib/a.jar", 0));
+        assert!(rule.reaches_every_nested("lib/b.jar", 0));
+        assert!(!rule.reaches_every_nested("lib/b.jar", 1));
+
+        let err = Rule::parse(Path::new("depth:>=x LICENSE")).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid Rule"));
+        assert_eq!(
+            Rule::new(Path::new("depth:x y")).path,
+            Path::new("depth:x y")
+        );
+    }
+}
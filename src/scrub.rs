@@ -0,0 +1,222 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::archive::{pack_archive_into, pack_reader_into};
+use crate::autotune::DEFAULT_LEVEL;
+use crate::context::{Context, MatchMode};
+use crate::filter::EntryFilter;
+use crate::progress::{NoProgress, Progress};
+use crate::report::Occurrence;
+use crate::sink::Sink;
+use crate::util::parse_csv;
+use crate::CancellationToken;
+
+/// Builder for a scrub, the filtering of the command line tool without the
+/// command line: an input archive, the paths to remove and the options,
+/// [`run`](Expurgator::run) into the scrubbed archive.
+///
+/// Paths are matched like the rows of a filter list, nested archives are
+/// entered through paths leading into them, e.g. `dist/lib.jar/LICENSE`.
+///
+/// ```no_run
+/// use expurgator::Expurgator;
+///
+/// let scrubbed = Expurgator::from_file("release.tar.gz")?
+///     .with_exclusions(["release/debug.log", "release/lib.jar/secrets.env"])
+///     .with_compression_level(9)
+///     .run()?;
+/// std::fs::write("release-clean.tar.gz", &scrubbed.archive)?;
+/// for path in &scrubbed.not_found {
+///     eprintln!("not found: {}", path.display());
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Expurgator<'a> {
+    input: Input,
+    exclusions: Vec<PathBuf>,
+    ctx: Context<'a>,
+}
+
+enum Input {
+    Bytes(Vec<u8>),
+    File(File),
+}
+
+/// Outcome of a scrub.
+#[derive(Debug, Clone)]
+pub struct Scrubbed<S = Vec<u8>> {
+    /// The scrubbed archive, in the format of the input.
+    pub archive: S,
+    /// Exclusions found and removed.
+    pub removed: Vec<PathBuf>,
+    /// Exclusions matching no entry.
+    pub not_found: Vec<PathBuf>,
+    /// Entries removed by each exclusion, with
+    /// [`with_match_all`](Expurgator::with_match_all) or a depth modifier.
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl<'a> Expurgator<'a> {
+    /// A scrub of the archive `input`, any format the command line tool
+    /// reads.
+    pub fn new(input: Vec<u8>) -> Self {
+        Expurgator {
+            input: Input::Bytes(input),
+            exclusions: Vec::new(),
+            ctx: Context::new(&NoProgress, DEFAULT_LEVEL),
+        }
+    }
+
+    /// A scrub of the archive file at `path`. Tar based archives are read
+    /// as they are scrubbed, [`run_into`](Expurgator::run_into) a file then
+    /// holds neither the input nor the output in memory.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Expurgator {
+            input: Input::File(File::open(path)?),
+            ..Expurgator::new(Vec::new())
+        })
+    }
+
+    /// A scrub of the archive read from `reader`, into memory.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input)?;
+        Ok(Expurgator::new(input))
+    }
+
+    /// Adds paths to remove.
+    pub fn with_exclusions<I, P>(mut self, exclusions: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.exclusions
+            .extend(exclusions.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds the paths to remove listed in column `index`, counted from 1, of
+    /// a CSV file.
+    pub fn with_exclusions_csv(self, path: &str, index: usize, has_headers: bool) -> Result<Self> {
+        Ok(self.with_exclusions(parse_csv(path, index, has_headers)?))
+    }
+
+    /// Compression level of the rewritten archives, 6 by default.
+    pub fn with_compression_level(mut self, compression_level: u32) -> Self {
+        self.ctx.compression_level = compression_level;
+        self
+    }
+
+    /// Removes or rewrites entries by a custom policy, after the exclusions.
+    pub fn with_filter<F: EntryFilter + 'static>(mut self, filter: F) -> Self {
+        self.ctx = self.ctx.with_filter(filter);
+        self
+    }
+
+    /// Removes every entry an exclusion matches, not only the first one.
+    pub fn with_match_all(mut self, match_all: bool) -> Self {
+        self.ctx.match_mode = if match_all {
+            MatchMode::All
+        } else {
+            MatchMode::First
+        };
+        self
+    }
+
+    pub fn with_progress(mut self, progress: &'a dyn Progress) -> Self {
+        self.ctx.progress = progress;
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.ctx = self.ctx.with_cancel(cancel);
+        self
+    }
+
+    /// Worker threads rewriting the nested archives of a zip in parallel.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.ctx = self.ctx.with_jobs(jobs);
+        self
+    }
+
+    /// Sets any other option of the [`Context`], e.g.
+    /// `|ctx| ctx.with_windows_safe(true)`.
+    pub fn with_context(mut self, configure: impl FnOnce(Context<'a>) -> Context<'a>) -> Self {
+        self.ctx = configure(self.ctx);
+        self
+    }
+
+    /// Scrubs the input into memory.
+    pub fn run(self) -> Result<Scrubbed> {
+        self.run_into(Vec::new())
+    }
+
+    /// Scrubs the input into `sink`, tar based archives and zips read from a
+    /// file are streamed into it.
+    pub fn run_into<S: Sink>(self, sink: S) -> Result<Scrubbed<S>> {
+        let Expurgator {
+            input,
+            exclusions,
+            ctx,
+        } = self;
+        let mut filter_list = exclusions.clone();
+        let archive = match input {
+            Input::Bytes(input) => pack_archive_into(&ctx, input, &mut filter_list, sink)?,
+            Input::File(file) => pack_reader_into(&ctx, file, &mut filter_list, sink)?,
+        };
+        let not_found = ctx.unmatched(filter_list);
+        let removed = exclusions
+            .into_iter()
+            .filter(|path| !not_found.contains(path))
+            .collect();
+        let occurrences = ctx.occurrences.lock().unwrap().clone();
+        Ok(Scrubbed {
+            archive,
+            removed,
+            not_found,
+            occurrences,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Decision;
+    use crate::reader::{ArchiveReader, EntryInfo};
+
+    #[test]
+    fn test_expurgator() {
+        let scrubbed = Expurgator::from_file("tests/archives/tar-test.tar.gz")
+            .unwrap()
+            .with_exclusions_csv("tests/assets/tar-test.csv", 2, false)
+            .unwrap()
+            .with_exclusions(["tar-test/missing.txt"])
+            .with_filter(|entry: &EntryInfo, _: Option<&[u8]>| -> Result<Decision> {
+                if entry.path.ends_with("file_1.txt") {
+                    Ok(Decision::Remove)
+                } else {
+                    Ok(Decision::Keep)
+                }
+            })
+            .run()
+            .unwrap();
+        assert_eq!(scrubbed.removed.len(), 5);
+        assert_eq!(scrubbed.not_found, [Path::new("tar-test/missing.txt")]);
+
+        let entries: Vec<_> = ArchiveReader::new(scrubbed.archive)
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path)
+            .collect();
+        assert!(!entries.is_empty());
+        assert!(!entries
+            .iter()
+            .any(|path| path.ends_with("file_2.txt") || path.ends_with("file_1.txt")));
+    }
+}
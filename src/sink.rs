@@ -0,0 +1,355 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use tempfile::TempPath;
+
+use crate::context::DEFAULT_BUFFER_SIZE;
+use crate::ratelimit::{RateLimit, Throttled};
+
+pub const DEFAULT_OUTPUT_DIR: &str = "out";
+
+/// Destination of a rewritten archive.
+///
+/// Implement this for custom destinations (object storage uploads, network
+/// streams, ...); `finish` is called once after the last byte was written.
+pub trait Sink: Write {
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.flush()?)
+    }
+
+    /// The sink as a seekable writer, if it is one. Zip outputs are written
+    /// into it directly, other sinks get them through a temporary file.
+    fn seekable(&mut self) -> Option<&mut dyn SeekWrite> {
+        None
+    }
+}
+
+/// A [`Write`] that can [`Seek`] as well.
+pub trait SeekWrite: Write + Seek {}
+
+impl<T: Write + Seek> SeekWrite for T {}
+
+impl Sink for Vec<u8> {}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+
+    fn seekable(&mut self) -> Option<&mut dyn SeekWrite> {
+        (**self).seekable()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+
+    fn seekable(&mut self) -> Option<&mut dyn SeekWrite> {
+        (**self).seekable()
+    }
+}
+
+/// What happens to an output file that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Clobber {
+    #[default]
+    Refuse,
+    Overwrite,
+    /// Keep the existing file as `<name>.bak`.
+    Backup,
+}
+
+/// Location, permissions and overwrite policy of created output files.
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    /// Directory the outputs are written to.
+    pub dir: String,
+    /// Unix permission bits, the process umask still applies.
+    pub mode: Option<u32>,
+    /// Refuse to create world-writable outputs.
+    pub hardened: bool,
+    pub clobber: Clobber,
+    /// Bandwidth limit of the writes.
+    pub bwlimit: Option<RateLimit>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            dir: DEFAULT_OUTPUT_DIR.to_string(),
+            mode: None,
+            hardened: false,
+            clobber: Clobber::default(),
+            bwlimit: None,
+        }
+    }
+}
+
+/// An output file written under a unique temporary name in the destination
+/// directory and renamed into place by [`AtomicFile::commit`], so a crash
+/// never leaves a truncated file behind. Dropping it uncommitted removes the
+/// temporary file.
+pub struct AtomicFile {
+    file: Throttled<File>,
+    path: PathBuf,
+    temp_path: Option<TempPath>,
+    clobber: Clobber,
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+impl AtomicFile {
+    pub fn create<P: AsRef<Path>>(path: P, options: &OutputOptions) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if options.clobber == Clobber::Refuse && path.exists() {
+            Err(anyhow!(
+                "Output Exists: The file '{}' already exists, use --force to overwrite it or --backup to keep a copy.",
+                path.display()
+            ))?;
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut prefix = std::ffi::OsString::from(".");
+        prefix.push(path.file_name().unwrap_or_default());
+        prefix.push(".");
+
+        let mut open_options = OpenOptions::new();
+        open_options.create_new(true).write(true);
+        #[cfg(unix)]
+        if let Some(mode) = options.mode {
+            use std::os::unix::fs::OpenOptionsExt;
+            open_options.mode(mode);
+        }
+        let (temp_file, temp_path) = tempfile::Builder::new()
+            .prefix(&prefix)
+            .suffix(".tmp")
+            .make_in(dir, |temp_path| open_options.open(temp_path))?
+            .into_parts();
+        let file = AtomicFile {
+            file: Throttled::new(temp_file, options.bwlimit.clone()),
+            path,
+            temp_path: Some(temp_path),
+            clobber: options.clobber,
+        };
+
+        #[cfg(unix)]
+        if options.hardened {
+            use std::os::unix::fs::PermissionsExt;
+            if file.file.get_ref().metadata()?.permissions().mode() & 0o002 != 0 {
+                Err(anyhow!(
+                    "Insecure Output: Refusing to create the world-writable file '{}', check the umask or --output-mode.",
+                    file.path.display()
+                ))?;
+            }
+        }
+        Ok(file)
+    }
+
+    /// Syncs the content to disk and renames the file into place. With
+    /// [`Clobber::Backup`] the existing file is linked, or copied where links
+    /// are not supported, to `<name>.bak` first, so the output path never
+    /// goes missing.
+    pub fn commit(&mut self) -> Result<()> {
+        let Some(temp_path) = self.temp_path.take() else {
+            return Ok(());
+        };
+        self.file.get_ref().sync_all()?;
+        if self.clobber == Clobber::Backup && self.path.exists() {
+            let backup = with_suffix(&self.path, ".bak");
+            if std::fs::hard_link(&self.path, &backup).is_err() {
+                std::fs::copy(&self.path, &backup)?;
+            }
+        }
+        temp_path.persist(&self.path)?;
+        #[cfg(unix)]
+        {
+            let dir = match self.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+pub struct FileSink {
+    writer: BufWriter<AtomicFile>,
+}
+
+impl FileSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        FileSink::with_capacity(DEFAULT_BUFFER_SIZE, path)
+    }
+
+    pub fn with_capacity<P: AsRef<Path>>(capacity: usize, path: P) -> Result<Self> {
+        FileSink::with_options(capacity, path, &OutputOptions::default())
+    }
+
+    pub fn with_options<P: AsRef<Path>>(
+        capacity: usize,
+        path: P,
+        options: &OutputOptions,
+    ) -> Result<Self> {
+        let file = AtomicFile::create(path, options)?;
+        Ok(FileSink {
+            writer: BufWriter::with_capacity(capacity, file),
+        })
+    }
+}
+
+impl Write for FileSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Sink for FileSink {
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().commit()
+    }
+
+    fn seekable(&mut self) -> Option<&mut dyn SeekWrite> {
+        Some(&mut self.writer)
+    }
+}
+
+/// Adapts any [`Write`] implementation (stdout, a socket, an HTTP request
+/// body, ...) into a [`Sink`].
+pub struct WriteSink<W: Write>(pub W);
+
+impl<W: Write> Write for WriteSink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Sink for WriteSink<W> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_sink() {
+        let file = assert_fs::NamedTempFile::new("output.bin").unwrap();
+        let mut sink = FileSink::create(file.path()).unwrap();
+        sink.write_all(b"abcd").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn test_file_sink_uncommitted() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("output.bin");
+        std::fs::write(&path, b"original").unwrap();
+        assert!(FileSink::create(&path).is_err());
+        {
+            let options = OutputOptions {
+                clobber: Clobber::Overwrite,
+                ..Default::default()
+            };
+            let mut sink = FileSink::with_options(DEFAULT_BUFFER_SIZE, &path, &options).unwrap();
+            sink.write_all(b"partial").unwrap();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_atomic_file_unique_temp() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("output.bin");
+        // a file of the user under the old fixed temporary name
+        std::fs::write(dir.path().join("output.bin.tmp"), b"mine").unwrap();
+        let options = OutputOptions::default();
+        let mut first = AtomicFile::create(&path, &options).unwrap();
+        let mut second = AtomicFile::create(&path, &options).unwrap();
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+        first.commit().unwrap();
+        drop(second);
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert_eq!(
+            std::fs::read(dir.path().join("output.bin.tmp")).unwrap(),
+            b"mine"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_atomic_file_backup() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("output.bin");
+        std::fs::write(&path, b"original").unwrap();
+        let options = OutputOptions {
+            clobber: Clobber::Backup,
+            ..Default::default()
+        };
+        let mut file = AtomicFile::create(&path, &options).unwrap();
+        file.write_all(b"new").unwrap();
+        file.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(
+            std::fs::read(dir.path().join("output.bin.bak")).unwrap(),
+            b"original"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let file = assert_fs::NamedTempFile::new("output.bin").unwrap();
+        let options = OutputOptions {
+            mode: Some(0o600),
+            hardened: true,
+            ..Default::default()
+        };
+        AtomicFile::create(file.path(), &options)
+            .unwrap()
+            .commit()
+            .unwrap();
+        let mode = std::fs::metadata(file.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,46 @@
+use std::{io::IsTerminal, time::Duration};
+
+use expurgator::Progress;
+use indicatif::{ProgressBar, ProgressStyle};
+
+pub struct Spinner(ProgressBar);
+
+impl Spinner {
+    /// Hidden when stderr is not a terminal.
+    pub fn new(color: bool) -> Self {
+        if !std::io::stderr().is_terminal() {
+            return Spinner(ProgressBar::hidden());
+        }
+        let progress_bar = ProgressBar::new_spinner();
+        progress_bar.enable_steady_tick(Duration::from_millis(120));
+        let template = if color {
+            "{spinner:.blue} {msg}"
+        } else {
+            "{spinner} {msg}"
+        };
+        progress_bar.set_style(
+            ProgressStyle::with_template(template)
+                .unwrap()
+                .tick_strings(&[
+                    "▹▹▹▹▹",
+                    "▸▹▹▹▹",
+                    "▹▸▹▹▹",
+                    "▹▹▸▹▹",
+                    "▹▹▹▸▹",
+                    "▹▹▹▹▸",
+                    "▪▪▪▪▪",
+                ]),
+        );
+        Spinner(progress_bar)
+    }
+}
+
+impl Progress for Spinner {
+    fn message(&self, message: String) {
+        self.0.set_message(message);
+    }
+
+    fn suspend(&self, f: &mut dyn FnMut()) {
+        self.0.suspend(f);
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::ratelimit::{read_file, RateLimit};
+use crate::zipfmt::{
+    find_eocd, read_eocd, read_u16, read_u32, write_u16, write_u32, CENTRAL_HEADER_LEN,
+    CENTRAL_HEADER_SIGNATURE, EOCD_LEN, LOCAL_HEADER_LEN, LOCAL_HEADER_SIGNATURE,
+};
+
+const SPANNING_SIGNATURE: u32 = 0x0807_4b50;
+
+fn split_part_path(path: &Path, disk: usize) -> PathBuf {
+    path.with_extension(format!("z{:02}", disk))
+}
+
+/// Reads `path` and, if it is the last volume of a split zip set, locates the
+/// sibling `.z01`, `.z02`, ... volumes and joins them into a single archive.
+pub fn read_zip_volumes(file_path: &str) -> Result<Vec<u8>> {
+    read_zip_volumes_limited(file_path, None)
+}
+
+/// [`read_zip_volumes`] limited to the rate of `bwlimit`.
+pub fn read_zip_volumes_limited(file_path: &str, bwlimit: Option<&RateLimit>) -> Result<Vec<u8>> {
+    let last_volume = read_file(file_path, bwlimit)?;
+    let path = Path::new(file_path);
+    if path.extension() != Some(OsStr::new("zip")) {
+        return Ok(last_volume);
+    }
+    let eocd = match find_eocd(&last_volume) {
+        Ok(eocd) if eocd.disk_number > 0 => eocd,
+        _ => return Ok(last_volume),
+    };
+
+    let mut volumes = Vec::new();
+    for disk in 1..=eocd.disk_number as usize {
+        let part = split_part_path(path, disk);
+        let bytes = read_file(part.to_str().unwrap(), bwlimit).map_err(|err| {
+            anyhow!(
+                "Missing Zip Volume: Failed to read split part '{}': {}",
+                part.display(),
+                err
+            )
+        })?;
+        volumes.push(bytes);
+    }
+    volumes.push(last_volume);
+    join_zip_volumes(&volumes)
+}
+
+/// Whether `file_path` is the last volume of a split zip set, which
+/// [`read_zip_volumes`] joins with its sibling volumes.
+pub fn is_zip_volume(file_path: &str) -> Result<bool> {
+    if Path::new(file_path).extension() != Some(OsStr::new("zip")) {
+        return Ok(false);
+    }
+    let eocd = read_eocd(&mut File::open(file_path)?);
+    Ok(eocd.is_ok_and(|eocd| eocd.disk_number > 0))
+}
+
+/// Concatenates the volumes of a split zip and rewrites the central directory
+/// so that every entry refers to the single resulting disk.
+pub fn join_zip_volumes(volumes: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut disk_offsets = Vec::with_capacity(volumes.len());
+    let mut joined = Vec::new();
+    for volume in volumes {
+        disk_offsets.push(joined.len());
+        joined.extend_from_slice(volume);
+    }
+    let disk_offset = |disk: u16| -> Result<usize> {
+        disk_offsets
+            .get(disk as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Invalid Zip Archive: Reference to missing volume {}.", disk))
+    };
+
+    let last_offset = *disk_offsets.last().unwrap_or(&0);
+    let eocd = find_eocd(&joined[last_offset..])?;
+    if eocd.zip64.is_some() {
+        Err(anyhow!(
+            "Unsupported Zip Archive: ZIP64 split archives are not supported."
+        ))?;
+    }
+    let eocd_offset = last_offset + eocd.offset;
+    let cd_start = disk_offset(eocd.cd_disk)? + eocd.cd_offset as usize;
+
+    let mut offset = cd_start;
+    let mut entries: u16 = 0;
+    while read_u32(&joined, offset)? == CENTRAL_HEADER_SIGNATURE {
+        let disk_start = read_u16(&joined, offset + 34)?;
+        let local_offset = read_u32(&joined, offset + 42)?;
+        if local_offset == u32::MAX {
+            Err(anyhow!(
+                "Unsupported Zip Archive: ZIP64 split archives are not supported."
+            ))?;
+        }
+        let absolute: u32 = (disk_offset(disk_start)? + local_offset as usize).try_into()?;
+        write_u16(&mut joined, offset + 34, 0);
+        write_u32(&mut joined, offset + 42, absolute);
+
+        let name_len = read_u16(&joined, offset + 28)? as usize;
+        let extra_len = read_u16(&joined, offset + 30)? as usize;
+        let comment_len = read_u16(&joined, offset + 32)? as usize;
+        offset += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+        entries += 1;
+    }
+
+    write_u16(&mut joined, eocd_offset + 4, 0);
+    write_u16(&mut joined, eocd_offset + 6, 0);
+    write_u16(&mut joined, eocd_offset + 8, entries);
+    write_u16(&mut joined, eocd_offset + 10, entries);
+    write_u32(&mut joined, eocd_offset + 16, cd_start.try_into()?);
+    Ok(joined)
+}
+
+struct Volumes {
+    size: usize,
+    parts: Vec<Vec<u8>>,
+}
+
+impl Volumes {
+    fn new(size: usize) -> Self {
+        Volumes {
+            size,
+            parts: vec![Vec::new()],
+        }
+    }
+
+    fn current(&mut self) -> &mut Vec<u8> {
+        self.parts.last_mut().unwrap()
+    }
+
+    fn disk(&self) -> u16 {
+        (self.parts.len() - 1) as u16
+    }
+
+    // headers must not straddle a volume boundary
+    fn reserve(&mut self, len: usize) -> Result<()> {
+        if len > self.size {
+            Err(anyhow!(
+                "Invalid Split Size: The split size is smaller than a single zip header."
+            ))?;
+        }
+        if self.current().len() + len > self.size {
+            self.parts.push(Vec::new());
+        }
+        Ok(())
+    }
+
+    fn write_spanning(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let size = self.size;
+            let current = self.current();
+            if current.len() == size {
+                self.parts.push(Vec::new());
+                continue;
+            }
+            let take = (size - current.len()).min(data.len());
+            current.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+    }
+}
+
+/// Splits a single-disk zip into volumes of at most `size` bytes.
+///
+/// The returned volumes are in disk order, the last one holds the central
+/// directory and should be written as `.zip`, the others as `.z01`, `.z02`, ...
+pub fn split_zip(bytes: &[u8], size: usize) -> Result<Vec<Vec<u8>>> {
+    let eocd = find_eocd(bytes)?;
+    if eocd.zip64.is_some() {
+        Err(anyhow!(
+            "Unsupported Zip Archive: ZIP64 archives cannot be split."
+        ))?;
+    }
+    let cd_start = eocd.cd_offset as usize;
+
+    let mut central_records = Vec::new();
+    let mut offset = cd_start;
+    while read_u32(bytes, offset)? == CENTRAL_HEADER_SIGNATURE {
+        let name_len = read_u16(bytes, offset + 28)? as usize;
+        let extra_len = read_u16(bytes, offset + 30)? as usize;
+        let comment_len = read_u16(bytes, offset + 32)? as usize;
+        let len = CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+        central_records.push(bytes[offset..offset + len].to_vec());
+        offset += len;
+    }
+
+    let mut local_offsets: Vec<usize> = central_records
+        .iter()
+        .map(|record| read_u32(record, 42).map(|offset| offset as usize))
+        .collect::<Result<_>>()?;
+    local_offsets.sort_unstable();
+
+    let mut volumes = Volumes::new(size);
+    volumes
+        .current()
+        .extend_from_slice(&SPANNING_SIGNATURE.to_le_bytes());
+
+    // (disk, offset within disk) of every local header, keyed by old offset
+    let mut relocations = Vec::with_capacity(local_offsets.len());
+    for (i, &start) in local_offsets.iter().enumerate() {
+        let end = local_offsets.get(i + 1).copied().unwrap_or(cd_start);
+        if read_u32(bytes, start)? != LOCAL_HEADER_SIGNATURE {
+            Err(anyhow!(
+                "Invalid Zip Archive: Local file header not found at offset {}.",
+                start
+            ))?;
+        }
+        let header_len = LOCAL_HEADER_LEN
+            + read_u16(bytes, start + 26)? as usize
+            + read_u16(bytes, start + 28)? as usize;
+        volumes.reserve(header_len)?;
+        relocations.push((start, volumes.disk(), volumes.current().len()));
+        volumes.write_spanning(&bytes[start..end]);
+    }
+
+    let mut cd_disk = None;
+    let mut cd_offset = 0;
+    let mut cd_size = 0;
+    for mut record in central_records {
+        let old_offset = read_u32(&record, 42)? as usize;
+        let (_, disk, offset) = relocations
+            .iter()
+            .find(|(start, _, _)| *start == old_offset)
+            .copied()
+            .ok_or_else(|| anyhow!("Invalid Zip Archive: Dangling central directory record."))?;
+        write_u16(&mut record, 34, disk);
+        write_u32(&mut record, 42, offset.try_into()?);
+
+        volumes.reserve(record.len())?;
+        if cd_disk.is_none() {
+            cd_disk = Some(volumes.disk());
+            cd_offset = volumes.current().len();
+        }
+        cd_size += record.len();
+        volumes.current().extend_from_slice(&record);
+    }
+
+    let comment = &bytes[eocd.offset + EOCD_LEN..];
+    volumes.reserve(EOCD_LEN + comment.len())?;
+    let cd_disk = cd_disk.unwrap_or_else(|| volumes.disk());
+    let disk = volumes.disk();
+    let entries: u16 = eocd.entries.try_into()?;
+    let entries_on_disk = if cd_disk == disk { entries } else { 0 };
+
+    let mut record = bytes[eocd.offset..].to_vec();
+    write_u16(&mut record, 4, disk);
+    write_u16(&mut record, 6, cd_disk);
+    write_u16(&mut record, 8, entries_on_disk);
+    write_u16(&mut record, 10, entries);
+    write_u32(&mut record, 12, cd_size.try_into()?);
+    write_u32(&mut record, 16, cd_offset.try_into()?);
+    volumes.current().extend_from_slice(&record);
+
+    Ok(volumes.parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn create_zip() -> Vec<u8> {
+        let mut result = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut result));
+            let options =
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            for i in 0..8 {
+                zip.start_file(format!("file_{}.txt", i), options).unwrap();
+                zip.write_all(&[b'a' + i as u8; 100]).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        result
+    }
+
+    #[test]
+    fn test_split_and_join_zip() {
+        let input = create_zip();
+        let volumes = split_zip(&input, 256).unwrap();
+        assert!(volumes.len() > 1);
+        assert!(volumes.iter().all(|volume| volume.len() <= 256));
+
+        let joined = join_zip_volumes(&volumes).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(joined)).unwrap();
+        assert_eq!(archive.len(), 8);
+        for i in 0..8 {
+            let mut content = Vec::new();
+            archive
+                .by_name(&format!("file_{}.txt", i))
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            assert_eq!(content, vec![b'a' + i as u8; 100]);
+        }
+    }
+
+    #[test]
+    fn test_is_zip_volume() {
+        let dir = tempfile::tempdir().unwrap();
+        let volumes = split_zip(&create_zip(), 256).unwrap();
+        let last = dir.path().join("split.zip");
+        std::fs::write(&last, volumes.last().unwrap()).unwrap();
+        let whole = dir.path().join("whole.zip");
+        std::fs::write(&whole, create_zip()).unwrap();
+        assert!(is_zip_volume(last.to_str().unwrap()).unwrap());
+        assert!(!is_zip_volume(whole.to_str().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_split_zip_too_small() {
+        assert!(split_zip(&create_zip(), 16).is_err());
+    }
+}
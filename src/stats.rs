@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use expurgator::internal::format_size;
+use expurgator::{EntryInfo, EntryKind};
+
+const DAY: u64 = 24 * 60 * 60;
+const AGE_BUCKETS: [(u64, &str); 5] = [
+    (30 * DAY, "< 1 month"),
+    (182 * DAY, "1-6 months"),
+    (365 * DAY, "6-12 months"),
+    (2 * 365 * DAY, "1-2 years"),
+    (u64::MAX, "> 2 years"),
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    pub count: usize,
+    pub size: u64,
+}
+
+impl Bucket {
+    fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.size += size;
+    }
+}
+
+/// File statistics of a single archive, nested archives excluded.
+#[derive(Debug, Default)]
+pub struct ArchiveStats {
+    pub by_extension: BTreeMap<String, Bucket>,
+    pub by_mime_type: BTreeMap<String, Bucket>,
+    pub by_age: BTreeMap<&'static str, Bucket>,
+    pub by_top_level: BTreeMap<String, Bucket>,
+}
+
+impl ArchiveStats {
+    fn add(&mut self, entry: &EntryInfo, relative: &Path, now: u64) {
+        let extension = relative.extension().map_or("(none)".to_string(), |ext| {
+            ext.to_string_lossy().to_lowercase()
+        });
+        self.by_extension
+            .entry(extension)
+            .or_default()
+            .add(entry.size);
+
+        let mime_type = entry.mime_type.as_deref().unwrap_or("(unknown)");
+        self.by_mime_type
+            .entry(mime_type.to_string())
+            .or_default()
+            .add(entry.size);
+
+        let age = match entry.mtime {
+            Some(mtime) => {
+                let age = now.saturating_sub(mtime);
+                AGE_BUCKETS
+                    .iter()
+                    .find(|(limit, _)| age < *limit)
+                    .map_or("> 2 years", |(_, name)| name)
+            }
+            None => "(unknown)",
+        };
+        self.by_age.entry(age).or_default().add(entry.size);
+
+        let mut components = relative.components();
+        let top_level = match (components.next(), components.next()) {
+            (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+            _ => "(root)".to_string(),
+        };
+        self.by_top_level
+            .entry(top_level)
+            .or_default()
+            .add(entry.size);
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let sections: [(&str, Vec<(&str, &Bucket)>); 4] = [
+            (
+                "extension",
+                self.by_extension
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v))
+                    .collect(),
+            ),
+            (
+                "mime type",
+                self.by_mime_type
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v))
+                    .collect(),
+            ),
+            ("age", self.by_age.iter().map(|(k, v)| (*k, v)).collect()),
+            (
+                "top-level directory",
+                self.by_top_level
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v))
+                    .collect(),
+            ),
+        ];
+        for (title, buckets) in sections {
+            let _ = writeln!(output, "by {}:", title);
+            for (name, bucket) in buckets {
+                let _ = writeln!(
+                    output,
+                    "  {:<32} {:>8} {:>12}",
+                    name,
+                    bucket.count,
+                    format_size(bucket.size)
+                );
+            }
+        }
+        output
+    }
+}
+
+/// Collects statistics for the outer archive (keyed by an empty path) and
+/// for every nested archive (keyed by its path), counting regular files.
+///
+/// `now` is the reference time in seconds since the epoch for age buckets.
+pub fn collect_stats(entries: &[EntryInfo], now: u64) -> BTreeMap<PathBuf, ArchiveStats> {
+    let mut result: BTreeMap<PathBuf, ArchiveStats> = BTreeMap::new();
+    result.entry(PathBuf::new()).or_default();
+    // entries are listed depth first, so the latest entry seen on the level
+    // above is the archive containing the current one
+    let mut latest: Vec<PathBuf> = Vec::new();
+    for entry in entries {
+        latest.truncate(entry.depth);
+        let archive = match entry.depth {
+            0 => PathBuf::new(),
+            depth => latest.get(depth - 1).cloned().unwrap_or_default(),
+        };
+        latest.push(entry.path.clone());
+
+        if entry.kind != EntryKind::File {
+            continue;
+        }
+        let relative = entry.path.strip_prefix(&archive).unwrap_or(&entry.path);
+        result
+            .entry(archive.clone())
+            .or_default()
+            .add(entry, relative, now);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expurgator::ArchiveReader;
+
+    #[test]
+    fn test_collect_stats() {
+        let input = std::fs::read("tests/archives/tar-test.tar.gz").unwrap();
+        let entries = ArchiveReader::new(input)
+            .entries()
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let stats = collect_stats(&entries, 1_700_000_000);
+        assert_eq!(stats.len(), 3);
+
+        let outer = &stats[Path::new("")];
+        assert_eq!(outer.by_extension["txt"], Bucket { count: 3, size: 60 });
+        assert_eq!(outer.by_mime_type["application/zip"].count, 1);
+        assert_eq!(outer.by_age["6-12 months"].count, 6);
+        assert_eq!(outer.by_top_level["tar-test"].count, 6);
+
+        let nested = &stats[Path::new("tar-test/nested/nestedtar.tar.xz")];
+        assert_eq!(nested.by_extension["txt"].count, 2);
+        assert_eq!(nested.by_top_level["nestedtar"].count, 2);
+    }
+}
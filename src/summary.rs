@@ -0,0 +1,170 @@
+use std::{fmt::Write, path::PathBuf};
+
+use anyhow::Result;
+
+use expurgator::internal::{estimate_reader, format_size, Messages, NestedGraph};
+use expurgator::ArchiveReader;
+
+/// What a removal is about to do, shown before it is confirmed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub input: String,
+    /// Rules by the option that gave them, and how many.
+    pub rules: Vec<(String, usize)>,
+    /// The filter list, whose first path is shown.
+    pub filter_list: Vec<PathBuf>,
+    /// Archives to process, the input and the nested ones.
+    pub archives: usize,
+    pub entries: usize,
+    /// Entries the filter list matches, and their size.
+    pub affected: usize,
+    pub affected_bytes: u64,
+    pub destinations: Vec<String>,
+}
+
+impl Summary {
+    /// Scans the archive of `reader` for the archives and the entries the
+    /// filter list matches, the way `--dry-run` does.
+    pub fn scan(input: &str, reader: &ArchiveReader, filter_list: &[PathBuf]) -> Result<Self> {
+        let estimate = estimate_reader(reader, filter_list)?;
+        let entries = reader.entries()?.collect::<Result<Vec<_>>>()?;
+        let graph = NestedGraph::new(input, estimate.input_size, &entries);
+        Ok(Summary {
+            input: input.to_string(),
+            filter_list: filter_list.to_vec(),
+            archives: graph.nodes.len(),
+            entries: entries.len(),
+            affected: estimate.removed.len(),
+            affected_bytes: estimate.removed_bytes(),
+            ..Default::default()
+        })
+    }
+
+    /// Adds `count` rules given by `option`, none are left out.
+    pub fn with_rules(mut self, option: &str, count: usize) -> Self {
+        if count > 0 {
+            self.rules.push((option.to_string(), count));
+        }
+        self
+    }
+
+    pub fn with_destination(mut self, destination: String) -> Self {
+        self.destinations.push(destination);
+        self
+    }
+
+    /// Renders the summary in the language of `messages`, followed by
+    /// `preview`, the start of the first entry of the filter list, if any.
+    pub fn render(&self, messages: &Messages, preview: Option<&str>) -> String {
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "{}",
+            Messages::format(&messages.summary_title, &[("input", &self.input)])
+        );
+        let _ = writeln!(output, "  {}", messages.summary_rules);
+        if self.rules.is_empty() {
+            let _ = writeln!(output, "    {}", messages.summary_no_rules);
+        }
+        let width = self
+            .rules
+            .iter()
+            .map(|(option, _)| option.len())
+            .max()
+            .unwrap_or_default();
+        for (option, count) in &self.rules {
+            let _ = writeln!(output, "    {:<width$}  {:>5}", option, count);
+        }
+        let _ = writeln!(
+            output,
+            "  {}",
+            Messages::format(
+                &messages.summary_archives,
+                &[
+                    ("count", &self.archives.to_string()),
+                    ("nested", &self.archives.saturating_sub(1).to_string())
+                ]
+            )
+        );
+        let _ = writeln!(
+            output,
+            "  {}",
+            Messages::format(
+                &messages.summary_entries,
+                &[
+                    ("count", &self.affected.to_string()),
+                    ("total", &self.entries.to_string()),
+                    ("size", &format_size(self.affected_bytes))
+                ]
+            )
+        );
+        if self.rules.len() > usize::from(!self.filter_list.is_empty()) {
+            let _ = writeln!(output, "  {}", messages.summary_entry_filters);
+        }
+        if let Some(first) = self.filter_list.first() {
+            let _ = writeln!(
+                output,
+                "  {}",
+                Messages::format(
+                    &messages.confirm_filter_help,
+                    &[("count", &self.filter_list.len().to_string())]
+                )
+            );
+            let _ = writeln!(output, "    {}", first.display());
+            output.push_str(preview.unwrap_or_default());
+        }
+        let _ = writeln!(output, "  {}", messages.summary_destinations);
+        for destination in &self.destinations {
+            let _ = writeln!(output, "    {}", destination);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        // read from the file as it is scanned
+        let reader = ArchiveReader::open("tests/archives/tar-test.tar.gz");
+        let filter_list = vec![PathBuf::from("tar-test/file_1.txt")];
+        let summary = Summary::scan("tar-test.tar.gz", &reader, &filter_list)
+            .unwrap()
+            .with_rules("--csv", filter_list.len())
+            .with_rules("--plugin", 0)
+            .with_rules("--exclude-license", 2)
+            .with_destination("out/tar-test.tar.gz".to_string());
+        // the nested zip is processed too
+        assert!(summary.archives > 1);
+        assert_eq!(summary.affected, 1);
+        assert!(summary.entries > 1);
+
+        let rendered = summary.render(&Messages::default(), Some("    abc\n"));
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[0], "About to rewrite tar-test.tar.gz:");
+        assert_eq!(lines[2], "    --csv                  1");
+        assert_eq!(lines[3], "    --exclude-license      2");
+        assert_eq!(
+            lines[4],
+            format!(
+                "  Archives to process: {}, {} of them nested",
+                summary.archives,
+                summary.archives - 1
+            )
+        );
+        assert!(lines[5].starts_with("  Entries affected: 1 of "));
+        assert_eq!(
+            lines[6],
+            "  The other rules may remove more entries during the run."
+        );
+        assert_eq!(lines[8], "    tar-test/file_1.txt");
+        assert_eq!(lines[9], "    abc");
+        assert_eq!(lines[11], "    out/tar-test.tar.gz");
+
+        let rendered = Summary::default().render(&Messages::default(), None);
+        assert!(rendered.contains("none, the archive is only rewritten"));
+        assert!(!rendered.contains("other rules"));
+    }
+}
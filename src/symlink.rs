@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Links followed at most when dereferencing a chain of them, longer chains
+/// are most likely cycles.
+const MAX_HOPS: usize = 40;
+
+/// Path within the archive the symlink at `path` points to, `None` if
+/// `target` is absolute or leads outside the archive root.
+pub fn resolve(path: &Path, target: &Path) -> Option<PathBuf> {
+    normalize(&path.parent().unwrap_or(Path::new("")).join(target))
+}
+
+/// `path` relative to the archive root without `.` and `..` components,
+/// `None` if it is absolute or leads outside the root.
+pub fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// The path the symlink at `path` ends up at through `links`, the targets of
+/// the symlinks of an archive by path: the first one that is not a link.
+/// `None` if the chain leads outside the archive root or loops. Paths are
+/// normalized with [`normalize`].
+pub fn follow(links: &HashMap<PathBuf, PathBuf>, path: &Path) -> Option<PathBuf> {
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_HOPS {
+        let Some(target) = links.get(&current) else {
+            return Some(current);
+        };
+        current = resolve(&current, target)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        for (path, target, resolved) in [
+            ("bin/sh", "bash", Some("bin/bash")),
+            ("./bin/sh", "./bash", Some("bin/bash")),
+            (
+                "usr/lib/libz.so",
+                "../../lib/libz.so.1",
+                Some("lib/libz.so.1"),
+            ),
+            ("link", "dir/file", Some("dir/file")),
+            ("etc/passwd", "/etc/passwd", None),
+            ("lib/escape", "../../outside", None),
+        ] {
+            assert_eq!(
+                resolve(Path::new(path), Path::new(target)),
+                resolved.map(PathBuf::from),
+                "{} -> {}",
+                path,
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_follow() {
+        let links: HashMap<PathBuf, PathBuf> = [
+            ("lib/libz.so", "libz.so.1"),
+            ("lib/libz.so.1", "libz.so.1.3"),
+            ("loop/a", "b"),
+            ("loop/b", "a"),
+            ("lib/ext", "../../etc"),
+        ]
+        .into_iter()
+        .map(|(path, target)| (PathBuf::from(path), PathBuf::from(target)))
+        .collect();
+        assert_eq!(
+            follow(&links, Path::new("lib/libz.so")),
+            Some(PathBuf::from("lib/libz.so.1.3"))
+        );
+        assert_eq!(follow(&links, Path::new("loop/a")), None);
+        assert_eq!(follow(&links, Path::new("lib/ext")), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("./usr/lib/../bin/sh")),
+            Some(PathBuf::from("usr/bin/sh"))
+        );
+        assert_eq!(normalize(Path::new("../etc")), None);
+        assert_eq!(normalize(Path::new("/etc")), None);
+    }
+}
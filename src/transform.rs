@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher};
+
+use crate::classify::{classify, ContentClass};
+use crate::elf::strip_debug;
+use crate::filter::{Decision, EntryFilter};
+use crate::image::{optimize_image, strip_image_metadata};
+use crate::minify::{minify_js, minify_xml};
+use crate::reader::{EntryInfo, EntryKind};
+
+/// Rewrites the content of a kept entry.
+pub trait Transform: Send + Sync {
+    fn transform(&self, entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>>;
+}
+
+impl<F> Transform for F
+where
+    F: Fn(&EntryInfo, &[u8]) -> Result<Vec<u8>> + Send + Sync,
+{
+    fn transform(&self, entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+        self(entry, content)
+    }
+}
+
+/// Selects the entries a [`Transform`] applies to.
+pub enum Selector {
+    /// Matched against the full entry path, e.g. `*.json` or `conf/**`.
+    Glob(GlobMatcher),
+    /// Matched against the MIME type inferred from the content.
+    Mime(String),
+}
+
+impl Selector {
+    /// Parses `mime:<type>` as a MIME selector and anything else as a glob.
+    pub fn parse(selector: &str) -> Result<Self> {
+        match selector.strip_prefix("mime:") {
+            Some(mime_type) => Ok(Selector::Mime(mime_type.to_string())),
+            None => Ok(Selector::Glob(Glob::new(selector)?.compile_matcher())),
+        }
+    }
+
+    pub fn matches(&self, entry: &EntryInfo, content: &[u8]) -> bool {
+        match self {
+            Selector::Glob(matcher) => matcher.is_match(&entry.path),
+            Selector::Mime(mime_type) => {
+                infer::get(content).is_some_and(|kind| kind.mime_type() == mime_type)
+            }
+        }
+    }
+}
+
+/// A `Transform` bound to a `Selector`, registered as an entry filter.
+pub struct Transformer {
+    selector: Selector,
+    transform: Box<dyn Transform>,
+}
+
+impl Transformer {
+    pub fn new<T: Transform + 'static>(selector: Selector, transform: T) -> Self {
+        Transformer {
+            selector,
+            transform: Box::new(transform),
+        }
+    }
+
+    /// Parses a `name=selector` mapping to one of the built-in transforms.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, selector) = spec.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "Invalid Transform: The transform '{}' is not in the form name=glob.",
+                spec
+            )
+        })?;
+        Ok(Transformer {
+            selector: Selector::parse(selector)?,
+            transform: builtin(name)?,
+        })
+    }
+}
+
+impl EntryFilter for Transformer {
+    fn decide(&self, entry: &EntryInfo, content: Option<&[u8]>) -> Result<Decision> {
+        match content {
+            Some(content)
+                if entry.kind == EntryKind::File && self.selector.matches(entry, content) =>
+            {
+                Ok(Decision::Replace(self.transform.transform(entry, content)?))
+            }
+            _ => Ok(Decision::Keep),
+        }
+    }
+}
+
+pub const BUILTIN_TRANSFORMS: [&str; 9] = [
+    "minify-json",
+    "minify-xml",
+    "minify-js",
+    "strip-comments",
+    "eol-lf",
+    "eol-crlf",
+    "strip-debug",
+    "optimize-images",
+    "strip-image-metadata",
+];
+
+pub fn builtin(name: &str) -> Result<Box<dyn Transform>> {
+    match name {
+        "minify-json" => Ok(Box::new(minify_json)),
+        "minify-xml" => Ok(Box::new(|entry: &EntryInfo, content: &[u8]| {
+            minify_xml(content).map_err(|err| transform_error(entry, err))
+        })),
+        "minify-js" => Ok(Box::new(|entry: &EntryInfo, content: &[u8]| {
+            minify_js(content).map_err(|err| transform_error(entry, err))
+        })),
+        "strip-comments" => Ok(Box::new(strip_comments)),
+        "eol-lf" => Ok(Box::new(|_: &EntryInfo, content: &[u8]| {
+            Ok(normalize_eol(content, false))
+        })),
+        "eol-crlf" => Ok(Box::new(|_: &EntryInfo, content: &[u8]| {
+            Ok(normalize_eol(content, true))
+        })),
+        "strip-debug" => Ok(Box::new(strip_debug_sections)),
+        "optimize-images" => Ok(Box::new(optimize_images)),
+        "strip-image-metadata" => Ok(Box::new(strip_images_metadata)),
+        _ => Err(anyhow!(
+            "Unknown Transform: The transform '{}' does not exist, expected one of: {}.",
+            name,
+            BUILTIN_TRANSFORMS.join(", ")
+        ))?,
+    }
+}
+
+fn transform_error(entry: &EntryInfo, err: anyhow::Error) -> anyhow::Error {
+    anyhow!(
+        "Transform Error: The entry '{}' could not be minified ({}).",
+        entry.path.display(),
+        err
+    )
+}
+
+fn minify_json(entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+    let value: serde_json::Value = serde_json::from_slice(content).map_err(|err| {
+        anyhow!(
+            "Transform Error: The entry '{}' is not valid JSON ({}).",
+            entry.path.display(),
+            err
+        )
+    })?;
+    Ok(serde_json::to_vec(&value)?)
+}
+
+/// Drops `#` and `!` comment lines, as used by .properties and shell style
+/// configuration files.
+fn strip_comments(_: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+    let mut result = Vec::with_capacity(content.len());
+    for line in content.split_inclusive(|&byte| byte == b'\n') {
+        let first = line.iter().find(|byte| !byte.is_ascii_whitespace());
+        if matches!(first, Some(b'#' | b'!')) {
+            continue;
+        }
+        result.extend_from_slice(line);
+    }
+    Ok(result)
+}
+
+/// Converts the line endings of text content to LF or CRLF, binary content
+/// is kept as is.
+pub fn normalize_eol(content: &[u8], crlf: bool) -> Vec<u8> {
+    if classify(content) != ContentClass::Text {
+        return content.to_vec();
+    }
+    let mut result = Vec::with_capacity(content.len());
+    for line in content.split_inclusive(|&byte| byte == b'\n') {
+        let Some(line) = line.strip_suffix(b"\n") else {
+            result.extend_from_slice(line);
+            break;
+        };
+        result.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+        result.extend_from_slice(if crlf { b"\r\n" } else { b"\n" });
+    }
+    result
+}
+
+/// Parses `--normalize-eol lf|crlf[=GLOB]`, all entries by default.
+pub fn parse_normalize_eol(spec: &str) -> Result<Transformer> {
+    let (style, selector) = spec.split_once('=').unwrap_or((spec, "**"));
+    match style {
+        "lf" | "crlf" => Transformer::parse(&format!("eol-{}={}", style, selector)),
+        _ => Err(anyhow!(
+            "Invalid Line Ending: '{}' is not one of: lf, crlf.",
+            style
+        ))?,
+    }
+}
+
+/// Drops the debug sections of ELF binaries, other content is kept as is.
+fn strip_debug_sections(entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+    let stripped = strip_debug(content).map_err(|err| {
+        anyhow!(
+            "Transform Error: The debug sections of '{}' could not be stripped ({}).",
+            entry.path.display(),
+            err
+        )
+    })?;
+    Ok(stripped.unwrap_or_else(|| content.to_vec()))
+}
+
+/// Losslessly shrinks PNG images, other content is kept as is.
+fn optimize_images(entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+    optimize_image(content).map_err(|err| {
+        anyhow!(
+            "Transform Error: The image '{}' could not be optimized ({}).",
+            entry.path.display(),
+            err
+        )
+    })
+}
+
+/// Drops the comments, XMP and Photoshop metadata of JPEG images.
+fn strip_images_metadata(entry: &EntryInfo, content: &[u8]) -> Result<Vec<u8>> {
+    strip_image_metadata(content).map_err(|err| {
+        anyhow!(
+            "Transform Error: The metadata of the image '{}' could not be stripped ({}).",
+            entry.path.display(),
+            err
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(path: &str) -> EntryInfo {
+        EntryInfo {
+            path: PathBuf::from(path),
+            size: 0,
+            mtime: None,
+            kind: EntryKind::File,
+            depth: 0,
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn test_transformer() {
+        let transformer = Transformer::parse("minify-json=*.json").unwrap();
+        let content = b"{ \"a\": [1, 2] }";
+        assert_eq!(
+            transformer
+                .decide(&entry("conf/app.json"), Some(content))
+                .unwrap(),
+            Decision::Replace(b"{\"a\":[1,2]}".to_vec())
+        );
+        assert_eq!(
+            transformer
+                .decide(&entry("conf/app.txt"), Some(content))
+                .unwrap(),
+            Decision::Keep
+        );
+        assert!(Transformer::parse("unknown=*.json").is_err());
+        assert!(Transformer::parse("minify-json").is_err());
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let content = b"# comment\nkey=value\n  ! other\nname=x # not a comment\n";
+        assert_eq!(
+            strip_comments(&entry("a.properties"), content).unwrap(),
+            b"key=value\nname=x # not a comment\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_eol() {
+        assert_eq!(normalize_eol(b"a\r\nb\nc", false), b"a\nb\nc");
+        assert_eq!(normalize_eol(b"a\r\nb\n", true), b"a\r\nb\r\n");
+        assert_eq!(normalize_eol(b"\x7fELF\n", true), b"\x7fELF\n");
+
+        let transformer = parse_normalize_eol("crlf=*.bat").unwrap();
+        assert_eq!(
+            transformer
+                .decide(&entry("run.bat"), Some(b"echo\n"))
+                .unwrap(),
+            Decision::Replace(b"echo\r\n".to_vec())
+        );
+        assert!(parse_normalize_eol("cr").is_err());
+    }
+}
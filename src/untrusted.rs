@@ -0,0 +1,139 @@
+use std::{
+    path::{Component, Path},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+
+/// Resource limits and strict checks for archives submitted by external
+/// users. Any anomaly is fatal instead of being skipped or prompted for.
+///
+/// Clones share the entry and size counters, so the limits apply to the
+/// whole scrub including nested archives.
+#[derive(Debug, Clone)]
+pub struct Untrusted {
+    pub max_entries: u64,
+    pub max_entry_size: u64,
+    pub max_total_size: u64,
+    pub max_depth: usize,
+    /// Largest accepted uncompressed to compressed size ratio of an entry.
+    pub max_ratio: u64,
+    entries: Arc<AtomicU64>,
+    total_size: Arc<AtomicU64>,
+}
+
+impl Default for Untrusted {
+    fn default() -> Self {
+        Untrusted {
+            max_entries: 100_000,
+            max_entry_size: 1 << 30,
+            max_total_size: 10 << 30,
+            max_depth: 8,
+            max_ratio: 100,
+            entries: Arc::default(),
+            total_size: Arc::default(),
+        }
+    }
+}
+
+impl Untrusted {
+    /// Rejects absolute paths, drive prefixes and `..` components.
+    pub fn check_path(&self, path: &str) -> Result<()> {
+        let escapes = path.starts_with(['/', '\\'])
+            || path.contains('\0')
+            || path.as_bytes().get(1) == Some(&b':')
+            || Path::new(path)
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+            || path.split('\\').any(|part| part == "..");
+        if escapes {
+            Err(anyhow!(
+                "Untrusted Archive: The entry '{}' has an absolute or parent path.",
+                path
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Accounts for an entry of `size` uncompressed bytes.
+    pub fn check_entry(&self, path: &str, size: u64) -> Result<()> {
+        self.check_path(path)?;
+        let entries = self.entries.fetch_add(1, Ordering::Relaxed) + 1;
+        if entries > self.max_entries {
+            Err(anyhow!(
+                "Untrusted Archive: More than {} entries.",
+                self.max_entries
+            ))?;
+        }
+        if size > self.max_entry_size {
+            Err(anyhow!(
+                "Untrusted Archive: The entry '{}' exceeds {} bytes.",
+                path,
+                self.max_entry_size
+            ))?;
+        }
+        let total_size = self.total_size.fetch_add(size, Ordering::Relaxed) + size;
+        if total_size > self.max_total_size {
+            Err(anyhow!(
+                "Untrusted Archive: The content exceeds {} bytes.",
+                self.max_total_size
+            ))?;
+        }
+        Ok(())
+    }
+
+    pub fn check_ratio(&self, path: &str, size: u64, compressed_size: u64) -> Result<()> {
+        if size > compressed_size.max(1).saturating_mul(self.max_ratio) {
+            Err(anyhow!(
+                "Untrusted Archive: The entry '{}' has a compression ratio above {}.",
+                path,
+                self.max_ratio
+            ))?;
+        }
+        Ok(())
+    }
+
+    pub fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth > self.max_depth {
+            Err(anyhow!(
+                "Untrusted Archive: Archives are nested deeper than {} levels.",
+                self.max_depth
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_path() {
+        let untrusted = Untrusted::default();
+        assert!(untrusted.check_path("a/b.txt").is_ok());
+        assert!(untrusted.check_path("./a/b.txt").is_ok());
+        assert!(untrusted.check_path("/etc/passwd").is_err());
+        assert!(untrusted.check_path("a/../../b").is_err());
+        assert!(untrusted.check_path("..\\b").is_err());
+        assert!(untrusted.check_path("C:/b").is_err());
+    }
+
+    #[test]
+    fn test_check_entry() {
+        let untrusted = Untrusted {
+            max_entries: 2,
+            max_total_size: 100,
+            ..Default::default()
+        };
+        let shared = untrusted.clone();
+        assert!(untrusted.check_entry("a", 60).is_ok());
+        assert!(shared.check_entry("b", 60).is_err());
+        assert!(untrusted.check_entry("c", 0).is_err());
+        assert!(untrusted.check_ratio("d", 1000, 5).is_err());
+        assert!(untrusted.check_ratio("d", 100, 5).is_ok());
+    }
+}
@@ -2,12 +2,18 @@ use std::{
     fs::{create_dir_all, OpenOptions},
     io::Write,
     path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, Result};
 use csv::ReaderBuilder;
-use indicatif::ProgressBar;
-use inquire::Confirm;
+use inquire::{Confirm, Password, PasswordDisplayMode};
+use sha2::{Digest, Sha256};
+
+use crate::locale::messages;
+use crate::progress::Progress;
+use crate::sink::{AtomicFile, OutputOptions};
 
 pub fn to_bytes(file_path: &str) -> Result<Vec<u8>> {
     let bytes = std::fs::read(file_path)?;
@@ -43,18 +49,123 @@ pub fn parse_compression(compression_level: u32) -> Result<u32> {
     }
 }
 
-pub fn prompt_csv(result: &[PathBuf]) -> Result<()> {
-    let ans = Confirm::new("Is this correct?")
+/// Parses a level or `auto`, which is `None`.
+pub fn parse_compression_setting(compression: &str) -> Result<Option<u32>> {
+    if compression == "auto" {
+        return Ok(None);
+    }
+    let level = compression.parse().map_err(|_| {
+        anyhow!("Invalid Compression Level: Please choose a compression between 0 and 9, or auto.")
+    })?;
+    Ok(Some(parse_compression(level)?))
+}
+
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| anyhow!("Invalid Mode: '{}' is not an octal permission mode.", mode))
+}
+
+pub fn parse_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let multiplier: usize = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        "T" | "TB" => 1 << 40,
+        _ => Err(anyhow!(
+            "Invalid Size: Unknown unit '{}', use one of B, KB, MB, GB or TB.",
+            unit
+        ))?,
+    };
+    let number: usize = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid Size: '{}' is not a valid size.", size))?;
+    number
+        .checked_mul(multiplier)
+        .filter(|size| *size > 0)
+        .ok_or_else(|| anyhow!("Invalid Size: '{}' is out of range.", size))
+}
+
+/// Parses a count with an optional decimal `k` or `m` suffix, e.g. `100k`.
+pub fn parse_count(count: &str) -> Result<usize> {
+    let count = count.trim();
+    let (number, multiplier) = match count.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&count[..i], 1_000),
+        Some((i, 'm' | 'M')) => (&count[..i], 1_000_000),
+        _ => (count, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|count| *count > 0)
+        .ok_or_else(|| anyhow!("Invalid Count: '{}' is not a valid count.", count))
+}
+
+/// Parses a duration in seconds with an optional `ms`, `s`, `m` or `h`
+/// suffix, e.g. `90s` or `30m`.
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let duration = duration.trim();
+    let digits = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(digits);
+    let invalid = || anyhow!("Invalid Duration: '{}' is not a valid duration.", duration);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.checked_mul(60).ok_or_else(invalid)?),
+        "h" => Duration::from_secs(number.checked_mul(3600).ok_or_else(invalid)?),
+        _ => Err(invalid())?,
+    };
+    if duration.is_zero() {
+        Err(invalid())?;
+    }
+    Ok(duration)
+}
+
+/// A yes/no question defaulting to no, answered in the language of the
+/// prompts.
+fn confirm(message: &str) -> Confirm<'_> {
+    Confirm::new(message)
         .with_default(false)
-        .with_help_message(
-            format!(
-                "File contains {} records, first value:\n{}",
-                result.len(),
-                result.first().unwrap().display(),
-            )
-            .as_str(),
-        )
-        .prompt();
+        .with_parser(&parse_answer)
+        .with_formatter(&format_answer)
+        .with_default_value_formatter(&format_default)
+        .with_error_message(&messages().invalid_answer)
+}
+
+fn parse_answer(answer: &str) -> Result<bool, ()> {
+    messages().parse_answer(answer).ok_or(())
+}
+
+fn format_answer(answer: bool) -> String {
+    let messages = messages();
+    if answer {
+        messages.answered_yes.clone()
+    } else {
+        messages.answered_no.clone()
+    }
+}
+
+/// Questions always default to no.
+fn format_default(_: bool) -> String {
+    messages().default_no.clone()
+}
+
+/// Shows `summary` on stderr, where it stays after the answer, and asks to
+/// confirm it.
+pub fn prompt_summary(summary: &str) -> Result<()> {
+    eprint!("{}", summary);
+    let ans = confirm(&messages().confirm_filter).prompt();
 
     match ans {
         Ok(true) => Ok(()),
@@ -63,12 +174,30 @@ pub fn prompt_csv(result: &[PathBuf]) -> Result<()> {
     }
 }
 
-pub fn prompt_error(progress_bar: &ProgressBar) -> Result<()> {
+/// Asks for a password without echoing it, twice if `confirm` is set.
+pub fn prompt_password(message: &str, confirm: bool) -> Result<String> {
+    let messages = messages();
+    let mut prompt = Password::new(message)
+        .with_display_mode(PasswordDisplayMode::Hidden)
+        .with_custom_confirmation_message(&messages.password_confirmation)
+        .with_custom_confirmation_error_message(&messages.password_mismatch);
+    if !confirm {
+        prompt = prompt.without_confirmation();
+    }
+    Ok(prompt.prompt()?)
+}
+
+/// Nested archives processed in parallel may fail at the same time, their
+/// prompts are asked one after the other.
+static PROMPT: Mutex<()> = Mutex::new(());
+
+pub fn prompt_error(progress: &dyn Progress) -> Result<()> {
+    let _prompt = PROMPT.lock().unwrap_or_else(|err| err.into_inner());
     let mut ans = Ok(false);
-    progress_bar.suspend(|| {
-        ans = Confirm::new("Do you want to continue?")
-            .with_default(false)
-            .with_help_message("Failed to process tar entry, this data will be skipped")
+    progress.suspend(&mut || {
+        let messages = messages();
+        ans = confirm(&messages.continue_on_error)
+            .with_help_message(&messages.continue_on_error_help)
             .prompt();
     });
     match ans {
@@ -88,24 +217,168 @@ pub fn infer_input_file(file_bytes: &[u8]) -> Result<String> {
     ))?
 }
 
-pub fn to_file(dst: &str, payload: Vec<u8>) -> Result<()> {
-    let mut out = String::from("out/");
+/// Path `dst` is written to in `dir`, creating the directory.
+pub fn output_path(dir: &str, dst: &str) -> Result<String> {
+    let out = format!("{}/", dir.trim_end_matches('/'));
     if !Path::new(out.as_str()).exists() {
         create_dir_all(out.as_str())?;
     }
-    let out_path = Path::new(dst);
-    out.push_str(out_path.file_name().unwrap().to_str().unwrap());
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(out)?;
+    Ok(output_file(dir, dst))
+}
+
+/// Path `dst` is written to in `dir`.
+pub fn output_file(dir: &str, dst: &str) -> String {
+    let mut out = format!("{}/", dir.trim_end_matches('/'));
+    out.push_str(Path::new(dst).file_name().unwrap().to_str().unwrap());
+    out
+}
+
+pub fn to_file(dst: &str, payload: Vec<u8>, options: &OutputOptions) -> Result<()> {
+    write_file(&output_path(&options.dir, dst)?, &payload, options)
+}
+
+/// Writes the volumes of a split zip, returns the written paths.
+pub fn to_zip_volumes(
+    dst: &str,
+    volumes: Vec<Vec<u8>>,
+    options: &OutputOptions,
+) -> Result<Vec<String>> {
+    let last = volumes.len() - 1;
+    let mut written = Vec::with_capacity(volumes.len());
+    for (disk, volume) in volumes.into_iter().enumerate() {
+        let part = if disk == last {
+            dst.to_string()
+        } else {
+            let part = Path::new(dst).with_extension(format!("z{:02}", disk + 1));
+            part.to_str().unwrap().to_string()
+        };
+        to_file(&part, volume, options)?;
+        written.push(output_path(&options.dir, &part)?);
+    }
+    Ok(written)
+}
+
+/// Writes numbered parts and their sha256 manifest, returns the written paths.
+pub fn to_split_files(
+    dst: &str,
+    payload: Vec<u8>,
+    split_size: usize,
+    options: &OutputOptions,
+) -> Result<Vec<String>> {
+    let file_name = Path::new(dst).file_name().unwrap().to_str().unwrap();
+    let mut manifest = String::new();
+    let mut written = Vec::new();
+    for (index, chunk) in payload.chunks(split_size).enumerate() {
+        let part = format!("{}.{:03}", file_name, index);
+        manifest.push_str(&format!("{}  {}\n", sha256_hex(chunk), part));
+        to_file(&part, chunk.to_vec(), options)?;
+        written.push(output_path(&options.dir, &part)?);
+    }
+    let manifest_name = format!("{}.sha256", file_name);
+    to_file(&manifest_name, manifest.into_bytes(), options)?;
+    written.push(output_path(&options.dir, &manifest_name)?);
+    Ok(written)
+}
+
+/// Parses a timestamp given as seconds since the epoch or as a UTC date
+/// (`2024-01-31` or `2024-01-31T12:00:00Z`).
+pub fn parse_timestamp(timestamp: &str) -> Result<SystemTime> {
+    let invalid = || {
+        anyhow!(
+            "Invalid Timestamp: '{}' is neither seconds since the epoch nor a YYYY-MM-DD[THH:MM:SS] date.",
+            timestamp
+        )
+    };
+    let timestamp = timestamp.trim();
+    if let Ok(seconds) = timestamp.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+    let timestamp = timestamp.trim_end_matches('Z');
+    let (date, time) = timestamp
+        .split_once(['T', ' '])
+        .unwrap_or((timestamp, "00:00:00"));
+    let fields = |value: &str, separator: char| -> Option<Vec<i64>> {
+        value
+            .split(separator)
+            .map(|field| field.parse().ok())
+            .collect()
+    };
+    let (Some(&[year, month, day]), Some(&[hour, minute, second])) =
+        (fields(date, '-').as_deref(), fields(time, ':').as_deref())
+    else {
+        Err(invalid())?
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        Err(invalid())?;
+    }
+    let seconds = unix_time(year, month, day, hour, minute, second);
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds.try_into().map_err(|_| invalid())?))
+}
 
-    file.write_all(&payload)?;
+/// Seconds since the epoch of a UTC date and time.
+pub(crate) fn unix_time(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+) -> i64 {
+    // days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    days * 86400 + hour * 3600 + minute * 60 + second
+}
 
+/// UTC date and time of `seconds` since the epoch, the inverse of
+/// [`unix_time`].
+pub(crate) fn civil_time(seconds: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let days = seconds.div_euclid(86400) + 719468;
+    let time = seconds.rem_euclid(86400);
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+pub fn set_mtime(path: &str, mtime: SystemTime) -> Result<()> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_modified(mtime)?;
     Ok(())
 }
 
+pub fn sha256_hex(payload: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(payload))
+}
+
+fn write_file(out: &str, payload: &[u8], options: &OutputOptions) -> Result<()> {
+    let mut file = AtomicFile::create(out, options)?;
+
+    file.write_all(payload)?;
+
+    file.commit()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,6 +388,73 @@ mod tests {
     fn test_parse_compression_level() {
         assert_eq!(parse_compression(5).unwrap(), 5);
         assert!(parse_compression(42).is_err());
+        assert_eq!(parse_compression_setting("5").unwrap(), Some(5));
+        assert_eq!(parse_compression_setting("auto").unwrap(), None);
+        assert!(parse_compression_setting("42").is_err());
+        assert!(parse_compression_setting("fast").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64KB").unwrap(), 64 << 10);
+        assert_eq!(parse_size("2GB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("10m").unwrap(), 10 << 20);
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("12XB").is_err());
+        assert!(parse_size("GB").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let seconds = |timestamp: &str| {
+            parse_timestamp(timestamp)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(seconds("1678176300"), 1678176300);
+        assert_eq!(seconds("2023-03-07T08:05:00Z"), 1678176300);
+        assert_eq!(seconds("2023-03-07"), 1678147200);
+        assert!(parse_timestamp("2023-13-07").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+        assert_eq!(civil_time(1678176300), (2023, 3, 7, 8, 5, 0));
+        assert_eq!(civil_time(951782400), (2000, 2, 29, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("250").unwrap(), 250);
+        assert_eq!(parse_count("100k").unwrap(), 100_000);
+        assert_eq!(parse_count("2M").unwrap(), 2_000_000);
+        assert!(parse_count("0").is_err());
+        assert!(parse_count("1x").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1d").is_err());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("0640").unwrap(), 0o640);
+        assert_eq!(parse_mode("600").unwrap(), 0o600);
+        assert!(parse_mode("0999").is_err());
+        assert!(parse_mode("77777").is_err());
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
     }
 
     #[test]
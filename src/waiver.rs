@@ -0,0 +1,143 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher};
+
+use crate::policy::Violation;
+use expurgator::internal::parse_timestamp;
+
+/// Ignore file read by `check` from the working directory by default.
+pub const IGNORE_FILE: &str = ".expurgatorignore";
+
+/// An accepted policy finding: violations of `rule` at paths matching
+/// `path` pass until the end of the `expires` day.
+#[derive(Debug, Clone)]
+pub struct Waiver {
+    pub rule: String,
+    pub path: GlobMatcher,
+    pub expires: String,
+    expires_at: SystemTime,
+}
+
+/// What the waivers say about a violation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Waived {
+    No,
+    Yes,
+    /// A waiver matches but expired on the given date.
+    Expired(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Waivers {
+    waivers: Vec<Waiver>,
+}
+
+impl Waivers {
+    /// Parses lines of `<rule> <path glob> <YYYY-MM-DD>`, `#` starts a
+    /// comment.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut waivers = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| {
+                anyhow!(
+                    "Invalid Waiver: Line {} is not in the form '<rule> <path> <expires>' ({}).",
+                    number + 1,
+                    reason
+                )
+            };
+            let &[rule, path, expires] = line.split_whitespace().collect::<Vec<_>>().as_slice()
+            else {
+                Err(invalid("expected three fields".to_string()))?
+            };
+            let path = Glob::new(path)
+                .map_err(|err| invalid(err.to_string()))?
+                .compile_matcher();
+            let expires_at = parse_timestamp(expires).map_err(|err| invalid(err.to_string()))?
+                + Duration::from_secs(86400);
+            waivers.push(Waiver {
+                rule: rule.to_string(),
+                path,
+                expires: expires.to_string(),
+                expires_at,
+            });
+        }
+        Ok(Waivers { waivers })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Waivers::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Looks up `violation` as of `now`, an unexpired waiver wins over
+    /// expired ones.
+    pub fn waived(&self, violation: &Violation, now: SystemTime) -> Waived {
+        let mut waived = Waived::No;
+        for waiver in &self.waivers {
+            if waiver.rule != violation.rule || !waiver.path.is_match(&violation.path) {
+                continue;
+            }
+            if now < waiver.expires_at {
+                return Waived::Yes;
+            }
+            waived = Waived::Expired(waiver.expires.clone());
+        }
+        waived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::policy::Severity;
+
+    fn violation(rule: &str, path: &str) -> Violation {
+        Violation {
+            path: PathBuf::from(path),
+            rule: rule.to_string(),
+            severity: Severity::Error,
+            message: String::new(),
+            removed: false,
+        }
+    }
+
+    #[test]
+    fn test_waivers() {
+        let waivers = Waivers::parse(
+            "# rule     path           expires\n\
+             images     **/*.jpg       2024-01-31\n\
+             \n\
+             secrets    app/test.pem   2024-06-30 # approved test key\n",
+        )
+        .unwrap();
+        let january = UNIX_EPOCH + Duration::from_secs(1706659200); // 2024-01-31
+        let march = UNIX_EPOCH + Duration::from_secs(1709251200); // 2024-03-01
+
+        assert_eq!(
+            waivers.waived(&violation("images", "a/b.jpg"), january),
+            Waived::Yes
+        );
+        assert_eq!(
+            waivers.waived(&violation("images", "a/b.jpg"), march),
+            Waived::Expired("2024-01-31".to_string())
+        );
+        assert_eq!(
+            waivers.waived(&violation("secrets", "app/test.pem"), march),
+            Waived::Yes
+        );
+        assert_eq!(
+            waivers.waived(&violation("secrets", "app/prod.pem"), march),
+            Waived::No
+        );
+        assert!(Waivers::parse("images **/*.jpg\n").is_err());
+        assert!(Waivers::parse("images **/*.jpg soon\n").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{borrow::Cow, fmt};
+
+use crate::reader::EntryInfo;
+use crate::util::sha256_hex;
+
+/// Longest path most Windows tools handle without the `\\?\` prefix.
+pub const MAX_PATH: usize = 260;
+
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const INVALID_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// Reason an entry path cannot be extracted as is on Windows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowsIssue {
+    TooLong(usize),
+    ReservedName(String),
+    InvalidCharacter(char),
+    TrailingDotOrSpace(String),
+}
+
+impl fmt::Display for WindowsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowsIssue::TooLong(length) => {
+                write!(f, "path of {} characters exceeds {}", length, MAX_PATH)
+            }
+            WindowsIssue::ReservedName(name) => write!(f, "reserved name '{}'", name),
+            WindowsIssue::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            WindowsIssue::TrailingDotOrSpace(name) => {
+                write!(f, "name '{}' ends with a dot or space", name)
+            }
+        }
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    // `nul.txt` is as reserved as `NUL`
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+fn is_invalid_char(c: char) -> bool {
+    INVALID_CHARS.contains(&c) || c.is_control()
+}
+
+fn is_special(name: &str) -> bool {
+    name.is_empty() || name == "." || name == ".."
+}
+
+/// Lists the problems of a `/` separated entry path.
+pub fn check_path(path: &str) -> Vec<WindowsIssue> {
+    let mut issues = Vec::new();
+    let length = path.chars().count();
+    if length >= MAX_PATH {
+        issues.push(WindowsIssue::TooLong(length));
+    }
+    for name in path.split('/').filter(|name| !is_special(name)) {
+        if is_reserved(name) {
+            issues.push(WindowsIssue::ReservedName(name.to_string()));
+        }
+        if let Some(c) = name.chars().find(|&c| is_invalid_char(c)) {
+            issues.push(WindowsIssue::InvalidCharacter(c));
+        }
+        if name.ends_with(['.', ' ']) {
+            issues.push(WindowsIssue::TrailingDotOrSpace(name.to_string()));
+        }
+    }
+    issues
+}
+
+/// Entries of a listing with at least one problem.
+pub fn check_entries(entries: &[EntryInfo]) -> Vec<(&EntryInfo, Vec<WindowsIssue>)> {
+    entries
+        .iter()
+        .map(|entry| (entry, check_path(&entry.path.to_string_lossy())))
+        .filter(|(_, issues)| !issues.is_empty())
+        .collect()
+}
+
+fn safe_name(name: &str) -> String {
+    let mut safe: String = name
+        .chars()
+        .map(|c| if is_invalid_char(c) { '_' } else { c })
+        .collect();
+    let trimmed = safe.trim_end_matches(['.', ' ']).len();
+    if trimmed < safe.len() {
+        let trailing = safe.len() - trimmed;
+        safe.truncate(trimmed);
+        safe.push_str(&"_".repeat(trailing));
+    }
+    if is_reserved(&safe) {
+        safe.insert(0, '_');
+    }
+    safe
+}
+
+/// Shortens the file name, keeping its extension and making it unique with
+/// a hash of the original path. Paths with overly long directories are left
+/// as they are.
+fn shorten(path: &str, original: &str) -> Option<String> {
+    let (dir, name) = match path.trim_end_matches('/').rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), path.trim_end_matches('/')),
+    };
+    let suffix = &path[dir.len() + name.len()..];
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.len() <= 16 => {
+            (stem, format!(".{}", extension))
+        }
+        _ => (name, String::new()),
+    };
+    let hash = &sha256_hex(original.as_bytes())[..8];
+    let budget = (MAX_PATH - 1)
+        .checked_sub(dir.chars().count() + suffix.len() + extension.len() + hash.len() + 1)?;
+    if budget == 0 {
+        return None;
+    }
+    let stem: String = stem.chars().take(budget).collect();
+    Some(format!("{}{}~{}{}{}", dir, stem, hash, extension, suffix))
+}
+
+/// Rewrites a `/` separated entry path so it extracts on Windows: invalid
+/// characters and trailing dots or spaces become `_`, reserved names get a
+/// `_` prefix and overly long file names are shortened.
+pub fn safe_path(path: &str) -> Cow<'_, str> {
+    if check_path(path).is_empty() {
+        return Cow::Borrowed(path);
+    }
+    let safe = path
+        .split('/')
+        .map(|name| {
+            if is_special(name) {
+                name.to_string()
+            } else {
+                safe_name(name)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if safe.chars().count() >= MAX_PATH {
+        if let Some(short) = shorten(&safe, path) {
+            return Cow::Owned(short);
+        }
+    }
+    Cow::Owned(safe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_path() {
+        assert!(check_path("docs/readme.txt").is_empty());
+        assert_eq!(
+            check_path("docs/nul.txt"),
+            vec![WindowsIssue::ReservedName("nul.txt".to_string())]
+        );
+        assert_eq!(
+            check_path("a:b/c."),
+            vec![
+                WindowsIssue::InvalidCharacter(':'),
+                WindowsIssue::TrailingDotOrSpace("c.".to_string())
+            ]
+        );
+        assert_eq!(
+            check_path(&"a".repeat(300)),
+            vec![WindowsIssue::TooLong(300)]
+        );
+    }
+
+    #[test]
+    fn test_safe_path() {
+        assert_eq!(safe_path("docs/readme.txt"), "docs/readme.txt");
+        assert_eq!(safe_path("CON/aux.h"), "_CON/_aux.h");
+        assert_eq!(safe_path("what?/notes. "), "what_/notes__");
+        assert_eq!(safe_path("dir/"), "dir/");
+
+        let long = format!("dir/{}.txt", "a".repeat(300));
+        let safe = safe_path(&long);
+        assert_eq!(safe.chars().count(), MAX_PATH - 1);
+        assert!(safe.starts_with("dir/aaa"));
+        assert!(safe.ends_with(".txt"));
+        assert!(check_path(&safe).is_empty());
+    }
+}
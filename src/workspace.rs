@@ -0,0 +1,269 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Seek,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use tempfile::NamedTempFile;
+
+use crate::flock::try_lock;
+use crate::list::format_size;
+use crate::memory::{MemoryLimit, Reservation};
+
+/// Prefix of the workspace directories in the root.
+const PREFIX: &str = "expurgator-ws-";
+/// File held open, and on Unix locked, while the workspace is in use.
+const LOCK_FILE: &str = ".lock";
+
+/// Directory for the temporary files of a run: nested archives spooled to
+/// disk, downloads being staged, generated data. Each run has its own
+/// directory under the root, removed when the workspace is dropped. Runs
+/// that crashed or were killed leave theirs behind, the next run removes
+/// them: a workspace whose lock is not held has no owner.
+///
+/// Clones share the directory and its size cap.
+#[derive(Debug, Clone)]
+pub struct Workspace(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    budget: Option<MemoryLimit>,
+    /// Reservations of the stored files, which live as long as the workspace.
+    stored: Mutex<Vec<Reservation>>,
+    _lock: File,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A spooled file, accounted for until dropped.
+#[derive(Debug)]
+pub struct Spool {
+    pub file: File,
+    pub len: u64,
+    _reservation: Option<Reservation>,
+}
+
+impl Spool {
+    /// The written `file`, rewound for reading, outside of any workspace.
+    pub fn from_file(mut file: File) -> Result<Self> {
+        let len = file.stream_position()?;
+        file.rewind()?;
+        Ok(Spool {
+            file,
+            len,
+            _reservation: None,
+        })
+    }
+}
+
+impl Workspace {
+    /// Creates a workspace under `root`, holding at most `max_size` bytes,
+    /// after removing the workspaces left behind by dead runs.
+    pub fn create(root: &Path, max_size: Option<u64>) -> Result<Self> {
+        std::fs::create_dir_all(root).map_err(|err| {
+            anyhow!(
+                "Invalid Workspace: Cannot create '{}' ({}).",
+                root.display(),
+                err
+            )
+        })?;
+        clean_stale(root);
+        let dir = tempfile::Builder::new().prefix(PREFIX).tempdir_in(root)?;
+        // removed by the workspace, or by the janitor after a crash
+        let dir = std::mem::ManuallyDrop::new(dir).path().to_path_buf();
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        // nobody else knows the new directory yet
+        try_lock(&lock)?;
+        Ok(Workspace(Arc::new(Inner {
+            dir,
+            budget: max_size.map(MemoryLimit::new),
+            stored: Mutex::default(),
+            _lock: lock,
+        })))
+    }
+
+    fn reserve(&self, what: &str, bytes: u64) -> Result<Option<Reservation>> {
+        let Some(budget) = &self.0.budget else {
+            return Ok(None);
+        };
+        match budget.try_reserve(bytes) {
+            Some(reservation) => Ok(Some(reservation)),
+            None => Err(anyhow!(
+                "Workspace Full: {} needs {}, the workspace in '{}' is capped at {} (--temp-max-size).",
+                what,
+                format_size(bytes),
+                self.0.dir.display(),
+                format_size(budget.max_memory)
+            ))?,
+        }
+    }
+
+    /// A new file in the workspace, accounted for once written by
+    /// [`Workspace::finish`] or [`Workspace::store`].
+    pub fn spool(&self) -> Result<NamedTempFile> {
+        Ok(NamedTempFile::new_in(&self.0.dir)?)
+    }
+
+    /// Accounts for the written `file` of `what`, rewound for reading and
+    /// removed when closed.
+    pub fn finish(&self, what: &str, file: NamedTempFile) -> Result<Spool> {
+        let mut file = file.into_file();
+        let len = file.stream_position()?;
+        let reservation = self.reserve(what, len)?;
+        file.rewind()?;
+        Ok(Spool {
+            file,
+            len,
+            _reservation: reservation,
+        })
+    }
+
+    /// The file stored under `key`, if any.
+    pub fn get(&self, key: &[u8; 32]) -> Result<Option<Spool>> {
+        let path = self.key_path(key);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Some(Spool {
+            file,
+            len,
+            _reservation: None,
+        }))
+    }
+
+    /// Stores the written `file` of `what` under `key`, the address of its
+    /// content, until the workspace is removed.
+    pub fn store(&self, what: &str, key: &[u8; 32], mut file: NamedTempFile) -> Result<Spool> {
+        let len = file.stream_position()?;
+        if let Some(reservation) = self.reserve(what, len)? {
+            self.0.stored.lock().unwrap().push(reservation);
+        }
+        // copies written by concurrent workers are the same content
+        let mut file = file.persist(self.key_path(key)).map_err(|err| err.error)?;
+        file.rewind()?;
+        Ok(Spool {
+            file,
+            len,
+            _reservation: None,
+        })
+    }
+
+    fn key_path(&self, key: &[u8; 32]) -> PathBuf {
+        let name: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        self.0.dir.join(name)
+    }
+}
+
+/// Removes the workspaces in `root` whose lock is not held, returns how
+/// many. Workspaces in use, and anything else in the root, are kept.
+pub fn clean_stale(root: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let owned = entry.file_name().to_string_lossy().starts_with(PREFIX);
+        if owned
+            && path.is_dir()
+            && is_stale(&path.join(LOCK_FILE))
+            && std::fs::remove_dir_all(&path).is_ok()
+        {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Whether nobody holds the lock of a workspace, taking it succeeds then. A
+/// workspace being created has no lock file yet.
+#[cfg(unix)]
+fn is_stale(lock: &Path) -> bool {
+    File::open(lock)
+        .ok()
+        .and_then(|file| try_lock(&file).ok())
+        .unwrap_or(false)
+}
+
+/// Windows does not delete a file its owner holds open.
+#[cfg(not(unix))]
+fn is_stale(lock: &Path) -> bool {
+    std::fs::remove_file(lock).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_workspace() {
+        let root = assert_fs::TempDir::new().unwrap();
+        let workspace = Workspace::create(root.path(), Some(10)).unwrap();
+        let dir = workspace.0.dir.to_path_buf();
+        assert!(dir.starts_with(root.path()));
+
+        let mut file = workspace.spool().unwrap();
+        file.write_all(b"123456").unwrap();
+        let mut spool = workspace.finish("a.zip", file).unwrap();
+        assert_eq!(spool.len, 6);
+        let mut content = String::new();
+        spool.file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "123456");
+
+        // over the cap while the first spool is held
+        let mut file = workspace.spool().unwrap();
+        file.write_all(b"123456").unwrap();
+        let err = workspace.finish("b.zip", file).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Workspace Full: b.zip needs 6 B"));
+        drop(spool);
+
+        let key = [7; 32];
+        assert!(workspace.get(&key).unwrap().is_none());
+        let mut file = workspace.spool().unwrap();
+        file.write_all(b"stored").unwrap();
+        workspace.store("c.zip", &key, file).unwrap();
+        let mut stored = workspace.get(&key).unwrap().unwrap();
+        let mut content = String::new();
+        stored.file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "stored");
+
+        // in use, kept by the janitor
+        assert_eq!(clean_stale(root.path()), 0);
+        drop(workspace);
+        assert!(!dir.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clean_stale() {
+        let root = assert_fs::TempDir::new().unwrap();
+        // left behind by a run that was killed
+        let stale = root.path().join(format!("{}crashed", PREFIX));
+        std::fs::create_dir(&stale).unwrap();
+        std::fs::write(stale.join(LOCK_FILE), b"").unwrap();
+        std::fs::write(stale.join("spool"), b"data").unwrap();
+        let other = root.path().join("other");
+        std::fs::create_dir(&other).unwrap();
+
+        let workspace = Workspace::create(root.path(), None).unwrap();
+        assert!(!stale.exists());
+        assert!(other.exists());
+        assert!(workspace.0.dir.exists());
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    ffi::c_void,
+    io::{self, Write},
+};
+
+use anyhow::{anyhow, Result};
+use lzma_sys::{
+    lzma_filter, lzma_match_finder, lzma_mode, lzma_options_lzma, lzma_stream, lzma_vli,
+};
+use xz2::stream::{Check, Stream};
+use xz2::write::XzEncoder;
+
+use crate::archive::WriteEncoder;
+use crate::util::parse_size;
+
+/// liblzma's flag making a preset slower for a slightly better ratio.
+const PRESET_EXTREME: u32 = 1 << 31;
+
+/// Id of the delta filter, lzma-sys does not declare it.
+const LZMA_FILTER_DELTA: lzma_vli = 0x03;
+/// The only type of delta filter, byte-wise.
+const LZMA_DELTA_TYPE_BYTE: u32 = 0;
+
+/// `lzma_options_delta` of liblzma.
+#[repr(C)]
+struct DeltaOptions {
+    kind: u32,
+    dist: u32,
+    reserved_int: [u32; 8],
+    reserved_ptr: [*mut c_void; 2],
+}
+
+/// Filters of a chain, in the order the data goes through them.
+pub const XZ_FILTERS: [&str; 8] = [
+    "delta", "x86", "arm", "armthumb", "powerpc", "ia64", "sparc", "lzma2",
+];
+
+#[derive(Clone)]
+enum XzFilter {
+    /// Distance in bytes of the values subtracted.
+    Delta(u32),
+    X86,
+    Arm,
+    ArmThumb,
+    PowerPc,
+    Ia64,
+    Sparc,
+    Lzma2(Lzma2Options),
+}
+
+/// Options of the lzma2 filter, unset ones come from the preset.
+#[derive(Clone, Default)]
+struct Lzma2Options {
+    preset: Option<u32>,
+    dict: Option<u32>,
+    lc: Option<u32>,
+    lp: Option<u32>,
+    pb: Option<u32>,
+    nice: Option<u32>,
+    depth: Option<u32>,
+    mf: Option<lzma_match_finder>,
+    mode: Option<lzma_mode>,
+}
+
+/// Settings of xz outputs beyond the compression level.
+#[derive(Clone, Default)]
+pub struct XzOptions {
+    /// Use the extreme variant of the preset.
+    pub extreme: bool,
+    /// Custom filter chain, the preset's single lzma2 filter if empty.
+    filters: Vec<XzFilter>,
+}
+
+impl XzOptions {
+    /// Parses a filter chain such as `delta:dist=4,lzma2:dict=64M`: filters
+    /// separated by commas, each followed by its `:key=value` options. The
+    /// chain ends with lzma2 and holds at most four filters.
+    pub fn new(extreme: bool, chain: Option<&str>) -> Result<Self> {
+        let Some(chain) = chain else {
+            return Ok(XzOptions {
+                extreme,
+                filters: Vec::new(),
+            });
+        };
+        let filters = chain
+            .split(',')
+            .map(parse_filter)
+            .collect::<Result<Vec<_>>>()?;
+        if filters.len() > 4 {
+            Err(anyhow!(
+                "Invalid Filter Chain: '{}' has more than four filters.",
+                chain
+            ))?;
+        }
+        let lzma2 = filters
+            .iter()
+            .position(|filter| matches!(filter, XzFilter::Lzma2(_)));
+        if lzma2 != Some(filters.len() - 1) {
+            Err(anyhow!(
+                "Invalid Filter Chain: '{}' must end with lzma2, and only with it.",
+                chain
+            ))?;
+        }
+        Ok(XzOptions { extreme, filters })
+    }
+
+    /// Whether the output is written with the plain preset of the level.
+    pub fn is_default(&self) -> bool {
+        !self.extreme && self.filters.is_empty()
+    }
+
+    /// xz encoder for `level` writing into `writer`.
+    pub(crate) fn encoder<'a, W: Write + 'a>(
+        &self,
+        writer: W,
+        level: u32,
+    ) -> Result<Box<dyn WriteEncoder<W> + 'a>> {
+        let preset = if self.extreme {
+            level | PRESET_EXTREME
+        } else {
+            level
+        };
+        if self.filters.is_empty() {
+            let stream = Stream::new_easy_encoder(preset, Check::Crc64)?;
+            return Ok(Box::new(XzEncoder::new_stream(writer, stream)));
+        }
+        Ok(Box::new(ChainEncoder::new(writer, &self.filters, preset)?))
+    }
+}
+
+/// xz encoder of a custom filter chain. It drives liblzma directly, as xz2
+/// cannot build chains with the delta filter.
+struct ChainEncoder<W: Write> {
+    stream: lzma_stream,
+    writer: Option<W>,
+    buffer: Vec<u8>,
+}
+
+// the stream is only used through `&mut self`
+unsafe impl<W: Write + Send> Send for ChainEncoder<W> {}
+
+impl<W: Write> ChainEncoder<W> {
+    fn new(writer: W, filters: &[XzFilter], preset: u32) -> Result<Self> {
+        // liblzma copies the options of the chain when the encoder is set up
+        let mut delta = Vec::new();
+        let mut lzma2 = Vec::new();
+        for filter in filters {
+            match filter {
+                XzFilter::Delta(dist) => delta.push(DeltaOptions {
+                    kind: LZMA_DELTA_TYPE_BYTE,
+                    dist: *dist,
+                    reserved_int: [0; 8],
+                    reserved_ptr: [std::ptr::null_mut(); 2],
+                }),
+                XzFilter::Lzma2(options) => lzma2.push(options.build(preset)?),
+                _ => {}
+            }
+        }
+        let (mut delta, mut lzma2) = (delta.iter_mut(), lzma2.iter_mut());
+        let mut chain: Vec<lzma_filter> = filters
+            .iter()
+            .map(|filter| {
+                let (id, options) = match filter {
+                    XzFilter::Delta(_) => (LZMA_FILTER_DELTA, options_ptr(delta.next())),
+                    XzFilter::X86 => (lzma_sys::LZMA_FILTER_X86, std::ptr::null_mut()),
+                    XzFilter::Arm => (lzma_sys::LZMA_FILTER_ARM, std::ptr::null_mut()),
+                    XzFilter::ArmThumb => (lzma_sys::LZMA_FILTER_ARMTHUMB, std::ptr::null_mut()),
+                    XzFilter::PowerPc => (lzma_sys::LZMA_FILTER_POWERPC, std::ptr::null_mut()),
+                    XzFilter::Ia64 => (lzma_sys::LZMA_FILTER_IA64, std::ptr::null_mut()),
+                    XzFilter::Sparc => (lzma_sys::LZMA_FILTER_SPARC, std::ptr::null_mut()),
+                    XzFilter::Lzma2(_) => (lzma_sys::LZMA_FILTER_LZMA2, options_ptr(lzma2.next())),
+                };
+                lzma_filter { id, options }
+            })
+            .collect();
+        chain.push(lzma_filter {
+            id: lzma_sys::LZMA_VLI_UNKNOWN,
+            options: std::ptr::null_mut(),
+        });
+
+        // LZMA_STREAM_INIT, every field zero
+        let mut stream: lzma_stream = unsafe { std::mem::zeroed() };
+        let ret = unsafe {
+            lzma_sys::lzma_stream_encoder(&mut stream, chain.as_ptr(), lzma_sys::LZMA_CHECK_CRC64)
+        };
+        if ret != lzma_sys::LZMA_OK {
+            unsafe { lzma_sys::lzma_end(&mut stream) };
+            Err(anyhow!(
+                "Invalid Filter Chain: liblzma rejected the chain (error {}).",
+                ret
+            ))?;
+        }
+        Ok(ChainEncoder {
+            stream,
+            writer: Some(writer),
+            buffer: vec![0; 64 << 10],
+        })
+    }
+
+    /// Runs the encoder on the pending input with `action`, writing what it
+    /// outputs. Returns whether the stream is complete.
+    fn code(&mut self, action: lzma_sys::lzma_action) -> io::Result<bool> {
+        self.stream.next_out = self.buffer.as_mut_ptr();
+        self.stream.avail_out = self.buffer.len();
+        let ret = unsafe { lzma_sys::lzma_code(&mut self.stream, action) };
+        let written = self.buffer.len() - self.stream.avail_out;
+        let writer = self.writer.as_mut().expect("encoder already finished");
+        writer.write_all(&self.buffer[..written])?;
+        match ret {
+            lzma_sys::LZMA_OK => Ok(false),
+            lzma_sys::LZMA_STREAM_END => Ok(true),
+            _ => Err(io::Error::other(format!(
+                "liblzma failed to encode (error {})",
+                ret
+            ))),
+        }
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.stream.avail_in = 0;
+        while !self.code(lzma_sys::LZMA_FINISH)? {}
+        let mut writer = self.writer.take().expect("encoder already finished");
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+fn options_ptr<T>(options: Option<&mut T>) -> *mut c_void {
+    options.map_or(std::ptr::null_mut(), |options| {
+        (options as *mut T).cast::<c_void>()
+    })
+}
+
+impl<W: Write> Write for ChainEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.next_in = buf.as_ptr();
+        self.stream.avail_in = buf.len();
+        while self.stream.avail_in > 0 {
+            self.code(lzma_sys::LZMA_RUN)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for ChainEncoder<W> {
+    fn drop(&mut self) {
+        unsafe { lzma_sys::lzma_end(&mut self.stream) };
+    }
+}
+
+impl<W: Write> WriteEncoder<W> for ChainEncoder<W> {
+    fn inner(self: Box<Self>) -> Result<W> {
+        Ok(self.finish()?)
+    }
+}
+
+impl Lzma2Options {
+    fn build(&self, preset: u32) -> Result<lzma_options_lzma> {
+        let mut options: lzma_options_lzma = unsafe { std::mem::zeroed() };
+        let preset = self.preset.unwrap_or(preset);
+        // non-zero when the preset is not supported
+        if unsafe { lzma_sys::lzma_lzma_preset(&mut options, preset) } != 0 {
+            Err(anyhow!(
+                "Invalid Filter Chain: The lzma2 preset {} is not supported.",
+                preset & !PRESET_EXTREME
+            ))?;
+        }
+        if let Some(dict) = self.dict {
+            options.dict_size = dict;
+        }
+        if let Some(lc) = self.lc {
+            options.lc = lc;
+        }
+        if let Some(lp) = self.lp {
+            options.lp = lp;
+        }
+        if let Some(pb) = self.pb {
+            options.pb = pb;
+        }
+        if let Some(nice) = self.nice {
+            options.nice_len = nice;
+        }
+        if let Some(depth) = self.depth {
+            options.depth = depth;
+        }
+        if let Some(mf) = self.mf {
+            options.mf = mf;
+        }
+        if let Some(mode) = self.mode {
+            options.mode = mode;
+        }
+        Ok(options)
+    }
+}
+
+fn parse_filter(spec: &str) -> Result<XzFilter> {
+    let mut parts = spec.trim().split(':');
+    let name = parts.next().unwrap_or_default();
+    let options = parts
+        .map(|option| {
+            option.split_once('=').ok_or_else(|| {
+                anyhow!(
+                    "Invalid Filter Chain: The option '{}' of {} is not key=value.",
+                    option,
+                    name
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let unknown = |key: &str| anyhow!("Invalid Filter Chain: {} has no option '{}'.", name, key);
+    let filter = match name {
+        "delta" => {
+            let mut dist = 1;
+            for (key, value) in options {
+                match key {
+                    "dist" => dist = parse_number(name, key, value, 1..=256)?,
+                    _ => Err(unknown(key))?,
+                }
+            }
+            return Ok(XzFilter::Delta(dist));
+        }
+        "x86" => XzFilter::X86,
+        "arm" => XzFilter::Arm,
+        "armthumb" => XzFilter::ArmThumb,
+        "powerpc" => XzFilter::PowerPc,
+        "ia64" => XzFilter::Ia64,
+        "sparc" => XzFilter::Sparc,
+        "lzma2" => {
+            let mut lzma2 = Lzma2Options::default();
+            for (key, value) in options {
+                match key {
+                    "preset" => lzma2.preset = Some(parse_preset(value)?),
+                    "dict" => {
+                        let dict = parse_size(value)?;
+                        if !(4096..=(1536 << 20)).contains(&dict) {
+                            Err(anyhow!(
+                                "Invalid Filter Chain: The lzma2 dictionary must be between 4K and 1536M."
+                            ))?;
+                        }
+                        lzma2.dict = Some(dict as u32);
+                    }
+                    "lc" => lzma2.lc = Some(parse_number(name, key, value, 0..=4)?),
+                    "lp" => lzma2.lp = Some(parse_number(name, key, value, 0..=4)?),
+                    "pb" => lzma2.pb = Some(parse_number(name, key, value, 0..=4)?),
+                    "nice" => lzma2.nice = Some(parse_number(name, key, value, 2..=273)?),
+                    "depth" => lzma2.depth = Some(parse_number(name, key, value, 0..=u32::MAX)?),
+                    "mf" => {
+                        lzma2.mf = Some(match value {
+                            "hc3" => lzma_sys::LZMA_MF_HC3,
+                            "hc4" => lzma_sys::LZMA_MF_HC4,
+                            "bt2" => lzma_sys::LZMA_MF_BT2,
+                            "bt3" => lzma_sys::LZMA_MF_BT3,
+                            "bt4" => lzma_sys::LZMA_MF_BT4,
+                            _ => Err(anyhow!(
+                                "Invalid Filter Chain: The match finder '{}' is not one of hc3, hc4, bt2, bt3 or bt4.",
+                                value
+                            ))?,
+                        })
+                    }
+                    "mode" => {
+                        lzma2.mode = Some(match value {
+                            "fast" => lzma_sys::LZMA_MODE_FAST,
+                            "normal" => lzma_sys::LZMA_MODE_NORMAL,
+                            _ => Err(anyhow!(
+                                "Invalid Filter Chain: The mode '{}' is not fast or normal.",
+                                value
+                            ))?,
+                        })
+                    }
+                    _ => Err(unknown(key))?,
+                }
+            }
+            return Ok(XzFilter::Lzma2(lzma2));
+        }
+        _ => Err(anyhow!(
+            "Invalid Filter Chain: '{}' is not one of: {}.",
+            name,
+            XZ_FILTERS.join(", ")
+        ))?,
+    };
+    // the branch/call/jump filters take no options here
+    match options.first() {
+        Some((key, _)) => Err(unknown(key)),
+        None => Ok(filter),
+    }
+}
+
+fn parse_number(
+    name: &str,
+    key: &str,
+    value: &str,
+    range: std::ops::RangeInclusive<u32>,
+) -> Result<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|number| range.contains(number))
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid Filter Chain: The {} option {} must be between {} and {}.",
+                name,
+                key,
+                range.start(),
+                range.end()
+            )
+        })
+}
+
+/// Parses a preset such as `6` or `9e`.
+fn parse_preset(value: &str) -> Result<u32> {
+    let (level, extreme) = match value.strip_suffix('e') {
+        Some(level) => (level, PRESET_EXTREME),
+        None => (value, 0),
+    };
+    Ok(parse_number("lzma2", "preset", level, 0..=9)? | extreme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use xz2::read::XzDecoder;
+
+    #[test]
+    fn test_xz_options() {
+        assert!(XzOptions::new(false, None).unwrap().is_default());
+        assert!(!XzOptions::new(true, None).unwrap().is_default());
+        assert!(XzOptions::new(false, Some("delta:dist=4,lzma2:dict=64M:mf=bt4")).is_ok());
+        assert!(XzOptions::new(false, Some("x86,lzma2:preset=9e")).is_ok());
+        // lzma2 ends the chain
+        assert!(XzOptions::new(false, Some("delta")).is_err());
+        assert!(XzOptions::new(false, Some("lzma2,delta")).is_err());
+        assert!(XzOptions::new(false, Some("delta,x86,arm,sparc,lzma2")).is_err());
+        assert!(XzOptions::new(false, Some("delta:dist=0,lzma2")).is_err());
+        assert!(XzOptions::new(false, Some("x86:start=4,lzma2")).is_err());
+        assert!(XzOptions::new(false, Some("lzma2:dict=1K")).is_err());
+        assert!(XzOptions::new(false, Some("zstd")).is_err());
+    }
+
+    #[test]
+    fn test_xz_encoder() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        for (extreme, chain) in [
+            (true, None),
+            (false, Some("delta:dist=4,lzma2:dict=1M")),
+            (
+                false,
+                Some("delta:dist=2,x86,lzma2:preset=9e:mf=bt2:mode=fast"),
+            ),
+            (false, Some("lzma2:lc=4:lp=0:pb=0:nice=64")),
+        ] {
+            let options = XzOptions::new(extreme, chain).unwrap();
+            let mut encoder = options.encoder(Vec::new(), 6).unwrap();
+            encoder.write_all(&data).unwrap();
+            let encoded = encoder.inner().unwrap();
+            let mut decoded = Vec::new();
+            XzDecoder::new(encoded.as_slice())
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+}
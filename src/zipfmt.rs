@@ -0,0 +1,625 @@
+//! Low level helpers for the zip on-disk format.
+
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+use anyhow::{anyhow, Result};
+
+pub(crate) const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+pub(crate) const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+pub(crate) const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_EOCD_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_LOCATOR_LEN: usize = 20;
+const ZIP64_EOCD_LEN: usize = 56;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+pub(crate) const EOCD_LEN: usize = 22;
+pub(crate) const CENTRAL_HEADER_LEN: usize = 46;
+pub(crate) const LOCAL_HEADER_LEN: usize = 30;
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    let field = bytes
+        .get(offset..offset + 2)
+        .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    Ok(u16::from_le_bytes([field[0], field[1]]))
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let field = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let field = bytes
+        .get(offset..offset + 8)
+        .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    Ok(u64::from_le_bytes(field.try_into()?))
+}
+
+pub(crate) fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(bytes: &mut [u8], offset: usize, value: u64) {
+    bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) struct EndOfCentralDirectory {
+    pub offset: usize,
+    /// Offset of the ZIP64 end record, its fields override the saturated
+    /// ones of the classic record.
+    pub zip64: Option<usize>,
+    pub disk_number: u16,
+    pub cd_disk: u16,
+    pub entries: u64,
+    pub cd_size: u64,
+    pub cd_offset: u64,
+}
+
+pub(crate) fn find_eocd(bytes: &[u8]) -> Result<EndOfCentralDirectory> {
+    find_eocd_at(bytes, 0)
+}
+
+/// Same as [`find_eocd`], for the end of an archive starting at its offset
+/// `base`. The offsets returned are within the whole archive.
+fn find_eocd_at(bytes: &[u8], base: usize) -> Result<EndOfCentralDirectory> {
+    let start = bytes.len().saturating_sub(EOCD_LEN + u16::MAX as usize);
+    let offset = (start..=bytes.len().saturating_sub(EOCD_LEN))
+        .rev()
+        .find(|&i| read_u32(bytes, i).ok() == Some(EOCD_SIGNATURE))
+        .ok_or_else(|| {
+            anyhow!("Invalid Zip Archive: End of central directory record not found.")
+        })?;
+    let mut eocd = EndOfCentralDirectory {
+        offset: base + offset,
+        zip64: None,
+        disk_number: read_u16(bytes, offset + 4)?,
+        cd_disk: read_u16(bytes, offset + 6)?,
+        entries: read_u16(bytes, offset + 10)?.into(),
+        cd_size: read_u32(bytes, offset + 12)?.into(),
+        cd_offset: read_u32(bytes, offset + 16)?.into(),
+    };
+    let locator = offset.checked_sub(ZIP64_LOCATOR_LEN);
+    let Some(locator) =
+        locator.filter(|&i| read_u32(bytes, i).ok() == Some(ZIP64_LOCATOR_SIGNATURE))
+    else {
+        return Ok(eocd);
+    };
+    let zip64: usize = read_u64(bytes, locator + 8)?.try_into()?;
+    let record = zip64
+        .checked_sub(base)
+        .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    if read_u32(bytes, record)? != ZIP64_EOCD_SIGNATURE {
+        Err(anyhow!(
+            "Invalid Zip Archive: ZIP64 end of central directory record not found."
+        ))?;
+    }
+    eocd.zip64 = Some(zip64);
+    eocd.disk_number = read_u32(bytes, record + 16)?.try_into()?;
+    eocd.cd_disk = read_u32(bytes, record + 20)?.try_into()?;
+    eocd.entries = read_u64(bytes, record + 32)?;
+    eocd.cd_size = read_u64(bytes, record + 40)?;
+    eocd.cd_offset = read_u64(bytes, record + 48)?;
+    Ok(eocd)
+}
+
+pub(crate) struct CentralRecord {
+    pub name: String,
+    /// Extent of the record within the bytes it was listed from.
+    pub range: Range<usize>,
+    pub local_offset: usize,
+    /// Position within the record of the local header offset, in its ZIP64
+    /// extra field when the classic one is saturated.
+    zip64_offset: Option<usize>,
+}
+
+/// Lists the central directory records of a single-disk archive.
+pub(crate) fn central_records(
+    bytes: &[u8],
+    eocd: &EndOfCentralDirectory,
+) -> Result<Vec<CentralRecord>> {
+    central_records_at(bytes, 0, eocd)
+}
+
+/// Same as [`central_records`], for the end of an archive starting at its
+/// offset `base`.
+fn central_records_at(
+    bytes: &[u8],
+    base: usize,
+    eocd: &EndOfCentralDirectory,
+) -> Result<Vec<CentralRecord>> {
+    if eocd.disk_number != 0 || eocd.cd_disk != 0 {
+        Err(anyhow!(
+            "Unsupported Zip Archive: Split archives must be joined first."
+        ))?;
+    }
+    // the capacity is bounded by what the bytes can hold
+    let mut records =
+        Vec::with_capacity(usize::try_from(eocd.entries)?.min(bytes.len() / CENTRAL_HEADER_LEN));
+    let mut offset = usize::try_from(eocd.cd_offset)?
+        .checked_sub(base)
+        .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    while read_u32(bytes, offset)? == CENTRAL_HEADER_SIGNATURE {
+        let name_len = read_u16(bytes, offset + 28)? as usize;
+        let extra_len = read_u16(bytes, offset + 30)? as usize;
+        let comment_len = read_u16(bytes, offset + 32)? as usize;
+        let len = CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+        let record = bytes
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+        let name = &record[CENTRAL_HEADER_LEN..CENTRAL_HEADER_LEN + name_len];
+        let (local_offset, zip64_offset) = match read_u32(record, 42)? {
+            u32::MAX => {
+                let extra = CENTRAL_HEADER_LEN + name_len;
+                let field = zip64_offset_field(record, extra..extra + extra_len)?;
+                (read_u64(record, field)?.try_into()?, Some(field))
+            }
+            local_offset => (local_offset as usize, None),
+        };
+        records.push(CentralRecord {
+            name: String::from_utf8_lossy(name).into_owned(),
+            range: offset..offset + len,
+            local_offset,
+            zip64_offset,
+        });
+        offset += len;
+    }
+    if records.len() as u64 != eocd.entries {
+        Err(anyhow!(
+            "Invalid Zip Archive: The central directory does not match its end record."
+        ))?;
+    }
+    Ok(records)
+}
+
+/// Position of the local header offset in the ZIP64 extra field among the
+/// `extra` fields of the central `record`. The field holds only the values
+/// saturated in the record, in a fixed order.
+fn zip64_offset_field(record: &[u8], extra: Range<usize>) -> Result<usize> {
+    let mut offset = extra.start;
+    while offset + 4 <= extra.end {
+        let id = read_u16(record, offset)?;
+        let len = read_u16(record, offset + 2)? as usize;
+        if id == ZIP64_EXTRA_ID {
+            let mut field = offset + 4;
+            // the uncompressed and compressed sizes come first
+            for size in [24, 20] {
+                if read_u32(record, size)? == u32::MAX {
+                    field += 8;
+                }
+            }
+            if field + 8 > offset + 4 + len {
+                break;
+            }
+            return Ok(field);
+        }
+        offset += 4 + len;
+    }
+    Err(anyhow!(
+        "Invalid Zip Archive: The ZIP64 extra field of a central directory record is missing."
+    ))?
+}
+
+/// The central directory of an archive and the end records following it.
+pub(crate) struct ZipDirectory<'a> {
+    /// The end of the archive, from its offset `base`.
+    bytes: Cow<'a, [u8]>,
+    base: usize,
+    pub eocd: EndOfCentralDirectory,
+    pub records: Vec<CentralRecord>,
+}
+
+impl<'a> ZipDirectory<'a> {
+    /// Lists the directory of the single-disk archive `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        let eocd = find_eocd(bytes)?;
+        let records = central_records(bytes, &eocd)?;
+        Ok(ZipDirectory {
+            bytes: Cow::Borrowed(bytes),
+            base: 0,
+            eocd,
+            records,
+        })
+    }
+
+    /// Reads the directory of the single-disk archive `reader` from its end,
+    /// without reading its entries.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<ZipDirectory<'static>> {
+        let (eocd, mut bytes, mut base) = read_end(reader)?;
+        let start = usize::try_from(eocd.cd_offset)?.min(eocd.offset);
+        if start < base {
+            base = start;
+            bytes = read_from(reader, base)?;
+        }
+        let records = central_records_at(&bytes, base, &eocd)?;
+        Ok(ZipDirectory {
+            bytes: Cow::Owned(bytes),
+            base,
+            eocd,
+            records,
+        })
+    }
+
+    /// Same as [`ZipDirectory::write_kept`], for the archive `bytes` the
+    /// directory was parsed from, returns the result.
+    pub fn remove_entries(&self, bytes: &[u8], keep: &[bool]) -> Result<Vec<u8>> {
+        let mut result = Vec::new();
+        self.write_kept(&mut Cursor::new(bytes), keep, None, &mut result)?;
+        Ok(result)
+    }
+
+    /// Writes the archive `reader` without the entries whose `keep` flag is
+    /// false, and with `comment` instead of its archive comment, if given.
+    /// The kept entries are not recompressed: local entries are copied
+    /// verbatim and only the central directory and its end record are
+    /// rebuilt.
+    pub fn write_kept<R: Read + Seek, W: Write>(
+        &self,
+        reader: &mut R,
+        keep: &[bool],
+        comment: Option<&[u8]>,
+        writer: &mut W,
+    ) -> Result<()> {
+        let (eocd, records) = (&self.eocd, &self.records);
+        let cd_start: usize = eocd.cd_offset.try_into()?;
+
+        let mut by_offset: Vec<usize> = (0..records.len()).collect();
+        by_offset.sort_unstable_by_key(|&index| records[index].local_offset);
+
+        // anything before the first entry (e.g. a self-extractor stub) is kept
+        let first = by_offset
+            .first()
+            .map_or(cd_start, |&index| records[index].local_offset);
+        let mut written = copy_range(reader, 0..first, writer)?;
+        let mut new_offsets = vec![0; records.len()];
+        for (position, &index) in by_offset.iter().enumerate() {
+            let start = records[index].local_offset;
+            let mut signature = [0; 4];
+            reader.seek(SeekFrom::Start(start as u64))?;
+            if reader.read_exact(&mut signature).is_err()
+                || u32::from_le_bytes(signature) != LOCAL_HEADER_SIGNATURE
+            {
+                Err(anyhow!(
+                    "Invalid Zip Archive: Local file header not found at offset {}.",
+                    start
+                ))?;
+            }
+            // the extent includes a trailing data descriptor, if any
+            let end = by_offset
+                .get(position + 1)
+                .map_or(cd_start, |&next| records[next].local_offset);
+            if end < start {
+                Err(anyhow!(
+                    "Invalid Zip Archive: The entry at offset {} overlaps the central directory.",
+                    start
+                ))?;
+            }
+            if keep[index] {
+                new_offsets[index] = written;
+                written += copy_range(reader, start..end, writer)?;
+            }
+        }
+
+        let cd_offset = written;
+        let mut central = Vec::new();
+        let mut entries: u64 = 0;
+        for (index, record) in records.iter().enumerate() {
+            if !keep[index] {
+                continue;
+            }
+            let start = central.len();
+            central.extend_from_slice(&self.bytes[record.range.clone()]);
+            // entries only move towards the start, the offset fits its field
+            match record.zip64_offset {
+                Some(field) => write_u64(&mut central, start + field, new_offsets[index] as u64),
+                None => write_u32(&mut central, start + 42, new_offsets[index].try_into()?),
+            }
+            entries += 1;
+        }
+        let cd_size = central.len();
+        writer.write_all(&central)?;
+
+        let (mut tail, eocd_start) = match eocd.zip64 {
+            None => {
+                let mut tail = self.bytes[eocd.offset - self.base..].to_vec();
+                write_u16(&mut tail, 8, entries.try_into()?);
+                write_u16(&mut tail, 10, entries.try_into()?);
+                write_u32(&mut tail, 12, cd_size.try_into()?);
+                write_u32(&mut tail, 16, cd_offset.try_into()?);
+                (tail, 0)
+            }
+            // the ZIP64 record and its locator are followed by the classic
+            // record, whose fields stay saturated
+            Some(zip64) => {
+                let eocd_start = eocd
+                    .offset
+                    .checked_sub(zip64 + ZIP64_LOCATOR_LEN)
+                    .ok_or_else(|| anyhow!("Invalid Zip Archive: Misplaced ZIP64 end record."))?
+                    + ZIP64_LOCATOR_LEN;
+                let mut tail = self
+                    .bytes
+                    .get(zip64 - self.base..)
+                    .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?
+                    .to_vec();
+                let zip64_start = cd_offset + cd_size;
+                write_u64(&mut tail, 24, entries);
+                write_u64(&mut tail, 32, entries);
+                write_u64(&mut tail, 40, cd_size as u64);
+                write_u64(&mut tail, 48, cd_offset as u64);
+                write_u64(
+                    &mut tail,
+                    eocd_start - ZIP64_LOCATOR_LEN + 8,
+                    zip64_start as u64,
+                );
+                (tail, eocd_start)
+            }
+        };
+        if let Some(comment) = comment {
+            let len: u16 = comment.len().try_into().map_err(|_| {
+                anyhow!(
+                    "Invalid Comment: The archive comment exceeds {} bytes.",
+                    u16::MAX
+                )
+            })?;
+            tail.truncate(eocd_start + EOCD_LEN);
+            write_u16(&mut tail, eocd_start + 20, len);
+            tail.extend_from_slice(comment);
+        }
+        writer.write_all(&tail)?;
+        Ok(())
+    }
+}
+
+/// Finds the end of central directory record of the archive `reader`,
+/// reading only the end of it.
+pub(crate) fn read_eocd<R: Read + Seek>(reader: &mut R) -> Result<EndOfCentralDirectory> {
+    Ok(read_end(reader)?.0)
+}
+
+/// Finds the end records of the archive `reader`, returns them with the end
+/// of the archive they were found in and its offset.
+fn read_end<R: Read + Seek>(reader: &mut R) -> Result<(EndOfCentralDirectory, Vec<u8>, usize)> {
+    let len: usize = reader.seek(SeekFrom::End(0))?.try_into()?;
+    // the end record with its comment, preceded by the ZIP64 ones
+    let base =
+        len.saturating_sub(EOCD_LEN + u16::MAX as usize + ZIP64_LOCATOR_LEN + ZIP64_EOCD_LEN);
+    let bytes = read_from(reader, base)?;
+    let eocd = find_eocd_at(&bytes, base)?;
+    Ok((eocd, bytes, base))
+}
+
+/// The bytes of `reader` from `offset` on.
+fn read_from<R: Read + Seek>(reader: &mut R, offset: usize) -> Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Copies the bytes of `reader` within `range` to `writer`, returns their
+/// count.
+fn copy_range<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    range: Range<usize>,
+    writer: &mut W,
+) -> Result<usize> {
+    let len = range.len();
+    reader.seek(SeekFrom::Start(range.start as u64))?;
+    let copied = std::io::copy(&mut reader.take(len as u64), writer)?;
+    if copied != len as u64 {
+        Err(anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+    }
+    Ok(len)
+}
+
+/// Appends the entries of `input` at `indices`, their local headers, data
+/// and central directory records copied verbatim, to the zip `output`.
+pub(crate) fn append_raw_entries(
+    output: &[u8],
+    input: &[u8],
+    indices: &[usize],
+) -> Result<Vec<u8>> {
+    let input_eocd = find_eocd(input)?;
+    if input_eocd.zip64.is_some() {
+        Err(anyhow!(
+            "Unsupported Zip Archive: Entries of ZIP64 archives cannot be copied as they are."
+        ))?;
+    }
+    let input_records = central_records(input, &input_eocd)?;
+    let mut offsets: Vec<usize> = input_records
+        .iter()
+        .map(|record| record.local_offset)
+        .collect();
+    offsets.push(input_eocd.cd_offset as usize);
+    offsets.sort_unstable();
+
+    let eocd = find_eocd(output)?;
+    if eocd.zip64.is_some() {
+        Err(anyhow!(
+            "Unsupported Zip Archive: Entries cannot be copied into a ZIP64 archive as they are."
+        ))?;
+    }
+    let cd_start = eocd.cd_offset as usize;
+    let cd_end = cd_start + eocd.cd_size as usize;
+    let mut result = output[..cd_start].to_vec();
+    let mut appended = Vec::new();
+    for &index in indices {
+        let record = &input_records[index];
+        let start = record.local_offset;
+        // the extent includes a trailing data descriptor, if any
+        let end = offsets
+            .iter()
+            .copied()
+            .find(|&offset| offset > start)
+            .unwrap_or(start);
+        let central = input
+            .get(record.range.clone())
+            .ok_or_else(|| anyhow!("Invalid Zip Archive: Unexpected end of data."))?;
+        let mut central = central.to_vec();
+        write_u32(&mut central, 42, result.len().try_into()?);
+        appended.push(central);
+        result.extend_from_slice(&input[start..end]);
+    }
+
+    let cd_offset = result.len();
+    result.extend_from_slice(&output[cd_start..cd_end]);
+    for central in appended {
+        result.extend_from_slice(&central);
+    }
+    let cd_size = result.len() - cd_offset;
+    let entries = eocd.entries as usize + indices.len();
+    let eocd_start = result.len();
+    result.extend_from_slice(&output[eocd.offset..]);
+    write_u16(&mut result, eocd_start + 8, entries.try_into()?);
+    write_u16(&mut result, eocd_start + 10, entries.try_into()?);
+    write_u32(&mut result, eocd_start + 12, cd_size.try_into()?);
+    write_u32(&mut result, eocd_start + 16, cd_offset.try_into()?);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use zip::write::SimpleFileOptions;
+
+    /// A stored zip of `entries` laid out as ZIP64: the local header offsets
+    /// are in the extra field of the central records and the counts in a
+    /// ZIP64 end record.
+    fn zip64_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut central = Vec::new();
+        for (name, data) in entries {
+            let offset = bytes.len() as u64;
+            let (crc, size) = (crc32fast::hash(data), data.len() as u32);
+            bytes.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            bytes.extend([45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            bytes.extend(crc.to_le_bytes());
+            bytes.extend(size.to_le_bytes());
+            bytes.extend(size.to_le_bytes());
+            bytes.extend((name.len() as u16).to_le_bytes());
+            bytes.extend(0u16.to_le_bytes());
+            bytes.extend(name.as_bytes());
+            bytes.extend(*data);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            central.extend(crc.to_le_bytes());
+            central.extend(size.to_le_bytes());
+            central.extend(size.to_le_bytes());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend(12u16.to_le_bytes());
+            central.extend([0; 10]);
+            central.extend(u32::MAX.to_le_bytes());
+            central.extend(name.as_bytes());
+            central.extend(ZIP64_EXTRA_ID.to_le_bytes());
+            central.extend(8u16.to_le_bytes());
+            central.extend(offset.to_le_bytes());
+        }
+        let (cd_offset, cd_size) = (bytes.len() as u64, central.len() as u64);
+        bytes.extend(central);
+        let zip64 = bytes.len() as u64;
+        let count = entries.len() as u64;
+        bytes.extend(ZIP64_EOCD_SIGNATURE.to_le_bytes());
+        bytes.extend(44u64.to_le_bytes());
+        bytes.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for field in [count, count, cd_size, cd_offset] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(zip64.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(EOCD_SIGNATURE.to_le_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend([0xff; 12]);
+        bytes.extend([0; 2]);
+        bytes
+    }
+
+    #[test]
+    fn test_remove_entries_zip64() {
+        let input = zip64_archive(&[
+            ("a.txt", b"first"),
+            ("b.txt", b"second"),
+            ("c.txt", b"third"),
+        ]);
+        let directory = ZipDirectory::parse(&input).unwrap();
+        assert_eq!(
+            (directory.eocd.entries, directory.eocd.zip64.is_some()),
+            (3, true)
+        );
+        assert_eq!(directory.records[2].local_offset, 81);
+
+        let output = directory
+            .remove_entries(&input, &[true, false, true])
+            .unwrap();
+        assert_eq!(find_eocd(&output).unwrap().entries, 2);
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        let mut content = String::new();
+        zip.by_name("c.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "third");
+        assert_eq!(zip.len(), 2);
+    }
+
+    #[test]
+    fn test_zip_directory_read() {
+        let input = zip64_archive(&[("a.txt", b"first"), ("b.txt", b"second")]);
+        let mut reader = std::io::Cursor::new(&input);
+        let directory = ZipDirectory::read(&mut reader).unwrap();
+        assert_eq!(directory.records.len(), 2);
+        assert_eq!(directory.records[1].local_offset, 40);
+
+        let mut output = Vec::new();
+        directory
+            .write_kept(&mut reader, &[false, true], Some(b"comment"), &mut output)
+            .unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(zip.comment(), b"comment");
+        let mut content = String::new();
+        zip.by_index(0)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!((zip.len(), content.as_str()), (1, "second"));
+    }
+
+    #[test]
+    fn test_remove_entries_invalid_offset() {
+        let mut input = Vec::new();
+        {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut input));
+            zip.start_file("a.txt", SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(b"content").unwrap();
+            // a local header signature past the central directory
+            zip.set_raw_comment(Box::from(LOCAL_HEADER_SIGNATURE.to_le_bytes()));
+            zip.finish().unwrap();
+        }
+        let eocd = find_eocd(&input).unwrap();
+        let record = eocd.cd_offset as usize;
+        let past = (eocd.offset + EOCD_LEN) as u32;
+        write_u32(&mut input, record + 42, past);
+        let directory = ZipDirectory::parse(&input).unwrap();
+        let err = directory.remove_entries(&input, &[true]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Invalid Zip Archive: The entry at offset {} overlaps the central directory.",
+                past
+            )
+        );
+    }
+}
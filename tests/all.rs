@@ -56,3 +56,1127 @@ fn test_extract_tar_gz() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_merge_conflict_error() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("merge")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("tests/archives/tar-test.tar.xz")
+        .arg("--output")
+        .arg("merged.tar.gz")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Merge Conflict"));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("list")
+        .arg("--tree")
+        .arg("tests/archives/tar-test.tar.gz")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nested.zip [archive]"));
+
+    Ok(())
+}
+
+#[test]
+fn test_compare_structure() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--compare-structure")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "structure preserved: 3 archive(s), 17 entries",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("list")
+        .arg("--metadata")
+        .arg("tests/archives/tar-test.tar.gz")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"uname\": \"repka\""))
+        .stdout(predicate::str::contains("\"format\": \"zip\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_nested_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    // the format is optional, so the flag follows the input
+    cmd.arg("list")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--nested-graph")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("digraph archives {"))
+        .stdout(predicate::str::contains("n0 -> n1;"));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("list")
+        .arg("--nested-graph=json")
+        .arg("tests/archives/tar-test.tar.gz")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\"path\": \"tar-test/nested/nested.zip\"",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cat() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("cat")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("tar-test/nested/nested.zip!zip-test/zippedfile_valid.txt")
+        .assert()
+        .success()
+        .stdout("imsovalid awyis");
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("cat")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("tar-test/missing.txt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Entry Not Found"));
+
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_preview() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        // the CSV has no header row
+        .arg("--with-headers")
+        .arg("--dry-run")
+        .arg("--preview")
+        .arg("8")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "would remove: tar-test/file_2.txt\n    qweqweqe\n    ... (12 more bytes)\n",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_dry_run_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        // the CSV has no header row
+        .arg("--with-headers")
+        .arg("--dry-run")
+        .arg("--tree")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file_2.txt 20 B [removed]"))
+        .stdout(predicate::str::contains("would remove").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_output_json_error() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/missing.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--output-json")
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with(r#"{"status":"error","#));
+
+    Ok(())
+}
+
+// macOS and Windows always have a desktop to open the report in
+#[cfg(target_os = "linux")]
+#[test]
+fn test_open_report_headless() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .arg("--input")
+        .arg("tests/archives/missing.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--output-json")
+        .arg("--open-report")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "(no desktop or terminal to open it in)",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_man_page() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("man")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".TH expurgator 1"));
+
+    Ok(())
+}
+
+#[test]
+fn test_env_configuration() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.env("EXPURGATOR_INPUT", "tests/archives/tar-test.tar.gz")
+        .env("EXPURGATOR_CSV", "tests/assets/tar-test.csv")
+        .env("EXPURGATOR_INDEX", "2")
+        .env("EXPURGATOR_COMPRESSION", "42")
+        .arg("--output-json")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid Compression Level"));
+
+    Ok(())
+}
+
+#[test]
+fn test_profile() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::FileWriteStr;
+
+    let config = assert_fs::NamedTempFile::new("expurgator.toml")?;
+    config.write_str(
+        "[profile.broken]\ncsv = \"tests/assets/tar-test.csv\"\nindex = 2\ncompression = 42\n",
+    )?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--config")
+        .arg(config.path())
+        .arg("--profile")
+        .arg("broken")
+        .arg("--output-json")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid Compression Level"));
+
+    // settings of newer versions are skipped, the others still apply
+    let output_dir = assert_fs::TempDir::new()?;
+    config.write_str(&format!(
+        "[profile.scrub]\ncsv = \"tests/assets/tar-test.csv\"\nindex = 2\noutput-dir = {:?}\nnon-interactive = true\nnewer-option = 1\n",
+        output_dir.path().display().to_string()
+    ))?;
+    Command::cargo_bin("expurgator")?
+        .arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--config")
+        .arg(config.path())
+        .arg("--profile")
+        .arg("scrub")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning: ignoring the setting 'newer-option' of the profile 'scrub'",
+        ));
+    assert!(output_dir.path().join("tar-test.tar.gz").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_plan_apply() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = assert_fs::NamedTempFile::new("plan.json")?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("plan")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--output")
+        .arg(plan.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("entries to remove"));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.xz")
+        .arg("--plan")
+        .arg(plan.path())
+        .arg("--output-json")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Plan Mismatch"));
+
+    Ok(())
+}
+
+#[test]
+fn test_plan_require_signed_by() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = assert_fs::NamedTempFile::new("plan.json")?;
+    std::fs::write(
+        plan.path(),
+        std::fs::read_to_string("tests/assets/tar-test.plan.json")?.replace("file_link", "file_1"),
+    )?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--plan")
+        .arg(plan.path())
+        .arg("--require-signed-by")
+        .arg("tests/assets/plan-key.pub")
+        .arg("--plan-signature")
+        .arg("tests/assets/tar-test.plan.json.minisig")
+        .arg("--output-json")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid Signature"));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let policy = assert_fs::NamedTempFile::new("policy.yaml")?;
+    std::fs::write(policy.path(), "forbidden-paths: ['**/*.jpg']\n")?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("check")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::starts_with(
+            "::error file=tests/archives/tar-test.tar.gz,title=Policy violation (forbidden-paths)::tar-test/nested/nested.zip/zip-test/nested/invalid-image.jpg",
+        ))
+        .stderr(predicate::str::contains("Policy Violation"));
+
+    let ignore_file = assert_fs::NamedTempFile::new(".expurgatorignore")?;
+    std::fs::write(
+        ignore_file.path(),
+        "forbidden-paths **/invalid-image.jpg 2000-01-01\n",
+    )?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("check")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .arg("--ignore-file")
+        .arg(ignore_file.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "The waiver expired on 2000-01-01.",
+        ));
+
+    std::fs::write(
+        ignore_file.path(),
+        "forbidden-paths **/invalid-image.jpg 9999-12-31\n",
+    )?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("check")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .arg("--ignore-file")
+        .arg(ignore_file.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 finding(s) waived"));
+
+    std::fs::write(
+        policy.path(),
+        "rules:\n  - id: images\n    match:\n      path: '**/*.jpg'\n    severity: warning\n",
+    )?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("check")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("::warning"));
+
+    std::fs::write(policy.path(), "max-entry-size: 10MB\n")?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("check")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let policy = assert_fs::NamedTempFile::new("policy.yaml")?;
+    std::fs::write(
+        policy.path(),
+        "rules:\n  - id: images\n    match:\n      path: '**/*.jpg'\n    action: remove\n",
+    )?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--policy")
+        .arg(policy.path())
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--non-interactive")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "removed: tar-test/nested/nested.zip/zip-test/nested/invalid-image.jpg [images]",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("tar-test.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("invalid-image.jpg").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_streamed() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = assert_fs::TempDir::new()?;
+    // the input, 953 bytes, is larger than --max-memory, it is only read from
+    // disk; its nested archives still fit
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--with-headers")
+        .arg("--max-memory")
+        .arg("940")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--non-interactive")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("tar-test.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tar-test/file_1.txt"))
+        .stdout(predicate::str::contains("tar-test/file_2.txt").not());
+
+    // interactive runs summarize the input from disk too, the confirmation
+    // then fails without a terminal
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--max-memory")
+        .arg("940")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--force")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("About to rewrite"))
+        .stderr(predicate::str::contains("Memory Limit").not());
+
+    // zips are rewritten from disk an entry at a time
+    let input = output_dir.path().join("input.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&input)?);
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        zip.start_file(name, options)?;
+        std::io::Write::write_all(&mut zip, &name.as_bytes().repeat(100))?;
+    }
+    zip.finish()?;
+    let csv = output_dir.path().join("input.csv");
+    std::fs::write(&csv, "path\nb.txt\n")?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(&input)
+        .arg("--csv")
+        .arg(&csv)
+        .arg("--index")
+        .arg("1")
+        .arg("--max-memory")
+        .arg("1000")
+        .arg("--output-dir")
+        .arg(output_dir.path().join("out"))
+        .arg("--non-interactive")
+        .assert()
+        .success();
+    let output = std::fs::File::open(output_dir.path().join("out/input.zip"))?;
+    let zip = zip::ZipArchive::new(output)?;
+    assert_eq!(zip.file_names().count(), 2);
+    assert!(zip.index_for_name("b.txt").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("-")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--output")
+        .arg("clean.tar.gz")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--yes")
+        .pipe_stdin("tests/archives/tar-test.tar.gz")?
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("clean.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tar-test/nested/file_link.txt").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(input.path().join("app.txt"), "app")?;
+    std::fs::write(input.path().join("secret.key"), "key")?;
+    let filter = assert_fs::NamedTempFile::new("filter.csv")?;
+    std::fs::write(filter.path(), "path\nsecret.key\n")?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--csv")
+        .arg(filter.path())
+        .arg("--index")
+        .arg("1")
+        .arg("--output")
+        .arg("release.zip")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--yes")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("release.zip"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app.txt"))
+        .stdout(predicate::str::contains("secret.key").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_extract() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--extract")
+        .arg(dir.path())
+        .arg("--yes")
+        .assert()
+        .success();
+
+    assert!(dir.path().join("tar-test/file_1.txt").is_file());
+    assert!(!dir.path().join("tar-test/nested/file_link.txt").exists());
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--csv")
+        .arg("tests/assets/tar-test.csv")
+        .arg("--index")
+        .arg("2")
+        .arg("--extract")
+        .arg(dir.path())
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_encrypt_output() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(input.path().join("app.txt"), "confidential")?;
+    let output = assert_fs::TempDir::new()?;
+    let password = output.path().join("password");
+    std::fs::write(&password, "s3cret\n")?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--exclude-binaries")
+        .arg("--output")
+        .arg("app.zip")
+        .arg("--output-dir")
+        .arg(output.path())
+        .arg("--encrypt-output")
+        .arg("--password-file")
+        .arg(&password)
+        .arg("--yes")
+        .assert()
+        .success();
+
+    let bytes = std::fs::read(output.path().join("app.zip"))?;
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    assert!(zip.by_name("app.txt").is_err());
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut zip.by_name_decrypt("app.txt", b"s3cret")?,
+        &mut content,
+    )?;
+    assert_eq!(content, "confidential");
+
+    // encrypted entries of an input stay encrypted
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(output.path().join("app.zip"))
+        .arg("--exclude-binaries")
+        .arg("--output")
+        .arg("rewritten.zip")
+        .arg("--output-dir")
+        .arg(output.path())
+        .arg("--yes")
+        .env("EXPURGATOR_PASSWORD", "s3cret")
+        .assert()
+        .success();
+    let bytes = std::fs::read(output.path().join("rewritten.zip"))?;
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    assert!(zip.by_name("app.txt").is_err());
+    assert!(zip.by_name_decrypt("app.txt", b"s3cret").is_ok());
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(output.path().join("app.zip"))
+        .arg("--exclude-binaries")
+        .arg("--output")
+        .arg("unreadable.zip")
+        .arg("--output-dir")
+        .arg(output.path())
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--encrypted skip-encrypted"));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--exclude-binaries")
+        .arg("--output")
+        .arg("out.tar.gz")
+        .arg("--output-dir")
+        .arg(output.path())
+        .arg("--encrypt-output")
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--encrypt-recipient"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_encrypted_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let input = dir.path().join("mixed.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&input)?);
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("plain.txt", options)?;
+    std::io::Write::write_all(&mut zip, b"plain")?;
+    zip.start_file(
+        "secret.txt",
+        options.with_aes_encryption(zip::AesMode::Aes256, "s3cret"),
+    )?;
+    std::io::Write::write_all(&mut zip, b"secret")?;
+    zip.finish()?;
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(&input)
+        .arg("--exclude-text")
+        .arg("--output")
+        .arg("out.zip")
+        .arg("--output-dir")
+        .arg(dir.path())
+        .arg("--encrypted")
+        .arg("fail")
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "1 entries are encrypted, e.g. 'secret.txt'",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(&input)
+        .arg("--exclude-text")
+        .arg("--output")
+        .arg("out.zip")
+        .arg("--output-dir")
+        .arg(dir.path())
+        .arg("--encrypted")
+        .arg("skip-encrypted")
+        .arg("--yes")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("removed: plain.txt [text]"))
+        .stderr(predicate::str::contains("skipped: secret.txt [encrypted]"));
+
+    let bytes = std::fs::read(dir.path().join("out.zip"))?;
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+    assert_eq!(zip.len(), 1);
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut zip.by_name_decrypt("secret.txt", b"s3cret")?,
+        &mut content,
+    )?;
+    assert_eq!(content, "secret");
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(input.path().join("app.txt"), "app")?;
+    std::fs::write(input.path().join("README"), "tampered")?;
+    std::fs::write(input.path().join("extra.txt"), "extra")?;
+    let manifest = assert_fs::NamedTempFile::new("SHA256SUMS")?;
+    std::fs::write(
+        manifest.path(),
+        "a172cedcae47474b615c54d510a5d84a8dea3032e958587430b413538be3f333  ./app.txt\n711a6108ba2ce6ca93dd47d6817f2361db10d8ab6eec89460b2dfc2c325efabe  ./README\n",
+    )?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--manifest")
+        .arg(manifest.path())
+        .arg("--output")
+        .arg("release.tar")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("removed: README [hash mismatch]"))
+        .stderr(predicate::str::contains(
+            "removed: extra.txt [not in manifest]",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("release.tar"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app.txt"))
+        .stdout(predicate::str::contains("README").not())
+        .stdout(predicate::str::contains("extra.txt").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_exclude_license() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(
+        input.path().join("server.py"),
+        "# SPDX-License-Identifier: AGPL-3.0-or-later\n",
+    )?;
+    std::fs::write(
+        input.path().join("client.py"),
+        "# SPDX-License-Identifier: MIT\n",
+    )?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--exclude-license")
+        .arg("AGPL-3.0")
+        .arg("--output")
+        .arg("bundle.zip")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "removed: server.py [AGPL-3.0-or-later]",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("bundle.zip"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("client.py"))
+        .stdout(predicate::str::contains("server.py").not());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pack_archive_in_place() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(
+        input.path().join("server.py"),
+        "# SPDX-License-Identifier: AGPL-3.0-or-later\n",
+    )?;
+    std::fs::write(input.path().join("client.py"), "print()\n")?;
+    let cache = assert_fs::TempDir::new()?;
+    let artifact = cache.path().join("artifact.zip");
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--output")
+        .arg("artifact.zip")
+        .arg("--output-dir")
+        .arg(cache.path())
+        .arg("--exclude-license")
+        .arg("MIT")
+        .assert()
+        .success();
+
+    // another job rewriting the same artifact
+    let lock = expurgator::internal::FileLock::acquire(&artifact)?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(&artifact)
+        .arg("--in-place")
+        .arg("--exclude-license")
+        .arg("AGPL-3.0")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("File Locked"));
+    drop(lock);
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("--input")
+        .arg(&artifact)
+        .arg("--in-place")
+        .arg("--exclude-license")
+        .arg("AGPL-3.0")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(&artifact)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("client.py"))
+        .stdout(predicate::str::contains("server.py").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_already_scrubbed() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = assert_fs::TempDir::new()?;
+    let scrub = || -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("expurgator")?;
+        cmd.arg("--input")
+            .arg("tests/archives/tar-test.tar.gz")
+            .arg("--csv")
+            .arg("tests/assets/tar-test.csv")
+            .arg("--index")
+            .arg("2")
+            .arg("--provenance")
+            .arg("entry")
+            .arg("--output-dir")
+            .arg(output_dir.path())
+            .arg("--yes");
+        Ok(cmd)
+    };
+    scrub()?.assert().success();
+
+    // no --force needed, the output is left as is
+    scrub()?.assert().success().stderr(predicate::str::contains(
+        "already scrubbed with identical rules",
+    ));
+
+    // other rules scrub again
+    scrub()?
+        .arg("--transform")
+        .arg("strip-comments=*.txt")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Output Exists"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_entropy_report() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(
+        input.path().join("server.py"),
+        "# SPDX-License-Identifier: AGPL-3.0-or-later\n",
+    )?;
+    let payload: Vec<u8> = (0..4096u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    std::fs::write(input.path().join("notes.txt"), payload)?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--exclude-license")
+        .arg("AGPL-3.0")
+        .arg("--entropy-report")
+        .arg("--output")
+        .arg("out.zip")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--output-json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            r#""anomalies":[{"path":"notes.txt""#,
+        ))
+        .stderr(predicate::str::contains("review: notes.txt [entropy"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_symlinks_drop() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--deny-ext")
+        .arg("dat")
+        .arg("--symlinks")
+        .arg("drop")
+        .arg("--output")
+        .arg("out.tar.gz")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--yes")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "removed: tar-test/nested/file_link.txt [symlink]",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("out.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("file_link.txt").not());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "yara"))]
+#[test]
+fn test_pack_archive_yara_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--yara")
+        .arg("rules.yar")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unsupported Option"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_clamav_invalid_address() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--clamav")
+        .arg("/run/clamd.sock")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid ClamAV Address"));
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_deny_ext() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(input.path().join("app.js"), "main();\n")?;
+    std::fs::write(input.path().join("app.js.map"), "{}\n")?;
+    std::fs::write(input.path().join("build.LOG"), "ok\n")?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--deny-ext")
+        .arg("map,.log")
+        .arg("--output")
+        .arg("out.zip")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .arg("--yes")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "removed: app.js.map [denied .map]",
+        ));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("out.zip"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app.js"))
+        .stdout(predicate::str::contains("build.LOG").not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_exclude_binaries() -> Result<(), Box<dyn std::error::Error>> {
+    let input = assert_fs::TempDir::new()?;
+    std::fs::write(input.path().join("main.c"), "int main() { return 0; }\n")?;
+    std::fs::write(input.path().join("main"), b"\x7fELF\x02\x01\x01\0")?;
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg(input.path())
+        .arg("--exclude-binaries")
+        .arg("--output")
+        .arg("source.tar.gz")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("removed: main [ELF]"));
+
+    let mut cmd = Command::cargo_bin("expurgator")?;
+    cmd.arg("list")
+        .arg(output_dir.path().join("source.tar.gz"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("main.c"))
+        .stdout(predicate::str::is_match("main\n").unwrap().not());
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_archive_chunk_hashes() -> Result<(), Box<dyn std::error::Error>> {
+    let output_dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("expurgator")?;
+
+    cmd.arg("--input")
+        .arg("tests/archives/tar-test.tar.gz")
+        .arg("--exclude-binaries")
+        .arg("--chunk-hashes")
+        .arg("1K")
+        .arg("--output")
+        .arg("out.tar.gz")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        .assert()
+        .success();
+
+    let output = std::fs::read(output_dir.path().join("out.tar.gz"))?;
+    let hashes: expurgator::internal::ChunkHashes = serde_json::from_slice(&std::fs::read(
+        output_dir.path().join("out.tar.gz.chunks.json"),
+    )?)?;
+    assert_eq!(hashes.file, "out.tar.gz");
+    assert_eq!(hashes.chunks.len(), output.len().div_ceil(1024));
+    assert_eq!(hashes.verified_len(&output), output.len() as u64);
+
+    Ok(())
+}